@@ -47,3 +47,9 @@ plugin_file="$(printf '%s/lib%s.so' "$bin_dir" "$plugin_name")"
 
 ln -f -s "$target_dir/$profile/libobs_openvr.so" "$plugin_file"
 file "$plugin_file"
+
+host_file="$bin_dir/obs-openvr-host"
+if [ -e "$target_dir/$profile/obs-openvr-host" ]; then
+	ln -f -s "$target_dir/$profile/obs-openvr-host" "$host_file"
+	file "$host_file"
+fi
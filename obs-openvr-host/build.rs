@@ -0,0 +1,28 @@
+extern crate cc;
+
+use std::{
+    env,
+    path::PathBuf,
+};
+
+fn main() {
+    // The host reads mirror textures through its own surfaceless EGL context, loading GL with the
+    // same glad the plugin uses
+    let glad_source_dir = env::var("OBS_SOURCE_DIR")
+        .map(PathBuf::from)
+        .map(|mut p| {
+            p.extend(["deps", "glad"]);
+            p
+        })
+        .expect("couldn't find OBS_SOURCE_DIR");
+    cc::Build::new()
+        .flag("-std=c11")
+        .flag("-Wno-unused-parameter")
+        .include(glad_source_dir.join("include"))
+        .file("src/gl.c")
+        .file(glad_source_dir.join("src/glad.c"))
+        .file(glad_source_dir.join("src/glad_egl.c"))
+        .compile("libobs-openvr-host-gl.a");
+    println!("cargo:rerun-if-changed=src/gl.c");
+    println!("cargo:rustc-link-lib=EGL");
+}
@@ -0,0 +1,174 @@
+use std::ffi::CString;
+use openvr::{
+    compositor::MirrorTextureInfo,
+    overlay::{
+        AlphaMode,
+        ChannelOrder,
+        OverlayImage,
+        OverlayRef,
+        PixelFormat,
+    },
+};
+use obs_openvr_ipc::protocol::{
+    Eye,
+    FrameInfo,
+    Layout,
+    Target,
+    Transfer,
+};
+use crate::gl::{
+    GlContext,
+    GlError,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CaptureError {
+    #[error("Overlay key contains a nul byte")]
+    InvalidKey,
+    #[error("Error finding overlay: {0:?}")]
+    NotFound(openvr::sys::EVROverlayError),
+    #[error("Error filling overlay image: {0:?}")]
+    Fill(openvr::sys::EVROverlayError),
+    #[error("Error getting mirror texture: {0:?}")]
+    Compositor(openvr::sys::EVRCompositorError),
+    #[error("{0}")]
+    Gl(#[from] GlError),
+    #[error("Mirror capture needs a GL context, which couldn't be created")]
+    NoGlContext,
+}
+
+pub enum Captured<'a> {
+    /// A new frame. `FrameInfo::sequence` is filled in when sending it.
+    Frame(FrameInfo, &'a [u8]),
+    Unchanged,
+    Hidden,
+    /// SteamVR is switching applications
+    Loading,
+}
+
+pub struct OverlayCapture {
+    key: CString,
+    overlay: Option<OverlayRef>,
+    image: OverlayImage,
+}
+
+impl OverlayCapture {
+    pub fn capture(&mut self) -> Result<Captured<'_>, CaptureError> {
+        let overlay = match self.overlay {
+            Some(v) => v,
+            None => {
+                let overlay = openvr::overlay::find_overlay(&self.key).map_err(CaptureError::NotFound)?;
+                debug!("found overlay {:?}: {}", &self.key, overlay);
+                *self.overlay.get_or_insert(overlay)
+            },
+        };
+        if !overlay.is_visible() {
+            // Send the first frame after it becomes visible again, changed or not
+            self.image.invalidate();
+            return Ok(Captured::Hidden);
+        }
+        if let Err(e) = self.image.fill(overlay.handle()) {
+            // The handle may belong to an overlay that was destroyed, so look it up again
+            self.overlay = None;
+            return Err(CaptureError::Fill(e));
+        }
+        if !self.image.changed() {
+            return Ok(Captured::Unchanged);
+        }
+        let (w, h) = self.image.dimensions();
+        let format = self.image.format();
+        Ok(Captured::Frame(FrameInfo {
+            width: w,
+            height: h,
+            stride: w * PixelFormat::BYTES_PER_PIXEL,
+            layout: match format.order {
+                ChannelOrder::Rgba => Layout::Rgba,
+                ChannelOrder::Bgra => Layout::Bgra,
+            },
+            premultiplied: format.alpha == AlphaMode::Premultiplied,
+            transfer: Transfer::Unknown,
+            timestamp: obs_openvr_ipc::monotonic_ns(),
+            sequence: 0,
+        }, self.image.data()))
+    }
+}
+
+pub struct MirrorCapture {
+    eye: openvr::sys::EVREye,
+    /// Released before the GL context is destroyed, since the host drops every capture first
+    texture: Option<MirrorTextureInfo>,
+    buffer: Vec<u8>,
+}
+
+impl MirrorCapture {
+    pub fn capture(&mut self, gl: Option<&GlContext>) -> Result<Captured<'_>, CaptureError> {
+        let gl = gl.ok_or(CaptureError::NoGlContext)?;
+        let texture = match self.texture.take() {
+            Some(v) => v,
+            None => unsafe { openvr::compositor::get_mirror_texture_gl(self.eye) }
+                .map_err(CaptureError::Compositor)?,
+        };
+        let texture = self.texture.get_or_insert(texture);
+        let (w, h) = gl.texture_size(texture.id);
+        let stride = w * 4;
+        self.buffer.resize(stride as usize * h as usize, 0);
+        let timestamp = obs_openvr_ipc::monotonic_ns();
+        unsafe {
+            let _lock = texture.lock();
+            gl.read_texture(texture.id, &mut self.buffer)?;
+        }
+        Ok(Captured::Frame(FrameInfo {
+            width: w,
+            height: h,
+            stride: stride,
+            layout: Layout::Rgba,
+            premultiplied: false,
+            transfer: gl.texture_transfer(texture.id),
+            timestamp: timestamp,
+            sequence: 0,
+        }, &self.buffer))
+    }
+}
+
+pub enum Capture {
+    Overlay(OverlayCapture),
+    Mirror(MirrorCapture),
+}
+
+impl Capture {
+    pub fn new(target: &Target) -> Result<Self, CaptureError> {
+        match target {
+            Target::Overlay(key) => Ok(Capture::Overlay(OverlayCapture {
+                key: CString::new(key.as_str()).map_err(|_| CaptureError::InvalidKey)?,
+                overlay: None,
+                image: OverlayImage::new(),
+            })),
+            Target::Mirror(eye) => Ok(Capture::Mirror(MirrorCapture {
+                eye: match eye {
+                    Eye::Left => openvr::sys::EVREye::EVREye_Eye_Left,
+                    Eye::Right => openvr::sys::EVREye::EVREye_Eye_Right,
+                },
+                texture: None,
+                buffer: Vec::new(),
+            })),
+        }
+    }
+
+    #[inline]
+    pub fn needs_gl(&self) -> bool {
+        match self {
+            Capture::Mirror(..) => true,
+            Capture::Overlay(..) => false,
+        }
+    }
+
+    pub fn capture(&mut self, gl: Option<&GlContext>) -> Result<Captured<'_>, CaptureError> {
+        if openvr::compositor::in_transition() {
+            return Ok(Captured::Loading);
+        }
+        match self {
+            Capture::Overlay(capture) => capture.capture(),
+            Capture::Mirror(capture) => capture.capture(gl),
+        }
+    }
+}
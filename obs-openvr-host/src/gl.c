@@ -0,0 +1,115 @@
+#include <glad/glad.h>
+#include <glad/glad_egl.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#ifndef EGL_PLATFORM_SURFACELESS_MESA
+#define EGL_PLATFORM_SURFACELESS_MESA 0x31DD
+#endif
+
+typedef EGLDisplay (*obs_openvr_host_get_platform_display_fn)(EGLenum platform, void *native_display, const EGLint *attribs);
+
+struct obs_openvr_host_gl_size {
+	GLint width;
+	GLint height;
+};
+
+struct obs_openvr_host_gl {
+	EGLDisplay display;
+	EGLContext context;
+};
+
+static EGLDisplay get_display(void) {
+	// Prefer a display that doesn't need a window system, since the host has no window
+	obs_openvr_host_get_platform_display_fn get_platform_display = (obs_openvr_host_get_platform_display_fn)eglGetProcAddress("eglGetPlatformDisplayEXT");
+	if (get_platform_display != NULL) {
+		EGLDisplay display = get_platform_display(EGL_PLATFORM_SURFACELESS_MESA, EGL_DEFAULT_DISPLAY, NULL);
+		if (display != EGL_NO_DISPLAY) {
+			return display;
+		}
+	}
+	return eglGetDisplay(EGL_DEFAULT_DISPLAY);
+}
+
+EGLint obs_openvr_host_gl_create(struct obs_openvr_host_gl **out) {
+	EGLDisplay display = get_display();
+	if (display == EGL_NO_DISPLAY) {
+		return eglGetError();
+	}
+	if (!eglInitialize(display, NULL, NULL)) {
+		return eglGetError();
+	}
+	if (!eglBindAPI(EGL_OPENGL_API)) {
+		return eglGetError();
+	}
+	const EGLint config_attribs[] = {
+		EGL_RENDERABLE_TYPE, EGL_OPENGL_BIT,
+		EGL_NONE,
+	};
+	EGLConfig config;
+	EGLint num_configs = 0;
+	if (!eglChooseConfig(display, config_attribs, &config, 1, &num_configs) || num_configs < 1) {
+		EGLint status = eglGetError();
+		return status != EGL_SUCCESS ? status : EGL_BAD_CONFIG;
+	}
+	const EGLint context_attribs[] = {
+		EGL_CONTEXT_MAJOR_VERSION, 3,
+		EGL_CONTEXT_MINOR_VERSION, 3,
+		EGL_CONTEXT_OPENGL_PROFILE_MASK, EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT,
+		EGL_NONE,
+	};
+	EGLContext context = eglCreateContext(display, config, EGL_NO_CONTEXT, context_attribs);
+	if (context == EGL_NO_CONTEXT) {
+		return eglGetError();
+	}
+	// The host never draws, so the context is made current without a surface
+	if (!eglMakeCurrent(display, EGL_NO_SURFACE, EGL_NO_SURFACE, context)) {
+		EGLint status = eglGetError();
+		eglDestroyContext(display, context);
+		return status;
+	}
+	gladLoadGLLoader((GLADloadproc)eglGetProcAddress);
+
+	struct obs_openvr_host_gl *ctx = (struct obs_openvr_host_gl *)calloc(1, sizeof(struct obs_openvr_host_gl));
+	if (ctx == NULL) {
+		eglMakeCurrent(display, EGL_NO_SURFACE, EGL_NO_SURFACE, EGL_NO_CONTEXT);
+		eglDestroyContext(display, context);
+		return EGL_BAD_ALLOC;
+	}
+	ctx->display = display;
+	ctx->context = context;
+	*out = ctx;
+	return EGL_SUCCESS;
+}
+
+void obs_openvr_host_gl_destroy(struct obs_openvr_host_gl *ctx) {
+	if (ctx == NULL) {
+		return;
+	}
+	eglMakeCurrent(ctx->display, EGL_NO_SURFACE, EGL_NO_SURFACE, EGL_NO_CONTEXT);
+	eglDestroyContext(ctx->display, ctx->context);
+	free(ctx);
+}
+
+void obs_openvr_host_gl_texture_size(GLuint texture, struct obs_openvr_host_gl_size *out) {
+	glBindTexture(GL_TEXTURE_2D, texture);
+	glGetTexLevelParameteriv(GL_TEXTURE_2D, 0, GL_TEXTURE_WIDTH, &out->width);
+	glGetTexLevelParameteriv(GL_TEXTURE_2D, 0, GL_TEXTURE_HEIGHT, &out->height);
+}
+
+GLint obs_openvr_host_gl_texture_internal_format(GLuint texture) {
+	GLint format = 0;
+	glBindTexture(GL_TEXTURE_2D, texture);
+	glGetTexLevelParameteriv(GL_TEXTURE_2D, 0, GL_TEXTURE_INTERNAL_FORMAT, &format);
+	return format;
+}
+
+GLenum obs_openvr_host_gl_read_texture(GLuint texture, uint8_t *img) {
+	// Clear errors left over from earlier calls
+	while (glGetError() != GL_NO_ERROR) {
+	}
+	glBindTexture(GL_TEXTURE_2D, texture);
+	glPixelStorei(GL_PACK_ALIGNMENT, 1);
+	glGetTexImage(GL_TEXTURE_2D, 0, GL_RGBA, GL_UNSIGNED_BYTE, img);
+	return glGetError();
+}
@@ -0,0 +1,94 @@
+use std::{
+    marker::PhantomData,
+    ptr,
+};
+use obs_openvr_ipc::protocol::Transfer;
+
+const EGL_SUCCESS: i32 = 0x3000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum GlError {
+    #[error("EGL error: 0x{0:x}")]
+    Egl(i32),
+    #[error("GL error: 0x{0:x}")]
+    Gl(u32),
+}
+
+/// A surfaceless EGL context, current on the thread that created it for its whole lifetime
+pub struct GlContext {
+    ctx: *mut libc::c_void,
+    /// Contexts are current per-thread, so this can't be sent to another one
+    _not_send: PhantomData<*const ()>,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct TextureSize {
+    width: i32,
+    height: i32,
+}
+
+impl GlContext {
+    /// Creates a context, and makes it current on this thread
+    pub fn new() -> Result<Self, GlError> {
+        let mut ctx = ptr::null_mut();
+        let status = unsafe { obs_openvr_host_gl_create(&mut ctx as *mut _) };
+        if status != EGL_SUCCESS {
+            return Err(GlError::Egl(status));
+        }
+        Ok(GlContext {
+            ctx: ctx,
+            _not_send: PhantomData {},
+        })
+    }
+
+    pub fn texture_size(&self, texture: u32) -> (u32, u32) {
+        let mut size = TextureSize::default();
+        unsafe {
+            obs_openvr_host_gl_texture_size(texture, &mut size as *mut _);
+        }
+        (size.width.max(0) as u32, size.height.max(0) as u32)
+    }
+
+    /// Transfer function of a texture's pixels, from its internal format
+    pub fn texture_transfer(&self, texture: u32) -> Transfer {
+        // GL_SRGB, GL_SRGB8, GL_SRGB_ALPHA, GL_SRGB8_ALPHA8
+        const SRGB_FORMATS: [i32; 4] = [0x8C40, 0x8C41, 0x8C42, 0x8C43];
+        // GL_RGBA32F, GL_RGB32F, GL_RGBA16F, GL_RGB16F, GL_R11F_G11F_B10F
+        const LINEAR_FORMATS: [i32; 5] = [0x8814, 0x8815, 0x881A, 0x881B, 0x8C3A];
+        let format = unsafe { obs_openvr_host_gl_texture_internal_format(texture) };
+        if SRGB_FORMATS.contains(&format) {
+            Transfer::Srgb
+        } else if LINEAR_FORMATS.contains(&format) {
+            Transfer::Linear
+        } else {
+            Transfer::Unknown
+        }
+    }
+
+    /// Reads a texture as tightly packed 8-bit RGBA into `img`, which must hold all of it
+    pub fn read_texture(&self, texture: u32, img: &mut [u8]) -> Result<(), GlError> {
+        let (w, h) = self.texture_size(texture);
+        assert!(img.len() >= w as usize * h as usize * 4, "texture doesn't fit in the image buffer");
+        match unsafe { obs_openvr_host_gl_read_texture(texture, img.as_mut_ptr()) } {
+            0 => Ok(()),
+            e => Err(GlError::Gl(e)),
+        }
+    }
+}
+
+impl Drop for GlContext {
+    fn drop(&mut self) {
+        unsafe {
+            obs_openvr_host_gl_destroy(self.ctx);
+        }
+    }
+}
+
+extern "C" {
+    fn obs_openvr_host_gl_create(out: *mut *mut libc::c_void) -> i32;
+    fn obs_openvr_host_gl_destroy(ctx: *mut libc::c_void);
+    fn obs_openvr_host_gl_texture_size(texture: u32, out: *mut TextureSize);
+    fn obs_openvr_host_gl_texture_internal_format(texture: u32) -> i32;
+    fn obs_openvr_host_gl_read_texture(texture: u32, img: *mut u8) -> u32;
+}
@@ -0,0 +1,335 @@
+//! Capture host for obs-openvr. Owns the OpenVR session, so SteamVR crashing or hanging takes
+//! down this process instead of OBS, and serves captures to the plugin over the Unix socket
+//! given with `--socket`.
+
+#[macro_use] extern crate log;
+extern crate env_logger;
+extern crate libc;
+extern crate openvr;
+extern crate obs_openvr_ipc;
+extern crate thiserror;
+
+mod capture;
+mod gl;
+
+use std::{
+    collections::HashMap,
+    env,
+    fs,
+    io,
+    os::unix::net::{
+        UnixListener,
+        UnixStream,
+    },
+    path::PathBuf,
+    process,
+    sync::mpsc::{
+        self,
+        RecvTimeoutError,
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use obs_openvr_ipc::{
+    protocol::{
+        PROTOCOL_VERSION,
+        Request,
+        Response,
+    },
+    server::FrameWriter,
+};
+use capture::{
+    Capture,
+    Captured,
+};
+use gl::GlContext;
+
+/// How often initializing OpenVR is retried after it failed
+const OPENVR_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+/// Longest time between checks for requests
+const MAX_WAIT: Duration = Duration::from_millis(100);
+
+/// What was last sent on a stream, so states are only reported when they change
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Reported {
+    Nothing,
+    Frame,
+    Hidden,
+    Loading,
+    Error(String),
+}
+
+struct Stream {
+    capture: Capture,
+    writer: FrameWriter,
+    interval: Duration,
+    next_capture: Instant,
+    reported: Reported,
+}
+
+struct Host {
+    connection: UnixStream,
+    streams: HashMap<u32, Stream>,
+    /// Declared after `streams`, so mirror textures are released while it is still current
+    gl: Option<GlContext>,
+    gl_attempted: bool,
+    openvr_initialized: bool,
+    last_openvr_attempt: Option<Instant>,
+}
+
+impl Host {
+    fn send(&mut self, response: &Response) -> io::Result<()> {
+        response.write_to(&mut self.connection)
+    }
+
+    fn handle(&mut self, request: Request) -> io::Result<()> {
+        match request {
+            Request::Hello { version } => {
+                if version != PROTOCOL_VERSION {
+                    let message = format!("plugin speaks protocol version {}, expected {}", version, PROTOCOL_VERSION);
+                    // Nothing else the plugin sends can be trusted to mean the same, so hang up
+                    self.send(&Response::Error {
+                        stream: 0,
+                        message: message.clone(),
+                    })?;
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                }
+                self.send(&Response::Hello {
+                    version: PROTOCOL_VERSION,
+                    pid: process::id(),
+                })?;
+            },
+            Request::Subscribe { stream: id, target, interval_us } => {
+                debug!("stream {}: capturing {:?} every {}us", id, &target, interval_us);
+                match Capture::new(&target) {
+                    Ok(capture) => {
+                        self.streams.insert(id, Stream {
+                            capture: capture,
+                            writer: FrameWriter::new(id),
+                            interval: Duration::from_micros(interval_us as u64),
+                            next_capture: Instant::now(),
+                            reported: Reported::Nothing,
+                        });
+                    },
+                    Err(e) => {
+                        self.streams.remove(&id);
+                        self.send(&Response::Error {
+                            stream: id,
+                            message: e.to_string(),
+                        })?;
+                    },
+                }
+            },
+            Request::Unsubscribe { stream } => {
+                debug!("stream {}: unsubscribed", stream);
+                self.streams.remove(&stream);
+            },
+            Request::Release { stream, buffer, slot } => {
+                if let Some(stream) = self.streams.get_mut(&stream) {
+                    stream.writer.release(buffer, slot);
+                }
+            },
+            Request::Ping { token } => {
+                self.send(&Response::Pong {
+                    token: token,
+                })?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Initializes OpenVR if it isn't yet, retrying every `OPENVR_RETRY_INTERVAL`
+    fn init_openvr(&mut self) -> Result<(), String> {
+        if self.openvr_initialized {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let due = self.last_openvr_attempt
+            .map(|last| now.duration_since(last) >= OPENVR_RETRY_INTERVAL)
+            .unwrap_or(true);
+        if due {
+            self.last_openvr_attempt = Some(now);
+            match openvr::init(openvr::sys::EVRApplicationType::EVRApplicationType_VRApplication_Background) {
+                Ok(result) if result.value() => {
+                    info!("OpenVR initialized");
+                    self.openvr_initialized = true;
+                    return Ok(());
+                },
+                Ok(..) => warn!("OpenVR failed to initialize, but with no error"),
+                Err(e) => warn!("OpenVR failed to initialize: {:?}", e),
+            }
+        }
+        Err("OpenVR is not initialized".to_owned())
+    }
+
+    /// Captures every stream that is due, and returns when the next one will be
+    fn capture_due(&mut self) -> io::Result<Option<Instant>> {
+        if self.streams.is_empty() {
+            return Ok(None);
+        }
+        let openvr_status = self.init_openvr();
+        if !self.gl_attempted && self.streams.values().any(|stream| stream.capture.needs_gl()) {
+            self.gl_attempted = true;
+            match GlContext::new() {
+                Ok(v) => self.gl = Some(v),
+                Err(e) => error!("error creating GL context for mirror capture: {}", &e),
+            }
+        }
+        let now = Instant::now();
+        let gl = self.gl.as_ref();
+        let connection = &mut self.connection;
+        let mut next = None;
+        for (&id, stream) in self.streams.iter_mut() {
+            if stream.next_capture <= now {
+                stream.next_capture = now + stream.interval;
+                let result = match openvr_status.as_ref() {
+                    Ok(..) => stream.capture.capture(gl).map_err(|e| e.to_string()),
+                    Err(e) => Err(e.clone()),
+                };
+                match result {
+                    Ok(Captured::Frame(info, data)) => {
+                        if !stream.writer.write(connection, info, data)? {
+                            trace!("stream {}: plugin holds every slot, dropped a frame", id);
+                        }
+                        stream.reported = Reported::Frame;
+                    },
+                    Ok(Captured::Unchanged) => {},
+                    Ok(Captured::Hidden) => {
+                        if stream.reported != Reported::Hidden {
+                            Response::Hidden {
+                                stream: id,
+                            }.write_to(connection)?;
+                            stream.reported = Reported::Hidden;
+                        }
+                    },
+                    Ok(Captured::Loading) => {
+                        if stream.reported != Reported::Loading {
+                            Response::Loading {
+                                stream: id,
+                            }.write_to(connection)?;
+                            stream.reported = Reported::Loading;
+                        }
+                    },
+                    Err(message) => {
+                        if stream.reported != Reported::Error(message.clone()) {
+                            warn!("stream {}: {}", id, &message);
+                            Response::Error {
+                                stream: id,
+                                message: message.clone(),
+                            }.write_to(connection)?;
+                            stream.reported = Reported::Error(message);
+                        }
+                    },
+                }
+            }
+            next = Some(next.map_or(stream.next_capture, |next: Instant| next.min(stream.next_capture)));
+        }
+        Ok(next)
+    }
+
+    /// Serves the plugin until it disconnects
+    fn run(&mut self) -> io::Result<()> {
+        let (requests, received) = mpsc::channel();
+        let mut reader = self.connection.try_clone()?;
+        thread::spawn(move || {
+            loop {
+                match Request::read_from(&mut reader) {
+                    Ok(Some(request)) => {
+                        if requests.send(request).is_err() {
+                            break;
+                        }
+                    },
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!("error reading request: {}", &e);
+                        break;
+                    },
+                }
+            }
+        });
+        let mut next_capture = None;
+        loop {
+            let wait = next_capture
+                .map(|next: Instant| next.saturating_duration_since(Instant::now()))
+                .unwrap_or(MAX_WAIT)
+                .min(MAX_WAIT);
+            match received.recv_timeout(wait) {
+                Ok(request) => self.handle(request)?,
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+            while let Ok(request) = received.try_recv() {
+                self.handle(request)?;
+            }
+            next_capture = self.capture_due()?;
+        }
+    }
+}
+
+fn socket_path() -> Option<PathBuf> {
+    let mut args = env::args_os().skip(1);
+    let mut ret = None;
+    while let Some(arg) = args.next() {
+        if arg == "--socket" {
+            ret = args.next().map(PathBuf::from);
+        } else {
+            return None;
+        }
+    }
+    ret
+}
+
+fn main() {
+    env_logger::init();
+    let socket = match socket_path() {
+        Some(v) => v,
+        None => {
+            eprintln!("usage: obs-openvr-host --socket <path>");
+            process::exit(2);
+        },
+    };
+    // Don't outlive OBS if it crashes without killing us
+    unsafe {
+        libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+    }
+
+    let _ = fs::remove_file(&socket);
+    let listener = match UnixListener::bind(&socket) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error binding {:?}: {}", &socket, &e);
+            process::exit(1);
+        },
+    };
+    let connection = listener.accept().map(|(connection, _)| connection);
+    let _ = fs::remove_file(&socket);
+    let connection = match connection {
+        Ok(v) => v,
+        Err(e) => {
+            error!("error accepting connection: {}", &e);
+            process::exit(1);
+        },
+    };
+    info!("plugin connected");
+
+    let mut host = Host {
+        connection: connection,
+        streams: HashMap::new(),
+        gl: None,
+        gl_attempted: false,
+        openvr_initialized: false,
+        last_openvr_attempt: None,
+    };
+    if let Err(e) = host.run() {
+        warn!("connection to plugin failed: {}", &e);
+    }
+    let openvr_initialized = host.openvr_initialized;
+    drop(host);
+    if openvr_initialized {
+        openvr::shutdown();
+    }
+    info!("exiting");
+}
@@ -0,0 +1,934 @@
+//! Plugin side of the protocol: launches the capture host, restarts it when it dies or stops
+//! responding, and hands the frames it sends to streams
+
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    env,
+    fs,
+    io,
+    mem,
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::{
+        Path,
+        PathBuf,
+    },
+    process::{
+        self,
+        Child,
+        Command,
+        Stdio,
+    },
+    sync::{
+        Arc,
+        Mutex,
+        atomic::{
+            AtomicBool,
+            AtomicU32,
+            AtomicU64,
+            Ordering,
+        },
+    },
+    thread::{
+        self,
+        JoinHandle,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use crate::{
+    protocol::{
+        FrameInfo,
+        PROTOCOL_VERSION,
+        ProtocolError,
+        Request,
+        Response,
+        Target,
+    },
+    shm::SharedMemory,
+};
+
+/// A running capture host
+pub trait HostProcess: Send {
+    fn has_exited(&mut self) -> bool;
+    /// Kills the host, and waits for it to exit
+    fn kill(&mut self);
+}
+
+impl HostProcess for Child {
+    fn has_exited(&mut self) -> bool {
+        !matches!(self.try_wait(), Ok(None))
+    }
+
+    fn kill(&mut self) {
+        let _ = Child::kill(self);
+        let _ = self.wait();
+    }
+}
+
+/// Starts capture hosts
+pub trait Launcher: Send + 'static {
+    /// Starts a host that listens on `socket`
+    fn launch(&mut self, socket: &Path) -> io::Result<Box<dyn HostProcess>>;
+}
+
+/// Launches the host binary at `program` as `program --socket <path>`
+#[derive(Debug, Clone)]
+pub struct CommandLauncher {
+    program: PathBuf,
+}
+
+impl CommandLauncher {
+    pub fn new<P: Into<PathBuf>>(program: P) -> Self {
+        CommandLauncher {
+            program: program.into(),
+        }
+    }
+}
+
+impl Launcher for CommandLauncher {
+    fn launch(&mut self, socket: &Path) -> io::Result<Box<dyn HostProcess>> {
+        let child = Command::new(&self.program)
+            .arg("--socket")
+            .arg(socket)
+            .stdin(Stdio::null())
+            .spawn()?;
+        info!("launched capture host {:?} (pid {})", &self.program, child.id());
+        Ok(Box::new(child))
+    }
+}
+
+/// Directory sockets are created in: `$XDG_RUNTIME_DIR`, or the temporary directory
+pub fn runtime_dir() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    pub socket_dir: PathBuf,
+    /// How long a new host has to accept the connection and answer `Hello`
+    pub connect_timeout: Duration,
+    pub ping_interval: Duration,
+    /// A host that sends nothing for this long is considered hung, and restarted
+    pub hang_timeout: Duration,
+    /// Delay before restarting a host, doubled after each failed start up to `max_backoff`
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            socket_dir: runtime_dir(),
+            connect_timeout: Duration::from_secs(5),
+            ping_interval: Duration::from_secs(1),
+            hang_timeout: Duration::from_secs(5),
+            min_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum SessionError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Protocol(#[from] ProtocolError),
+    #[error("Capture host exited")]
+    Exited,
+    #[error("Timed out connecting to capture host")]
+    Timeout,
+    #[error("Unexpected response to hello: {0:?}")]
+    Handshake(Option<Response>),
+    #[error("Capture host speaks protocol version {0}, expected {}", PROTOCOL_VERSION)]
+    Version(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum StreamError {
+    #[error("Capture host is not running")]
+    Disconnected,
+    #[error("Capture host error: {0}")]
+    Host(String),
+    #[error("Error mapping capture host buffer: {0}")]
+    Buffer(String),
+}
+
+/// Outcome of `RemoteStream::poll`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Poll {
+    /// A new frame is in `RemoteStream::data`
+    Frame,
+    Unchanged,
+    /// The target isn't visible
+    Hidden,
+    /// SteamVR is switching applications
+    Loading,
+}
+
+/// Size of a buffer of `slots` slots of `slot_size` bytes, unless it can't be mapped
+fn buffer_len(slot_size: u64, slots: u8) -> Option<usize> {
+    usize::try_from(slot_size).ok()?.checked_mul(slots as usize)
+}
+
+/// Whether a frame described by `info` fits in a slot of `slot_size` bytes, with rows at least as
+/// long as its pixels
+fn frame_fits(info: &FrameInfo, slot_size: usize) -> bool {
+    let row_len = info.width as u64 * info.layout.bytes_per_pixel() as u64;
+    info.stride as u64 >= row_len && info.stride as u64 * info.height as u64 <= slot_size as u64
+}
+
+struct Buffer {
+    id: u32,
+    memory: SharedMemory,
+    slot_size: usize,
+    slots: u8,
+}
+
+#[derive(Default)]
+struct StreamState {
+    buffer: Option<Buffer>,
+    info: Option<FrameInfo>,
+    data: Vec<u8>,
+    /// Incremented for every frame received
+    generation: u64,
+    hidden: bool,
+    loading: bool,
+    error: Option<StreamError>,
+}
+
+impl StreamState {
+    fn reset(&mut self) {
+        self.buffer = None;
+        self.hidden = false;
+        self.loading = false;
+        self.error = None;
+    }
+}
+
+struct StreamShared {
+    target: Target,
+    interval_us: u32,
+    state: Mutex<StreamState>,
+}
+
+impl StreamShared {
+    fn subscribe_request(&self, id: u32) -> Request {
+        Request::Subscribe {
+            stream: id,
+            target: self.target.clone(),
+            interval_us: self.interval_us,
+        }
+    }
+}
+
+struct ClientShared {
+    options: ClientOptions,
+    running: AtomicBool,
+    connected: AtomicBool,
+    launches: AtomicU64,
+    host_pid: AtomicU32,
+    /// Write half of the current connection. Locked after `streams` when both are needed.
+    writer: Mutex<Option<UnixStream>>,
+    streams: Mutex<HashMap<u32, Arc<StreamShared>>>,
+    next_stream: AtomicU32,
+    /// When anything was last received from the host
+    last_seen: Mutex<Instant>,
+}
+
+impl ClientShared {
+    #[inline]
+    fn running(&self) -> bool {
+        self.running.load(Ordering::Acquire)
+    }
+
+    /// Sends `request` to the current host, if there is one. Write errors close the connection,
+    /// which the supervisor then notices.
+    fn send(&self, request: &Request) {
+        let mut writer = self.writer.lock().unwrap();
+        let failed = match writer.as_mut() {
+            Some(stream) => request.write_to(stream).is_err(),
+            None => false,
+        };
+        if failed {
+            if let Some(stream) = writer.take() {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    /// Sleeps for `duration`, or until the client is stopped
+    fn sleep(&self, duration: Duration) {
+        const STEP: Duration = Duration::from_millis(50);
+        let end = Instant::now() + duration;
+        while self.running() {
+            let now = Instant::now();
+            if now >= end {
+                break;
+            }
+            thread::sleep((end - now).min(STEP));
+        }
+    }
+
+    fn stream(&self, id: u32) -> Option<Arc<StreamShared>> {
+        self.streams.lock().unwrap().get(&id).cloned()
+    }
+
+    #[allow(clippy::redundant_field_names)]
+    fn dispatch(&self, response: Response) {
+        match response {
+            Response::Buffer { stream, buffer, name, slot_size, slots } => {
+                let stream = match self.stream(stream) {
+                    Some(v) => v,
+                    None => return,
+                };
+                let mut state = stream.state.lock().unwrap();
+                let len = match buffer_len(slot_size, slots) {
+                    Some(v) => v,
+                    None => {
+                        warn!("capture host buffer {} is too large: {} slots of {} bytes", &name, slots, slot_size);
+                        state.buffer = None;
+                        state.error = Some(StreamError::Buffer(format!("{} slots of {} bytes don't fit in memory", slots, slot_size)));
+                        return;
+                    },
+                };
+                // Can't overflow if `buffer_len` didn't
+                let slot_size = slot_size as usize;
+                match SharedMemory::open(&name, len) {
+                    Ok(memory) => {
+                        state.buffer = Some(Buffer {
+                            id: buffer,
+                            memory: memory,
+                            slot_size: slot_size,
+                            slots: slots,
+                        });
+                    },
+                    Err(e) => {
+                        warn!("error opening capture host buffer {}: {}", &name, &e);
+                        state.buffer = None;
+                        state.error = Some(StreamError::Buffer(e.to_string()));
+                    },
+                }
+            },
+            Response::Frame { stream: id, buffer, slot, info } => {
+                if let Some(stream) = self.stream(id) {
+                    let mut state = stream.state.lock().unwrap();
+                    let state = &mut *state;
+                    let data = state.buffer.as_ref()
+                        .filter(|b| b.id == buffer && slot < b.slots && frame_fits(&info, b.slot_size))
+                        .map(|b| &b.memory.as_slice()[slot as usize * b.slot_size..][..info.data_len()]);
+                    match data {
+                        Some(data) => {
+                            state.data.clear();
+                            state.data.extend_from_slice(data);
+                            state.info = Some(info);
+                            state.generation += 1;
+                            state.hidden = false;
+                            state.loading = false;
+                            state.error = None;
+                        },
+                        None => warn!("stream {}: ignoring frame in unknown slot {} of buffer {}, or too large for it", id, slot, buffer),
+                    }
+                }
+                self.send(&Request::Release {
+                    stream: id,
+                    buffer: buffer,
+                    slot: slot,
+                });
+            },
+            Response::Hidden { stream } => {
+                if let Some(stream) = self.stream(stream) {
+                    let mut state = stream.state.lock().unwrap();
+                    state.hidden = true;
+                    state.loading = false;
+                }
+            },
+            Response::Loading { stream } => {
+                if let Some(stream) = self.stream(stream) {
+                    let mut state = stream.state.lock().unwrap();
+                    state.loading = true;
+                    state.hidden = false;
+                }
+            },
+            Response::Error { stream, message } => {
+                if let Some(stream) = self.stream(stream) {
+                    stream.state.lock().unwrap().error = Some(StreamError::Host(message));
+                }
+            },
+            Response::Pong { .. } => {},
+            Response::Hello { .. } => warn!("unexpected hello from capture host"),
+        }
+    }
+
+    /// Connects to a host that was just launched
+    fn connect(&self, socket: &Path, process: &mut dyn HostProcess) -> Result<UnixStream, SessionError> {
+        let deadline = Instant::now() + self.options.connect_timeout;
+        let mut stream = loop {
+            if process.has_exited() {
+                return Err(SessionError::Exited);
+            }
+            match UnixStream::connect(socket) {
+                Ok(v) => break v,
+                Err(e) if Instant::now() >= deadline => {
+                    debug!("last error connecting to capture host: {}", &e);
+                    return Err(SessionError::Timeout);
+                },
+                Err(_) => thread::sleep(Duration::from_millis(20)),
+            }
+        };
+        stream.set_read_timeout(Some(deadline.saturating_duration_since(Instant::now()).max(Duration::from_millis(1))))?;
+        Request::Hello {
+            version: PROTOCOL_VERSION,
+        }.write_to(&mut stream)?;
+        match Response::read_from(&mut stream)? {
+            Some(Response::Hello { version, pid }) if version == PROTOCOL_VERSION => {
+                self.host_pid.store(pid, Ordering::Relaxed);
+            },
+            Some(Response::Hello { version, .. }) => return Err(SessionError::Version(version)),
+            response => return Err(SessionError::Handshake(response)),
+        }
+        stream.set_read_timeout(None)?;
+        Ok(stream)
+    }
+
+    /// Serves a connected host until it disconnects, exits or hangs, or the client is stopped
+    #[allow(clippy::redundant_field_names)]
+    fn run_session(self: &Arc<Self>, stream: UnixStream, process: &mut dyn HostProcess) -> Result<(), SessionError> {
+        let mut reader = stream.try_clone()?;
+        *self.last_seen.lock().unwrap() = Instant::now();
+        {
+            let streams = self.streams.lock().unwrap();
+            *self.writer.lock().unwrap() = Some(stream);
+            self.connected.store(true, Ordering::Release);
+            for (&id, stream) in streams.iter() {
+                self.send(&stream.subscribe_request(id));
+            }
+        }
+        let reader_done = Arc::new(AtomicBool::new(false));
+        let reader_thread = {
+            let shared = self.clone();
+            let reader_done = reader_done.clone();
+            thread::spawn(move || {
+                loop {
+                    match Response::read_from(&mut reader) {
+                        Ok(Some(response)) => {
+                            *shared.last_seen.lock().unwrap() = Instant::now();
+                            shared.dispatch(response);
+                        },
+                        Ok(None) => break,
+                        Err(e) => {
+                            if shared.connected.load(Ordering::Acquire) {
+                                warn!("error reading from capture host: {}", &e);
+                            }
+                            break;
+                        },
+                    }
+                }
+                reader_done.store(true, Ordering::Release);
+            })
+        };
+
+        let poll_interval = (self.options.ping_interval / 4).min(Duration::from_millis(100));
+        let mut last_ping = Instant::now();
+        let mut token = 0u64;
+        let ret = loop {
+            if !self.running() {
+                break Ok(());
+            }
+            if reader_done.load(Ordering::Acquire) {
+                info!("capture host closed the connection");
+                break Ok(());
+            }
+            if process.has_exited() {
+                break Err(SessionError::Exited);
+            }
+            let silent = self.last_seen.lock().unwrap().elapsed();
+            if silent >= self.options.hang_timeout {
+                warn!("capture host hasn't responded in {:?}, restarting it", silent);
+                break Ok(());
+            }
+            if last_ping.elapsed() >= self.options.ping_interval {
+                self.send(&Request::Ping {
+                    token: token,
+                });
+                token += 1;
+                last_ping = Instant::now();
+            }
+            thread::sleep(poll_interval);
+        };
+
+        self.connected.store(false, Ordering::Release);
+        if let Some(stream) = self.writer.lock().unwrap().take() {
+            // Also unblocks the reader
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        let _ = reader_thread.join();
+        ret
+    }
+
+    fn supervise(self: Arc<Self>, mut launcher: Box<dyn Launcher>) {
+        let mut backoff = self.options.min_backoff;
+        while self.running() {
+            let launch = self.launches.fetch_add(1, Ordering::Relaxed) + 1;
+            let socket = self.options.socket_dir.join(format!("obs-openvr-{}-{}.sock", process::id(), launch));
+            let _ = fs::remove_file(&socket);
+            let started = Instant::now();
+            match launcher.launch(&socket) {
+                Ok(mut host) => {
+                    let result = self.connect(&socket, &mut *host)
+                        .and_then(|stream| self.run_session(stream, &mut *host));
+                    if let Err(e) = result {
+                        warn!("capture host session ended: {}", &e);
+                    }
+                    host.kill();
+                },
+                Err(e) => warn!("error launching capture host: {}", &e),
+            }
+            let _ = fs::remove_file(&socket);
+            for stream in self.streams.lock().unwrap().values() {
+                stream.state.lock().unwrap().reset();
+            }
+            if !self.running() {
+                break;
+            }
+            // A host that ran for a while before failing is restarted right away
+            if started.elapsed() >= self.options.max_backoff {
+                backoff = self.options.min_backoff;
+            }
+            debug!("restarting capture host in {:?}", backoff);
+            self.sleep(backoff);
+            backoff = (backoff * 2).min(self.options.max_backoff);
+        }
+    }
+}
+
+/// Connection to the capture host, launching and supervising it on a background thread
+pub struct HostClient {
+    shared: Arc<ClientShared>,
+    supervisor: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl HostClient {
+    #[allow(clippy::redundant_field_names)]
+    pub fn start<L: Launcher>(launcher: L, options: ClientOptions) -> Self {
+        let shared = Arc::new(ClientShared {
+            options: options,
+            running: AtomicBool::new(true),
+            connected: AtomicBool::new(false),
+            launches: AtomicU64::new(0),
+            host_pid: AtomicU32::new(0),
+            writer: Mutex::new(None),
+            streams: Mutex::new(HashMap::new()),
+            next_stream: AtomicU32::new(0),
+            last_seen: Mutex::new(Instant::now()),
+        });
+        let supervisor = {
+            let shared = shared.clone();
+            thread::spawn(move || shared.supervise(Box::new(launcher)))
+        };
+        HostClient {
+            shared: shared,
+            supervisor: Mutex::new(Some(supervisor)),
+        }
+    }
+
+    /// Starts capturing `target` every `interval`. The stream survives host restarts.
+    #[allow(clippy::redundant_field_names)]
+    pub fn open(&self, target: Target, interval: Duration) -> RemoteStream {
+        let id = self.shared.next_stream.fetch_add(1, Ordering::Relaxed);
+        let stream = Arc::new(StreamShared {
+            target: target,
+            interval_us: interval.as_micros().min(u32::MAX as u128) as u32,
+            state: Mutex::new(StreamState::default()),
+        });
+        {
+            let mut streams = self.shared.streams.lock().unwrap();
+            streams.insert(id, stream.clone());
+            self.shared.send(&stream.subscribe_request(id));
+        }
+        RemoteStream {
+            client: self.shared.clone(),
+            id: id,
+            shared: stream,
+            generation: None,
+            info: None,
+            data: Vec::new(),
+            invalidated: false,
+        }
+    }
+
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::Acquire)
+    }
+
+    /// Number of times a host was launched
+    #[inline]
+    pub fn launches(&self) -> u64 {
+        self.shared.launches.load(Ordering::Relaxed)
+    }
+
+    /// Process ID of the connected host
+    pub fn host_pid(&self) -> Option<u32> {
+        if self.is_connected() {
+            Some(self.shared.host_pid.load(Ordering::Relaxed))
+        } else {
+            None
+        }
+    }
+
+    /// Kills the host and stops restarting it. Open streams stay disconnected.
+    pub fn stop(&self) {
+        self.shared.running.store(false, Ordering::Release);
+        if let Some(supervisor) = self.supervisor.lock().unwrap().take() {
+            let _ = supervisor.join();
+        }
+    }
+}
+
+impl Drop for HostClient {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Frames of one target, received from the capture host
+pub struct RemoteStream {
+    client: Arc<ClientShared>,
+    id: u32,
+    shared: Arc<StreamShared>,
+    /// Generation of the frame in `data`
+    generation: Option<u64>,
+    info: Option<FrameInfo>,
+    data: Vec<u8>,
+    invalidated: bool,
+}
+
+impl RemoteStream {
+    /// Takes the latest frame the host sent, if it is newer than the one in `data`
+    pub fn poll(&mut self) -> Result<Poll, StreamError> {
+        if !self.client.connected.load(Ordering::Acquire) {
+            return Err(StreamError::Disconnected);
+        }
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(e) = state.error.as_ref() {
+            return Err(e.clone());
+        }
+        if state.hidden {
+            return Ok(Poll::Hidden);
+        }
+        if state.loading {
+            return Ok(Poll::Loading);
+        }
+        if state.info.is_some() && self.generation != Some(state.generation) {
+            mem::swap(&mut self.data, &mut state.data);
+            self.info = state.info;
+            self.generation = Some(state.generation);
+            self.invalidated = false;
+            return Ok(Poll::Frame);
+        }
+        if self.invalidated && self.info.is_some() {
+            self.invalidated = false;
+            return Ok(Poll::Frame);
+        }
+        Ok(Poll::Unchanged)
+    }
+
+    /// Makes the next `poll` return the current frame again, even if there is no newer one
+    #[inline]
+    pub fn invalidate(&mut self) {
+        self.invalidated = true;
+    }
+
+    #[inline]
+    pub fn target(&self) -> &Target {
+        &self.shared.target
+    }
+
+    /// Description of the frame in `data`
+    #[inline]
+    pub fn info(&self) -> Option<&FrameInfo> {
+        self.info.as_ref()
+    }
+
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Drop for RemoteStream {
+    fn drop(&mut self) {
+        let mut streams = self.client.streams.lock().unwrap();
+        streams.remove(&self.id);
+        self.client.send(&Request::Unsubscribe {
+            stream: self.id,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        os::unix::net::UnixListener,
+        sync::mpsc,
+    };
+    use crate::{
+        protocol::{
+            Eye,
+            Layout,
+            Transfer,
+        },
+        server::FrameWriter,
+    };
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Behavior {
+        Normal,
+        /// Closes the connection after sending the first frame
+        ExitAfterFrame,
+        /// Stops reading and responding after sending the first frame
+        HangAfterFrame,
+        /// Reports every target as hidden
+        Hidden,
+        /// Reports every target as loading
+        Loading,
+        /// Reports an error for every target
+        Fail,
+    }
+
+    struct FakeProcess {
+        killed: Arc<AtomicBool>,
+        exited: Arc<AtomicBool>,
+    }
+
+    impl HostProcess for FakeProcess {
+        fn has_exited(&mut self) -> bool {
+            self.exited.load(Ordering::Acquire)
+        }
+
+        fn kill(&mut self) {
+            self.killed.store(true, Ordering::Release);
+        }
+    }
+
+    /// Launches hosts running on threads, with the behavior of the launch's index in
+    /// `behaviors`, or the last one
+    struct FakeLauncher {
+        behaviors: Vec<Behavior>,
+        launched: usize,
+        kills: mpsc::Sender<usize>,
+    }
+
+    const FRAME_WIDTH: u32 = 4;
+    const FRAME_HEIGHT: u32 = 2;
+
+    #[allow(clippy::redundant_field_names)]
+    fn run_fake_host(listener: UnixListener, behavior: Behavior, launch: usize, killed: Arc<AtomicBool>) {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+        let mut writers = HashMap::new();
+        let mut reader = stream.try_clone().unwrap();
+        let mut hung = false;
+        while !killed.load(Ordering::Acquire) {
+            if hung {
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            let request = match Request::read_from(&mut reader) {
+                Ok(Some(v)) => v,
+                Ok(None) => return,
+                Err(ProtocolError::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => continue,
+                Err(e) => panic!("fake host read error: {}", e),
+            };
+            match request {
+                Request::Hello { version } => Response::Hello {
+                    version: version,
+                    pid: launch as u32,
+                }.write_to(&mut stream).unwrap(),
+                Request::Subscribe { stream: id, .. } => match behavior {
+                    Behavior::Hidden => Response::Hidden { stream: id }.write_to(&mut stream).unwrap(),
+                    Behavior::Loading => Response::Loading { stream: id }.write_to(&mut stream).unwrap(),
+                    Behavior::Fail => Response::Error { stream: id, message: "no such overlay".into() }.write_to(&mut stream).unwrap(),
+                    _ => {
+                        let writer = writers.entry(id).or_insert_with(|| FrameWriter::new(id));
+                        let info = FrameInfo {
+                            width: FRAME_WIDTH,
+                            height: FRAME_HEIGHT,
+                            stride: FRAME_WIDTH * 4,
+                            layout: Layout::Bgra,
+                            premultiplied: false,
+                            transfer: Transfer::Srgb,
+                            timestamp: 0,
+                            sequence: 0,
+                        };
+                        // Every pixel holds the launch index, so tests can tell hosts apart
+                        let data = vec![launch as u8; info.data_len()];
+                        assert!(writer.write(&mut stream, info, &data).unwrap());
+                        match behavior {
+                            Behavior::ExitAfterFrame => return,
+                            Behavior::HangAfterFrame => hung = true,
+                            _ => {},
+                        }
+                    },
+                },
+                Request::Release { stream: id, buffer, slot } => {
+                    if let Some(writer) = writers.get_mut(&id) {
+                        writer.release(buffer, slot);
+                    }
+                },
+                Request::Ping { token } => Response::Pong { token: token }.write_to(&mut stream).unwrap(),
+                Request::Unsubscribe { stream: id } => {
+                    writers.remove(&id);
+                },
+            }
+        }
+    }
+
+    impl Launcher for FakeLauncher {
+        #[allow(clippy::redundant_field_names)]
+        fn launch(&mut self, socket: &Path) -> io::Result<Box<dyn HostProcess>> {
+            let launch = self.launched;
+            self.launched += 1;
+            let behavior = self.behaviors[launch.min(self.behaviors.len() - 1)];
+            let listener = UnixListener::bind(socket)?;
+            let killed = Arc::new(AtomicBool::new(false));
+            let exited = Arc::new(AtomicBool::new(false));
+            {
+                let killed = killed.clone();
+                let exited = exited.clone();
+                let kills = self.kills.clone();
+                thread::spawn(move || {
+                    run_fake_host(listener, behavior, launch, killed.clone());
+                    exited.store(true, Ordering::Release);
+                    if killed.load(Ordering::Acquire) {
+                        let _ = kills.send(launch);
+                    }
+                });
+            }
+            Ok(Box::new(FakeProcess {
+                killed: killed,
+                exited: exited,
+            }))
+        }
+    }
+
+    fn test_options() -> ClientOptions {
+        ClientOptions {
+            socket_dir: env::temp_dir(),
+            connect_timeout: Duration::from_secs(2),
+            ping_interval: Duration::from_millis(50),
+            hang_timeout: Duration::from_millis(300),
+            min_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(100),
+        }
+    }
+
+    #[allow(clippy::redundant_field_names)]
+    fn start(behaviors: Vec<Behavior>) -> (HostClient, mpsc::Receiver<usize>) {
+        let (kills, killed) = mpsc::channel();
+        let launcher = FakeLauncher {
+            behaviors: behaviors,
+            launched: 0,
+            kills: kills,
+        };
+        (HostClient::start(launcher, test_options()), killed)
+    }
+
+    /// Polls `stream` until `f` accepts the result, or panics after a few seconds
+    fn poll_until<F: FnMut(&Result<Poll, StreamError>, &RemoteStream) -> bool>(stream: &mut RemoteStream, mut f: F) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let result = stream.poll();
+            if f(&result, stream) {
+                return;
+            }
+            assert!(Instant::now() < deadline, "timed out polling stream, last result: {:?}", result);
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn frame_from(launch: u8) -> impl FnMut(&Result<Poll, StreamError>, &RemoteStream) -> bool {
+        move |result, stream| *result == Ok(Poll::Frame) && stream.data()[0] == launch
+    }
+
+    #[test]
+    fn streams_receive_frames() {
+        let (client, _) = start(vec![Behavior::Normal]);
+        let mut stream = client.open(Target::Overlay("test.overlay".into()), Duration::from_millis(10));
+        poll_until(&mut stream, frame_from(0));
+        let info = *stream.info().unwrap();
+        assert_eq!((info.width, info.height, info.layout), (FRAME_WIDTH, FRAME_HEIGHT, Layout::Bgra));
+        assert_eq!(stream.data().len(), info.data_len());
+        assert_eq!(stream.poll(), Ok(Poll::Unchanged));
+        stream.invalidate();
+        assert_eq!(stream.poll(), Ok(Poll::Frame));
+        assert_eq!(stream.poll(), Ok(Poll::Unchanged));
+        assert_eq!(client.host_pid(), Some(0));
+        assert_eq!(client.launches(), 1);
+    }
+
+    #[test]
+    fn rejects_oversized_buffers_and_frames() {
+        assert_eq!(buffer_len(1024, 3), Some(3072));
+        assert_eq!(buffer_len(u64::MAX / 2, 3), None);
+        let info = FrameInfo {
+            width: FRAME_WIDTH,
+            height: FRAME_HEIGHT,
+            stride: FRAME_WIDTH * 4,
+            layout: Layout::Bgra,
+            premultiplied: false,
+            transfer: Transfer::Srgb,
+            timestamp: 0,
+            sequence: 0,
+        };
+        assert!(frame_fits(&info, info.data_len()));
+        assert!(!frame_fits(&info, info.data_len() - 1));
+        assert!(!frame_fits(&FrameInfo { stride: FRAME_WIDTH * 3, ..info }, info.data_len()));
+        assert!(!frame_fits(&FrameInfo { height: u32::MAX, stride: u32::MAX, ..info }, usize::MAX / 2));
+    }
+
+    #[test]
+    fn restarts_host_that_exits() {
+        let (client, _) = start(vec![Behavior::ExitAfterFrame, Behavior::Normal]);
+        let mut stream = client.open(Target::Mirror(Eye::Left), Duration::from_millis(10));
+        // Resubscribed on the restarted host
+        poll_until(&mut stream, frame_from(1));
+        assert_eq!(client.launches(), 2);
+    }
+
+    #[test]
+    fn restarts_hung_host() {
+        let (client, killed) = start(vec![Behavior::HangAfterFrame, Behavior::Normal]);
+        let mut stream = client.open(Target::Overlay("test.overlay".into()), Duration::from_millis(10));
+        poll_until(&mut stream, frame_from(1));
+        assert_eq!(killed.recv_timeout(Duration::from_secs(1)), Ok(0));
+    }
+
+    #[test]
+    fn reports_host_state() {
+        let (client, _) = start(vec![Behavior::Hidden]);
+        let mut stream = client.open(Target::Overlay("test.overlay".into()), Duration::from_millis(10));
+        poll_until(&mut stream, |result, _| *result == Ok(Poll::Hidden));
+        drop(stream);
+        client.stop();
+        assert!(!client.is_connected());
+
+        let (client, _) = start(vec![Behavior::Loading]);
+        let mut stream = client.open(Target::Mirror(Eye::Right), Duration::from_millis(10));
+        poll_until(&mut stream, |result, _| *result == Ok(Poll::Loading));
+        drop(stream);
+        client.stop();
+
+        let (client, _) = start(vec![Behavior::Fail]);
+        let mut stream = client.open(Target::Overlay("test.overlay".into()), Duration::from_millis(10));
+        poll_until(&mut stream, |result, _| *result == Err(StreamError::Host("no such overlay".into())));
+    }
+}
@@ -0,0 +1,26 @@
+//! Protocol between the obs-openvr plugin and `obs-openvr-host`, a helper process that owns the
+//! OpenVR session, so SteamVR crashing or hanging can't take OBS down with it
+//!
+//! The plugin launches the host and connects to it over a Unix socket (see `protocol`). Captured
+//! frames are written to POSIX shared memory (see `shm`), and only described on the socket.
+
+extern crate libc;
+#[macro_use] extern crate log;
+extern crate thiserror;
+
+pub mod protocol;
+pub mod shm;
+pub mod server;
+pub mod client;
+
+/// Current `CLOCK_MONOTONIC` time in nanoseconds, the clock frame timestamps are taken from
+pub fn monotonic_ns() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts as *mut _);
+    }
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
@@ -0,0 +1,601 @@
+//! Messages exchanged between the plugin and the capture host, and their wire encoding
+//!
+//! Every message is a little-endian `u32` payload length followed by the payload, which starts
+//! with a one byte message tag.
+
+use std::{
+    convert::TryInto,
+    io::{
+        self,
+        Read,
+        Write,
+    },
+};
+
+/// Bumped whenever the encoding of any message changes
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Largest payload either side accepts, to bound allocations on corrupt input
+pub const MAX_MESSAGE_SIZE: u32 = 64 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProtocolError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("Message of {0} bytes exceeds the maximum size")]
+    TooLarge(u32),
+    #[error("Unknown message tag: {0}")]
+    UnknownTag(u8),
+    #[error("Invalid value {1} for {0}")]
+    InvalidValue(&'static str, u32),
+    #[error("Message ended early")]
+    Truncated,
+    #[error("Message has {0} trailing bytes")]
+    Trailing(usize),
+    #[error("Invalid UTF-8 in string")]
+    Utf8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+/// What a stream captures
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// An overlay, by its key
+    Overlay(String),
+    /// The compositor mirror texture of one eye
+    Mirror(Eye),
+}
+
+/// Layout of each pixel in a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Rgb,
+    Rgba,
+    Bgra,
+}
+
+impl Layout {
+    pub fn bytes_per_pixel(self) -> u32 {
+        match self {
+            Layout::Rgb => 3,
+            Layout::Rgba | Layout::Bgra => 4,
+        }
+    }
+}
+
+/// Transfer function of the pixel values, if the host knows it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    Unknown,
+    Srgb,
+    Linear,
+}
+
+/// Describes a frame written to a shared memory slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row
+    pub stride: u32,
+    pub layout: Layout,
+    pub premultiplied: bool,
+    pub transfer: Transfer,
+    /// `CLOCK_MONOTONIC` time the frame was captured at, in nanoseconds. On Linux this is the
+    /// same clock as `os_gettime_ns`.
+    pub timestamp: u64,
+    /// Incremented for every frame the host sends on a stream
+    pub sequence: u64,
+}
+
+impl FrameInfo {
+    /// Number of bytes the frame occupies in its slot
+    #[inline]
+    pub fn data_len(&self) -> usize {
+        self.stride as usize * self.height as usize
+    }
+}
+
+/// Messages sent by the plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// First message on every connection
+    Hello {
+        version: u32,
+    },
+    /// Starts capturing `target` every `interval_us` microseconds. `stream` is chosen by the
+    /// plugin, and identifies the stream in every later message.
+    Subscribe {
+        stream: u32,
+        target: Target,
+        interval_us: u32,
+    },
+    Unsubscribe {
+        stream: u32,
+    },
+    /// The plugin is done reading `slot` of `buffer`, so the host may write to it again
+    Release {
+        stream: u32,
+        buffer: u32,
+        slot: u8,
+    },
+    Ping {
+        token: u64,
+    },
+}
+
+/// Messages sent by the capture host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// Reply to `Request::Hello`
+    Hello {
+        version: u32,
+        pid: u32,
+    },
+    /// Frames of `stream` are from now on written to the shared memory object `name`, which
+    /// holds `slots` slots of `slot_size` bytes each
+    Buffer {
+        stream: u32,
+        buffer: u32,
+        name: String,
+        slot_size: u64,
+        slots: u8,
+    },
+    /// A new frame was written to `slot` of `buffer`. The slot stays reserved for the plugin
+    /// until it sends `Request::Release`.
+    Frame {
+        stream: u32,
+        buffer: u32,
+        slot: u8,
+        info: FrameInfo,
+    },
+    /// The target exists, but isn't visible
+    Hidden {
+        stream: u32,
+    },
+    /// SteamVR is switching applications, so the target shows the compositor's loading
+    /// environment
+    Loading {
+        stream: u32,
+    },
+    /// Capturing the target failed
+    Error {
+        stream: u32,
+        message: String,
+    },
+    Pong {
+        token: u64,
+    },
+}
+
+mod tag {
+    pub const HELLO: u8 = 1;
+    pub const SUBSCRIBE: u8 = 2;
+    pub const UNSUBSCRIBE: u8 = 3;
+    pub const RELEASE: u8 = 4;
+    pub const PING: u8 = 5;
+
+    pub const BUFFER: u8 = 129;
+    pub const FRAME: u8 = 130;
+    pub const HIDDEN: u8 = 131;
+    pub const ERROR: u8 = 132;
+    pub const PONG: u8 = 133;
+    pub const LOADING: u8 = 134;
+
+    pub const TARGET_OVERLAY: u8 = 0;
+    pub const TARGET_MIRROR: u8 = 1;
+}
+
+/// Payload of a message being encoded
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn new(tag: u8) -> Self {
+        Encoder(vec![tag])
+    }
+
+    fn u8(&mut self, v: u8) -> &mut Self {
+        self.0.push(v);
+        self
+    }
+
+    fn u32(&mut self, v: u32) -> &mut Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn u64(&mut self, v: u64) -> &mut Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn bool(&mut self, v: bool) -> &mut Self {
+        self.u8(v as u8)
+    }
+
+    fn str(&mut self, v: &str) -> &mut Self {
+        self.u32(v.len() as u32);
+        self.0.extend_from_slice(v.as_bytes());
+        self
+    }
+
+    fn target(&mut self, target: &Target) -> &mut Self {
+        match target {
+            Target::Overlay(key) => self.u8(tag::TARGET_OVERLAY).str(key),
+            Target::Mirror(eye) => self.u8(tag::TARGET_MIRROR).u8(match eye {
+                Eye::Left => 0,
+                Eye::Right => 1,
+            }),
+        }
+    }
+
+    fn frame_info(&mut self, info: &FrameInfo) -> &mut Self {
+        let layout = match info.layout {
+            Layout::Rgb => 0,
+            Layout::Rgba => 1,
+            Layout::Bgra => 2,
+        };
+        let transfer = match info.transfer {
+            Transfer::Unknown => 0,
+            Transfer::Srgb => 1,
+            Transfer::Linear => 2,
+        };
+        self.u32(info.width)
+            .u32(info.height)
+            .u32(info.stride)
+            .u8(layout)
+            .bool(info.premultiplied)
+            .u8(transfer)
+            .u64(info.timestamp)
+            .u64(info.sequence)
+    }
+
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut message = Vec::with_capacity(4 + self.0.len());
+        message.extend_from_slice(&(self.0.len() as u32).to_le_bytes());
+        message.extend_from_slice(&self.0);
+        w.write_all(&message)
+    }
+}
+
+/// Payload of a message being decoded
+struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ProtocolError> {
+        if self.0.len() < n {
+            return Err(ProtocolError::Truncated);
+        }
+        let (ret, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(ret)
+    }
+
+    fn u8(&mut self) -> Result<u8, ProtocolError> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ProtocolError> {
+        self.take(4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, ProtocolError> {
+        self.take(8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn bool(&mut self) -> Result<bool, ProtocolError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            v => Err(ProtocolError::InvalidValue("bool", v as u32)),
+        }
+    }
+
+    fn str(&mut self) -> Result<String, ProtocolError> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| ProtocolError::Utf8)
+    }
+
+    fn target(&mut self) -> Result<Target, ProtocolError> {
+        match self.u8()? {
+            tag::TARGET_OVERLAY => self.str().map(Target::Overlay),
+            tag::TARGET_MIRROR => match self.u8()? {
+                0 => Ok(Target::Mirror(Eye::Left)),
+                1 => Ok(Target::Mirror(Eye::Right)),
+                v => Err(ProtocolError::InvalidValue("eye", v as u32)),
+            },
+            v => Err(ProtocolError::InvalidValue("target", v as u32)),
+        }
+    }
+
+    #[allow(clippy::redundant_field_names)]
+    fn frame_info(&mut self) -> Result<FrameInfo, ProtocolError> {
+        let width = self.u32()?;
+        let height = self.u32()?;
+        let stride = self.u32()?;
+        let layout = match self.u8()? {
+            0 => Layout::Rgb,
+            1 => Layout::Rgba,
+            2 => Layout::Bgra,
+            v => return Err(ProtocolError::InvalidValue("layout", v as u32)),
+        };
+        let premultiplied = self.bool()?;
+        let transfer = match self.u8()? {
+            0 => Transfer::Unknown,
+            1 => Transfer::Srgb,
+            2 => Transfer::Linear,
+            v => return Err(ProtocolError::InvalidValue("transfer", v as u32)),
+        };
+        Ok(FrameInfo {
+            width: width,
+            height: height,
+            stride: stride,
+            layout: layout,
+            premultiplied: premultiplied,
+            transfer: transfer,
+            timestamp: self.u64()?,
+            sequence: self.u64()?,
+        })
+    }
+
+    fn finish<T>(self, value: T) -> Result<T, ProtocolError> {
+        if self.0.is_empty() {
+            Ok(value)
+        } else {
+            Err(ProtocolError::Trailing(self.0.len()))
+        }
+    }
+}
+
+/// Reads one message payload. Returns `None` if the stream ended cleanly between messages.
+fn read_payload<R: Read>(r: &mut R) -> Result<Option<Vec<u8>>, ProtocolError> {
+    let mut len = [0u8; 4];
+    let mut read = 0;
+    while read < len.len() {
+        match r.read(&mut len[read..]) {
+            Ok(0) if read == 0 => return Ok(None),
+            Ok(0) => return Err(ProtocolError::Truncated),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let len = u32::from_le_bytes(len);
+    if len > MAX_MESSAGE_SIZE {
+        return Err(ProtocolError::TooLarge(len));
+    }
+    let mut payload = vec![0u8; len as usize];
+    r.read_exact(&mut payload).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => ProtocolError::Truncated,
+        _ => e.into(),
+    })?;
+    Ok(Some(payload))
+}
+
+impl Request {
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut e;
+        match self {
+            Request::Hello { version } => {
+                e = Encoder::new(tag::HELLO);
+                e.u32(*version);
+            },
+            Request::Subscribe { stream, target, interval_us } => {
+                e = Encoder::new(tag::SUBSCRIBE);
+                e.u32(*stream).target(target).u32(*interval_us);
+            },
+            Request::Unsubscribe { stream } => {
+                e = Encoder::new(tag::UNSUBSCRIBE);
+                e.u32(*stream);
+            },
+            Request::Release { stream, buffer, slot } => {
+                e = Encoder::new(tag::RELEASE);
+                e.u32(*stream).u32(*buffer).u8(*slot);
+            },
+            Request::Ping { token } => {
+                e = Encoder::new(tag::PING);
+                e.u64(*token);
+            },
+        }
+        e.write_to(w)
+    }
+
+    /// Reads the next request. Returns `None` if the connection was closed between messages.
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Option<Self>, ProtocolError> {
+        let payload = match read_payload(r)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let mut d = Decoder(&payload);
+        let ret = match d.u8()? {
+            tag::HELLO => Request::Hello {
+                version: d.u32()?,
+            },
+            tag::SUBSCRIBE => Request::Subscribe {
+                stream: d.u32()?,
+                target: d.target()?,
+                interval_us: d.u32()?,
+            },
+            tag::UNSUBSCRIBE => Request::Unsubscribe {
+                stream: d.u32()?,
+            },
+            tag::RELEASE => Request::Release {
+                stream: d.u32()?,
+                buffer: d.u32()?,
+                slot: d.u8()?,
+            },
+            tag::PING => Request::Ping {
+                token: d.u64()?,
+            },
+            v => return Err(ProtocolError::UnknownTag(v)),
+        };
+        d.finish(ret).map(Some)
+    }
+}
+
+impl Response {
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut e;
+        match self {
+            Response::Hello { version, pid } => {
+                e = Encoder::new(tag::HELLO);
+                e.u32(*version).u32(*pid);
+            },
+            Response::Buffer { stream, buffer, name, slot_size, slots } => {
+                e = Encoder::new(tag::BUFFER);
+                e.u32(*stream).u32(*buffer).str(name).u64(*slot_size).u8(*slots);
+            },
+            Response::Frame { stream, buffer, slot, info } => {
+                e = Encoder::new(tag::FRAME);
+                e.u32(*stream).u32(*buffer).u8(*slot).frame_info(info);
+            },
+            Response::Hidden { stream } => {
+                e = Encoder::new(tag::HIDDEN);
+                e.u32(*stream);
+            },
+            Response::Loading { stream } => {
+                e = Encoder::new(tag::LOADING);
+                e.u32(*stream);
+            },
+            Response::Error { stream, message } => {
+                e = Encoder::new(tag::ERROR);
+                e.u32(*stream).str(message);
+            },
+            Response::Pong { token } => {
+                e = Encoder::new(tag::PONG);
+                e.u64(*token);
+            },
+        }
+        e.write_to(w)
+    }
+
+    /// Reads the next response. Returns `None` if the connection was closed between messages.
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Option<Self>, ProtocolError> {
+        let payload = match read_payload(r)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let mut d = Decoder(&payload);
+        let ret = match d.u8()? {
+            tag::HELLO => Response::Hello {
+                version: d.u32()?,
+                pid: d.u32()?,
+            },
+            tag::BUFFER => Response::Buffer {
+                stream: d.u32()?,
+                buffer: d.u32()?,
+                name: d.str()?,
+                slot_size: d.u64()?,
+                slots: d.u8()?,
+            },
+            tag::FRAME => Response::Frame {
+                stream: d.u32()?,
+                buffer: d.u32()?,
+                slot: d.u8()?,
+                info: d.frame_info()?,
+            },
+            tag::HIDDEN => Response::Hidden {
+                stream: d.u32()?,
+            },
+            tag::LOADING => Response::Loading {
+                stream: d.u32()?,
+            },
+            tag::ERROR => Response::Error {
+                stream: d.u32()?,
+                message: d.str()?,
+            },
+            tag::PONG => Response::Pong {
+                token: d.u64()?,
+            },
+            v => return Err(ProtocolError::UnknownTag(v)),
+        };
+        d.finish(ret).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn frame_info() -> FrameInfo {
+        FrameInfo {
+            width: 1920,
+            height: 1080,
+            stride: 1920 * 4,
+            layout: Layout::Bgra,
+            premultiplied: true,
+            transfer: Transfer::Linear,
+            timestamp: 123_456_789_000,
+            sequence: 42,
+        }
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let requests = [
+            Request::Hello { version: PROTOCOL_VERSION },
+            Request::Subscribe { stream: 7, target: Target::Overlay("system.vrdashboard".into()), interval_us: 16_666 },
+            Request::Subscribe { stream: 8, target: Target::Mirror(Eye::Right), interval_us: 0 },
+            Request::Unsubscribe { stream: 7 },
+            Request::Release { stream: 8, buffer: 3, slot: 1 },
+            Request::Ping { token: u64::MAX },
+        ];
+        let mut wire = Vec::new();
+        for request in requests.iter() {
+            request.write_to(&mut wire).unwrap();
+        }
+        let mut r = Cursor::new(wire);
+        for request in requests.iter() {
+            assert_eq!(Request::read_from(&mut r).unwrap().as_ref(), Some(request));
+        }
+        assert!(Request::read_from(&mut r).unwrap().is_none());
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let responses = [
+            Response::Hello { version: PROTOCOL_VERSION, pid: 1234 },
+            Response::Buffer { stream: 1, buffer: 2, name: "/obs-openvr-1234-1-2".into(), slot_size: 1 << 20, slots: 3 },
+            Response::Frame { stream: 1, buffer: 2, slot: 0, info: frame_info() },
+            Response::Hidden { stream: 1 },
+            Response::Loading { stream: 1 },
+            Response::Error { stream: 1, message: "Overlay not found".into() },
+            Response::Pong { token: 5 },
+        ];
+        let mut wire = Vec::new();
+        for response in responses.iter() {
+            response.write_to(&mut wire).unwrap();
+        }
+        let mut r = Cursor::new(wire);
+        for response in responses.iter() {
+            assert_eq!(Response::read_from(&mut r).unwrap().as_ref(), Some(response));
+        }
+        assert!(Response::read_from(&mut r).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let mut wire = Vec::new();
+        Response::Frame { stream: 1, buffer: 2, slot: 0, info: frame_info() }.write_to(&mut wire).unwrap();
+        wire.truncate(wire.len() - 1);
+        assert!(matches!(Response::read_from(&mut Cursor::new(&wire)), Err(ProtocolError::Truncated)));
+
+        let oversized = (MAX_MESSAGE_SIZE + 1).to_le_bytes();
+        assert!(matches!(Request::read_from(&mut Cursor::new(&oversized)), Err(ProtocolError::TooLarge(..))));
+
+        let unknown = [1, 0, 0, 0, 99];
+        assert!(matches!(Request::read_from(&mut Cursor::new(&unknown)), Err(ProtocolError::UnknownTag(99))));
+
+        let trailing = [2, 0, 0, 0, tag::UNSUBSCRIBE, 0];
+        assert!(matches!(Request::read_from(&mut Cursor::new(&trailing)), Err(ProtocolError::Truncated)));
+        let trailing = [6, 0, 0, 0, tag::UNSUBSCRIBE, 1, 0, 0, 0, 0];
+        assert!(matches!(Request::read_from(&mut Cursor::new(&trailing)), Err(ProtocolError::Trailing(1))));
+    }
+}
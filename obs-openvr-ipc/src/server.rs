@@ -0,0 +1,214 @@
+//! Host side of the protocol
+
+use std::{
+    io::{
+        self,
+        Write,
+    },
+    process,
+};
+use crate::{
+    protocol::{
+        FrameInfo,
+        Response,
+    },
+    shm::SharedMemory,
+};
+
+/// Number of slots in each stream's buffer. One for the plugin to read, one for the host to
+/// write, and one spare so a slow reader doesn't make the host drop frames right away.
+pub const SLOTS: u8 = 3;
+
+/// Slot sizes are rounded up to this, so small size changes don't reallocate the buffer
+const SLOT_ALIGN: usize = 64 * 1024;
+
+struct Buffer {
+    id: u32,
+    memory: SharedMemory,
+    slot_size: usize,
+    /// Slots the plugin hasn't released yet
+    lent: [bool; SLOTS as usize],
+}
+
+/// Writes the frames of one stream to shared memory, and tells the plugin about them
+pub struct FrameWriter {
+    stream: u32,
+    buffer: Option<Buffer>,
+    next_buffer: u32,
+    sequence: u64,
+}
+
+impl FrameWriter {
+    #[allow(clippy::redundant_field_names)]
+    pub fn new(stream: u32) -> Self {
+        FrameWriter {
+            stream: stream,
+            buffer: None,
+            next_buffer: 0,
+            sequence: 0,
+        }
+    }
+
+    /// Returns a buffer with slots of at least `len` bytes, and whether it is new
+    #[allow(clippy::redundant_field_names)]
+    fn buffer(&mut self, len: usize) -> io::Result<(&mut Buffer, bool)> {
+        let fits = self.buffer.as_ref().map(|buffer| buffer.slot_size >= len).unwrap_or(false);
+        if fits {
+            return Ok((self.buffer.as_mut().unwrap(), false));
+        }
+        // Drop the old buffer first. The plugin keeps its own mapping of it until it sees the
+        // new one.
+        self.buffer = None;
+        let id = self.next_buffer;
+        self.next_buffer += 1;
+        let slot_size = len.max(1).div_ceil(SLOT_ALIGN) * SLOT_ALIGN;
+        let name = format!("/obs-openvr-{}-{}-{}", process::id(), self.stream, id);
+        let memory = SharedMemory::create(&name, slot_size * SLOTS as usize)?;
+        trace!("stream {}: created buffer {} with {} byte slots", self.stream, &name, slot_size);
+        Ok((self.buffer.get_or_insert(Buffer {
+            id: id,
+            memory: memory,
+            slot_size: slot_size,
+            lent: [false; SLOTS as usize],
+        }), true))
+    }
+
+    /// Copies a frame to a free slot, and sends it (plus the buffer, if it is new) to `w`.
+    /// `data` holds `info.height` rows of `info.stride` bytes. `info.sequence` is filled in.
+    /// Returns `false` without sending anything if the plugin still holds every slot.
+    #[allow(clippy::redundant_field_names)]
+    pub fn write<W: Write>(&mut self, w: &mut W, mut info: FrameInfo, data: &[u8]) -> io::Result<bool> {
+        let len = info.data_len();
+        if data.len() < len {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame data is smaller than its stride and height"));
+        }
+        let stream = self.stream;
+        let sequence = self.sequence;
+        let (buffer, created) = self.buffer(len)?;
+        if created {
+            Response::Buffer {
+                stream: stream,
+                buffer: buffer.id,
+                name: buffer.memory.name().to_owned(),
+                slot_size: buffer.slot_size as u64,
+                slots: SLOTS,
+            }.write_to(w)?;
+        }
+        let slot = match buffer.lent.iter().position(|&lent| !lent) {
+            Some(v) => v,
+            None => return Ok(false),
+        };
+        let offset = slot * buffer.slot_size;
+        buffer.memory.as_mut_slice()[offset..offset + len].copy_from_slice(&data[..len]);
+        buffer.lent[slot] = true;
+        info.sequence = sequence;
+        Response::Frame {
+            stream: stream,
+            buffer: buffer.id,
+            slot: slot as u8,
+            info: info,
+        }.write_to(w)?;
+        self.sequence += 1;
+        Ok(true)
+    }
+
+    /// Handles `Request::Release`. Releases of replaced buffers are ignored.
+    pub fn release(&mut self, buffer: u32, slot: u8) {
+        match self.buffer.as_mut() {
+            Some(current) if current.id == buffer && slot < SLOTS => current.lent[slot as usize] = false,
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::protocol::{
+        Layout,
+        Transfer,
+    };
+
+    #[allow(clippy::redundant_field_names)]
+    fn info(width: u32, height: u32) -> FrameInfo {
+        FrameInfo {
+            width: width,
+            height: height,
+            stride: width * 4,
+            layout: Layout::Rgba,
+            premultiplied: false,
+            transfer: Transfer::Srgb,
+            timestamp: 0,
+            sequence: 0,
+        }
+    }
+
+    fn responses(wire: Vec<u8>) -> Vec<Response> {
+        let mut r = Cursor::new(wire);
+        let mut ret = Vec::new();
+        while let Some(response) = Response::read_from(&mut r).unwrap() {
+            ret.push(response);
+        }
+        ret
+    }
+
+    #[test]
+    fn writes_to_free_slots() {
+        let mut writer = FrameWriter::new(9);
+        let mut wire = Vec::new();
+        let data = vec![0xabu8; 16 * 16 * 4];
+        for _ in 0..SLOTS {
+            assert!(writer.write(&mut wire, info(16, 16), &data).unwrap());
+        }
+        // Every slot is lent out
+        assert!(!writer.write(&mut wire, info(16, 16), &data).unwrap());
+
+        let sent = responses(wire);
+        assert_eq!(sent.len(), 1 + SLOTS as usize);
+        let (buffer, name) = match &sent[0] {
+            Response::Buffer { stream: 9, buffer, name, slots: SLOTS, .. } => (*buffer, name.clone()),
+            r => panic!("expected a buffer, got {:?}", r),
+        };
+        let mut slots = Vec::new();
+        for (sequence, response) in sent[1..].iter().enumerate() {
+            match response {
+                Response::Frame { stream: 9, buffer: b, slot, info } if *b == buffer => {
+                    assert_eq!(info.sequence, sequence as u64);
+                    slots.push(*slot);
+                },
+                r => panic!("expected a frame, got {:?}", r),
+            }
+        }
+        slots.sort();
+        assert_eq!(slots, (0..SLOTS).collect::<Vec<_>>());
+
+        let reader = SharedMemory::open(&name, SLOT_ALIGN * SLOTS as usize).unwrap();
+        assert!(reader.as_slice()[..data.len()].iter().all(|&b| b == 0xab));
+
+        writer.release(buffer, 1);
+        // Releasing a buffer that doesn't exist anymore does nothing
+        writer.release(buffer + 1, 0);
+        let mut wire = Vec::new();
+        assert!(writer.write(&mut wire, info(16, 16), &data).unwrap());
+        match responses(wire).as_slice() {
+            [Response::Frame { slot: 1, .. }] => {},
+            r => panic!("expected a frame in slot 1, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn reallocates_for_larger_frames() {
+        let mut writer = FrameWriter::new(1);
+        let mut wire = Vec::new();
+        writer.write(&mut wire, info(16, 16), &vec![0u8; 16 * 16 * 4]).unwrap();
+        writer.write(&mut wire, info(512, 512), &vec![0u8; 512 * 512 * 4]).unwrap();
+        let buffers: Vec<(u32, u64)> = responses(wire).into_iter()
+            .filter_map(|r| match r {
+                Response::Buffer { buffer, slot_size, .. } => Some((buffer, slot_size)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(buffers, vec![(0, SLOT_ALIGN as u64), (1, 512 * 512 * 4)]);
+    }
+}
@@ -0,0 +1,183 @@
+//! POSIX shared memory objects frames are passed through
+
+use std::{
+    ffi::CString,
+    io,
+    ptr,
+    slice,
+};
+
+/// A mapped POSIX shared memory object. The creator unlinks the object when dropped, which
+/// doesn't invalidate mappings other processes already have.
+pub struct SharedMemory {
+    name: CString,
+    ptr: *mut u8,
+    len: usize,
+    owner: bool,
+}
+
+fn shm_name(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "shared memory name contains a nul byte"))
+}
+
+impl SharedMemory {
+    /// Creates a new object `name` of `len` bytes, mapped read-write. Fails if `name` already
+    /// exists.
+    #[allow(clippy::redundant_field_names)]
+    pub fn create(name: &str, len: usize) -> io::Result<Self> {
+        let name = shm_name(name)?;
+        let fd = unsafe {
+            libc::shm_open(name.as_ptr(), libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC, 0o600)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ret = unsafe {
+            if libc::ftruncate(fd, len as libc::off_t) != 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Self::map(fd, len, libc::PROT_READ | libc::PROT_WRITE)
+            }
+        };
+        unsafe {
+            libc::close(fd);
+        }
+        match ret {
+            Ok(ptr) => Ok(SharedMemory {
+                name: name,
+                ptr: ptr,
+                len: len,
+                owner: true,
+            }),
+            Err(e) => {
+                unsafe {
+                    libc::shm_unlink(name.as_ptr());
+                }
+                Err(e)
+            },
+        }
+    }
+
+    /// Maps the first `len` bytes of the existing object `name` read-only
+    #[allow(clippy::redundant_field_names)]
+    pub fn open(name: &str, len: usize) -> io::Result<Self> {
+        let name = shm_name(name)?;
+        let fd = unsafe {
+            libc::shm_open(name.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC, 0)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ret = unsafe {
+            let mut stat: libc::stat = std::mem::zeroed();
+            if libc::fstat(fd, &mut stat as *mut _) != 0 {
+                Err(io::Error::last_os_error())
+            } else if (stat.st_size as u64) < len as u64 {
+                Err(io::Error::new(io::ErrorKind::UnexpectedEof, "shared memory object is smaller than expected"))
+            } else {
+                Self::map(fd, len, libc::PROT_READ)
+            }
+        };
+        unsafe {
+            libc::close(fd);
+        }
+        ret.map(|ptr| SharedMemory {
+            name: name,
+            ptr: ptr,
+            len: len,
+            owner: false,
+        })
+    }
+
+    unsafe fn map(fd: libc::c_int, len: usize, prot: libc::c_int) -> io::Result<*mut u8> {
+        if len == 0 {
+            return Ok(ptr::NonNull::dangling().as_ptr());
+        }
+        let ptr = libc::mmap(ptr::null_mut(), len, prot, libc::MAP_SHARED, fd, 0);
+        if ptr == libc::MAP_FAILED {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(ptr as *mut u8)
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.to_str().unwrap()
+    }
+
+    /// Mapped size in bytes
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.len
+    }
+
+    /// The mapped bytes. Another process may be writing to them, so callers have to agree on
+    /// who owns which range at any time (see `Response::Frame`).
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    /// Mutable access to the mapped bytes. Panics if the object was opened read-only.
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        assert!(self.owner, "shared memory opened read-only");
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        unsafe {
+            if self.len > 0 {
+                libc::munmap(self.ptr as *mut libc::c_void, self.len);
+            }
+            if self.owner {
+                libc::shm_unlink(self.name.as_ptr());
+            }
+        }
+    }
+}
+
+// The mapping is owned by this struct alone
+unsafe impl Send for SharedMemory {}
+unsafe impl Sync for SharedMemory {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_name(suffix: &str) -> String {
+        format!("/obs-openvr-test-{}-{}", std::process::id(), suffix)
+    }
+
+    #[test]
+    fn reader_sees_writes() {
+        let name = test_name("rw");
+        let mut writer = SharedMemory::create(&name, 4096).unwrap();
+        let reader = SharedMemory::open(&name, 4096).unwrap();
+        writer.as_mut_slice()[..4].copy_from_slice(b"obs!");
+        writer.as_mut_slice()[4095] = 7;
+        assert_eq!(&reader.as_slice()[..4], b"obs!");
+        assert_eq!(reader.as_slice()[4095], 7);
+    }
+
+    #[test]
+    fn creator_unlinks() {
+        let name = test_name("unlink");
+        let writer = SharedMemory::create(&name, 16).unwrap();
+        assert!(SharedMemory::create(&name, 16).is_err());
+        let reader = SharedMemory::open(&name, 16).unwrap();
+        drop(writer);
+        // Existing mappings stay valid, but the name is gone
+        assert_eq!(reader.as_slice().len(), 16);
+        assert!(SharedMemory::open(&name, 16).is_err());
+    }
+
+    #[test]
+    fn open_checks_size() {
+        let name = test_name("size");
+        let _writer = SharedMemory::create(&name, 16).unwrap();
+        assert!(SharedMemory::open(&name, 32).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Publishing live tracked device state and VR events from obs-openvr to other programs, as OSC
+//! over UDP and JSON over a local WebSocket. See `schema` for what's sent.
+
+#[macro_use] extern crate log;
+extern crate thiserror;
+
+pub mod osc;
+pub mod schema;
+pub mod websocket;
+mod sha1;
+
+use std::{
+    io,
+    net::{
+        Ipv4Addr,
+        SocketAddr,
+        UdpSocket,
+    },
+};
+use schema::{
+    Event,
+    State,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PublishError {
+    #[error("error opening OSC socket: {0}")]
+    Osc(io::Error),
+    #[error("error starting WebSocket server: {0}")]
+    WebSocket(io::Error),
+}
+
+/// Where to publish. Either transport may be left out.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PublisherConfig {
+    /// Address OSC packets are sent to
+    pub osc: Option<SocketAddr>,
+    /// Port the WebSocket server listens on, on the loopback interface. 0 picks any free port.
+    pub websocket_port: Option<u16>,
+}
+
+pub struct Publisher {
+    osc: Option<(UdpSocket, SocketAddr)>,
+    websocket: Option<websocket::Server>,
+}
+
+impl Publisher {
+    #[allow(clippy::redundant_field_names)]
+    pub fn new(config: &PublisherConfig) -> Result<Self, PublishError> {
+        let osc = match config.osc {
+            Some(addr) => {
+                let bind_addr: SocketAddr = if addr.is_ipv4() {
+                    (Ipv4Addr::UNSPECIFIED, 0).into()
+                } else {
+                    "[::]:0".parse().unwrap()
+                };
+                let socket = UdpSocket::bind(bind_addr).map_err(PublishError::Osc)?;
+                Some((socket, addr))
+            },
+            None => None,
+        };
+        let websocket = match config.websocket_port {
+            Some(port) => {
+                let server = websocket::Server::bind((Ipv4Addr::LOCALHOST, port).into())
+                    .map_err(PublishError::WebSocket)?;
+                Some(server)
+            },
+            None => None,
+        };
+        Ok(Publisher {
+            osc: osc,
+            websocket: websocket,
+        })
+    }
+
+    /// Address the WebSocket server listens on, if it's enabled
+    pub fn websocket_addr(&self) -> Option<SocketAddr> {
+        self.websocket.as_ref().map(websocket::Server::local_addr)
+    }
+
+    fn send_osc(&self, packet: &[u8]) {
+        if let Some((socket, addr)) = self.osc.as_ref() {
+            if let Err(e) = socket.send_to(packet, addr) {
+                debug!("Error sending OSC packet to {}: {}", addr, &e);
+            }
+        }
+    }
+
+    pub fn publish_state(&self, state: &State) {
+        if self.osc.is_some() {
+            self.send_osc(&state.to_osc());
+        }
+        if let Some(server) = self.websocket.as_ref() {
+            if server.client_count() > 0 {
+                server.broadcast(&state.to_json());
+            }
+        }
+    }
+
+    pub fn publish_event(&self, event: &Event) {
+        if self.osc.is_some() {
+            self.send_osc(&event.to_osc());
+        }
+        if let Some(server) = self.websocket.as_ref() {
+            server.broadcast(&event.to_json());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn sends_osc_to_local_listener() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let publisher = Publisher::new(&PublisherConfig {
+            osc: Some(listener.local_addr().unwrap()),
+            websocket_port: None,
+        }).unwrap();
+        assert_eq!(publisher.websocket_addr(), None);
+
+        let state = State {
+            time_ns: 0,
+            app: None,
+            devices: Vec::new(),
+        };
+        publisher.publish_state(&state);
+        let event = Event {
+            time_ns: 0,
+            event: "dashboard_opened".to_owned(),
+            detail: String::new(),
+        };
+        publisher.publish_event(&event);
+
+        let mut buf = [0u8; 1024];
+        let (n, _) = listener.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], &state.to_osc()[..]);
+        let (n, _) = listener.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], &event.to_osc()[..]);
+    }
+}
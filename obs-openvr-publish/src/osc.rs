@@ -0,0 +1,124 @@
+//! Encoding OSC 1.0 messages and bundles
+
+/// An OSC argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Int(i32),
+    /// 64-bit integer (`h`), an optional OSC 1.0 type most implementations support
+    Long(i64),
+    Float(f32),
+    String(String),
+    /// `T` or `F`, which have no data
+    Bool(bool),
+}
+
+impl Arg {
+    fn type_tag(&self) -> u8 {
+        match self {
+            Arg::Int(..) => b'i',
+            Arg::Long(..) => b'h',
+            Arg::Float(..) => b'f',
+            Arg::String(..) => b's',
+            Arg::Bool(true) => b'T',
+            Arg::Bool(false) => b'F',
+        }
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Arg::Int(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Arg::Long(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Arg::Float(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Arg::String(s) => write_string(out, s.as_bytes()),
+            Arg::Bool(..) => {},
+        }
+    }
+}
+
+/// Writes a nul-terminated string, padded to a multiple of 4 bytes
+fn write_string(out: &mut Vec<u8>, s: &[u8]) {
+    out.extend_from_slice(s);
+    let padding = 4 - s.len() % 4;
+    out.resize(out.len() + padding, 0);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub address: String,
+    pub args: Vec<Arg>,
+}
+
+impl Message {
+    #[allow(clippy::redundant_field_names)]
+    pub fn new<S: Into<String>>(address: S, args: Vec<Arg>) -> Self {
+        Message {
+            address: address.into(),
+            args: args,
+        }
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        write_string(out, self.address.as_bytes());
+        let tags: Vec<u8> = std::iter::once(b',')
+            .chain(self.args.iter().map(Arg::type_tag))
+            .collect();
+        write_string(out, &tags);
+        self.args.iter().for_each(|arg| arg.write_to(out));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.write_to(&mut ret);
+        ret
+    }
+}
+
+/// Time tag meaning "immediately"
+const IMMEDIATELY: u64 = 1;
+
+/// A bundle of `messages`, to be handled immediately and all at once
+pub fn bundle(messages: &[Message]) -> Vec<u8> {
+    let mut ret = Vec::new();
+    write_string(&mut ret, b"#bundle");
+    ret.extend_from_slice(&IMMEDIATELY.to_be_bytes());
+    for message in messages {
+        let size_at = ret.len();
+        ret.extend_from_slice(&[0; 4]);
+        message.write_to(&mut ret);
+        let size = (ret.len() - size_at - 4) as i32;
+        ret[size_at..size_at + 4].copy_from_slice(&size.to_be_bytes());
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_are_padded() {
+        let message = Message::new("/vr/app", vec![Arg::String("abcd".to_owned())]);
+        assert_eq!(message.to_bytes(), b"/vr/app\0,s\0\0abcd\0\0\0\0".to_vec());
+    }
+
+    #[test]
+    fn numbers_are_big_endian() {
+        let message = Message::new("/a", vec![Arg::Int(1), Arg::Float(1.0), Arg::Bool(true), Arg::Long(-1)]);
+        let mut expected = b"/a\0\0,ifTh\0\0\0".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 1, 0x3f, 0x80, 0, 0]);
+        expected.extend_from_slice(&[0xff; 8]);
+        assert_eq!(message.to_bytes(), expected);
+    }
+
+    #[test]
+    fn bundles_prefix_sizes() {
+        let message = Message::new("/a", vec![Arg::Int(7)]);
+        let bundle = bundle(&[message.clone(), message.clone()]);
+        assert_eq!(&bundle[..8], b"#bundle\0");
+        assert_eq!(&bundle[8..16], &[0, 0, 0, 0, 0, 0, 0, 1]);
+        let size = message.to_bytes().len();
+        assert_eq!(&bundle[16..20], &(size as i32).to_be_bytes());
+        assert_eq!(&bundle[20..20 + size], &message.to_bytes()[..]);
+        assert_eq!(bundle.len(), 16 + 2 * (4 + size));
+    }
+}
@@ -0,0 +1,291 @@
+//! What is published, and how it's encoded for each transport
+//!
+//! Over WebSocket, every message is a JSON object with a `type`:
+//!
+//! - `state`, sent at the configured rate: `time_ns` (OBS clock), `app` (key of the scene
+//!   application, or `null`) and `devices`, one object per connected device with `name`, `index`,
+//!   `class`, `valid`, `position` (`[x, y, z]` in meters, +Y up, -Z forward), `rotation`
+//!   (`[w, x, y, z]` quaternion), `battery` (0 to 1, or `null`), `charging` (or `null`) and
+//!   `buttons` (`null` for devices without any, otherwise `pressed` and `touched` bit masks of
+//!   `EVRButtonId`s and `axes`, five `[x, y]` pairs).
+//! - `event`, sent as VR events happen: `time_ns`, `event` (like `app_started`) and `detail`
+//!   (like the app key, or an empty string).
+//!
+//! Over OSC, each state is a bundle of messages: `/vr/app` (`s`, empty without an app), and for
+//! each device `/vr/device/<name>/pose` (`fffffff`: position, then rotation), `/vr/device/<name>/valid`
+//! (`T` or `F`), `/vr/device/<name>/battery` (`f`) and `/vr/device/<name>/charging` (`T` or `F`)
+//! when known, and `/vr/device/<name>/buttons` (`hh`: pressed, touched) and
+//! `/vr/device/<name>/axes` (ten `f`s) for devices with buttons. Events are `/vr/event` messages
+//! (`ss`: event, detail).
+
+use std::fmt::Write;
+use crate::osc::{
+    self,
+    Arg,
+    Message,
+};
+
+/// Buttons and axes of a controller, see `VRControllerState_t`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Buttons {
+    /// Bit mask of the pressed `EVRButtonId`s
+    pub pressed: u64,
+    pub touched: u64,
+    pub axes: [[f32; 2]; 5],
+}
+
+/// State of a connected tracked device
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    /// `hmd`, `left_controller`, `right_controller`, or `<class>_<index>` for other devices
+    pub name: String,
+    pub index: u32,
+    pub class: &'static str,
+    /// Whether the pose is meaningful
+    pub valid: bool,
+    pub position: [f32; 3],
+    pub rotation: [f32; 4],
+    /// Charge from 0 to 1, for devices with batteries
+    pub battery: Option<f32>,
+    pub charging: Option<bool>,
+    pub buttons: Option<Buttons>,
+}
+
+/// Everything published at the configured rate
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    /// OBS clock time (`os_gettime_ns`) of the state
+    pub time_ns: u64,
+    pub app: Option<String>,
+    pub devices: Vec<Device>,
+}
+
+/// A VR event, published as it happens
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub time_ns: u64,
+    pub event: String,
+    pub detail: String,
+}
+
+fn json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn json_number(out: &mut String, v: f32) {
+    if v.is_finite() {
+        let _ = write!(out, "{}", v);
+    } else {
+        out.push_str("null");
+    }
+}
+
+fn json_numbers(out: &mut String, values: &[f32]) {
+    out.push('[');
+    for (i, v) in values.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        json_number(out, *v);
+    }
+    out.push(']');
+}
+
+impl Device {
+    fn write_json(&self, out: &mut String) {
+        out.push_str("{\"name\":");
+        json_string(out, &self.name);
+        let _ = write!(out, ",\"index\":{},\"class\":", self.index);
+        json_string(out, self.class);
+        let _ = write!(out, ",\"valid\":{},\"position\":", self.valid);
+        json_numbers(out, &self.position);
+        out.push_str(",\"rotation\":");
+        json_numbers(out, &self.rotation);
+        out.push_str(",\"battery\":");
+        match self.battery {
+            Some(battery) => json_number(out, battery),
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"charging\":");
+        match self.charging {
+            Some(charging) => {
+                let _ = write!(out, "{}", charging);
+            },
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"buttons\":");
+        match self.buttons.as_ref() {
+            Some(buttons) => {
+                let _ = write!(out, "{{\"pressed\":{},\"touched\":{},\"axes\":[", buttons.pressed, buttons.touched);
+                for (i, axis) in buttons.axes.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    json_numbers(out, axis);
+                }
+                out.push_str("]}");
+            },
+            None => out.push_str("null"),
+        }
+        out.push('}');
+    }
+
+    fn osc_messages(&self, out: &mut Vec<Message>) {
+        let address = |field: &str| format!("/vr/device/{}/{}", &self.name, field);
+        let pose = self.position.iter()
+            .chain(self.rotation.iter())
+            .map(|&v| Arg::Float(v))
+            .collect();
+        out.push(Message::new(address("pose"), pose));
+        out.push(Message::new(address("valid"), vec![Arg::Bool(self.valid)]));
+        if let Some(battery) = self.battery {
+            out.push(Message::new(address("battery"), vec![Arg::Float(battery)]));
+        }
+        if let Some(charging) = self.charging {
+            out.push(Message::new(address("charging"), vec![Arg::Bool(charging)]));
+        }
+        if let Some(buttons) = self.buttons.as_ref() {
+            out.push(Message::new(address("buttons"), vec![Arg::Long(buttons.pressed as i64), Arg::Long(buttons.touched as i64)]));
+            let axes = buttons.axes.iter()
+                .flat_map(|axis| axis.iter())
+                .map(|&v| Arg::Float(v))
+                .collect();
+            out.push(Message::new(address("axes"), axes));
+        }
+    }
+}
+
+impl State {
+    pub fn to_json(&self) -> String {
+        let mut ret = String::new();
+        let _ = write!(ret, "{{\"type\":\"state\",\"time_ns\":{},\"app\":", self.time_ns);
+        match self.app.as_ref() {
+            Some(app) => json_string(&mut ret, app),
+            None => ret.push_str("null"),
+        }
+        ret.push_str(",\"devices\":[");
+        for (i, device) in self.devices.iter().enumerate() {
+            if i > 0 {
+                ret.push(',');
+            }
+            device.write_json(&mut ret);
+        }
+        ret.push_str("]}");
+        ret
+    }
+
+    /// The state as an OSC bundle
+    pub fn to_osc(&self) -> Vec<u8> {
+        let mut messages = vec![Message::new("/vr/app", vec![Arg::String(self.app.clone().unwrap_or_default())])];
+        self.devices.iter().for_each(|device| device.osc_messages(&mut messages));
+        osc::bundle(&messages)
+    }
+}
+
+impl Event {
+    pub fn to_json(&self) -> String {
+        let mut ret = String::new();
+        let _ = write!(ret, "{{\"type\":\"event\",\"time_ns\":{},\"event\":", self.time_ns);
+        json_string(&mut ret, &self.event);
+        ret.push_str(",\"detail\":");
+        json_string(&mut ret, &self.detail);
+        ret.push('}');
+        ret
+    }
+
+    pub fn to_osc(&self) -> Vec<u8> {
+        Message::new("/vr/event", vec![Arg::String(self.event.clone()), Arg::String(self.detail.clone())]).to_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> State {
+        State {
+            time_ns: 42,
+            app: Some("steam.app.620".to_owned()),
+            devices: vec![
+                Device {
+                    name: "hmd".to_owned(),
+                    index: 0,
+                    class: "hmd",
+                    valid: true,
+                    position: [0.0, 1.5, -0.25],
+                    rotation: [1.0, 0.0, 0.0, 0.0],
+                    battery: None,
+                    charging: None,
+                    buttons: None,
+                },
+                Device {
+                    name: "left_controller".to_owned(),
+                    index: 1,
+                    class: "controller",
+                    valid: false,
+                    position: [0.0; 3],
+                    rotation: [1.0, 0.0, 0.0, 0.0],
+                    battery: Some(0.5),
+                    charging: Some(false),
+                    buttons: Some(Buttons {
+                        pressed: 1 << 33,
+                        ..Default::default()
+                    }),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn state_json() {
+        assert_eq!(state().to_json(), concat!(
+            r#"{"type":"state","time_ns":42,"app":"steam.app.620","devices":["#,
+            r#"{"name":"hmd","index":0,"class":"hmd","valid":true,"position":[0,1.5,-0.25],"rotation":[1,0,0,0],"battery":null,"charging":null,"buttons":null},"#,
+            r#"{"name":"left_controller","index":1,"class":"controller","valid":false,"position":[0,0,0],"rotation":[1,0,0,0],"battery":0.5,"charging":false,"#,
+            r#""buttons":{"pressed":8589934592,"touched":0,"axes":[[0,0],[0,0],[0,0],[0,0],[0,0]]}}]}"#,
+        ));
+    }
+
+    #[test]
+    fn event_json_escapes() {
+        let event = Event {
+            time_ns: 1,
+            event: "app_started".to_owned(),
+            detail: "a \"b\"\n".to_owned(),
+        };
+        assert_eq!(event.to_json(), r#"{"type":"event","time_ns":1,"event":"app_started","detail":"a \"b\"\n"}"#);
+    }
+
+    #[test]
+    fn state_osc_addresses() {
+        let mut messages = Vec::new();
+        state().devices.iter().for_each(|device| device.osc_messages(&mut messages));
+        let addresses: Vec<&str> = messages.iter().map(|message| message.address.as_str()).collect();
+        assert_eq!(addresses, [
+            "/vr/device/hmd/pose",
+            "/vr/device/hmd/valid",
+            "/vr/device/left_controller/pose",
+            "/vr/device/left_controller/valid",
+            "/vr/device/left_controller/battery",
+            "/vr/device/left_controller/charging",
+            "/vr/device/left_controller/buttons",
+            "/vr/device/left_controller/axes",
+        ]);
+        assert_eq!(messages[0].args.len(), 7);
+        assert_eq!(messages[7].args.len(), 10);
+    }
+}
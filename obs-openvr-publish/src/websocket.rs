@@ -0,0 +1,278 @@
+//! A minimal WebSocket (RFC 6455) server, which only broadcasts text messages to its clients.
+//! Anything clients send is ignored.
+
+use std::{
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    net::{
+        SocketAddr,
+        TcpListener,
+        TcpStream,
+    },
+    sync::{
+        Arc,
+        Mutex,
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+    },
+    thread::{
+        self,
+        JoinHandle,
+    },
+    time::Duration,
+};
+use crate::sha1::sha1;
+
+/// Appended to the client's key to compute `Sec-WebSocket-Accept`
+#[allow(clippy::redundant_static_lifetimes)]
+const ACCEPT_GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Longest handshake request read from a client
+const MAX_REQUEST_LENGTH: usize = 8 * 1024;
+/// How long clients get to send their handshake, and to take each message
+const CLIENT_TIMEOUT: Duration = Duration::from_millis(500);
+/// How often the accepting thread checks whether the server was dropped
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+#[allow(clippy::redundant_static_lifetimes)]
+const BASE64_ALPHABET: &'static [u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut ret = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                ret.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+    ret
+}
+
+/// `Sec-WebSocket-Accept` for a client's `Sec-WebSocket-Key`
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), ACCEPT_GUID).as_bytes()))
+}
+
+/// Value of the `Sec-WebSocket-Key` header in a handshake request
+fn request_key(request: &str) -> Option<&str> {
+    request.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            Some((parts.next()?, parts.next()?))
+        })
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("sec-websocket-key"))
+        .map(|(_, value)| value.trim())
+}
+
+/// Header of an unmasked, final text frame with `len` bytes of payload
+fn text_frame_header(len: usize) -> Vec<u8> {
+    let mut ret = vec![0x81];
+    if len < 126 {
+        ret.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        ret.push(126);
+        ret.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        ret.push(127);
+        ret.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    ret
+}
+
+/// Reads the handshake request, and answers it. Returns whether `stream` is now a WebSocket.
+fn handshake(stream: &mut TcpStream) -> io::Result<bool> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.ends_with(b"\r\n\r\n") {
+        if request.len() >= MAX_REQUEST_LENGTH {
+            return Ok(false);
+        }
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Ok(false);
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    match request_key(&request) {
+        Some(key) => {
+            write!(
+                stream,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(key)
+            )?;
+            Ok(true)
+        },
+        None => {
+            stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")?;
+            Ok(false)
+        },
+    }
+}
+
+fn accept_clients(listener: TcpListener, clients: Arc<Mutex<Vec<TcpStream>>>, running: Arc<AtomicBool>) {
+    while running.load(Ordering::Relaxed) {
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_INTERVAL);
+                continue;
+            },
+            Err(e) => {
+                warn!("Error accepting WebSocket client: {}", &e);
+                thread::sleep(ACCEPT_INTERVAL);
+                continue;
+            },
+        };
+        let peer = stream.peer_addr().ok();
+        let accepted = stream.set_nonblocking(false)
+            .and_then(|_| handshake(&mut stream))
+            .and_then(|accepted| stream.set_write_timeout(Some(CLIENT_TIMEOUT)).map(|_| accepted));
+        match accepted {
+            Ok(true) => {
+                debug!("WebSocket client connected: {:?}", peer);
+                clients.lock().unwrap().push(stream);
+            },
+            Ok(false) => debug!("Rejected WebSocket client {:?}", peer),
+            Err(e) => debug!("WebSocket handshake with {:?} failed: {}", peer, &e),
+        }
+    }
+}
+
+/// Broadcasts text messages to every client that connects. Stops listening when dropped.
+pub struct Server {
+    addr: SocketAddr,
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    #[allow(clippy::redundant_field_names)]
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let clients = clients.clone();
+            let running = running.clone();
+            thread::Builder::new()
+                .name("obs-openvr-websocket".to_owned())
+                .spawn(move || accept_clients(listener, clients, running))?
+        };
+        Ok(Server {
+            addr: addr,
+            clients: clients,
+            running: running,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address the server listens on, with the actual port if it was bound to port 0
+    #[inline]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    /// Sends `text` to every client, disconnecting the ones that can't take it
+    pub fn broadcast(&self, text: &str) {
+        let mut frame = text_frame_header(text.len());
+        frame.extend_from_slice(text.as_bytes());
+        self.clients.lock().unwrap().retain(|mut client| match client.write_all(&frame) {
+            Ok(()) => true,
+            Err(e) => {
+                debug!("WebSocket client {:?} disconnected: {}", client.peer_addr().ok(), &e);
+                false
+            },
+        });
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(Err(e)) = self.thread.take().map(JoinHandle::join) {
+            error!("Failed joining WebSocket thread: {:?}", &e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+
+    #[test]
+    fn rfc_example_accept_key() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+    }
+
+    #[test]
+    fn frame_lengths() {
+        assert_eq!(text_frame_header(5), vec![0x81, 5]);
+        assert_eq!(text_frame_header(300), vec![0x81, 126, 1, 44]);
+        assert_eq!(text_frame_header(70000)[..2], [0x81, 127]);
+    }
+
+    #[test]
+    fn broadcasts_to_local_client() {
+        let server = Server::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut client = TcpStream::connect(server.local_addr()).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(client, "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n").unwrap();
+        let mut reader = io::BufReader::new(client.try_clone().unwrap());
+        let mut response = String::new();
+        while !response.ends_with("\r\n\r\n") {
+            reader.read_line(&mut response).unwrap();
+        }
+        assert!(response.starts_with("HTTP/1.1 101"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        // The server adds the client after the handshake
+        for _ in 0..50 {
+            if server.client_count() == 1 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        server.broadcast("hello");
+        let mut frame = [0u8; 7];
+        reader.read_exact(&mut frame).unwrap();
+        assert_eq!(&frame, b"\x81\x05hello");
+    }
+
+    #[test]
+    fn rejects_plain_http() {
+        let server = Server::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut client = TcpStream::connect(server.local_addr()).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(client, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        io::BufReader::new(client).read_line(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400"));
+        assert_eq!(server.client_count(), 0);
+    }
+}
@@ -141,6 +141,7 @@ fn main() {
             .include(glad_source_dir.join("include"))
             .files(g_sources.into_iter().chain(g_headers.into_iter()))
             .compile("libobs-openvr-mirror-utils.a");
+        add_link_library("EGL", None);
 
         // add_link_library("obsglad", Some("dylib"));
     }
@@ -0,0 +1,292 @@
+use std::{
+    ffi::CStr,
+    mem,
+    num::NonZeroU32,
+    sync::{
+        Arc,
+        Mutex,
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use obs::source::AsyncVideoSource;
+use crate::{
+    backend::{
+        Capture,
+        CaptureBackend,
+        Delivery,
+        FrameData,
+        add_async_properties,
+        add_common_properties,
+        pacing::Pacing,
+        placeholder::{
+            self,
+            Placeholder,
+            PlaceholderSettings,
+            Shown,
+            VisualState,
+        },
+        privacy::{
+            self,
+            Privacy,
+            PrivacyEffect,
+        },
+        retry::BackendSlot,
+    },
+    color::Encoding,
+    frame::{
+        self,
+        OutputFormat,
+    },
+    metrics::Stage,
+    pixel::{
+        self,
+        ImageBuf,
+        ImageRef,
+    },
+    presence,
+    source_handle::SourceHandle,
+    status::{
+        SourceStatus,
+        StatusProvider,
+        add_status_properties,
+    },
+    thread_utils::JoinOnDrop,
+    timing::Timer,
+};
+
+/// Source capturing frames from `B` on a worker thread, and handing them to OBS as async frames
+pub struct AsyncCaptureSource<B: CaptureBackend> {
+    handle: *mut obs::sys::obs_source_t,
+    running: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinOnDrop<()>>>,
+    status: Arc<SourceStatus>,
+}
+
+/// Converts frames to what OBS takes, reusing buffers between frames
+struct FrameOutput {
+    source: SourceHandle,
+    format: OutputFormat,
+    /// sRGB-encoded copy of linear frames
+    encoded: ImageBuf,
+    converted: ImageBuf,
+}
+
+impl FrameOutput {
+    fn new(source: SourceHandle, format: OutputFormat) -> Self {
+        FrameOutput {
+            source: source,
+            format: format,
+            encoded: ImageBuf::new(pixel::Format::Rgba, 0, 0),
+            converted: ImageBuf::new(pixel::Format::Rgba, 0, 0),
+        }
+    }
+
+    fn output(&mut self, mut image: ImageRef<'_>, encoding: Encoding, timestamp: u64, status: &SourceStatus) {
+        let mut timer = Timer::new();
+        if encoding == Encoding::Linear && image.format.bytes_per_pixel().is_some() {
+            // OBS only takes sRGB-encoded 8-bit async frames
+            pixel::convert_into(&image, image.format, Default::default(), &mut self.encoded);
+            pixel::encode_srgb(self.encoded.planes_mut()[0], image.format);
+            image = self.encoded.as_image();
+        }
+        let target = self.format.target(image.format);
+        if target != image.format {
+            pixel::convert_into(&image, target, Default::default(), &mut self.converted);
+            image = self.converted.as_image();
+        }
+        status.record_stage(Stage::Convert, timer.checkpoint());
+        unsafe {
+            frame::output_frame(self.source, &image, timestamp);
+        }
+        status.record_stage(Stage::Output, timer.checkpoint());
+    }
+
+    /// Moves `placeholder` to `state`, outputting a placeholder frame if one has to be shown
+    fn enter_state(&mut self, placeholder: &mut Placeholder, state: VisualState, dimensions: (u32, u32), status: &SourceStatus) {
+        match placeholder.enter(state, dimensions) {
+            None | Some(Shown::Hold) => {},
+            Some(Shown::Image(image)) => self.output(image, Encoding::Srgb, unsafe { obs::sys::os_gettime_ns() }, status),
+            Some(Shown::Transparent) => unsafe {
+                self.source.clear_video();
+            },
+        }
+    }
+}
+
+fn spawn_capture_thread<B: CaptureBackend>(source: SourceHandle, running: Arc<AtomicBool>, config: B::Config, output_format: OutputFormat, placeholder_settings: PlaceholderSettings, privacy_effect: PrivacyEffect, status: Arc<SourceStatus>) -> thread::JoinHandle<()> {
+    const BACKOFF_VISIBILITY: Duration = Duration::from_millis(500);
+    const BACKOFF_ERROR: Duration = Duration::from_secs(1);
+
+    running.store(true, Ordering::Relaxed);
+    thread::spawn(move || {
+        let mut output = FrameOutput::new(source, output_format);
+        let mut placeholder = Placeholder::new(placeholder_settings);
+        let mut privacy = Privacy::new(privacy_effect);
+        // Of the last frame, which placeholder images are fitted to
+        let mut dimensions = (0, 0);
+        let mut slot = BackendSlot::<B>::new(Delivery::Async);
+        while running.load(Ordering::Relaxed) {
+            // Retried with backoff while creating it fails
+            let backend = match slot.get(&config, &status, Instant::now()) {
+                Some(v) => v,
+                None => {
+                    status.record_skipped();
+                    output.enter_state(&mut placeholder, VisualState::Disconnected, dimensions, &status);
+                    thread::sleep(BACKOFF_VISIBILITY);
+                    continue;
+                },
+            };
+            if !source.is_visible() {
+                // Always send the first frame after becoming visible again
+                backend.invalidate();
+                thread::sleep(BACKOFF_VISIBILITY);
+                continue;
+            }
+            if placeholder.hides_while_away() && !presence::is_user_present() {
+                status.record_skipped();
+                output.enter_state(&mut placeholder, VisualState::Away, dimensions, &status);
+                thread::sleep(BACKOFF_VISIBILITY);
+                continue;
+            }
+            let started = Instant::now();
+            let privacy_changed = privacy.poll();
+            if privacy_changed || placeholder.state() != VisualState::Live {
+                // A placeholder or obscured frame may be showing, so the next frame has to replace it
+                backend.invalidate();
+            }
+            let frame = match backend.capture(&status) {
+                Ok(Capture::Frame(frame)) => frame,
+                Ok(Capture::Unchanged) => {
+                    status.record_unchanged();
+                    backend.wait_next_capture(started);
+                    continue;
+                },
+                Ok(Capture::Hidden) => {
+                    status.record_skipped();
+                    output.enter_state(&mut placeholder, VisualState::Idle, dimensions, &status);
+                    thread::sleep(BACKOFF_VISIBILITY);
+                    continue;
+                },
+                Ok(Capture::Loading) => {
+                    status.record_skipped();
+                    output.enter_state(&mut placeholder, VisualState::Loading, dimensions, &status);
+                    backend.wait_next_capture(started);
+                    continue;
+                },
+                Ok(Capture::Disconnected) => {
+                    status.record_skipped();
+                    output.enter_state(&mut placeholder, VisualState::Disconnected, dimensions, &status);
+                    thread::sleep(BACKOFF_VISIBILITY);
+                    continue;
+                },
+                Ok(Capture::NotDue) => {
+                    backend.wait_next_capture(started);
+                    continue;
+                },
+                Err(e) => {
+                    error!("{:?}: capture error: {}", B::ASYNC_NAME, &e);
+                    status.record_error(&e);
+                    output.enter_state(&mut placeholder, VisualState::Disconnected, dimensions, &status);
+                    thread::sleep(BACKOFF_ERROR);
+                    continue;
+                },
+            };
+            let mut image = match frame.data {
+                FrameData::Image(image) => image,
+                FrameData::Texture(..) => {
+                    error!("{:?}: backend produced a texture for async delivery", B::ASYNC_NAME);
+                    status.record_error("Capture backend can't deliver async frames");
+                    return;
+                },
+            };
+            placeholder.enter(VisualState::Live, frame.dimensions);
+            dimensions = frame.dimensions;
+            let mut encoding = frame.encoding;
+            if privacy.is_obscuring() {
+                if privacy.effect() == PrivacyEffect::Image {
+                    encoding = Encoding::Srgb;
+                }
+                image = privacy.obscure(&image, &mut placeholder);
+            }
+            output.output(image, encoding, frame.timestamp, &status);
+            status.record_frame(frame.dimensions);
+            backend.wait_next_capture(started);
+        }
+    })
+}
+
+impl<B: CaptureBackend> AsyncCaptureSource<B> {
+    fn stop(&self) {
+        let mut thread_handle = self.thread.lock().unwrap();
+        self.running.store(false, Ordering::Relaxed);
+        mem::drop(thread_handle.take());
+    }
+}
+
+impl<B: CaptureBackend> AsyncVideoSource for AsyncCaptureSource<B> {
+    const ID: &'static CStr = B::ASYNC_ID;
+    const OUTPUT_FLAGS: Option<NonZeroU32> = NonZeroU32::new(B::OUTPUT_FLAGS);
+
+    fn create(settings: &mut obs::sys::obs_data, source: *mut obs::sys::obs_source_t) -> Self {
+        let ret = AsyncCaptureSource {
+            handle: source,
+            running: Arc::new(AtomicBool::new(false)),
+            thread: Mutex::new(None),
+            status: Arc::new(SourceStatus::new(source, B::TRACKS_OVERLAY)),
+        };
+        ret.update(settings);
+        ret
+    }
+
+    fn get_name() -> &'static CStr {
+        B::ASYNC_NAME
+    }
+
+    fn get_defaults(settings: &mut obs::sys::obs_data) {
+        B::get_defaults(settings);
+        placeholder::set_defaults(settings);
+        privacy::set_defaults(settings);
+    }
+
+    fn update(&self, data: &obs::sys::obs_data) {
+        self.status.update_settings(data);
+        Pacing::from_settings(data).apply(SourceHandle::from(self.handle));
+        self.stop();
+        let config = B::config(data);
+        trace!("{:?}: starting capture with {:?}", B::ASYNC_NAME, &config);
+        let mut thread_handle = self.thread.lock().unwrap();
+        *thread_handle = Some(spawn_capture_thread::<B>(SourceHandle::from(self.handle), self.running.clone(), config, OutputFormat::from_settings(data), PlaceholderSettings::from_settings(data), PrivacyEffect::from_settings(data), self.status.clone()).into());
+    }
+
+    fn get_properties(&self) -> obs::Properties {
+        let mut props = obs::Properties::new();
+        B::add_properties(&mut props);
+        add_common_properties(&mut props);
+        B::add_async_properties(&mut props);
+        add_async_properties(&mut props);
+        placeholder::add_placeholder_properties(&mut props);
+        privacy::add_privacy_properties(&mut props);
+        add_status_properties(&mut props, self);
+        props
+    }
+}
+
+impl<B: CaptureBackend> StatusProvider for AsyncCaptureSource<B> {
+    fn status(&self) -> Option<Arc<SourceStatus>> {
+        Some(self.status.clone())
+    }
+}
+
+impl<B: CaptureBackend> Drop for AsyncCaptureSource<B> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
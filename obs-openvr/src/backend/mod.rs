@@ -0,0 +1,212 @@
+//! Capture backends, and the OBS sources delivering their frames on the render thread or
+//! asynchronously
+
+mod async_source;
+pub mod pacing;
+pub mod placeholder;
+pub mod privacy;
+mod retry;
+mod sync_source;
+
+use std::{
+    ffi::CStr,
+    fmt::{
+        Debug,
+        Display,
+    },
+    sync::Arc,
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use obs::data::ObsData;
+use crate::{
+    color::Encoding,
+    pixel::{
+        ImageBuf,
+        ImageRef,
+    },
+    status::SourceStatus,
+};
+
+pub use async_source::AsyncCaptureSource;
+pub use sync_source::SyncCaptureSource;
+
+/// Where a source captures frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Capture inside `video_tick` on the OBS graphics thread
+    RenderThread,
+    /// Capture on a dedicated worker thread, pushing frames with `obs_source_output_video2`
+    Async,
+}
+
+impl Delivery {
+    pub fn from_settings(data: &obs::sys::obs_data) -> Self {
+        match data.get_cstr(keys::DELIVERY) {
+            Some(v) if v == keys::DELIVERY_ASYNC => Delivery::Async,
+            _ => Delivery::RenderThread,
+        }
+    }
+}
+
+/// A GPU texture a backend captured into, drawn by the render thread source
+pub trait TextureFrame: Send + Sync {
+    /// Draws the texture at the origin. Called from `video_render`.
+    fn draw(&self);
+
+    /// Copies the texture's pixels into `dst` as packed RGBA or BGRA. Returns `false` if they
+    /// aren't available in CPU memory.
+    fn read(&self, _dst: &mut ImageBuf) -> bool {
+        false
+    }
+}
+
+pub enum FrameData<'a> {
+    /// Packed or planar pixels in CPU memory
+    Image(ImageRef<'a>),
+    /// A texture only the render thread source can draw. Backends must not produce these for
+    /// `Delivery::Async`.
+    Texture(Arc<dyn TextureFrame>),
+}
+
+/// A captured frame
+pub struct Frame<'a> {
+    pub dimensions: (u32, u32),
+    pub data: FrameData<'a>,
+    /// Transfer function of the pixel values
+    pub encoding: Encoding,
+    /// Whether colors are premultiplied by alpha
+    pub premultiplied: bool,
+    /// OBS clock time (`os_gettime_ns`) the frame was captured at
+    pub timestamp: u64,
+}
+
+/// Outcome of a call to `CaptureBackend::capture`
+pub enum Capture<'a> {
+    Frame(Frame<'a>),
+    /// The content is the same as in the last frame
+    Unchanged,
+    /// Nothing was captured because the captured content isn't visible
+    Hidden,
+    /// Nothing was captured because SteamVR is switching applications
+    Loading,
+    /// Nothing was captured because OpenVR (or the capture host) isn't available
+    Disconnected,
+    /// Nothing was captured because it isn't time for the next frame yet
+    NotDue,
+}
+
+/// Something that captures frames, delivered to OBS by `SyncCaptureSource` (on the render
+/// thread) and `AsyncCaptureSource` (from a worker thread)
+pub trait CaptureBackend: Sized + 'static {
+    /// Everything the backend is created from, read from the source's settings
+    type Config: Debug + Clone + PartialEq + Send + 'static;
+    type Error: Debug + Display;
+
+    /// Type ID and name of the render thread source
+    const SYNC_ID: &'static [u8];
+    const SYNC_NAME: &'static CStr;
+    /// Type ID and name of the async source
+    const ASYNC_ID: &'static CStr;
+    const ASYNC_NAME: &'static CStr;
+    /// Output flags of both sources, besides the ones each source sets itself
+    const OUTPUT_FLAGS: u32 = 0;
+    /// Whether the source status reports an OpenVR overlay
+    const TRACKS_OVERLAY: bool = false;
+
+    fn config(data: &obs::sys::obs_data) -> Self::Config;
+
+    fn get_defaults(_settings: &mut obs::sys::obs_data) {
+    }
+
+    /// Properties used with either delivery, besides `add_common_properties`
+    fn add_properties(props: &mut obs::Properties);
+
+    /// Properties only used with `Delivery::Async`, on top of `add_async_properties`
+    fn add_async_properties(_props: &mut obs::Properties) {
+    }
+
+    /// Creates a backend for `delivery`. Async backends are created on their capture thread.
+    fn create(config: &Self::Config, delivery: Delivery, status: &SourceStatus) -> Result<Self, Self::Error>;
+
+    /// Applies changed settings without recreating the backend. Returns `false` if the backend
+    /// has to be recreated instead.
+    fn reconfigure(&mut self, _config: &Self::Config) -> bool {
+        false
+    }
+
+    /// Whether the backend was created in an OpenVR session that has ended, so it has to be
+    /// recreated to capture again
+    fn is_stale(&self) -> bool {
+        false
+    }
+
+    /// Makes the next capture produce a frame, even if the content didn't change
+    fn invalidate(&mut self) {
+    }
+
+    /// Captures a frame, recording stage timings to `status`
+    fn capture(&mut self, status: &SourceStatus) -> Result<Capture<'_>, Self::Error>;
+
+    /// Waits on the async capture thread until the next capture is due. `started` is when the
+    /// last capture started. Defaults to the OBS frame interval.
+    fn wait_next_capture(&self, started: Instant) {
+        let frame_interval = Duration::from_nanos(unsafe { obs::sys::obs_get_frame_interval_ns() });
+        if let Some(remaining) = frame_interval.checked_sub(started.elapsed()) {
+            thread::sleep(remaining);
+        }
+    }
+}
+
+/// Properties of every async capture source
+pub(crate) fn add_async_properties(props: &mut obs::Properties) {
+    use obs::properties::{
+        PropertiesExt,
+        PropertyDescription,
+    };
+
+    let pacing_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Frame pacing\0") };
+    props.add_string_list_complete(PropertyDescription::new(keys::PACING, Some(pacing_description)), pacing::Pacing::ALL.iter().map(|pacing| (pacing.description(), pacing.key())));
+    crate::frame::add_output_format_property(props);
+}
+
+/// Properties of every capture source, whatever its backend
+pub(crate) fn add_common_properties(props: &mut obs::Properties) {
+    crate::remote::add_capture_host_property(props);
+    crate::color::add_color_space_property(props);
+}
+
+/// Properties only `B` has, for either delivery
+pub(crate) fn add_backend_properties<B: CaptureBackend>(props: &mut obs::Properties) {
+    B::add_properties(props);
+    B::add_async_properties(props);
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const DELIVERY: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"delivery\0")
+    };
+    pub const DELIVERY_RENDER_THREAD: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"render_thread\0")
+    };
+    pub const DELIVERY_ASYNC: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"async\0")
+    };
+    pub const PACING: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"pacing\0")
+    };
+    pub const PACING_BUFFERED: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"buffered\0")
+    };
+    pub const PACING_UNBUFFERED: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"unbuffered\0")
+    };
+    pub const PACING_LATEST: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"latest\0")
+    };
+}
@@ -0,0 +1,59 @@
+use std::ffi::CStr;
+use obs::data::ObsData;
+use crate::{
+    backend::keys,
+    source_handle::SourceHandle,
+};
+
+/// How OBS presents frames from async capture sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pacing {
+    /// Frames are buffered and shown according to their timestamps, in sync with audio
+    Buffered,
+    /// Frames are shown as soon as they arrive (`obs_source_set_async_unbuffered`), but their
+    /// timestamps are still used for audio sync
+    Unbuffered,
+    /// Only the latest frame is shown, with its timestamp decoupled from audio
+    Latest,
+}
+
+impl Pacing {
+    pub const ALL: [Pacing; 3] = [Pacing::Buffered, Pacing::Unbuffered, Pacing::Latest];
+
+    pub fn from_settings(data: &obs::sys::obs_data) -> Self {
+        let value = data.get_cstr(keys::PACING);
+        Self::ALL.iter()
+            .find(|pacing| Some(pacing.key()) == value)
+            .copied()
+            .unwrap_or(Pacing::Buffered)
+    }
+
+    pub fn key(self) -> &'static CStr {
+        match self {
+            Pacing::Buffered => keys::PACING_BUFFERED,
+            Pacing::Unbuffered => keys::PACING_UNBUFFERED,
+            Pacing::Latest => keys::PACING_LATEST,
+        }
+    }
+
+    pub fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            Pacing::Buffered => b"Buffered (synced to audio)\0",
+            Pacing::Unbuffered => b"Unbuffered\0",
+            Pacing::Latest => b"Latest frame only\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+
+    pub fn apply(self, source: SourceHandle) {
+        let (unbuffered, decoupled) = match self {
+            Pacing::Buffered => (false, false),
+            Pacing::Unbuffered => (true, false),
+            Pacing::Latest => (true, true),
+        };
+        unsafe {
+            obs::sys::obs_source_set_async_unbuffered(source.handle(), unbuffered);
+            obs::sys::obs_source_set_async_decoupled(source.handle(), decoupled);
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! What sources show while they have nothing live to capture: OpenVR being unavailable, SteamVR
+//! switching applications, the captured content being hidden, or nobody wearing the headset
+
+use std::{
+    ffi::{
+        CStr,
+        CString,
+    },
+    iter,
+};
+use obs::data::ObsData;
+use crate::pixel::{
+    self,
+    Filter,
+    ImageBuf,
+    ImageRef,
+};
+
+/// What a source's captures currently look like
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisualState {
+    /// Frames are being captured
+    Live,
+    /// OpenVR or the capture host isn't available, or capturing fails
+    Disconnected,
+    /// SteamVR is switching applications, so captures would show the compositor's loading
+    /// environment or a faded out scene
+    Loading,
+    /// The captured content isn't visible
+    Idle,
+    /// Nobody is wearing the headset
+    Away,
+}
+
+/// What a source shows instead of captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaceholderMode {
+    /// Keep showing the last captured frame
+    Hold,
+    /// Show the placeholder image, or nothing if there is none
+    Image,
+    Transparent,
+}
+
+impl PlaceholderMode {
+    pub const ALL: [PlaceholderMode; 3] = [PlaceholderMode::Hold, PlaceholderMode::Image, PlaceholderMode::Transparent];
+
+    fn from_key(value: Option<&CStr>, default: PlaceholderMode) -> Self {
+        Self::ALL.iter()
+            .find(|mode| Some(mode.key()) == value)
+            .copied()
+            .unwrap_or(default)
+    }
+
+    pub fn key(self) -> &'static CStr {
+        match self {
+            PlaceholderMode::Hold => keys::PLACEHOLDER_HOLD,
+            PlaceholderMode::Image => keys::PLACEHOLDER_IMAGE,
+            PlaceholderMode::Transparent => keys::PLACEHOLDER_TRANSPARENT,
+        }
+    }
+
+    pub fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            PlaceholderMode::Hold => b"Keep last frame\0",
+            PlaceholderMode::Image => b"Show placeholder image\0",
+            PlaceholderMode::Transparent => b"Transparent\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+}
+
+const DEFAULT_DISCONNECTED: PlaceholderMode = PlaceholderMode::Transparent;
+const DEFAULT_LOADING: PlaceholderMode = PlaceholderMode::Hold;
+const DEFAULT_IDLE: PlaceholderMode = PlaceholderMode::Hold;
+
+/// What a source shows in each state without captures
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaceholderSettings {
+    pub disconnected: PlaceholderMode,
+    pub loading: PlaceholderMode,
+    pub idle: PlaceholderMode,
+    /// What to show while nobody is wearing the headset, or `None` to keep capturing
+    pub away: Option<PlaceholderMode>,
+    /// Path of the placeholder image
+    pub image: Option<CString>,
+}
+
+impl PlaceholderSettings {
+    pub fn from_settings(data: &obs::sys::obs_data) -> Self {
+        PlaceholderSettings {
+            disconnected: PlaceholderMode::from_key(data.get_cstr(keys::DISCONNECTED), DEFAULT_DISCONNECTED),
+            loading: PlaceholderMode::from_key(data.get_cstr(keys::LOADING), DEFAULT_LOADING),
+            idle: PlaceholderMode::from_key(data.get_cstr(keys::IDLE), DEFAULT_IDLE),
+            away: data.get_cstr(keys::AWAY).and_then(|value| {
+                PlaceholderMode::ALL.iter()
+                    .find(|mode| mode.key() == value)
+                    .copied()
+            }),
+            image: data.get_cstr(keys::IMAGE)
+                .filter(|path| !path.to_bytes().is_empty())
+                .map(|path| path.to_owned()),
+        }
+    }
+
+    /// What to show in `state`, or `None` if it is live
+    pub fn mode(&self, state: VisualState) -> Option<PlaceholderMode> {
+        match state {
+            VisualState::Live => None,
+            VisualState::Disconnected => Some(self.disconnected),
+            VisualState::Loading => Some(self.loading),
+            VisualState::Idle => Some(self.idle),
+            VisualState::Away => self.away,
+        }
+    }
+}
+
+impl Default for PlaceholderSettings {
+    fn default() -> Self {
+        PlaceholderSettings {
+            disconnected: DEFAULT_DISCONNECTED,
+            loading: DEFAULT_LOADING,
+            idle: DEFAULT_IDLE,
+            away: None,
+            image: None,
+        }
+    }
+}
+
+pub fn set_defaults(settings: &mut obs::sys::obs_data) {
+    settings.set_default_string(keys::DISCONNECTED, DEFAULT_DISCONNECTED.key());
+    settings.set_default_string(keys::LOADING, DEFAULT_LOADING.key());
+    settings.set_default_string(keys::IDLE, DEFAULT_IDLE.key());
+    settings.set_default_string(keys::AWAY, keys::AWAY_KEEP_CAPTURING);
+}
+
+pub fn add_placeholder_properties(props: &mut obs::Properties) {
+    use obs::properties::{
+        PropertiesExt,
+        PropertyDescription,
+    };
+
+    let mut group = obs::Properties::new();
+    let lists: [(&'static CStr, &'static [u8]); 3] = [
+        (keys::DISCONNECTED, b"When OpenVR is unavailable\0"),
+        (keys::LOADING, b"While SteamVR switches apps\0"),
+        (keys::IDLE, b"While hidden\0"),
+    ];
+    for &(key, description) in lists.iter() {
+        let description = unsafe { CStr::from_bytes_with_nul_unchecked(description) };
+        group.add_string_list_complete(PropertyDescription::new(key, Some(description)), PlaceholderMode::ALL.iter().map(|mode| (mode.description(), mode.key())));
+    }
+    let away_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"While the headset is off\0") };
+    let keep_capturing_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Keep capturing\0") };
+    let away_modes = iter::once((keep_capturing_description, keys::AWAY_KEEP_CAPTURING))
+        .chain(PlaceholderMode::ALL.iter().map(|mode| (mode.description(), mode.key())));
+    group.add_string_list_complete(PropertyDescription::new(keys::AWAY, Some(away_description)), away_modes);
+    let image_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Placeholder image\0") };
+    let image_filter: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Images (*.png *.jpg *.jpeg *.bmp *.gif *.webp)\0") };
+    group.add_path(keys::IMAGE, image_description, obs::sys::obs_path_type_OBS_PATH_FILE, Some(image_filter), None);
+    let group_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Placeholders\0") };
+    props.add_group(keys::GROUP, group_description, group);
+}
+
+/// Decodes the image at `path` to packed RGBA or BGRA
+fn load_image(path: &CStr) -> Option<ImageBuf> {
+    let file = obs::graphics::ImageFile::load(path)?;
+    let (w, h) = file.dimensions();
+    let format = match file.color_format() {
+        obs::sys::gs_color_format_GS_RGBA => pixel::Format::Rgba,
+        _ => pixel::Format::Bgra,
+    };
+    let mut ret = pixel::convert(&ImageRef::new(format, w, h, file.data()), format, Default::default());
+    if file.color_format() == obs::sys::gs_color_format_GS_BGRX {
+        ret.planes_mut()[0].chunks_exact_mut(4).for_each(|px| px[3] = 0xff);
+    }
+    Some(ret)
+}
+
+/// Largest rectangle with the aspect ratio of `content` that fits in `bounds`, centered
+fn fit(content: (u32, u32), bounds: (u32, u32)) -> pixel::Rect {
+    let (cw, ch) = (content.0.max(1) as u64, content.1.max(1) as u64);
+    let (bw, bh) = (bounds.0 as u64, bounds.1 as u64);
+    let (w, h) = if cw * bh > ch * bw {
+        (bw, ch * bw / cw)
+    } else {
+        (cw * bh / ch, bh)
+    };
+    pixel::Rect::new(((bw - w) / 2) as u32, ((bh - h) / 2) as u32, w as u32, h as u32)
+}
+
+/// What to show instead of captures
+#[derive(Debug, Clone, Copy)]
+pub enum Shown<'a> {
+    Hold,
+    Image(ImageRef<'a>),
+    Transparent,
+}
+
+/// Tracks the visual state of a source, and decides what it shows when it changes
+pub struct Placeholder {
+    settings: PlaceholderSettings,
+    state: VisualState,
+    /// Whether the next `enter` has to apply its state even if it didn't change
+    dirty: bool,
+    image: Option<ImageBuf>,
+    /// `image` fitted to the source's dimensions
+    fitted: ImageBuf,
+}
+
+impl Placeholder {
+    pub fn new(settings: PlaceholderSettings) -> Self {
+        let mut ret = Placeholder {
+            settings: PlaceholderSettings::default(),
+            state: VisualState::Live,
+            dirty: false,
+            image: None,
+            fitted: ImageBuf::new(pixel::Format::Rgba, 0, 0),
+        };
+        ret.update(settings);
+        ret
+    }
+
+    /// Applies changed settings, reloading the image if its path changed
+    pub fn update(&mut self, settings: PlaceholderSettings) {
+        if settings == self.settings && (self.image.is_some() || settings.image.is_none()) {
+            return;
+        }
+        if settings.image != self.settings.image || self.image.is_none() {
+            self.image = settings.image.as_ref().and_then(|path| {
+                let image = load_image(path);
+                if image.is_none() {
+                    warn!("Error loading placeholder image {:?}", path);
+                }
+                image
+            });
+            self.fitted.reset(pixel::Format::Rgba, 0, 0);
+        }
+        self.settings = settings;
+        self.dirty = true;
+    }
+
+    #[inline]
+    pub fn state(&self) -> VisualState {
+        self.state
+    }
+
+    /// Whether the source stops capturing while nobody is wearing the headset
+    #[inline]
+    pub fn hides_while_away(&self) -> bool {
+        self.settings.away.is_some()
+    }
+
+    /// Records that the source is in `state`. Returns what to show instead of captures if
+    /// `state` isn't live, and it was just entered or the settings changed. `dimensions` are the
+    /// source's, which the image is fitted to; it keeps its own size if they're zero.
+    pub fn enter(&mut self, state: VisualState, dimensions: (u32, u32)) -> Option<Shown<'_>> {
+        if state == self.state && !self.dirty {
+            return None;
+        }
+        if state != self.state {
+            debug!("visual state: {:?} -> {:?}", self.state, state);
+        }
+        self.state = state;
+        self.dirty = false;
+        let shown = match self.settings.mode(state)? {
+            PlaceholderMode::Hold => Shown::Hold,
+            PlaceholderMode::Transparent => Shown::Transparent,
+            PlaceholderMode::Image => self.image(dimensions)
+                .map_or(Shown::Transparent, Shown::Image),
+        };
+        Some(shown)
+    }
+
+    /// The placeholder image fitted to `dimensions`, or `None` if there is none. It keeps its
+    /// own size if `dimensions` are zero.
+    pub fn image(&mut self, dimensions: (u32, u32)) -> Option<ImageRef<'_>> {
+        let image = self.image.as_ref()?;
+        if dimensions.0 == 0 || dimensions.1 == 0 || image.dimensions() == dimensions {
+            return Some(image.as_image());
+        }
+        if self.fitted.dimensions() != dimensions || self.fitted.format() != image.format() {
+            let rect = fit(image.dimensions(), dimensions);
+            let scaled = pixel::scale(&image.as_image(), rect.width.max(1), rect.height.max(1), Filter::Box);
+            self.fitted.reset(image.format(), dimensions.0, dimensions.1);
+            self.fitted.clear();
+            pixel::blit(&scaled.as_image(), &mut self.fitted, rect.x, rect.y);
+        }
+        Some(self.fitted.as_image())
+    }
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const GROUP: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"placeholders\0")
+    };
+    pub const DISCONNECTED: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"placeholder_disconnected\0")
+    };
+    pub const LOADING: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"placeholder_loading\0")
+    };
+    pub const IDLE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"placeholder_idle\0")
+    };
+    pub const AWAY: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"placeholder_away\0")
+    };
+    pub const IMAGE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"placeholder_image\0")
+    };
+    pub const PLACEHOLDER_HOLD: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"hold\0")
+    };
+    pub const PLACEHOLDER_IMAGE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"image\0")
+    };
+    pub const PLACEHOLDER_TRANSPARENT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"transparent\0")
+    };
+    pub const AWAY_KEEP_CAPTURING: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"keep_capturing\0")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_image(image: ImageBuf) -> Placeholder {
+        let mut ret = Placeholder::new(PlaceholderSettings {
+            disconnected: PlaceholderMode::Image,
+            loading: PlaceholderMode::Hold,
+            idle: PlaceholderMode::Transparent,
+            away: None,
+            image: None,
+        });
+        ret.image = Some(image);
+        ret
+    }
+
+    #[test]
+    fn fit_centers_content() {
+        assert_eq!(fit((200, 100), (100, 100)), pixel::Rect::new(0, 25, 100, 50));
+        assert_eq!(fit((100, 200), (100, 100)), pixel::Rect::new(25, 0, 50, 100));
+        assert_eq!(fit((16, 9), (1920, 1080)), pixel::Rect::new(0, 0, 1920, 1080));
+    }
+
+    #[test]
+    fn only_reports_state_changes() {
+        let mut placeholder = with_image(ImageBuf::new(pixel::Format::Rgba, 2, 2));
+        assert!(placeholder.enter(VisualState::Live, (2, 2)).is_none());
+        assert!(matches!(placeholder.enter(VisualState::Idle, (2, 2)), Some(Shown::Transparent)));
+        assert!(placeholder.enter(VisualState::Idle, (2, 2)).is_none());
+        assert!(matches!(placeholder.enter(VisualState::Loading, (2, 2)), Some(Shown::Hold)));
+        assert!(placeholder.enter(VisualState::Live, (2, 2)).is_none());
+        assert_eq!(placeholder.state(), VisualState::Live);
+    }
+
+    #[test]
+    fn away_keeps_capturing_unless_set() {
+        assert!(!Placeholder::new(PlaceholderSettings::default()).hides_while_away());
+        let mut placeholder = Placeholder::new(PlaceholderSettings {
+            away: Some(PlaceholderMode::Transparent),
+            ..Default::default()
+        });
+        assert!(placeholder.hides_while_away());
+        assert!(matches!(placeholder.enter(VisualState::Away, (2, 2)), Some(Shown::Transparent)));
+    }
+
+    #[test]
+    fn image_is_fitted_to_source() {
+        let mut image = ImageBuf::new(pixel::Format::Rgba, 2, 1);
+        image.planes_mut()[0].iter_mut().for_each(|v| *v = 0xff);
+        let mut placeholder = with_image(image);
+        let fitted = match placeholder.enter(VisualState::Disconnected, (4, 4)) {
+            Some(Shown::Image(image)) => image,
+            shown => panic!("expected an image, got {:?}", shown),
+        };
+        assert_eq!((fitted.width, fitted.height), (4, 4));
+        // Letterboxed, with transparent rows above and below
+        let opaque_rows: Vec<bool> = (0..4).map(|y| fitted.row(0, y).iter().all(|&v| v == 0xff)).collect();
+        assert_eq!(opaque_rows, vec![false, true, true, false]);
+        // Keeps its own size before the source has any
+        placeholder.enter(VisualState::Live, (0, 0));
+        match placeholder.enter(VisualState::Disconnected, (0, 0)) {
+            Some(Shown::Image(image)) => assert_eq!((image.width, image.height), (2, 1)),
+            shown => panic!("expected an image, got {:?}", shown),
+        }
+    }
+}
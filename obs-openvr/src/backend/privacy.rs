@@ -0,0 +1,371 @@
+//! Privacy mode: obscuring captures while sensitive overlays or the SteamVR dashboard are visible
+//!
+//! Which overlays count as sensitive is plugin-wide, read from `privacy.json` in the module's
+//! config directory. How a source obscures its captures is set per source.
+
+use std::{
+    ffi::{
+        CStr,
+        CString,
+    },
+    path::PathBuf,
+    sync::Mutex,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use obs::data::ObsData;
+use crate::{
+    backend::placeholder::Placeholder,
+    config::ConfigFile,
+    pixel::{
+        self,
+        Filter,
+        ImageBuf,
+        ImageRef,
+    },
+};
+
+/// How often overlay visibility is checked, at most
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+const CONFIG_FILE: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"privacy.json\0") };
+
+/// Which overlays trigger privacy mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrivacyConfig {
+    /// Whether the SteamVR dashboard being open triggers privacy mode
+    pub dashboard: bool,
+    /// Keys of the overlays that trigger privacy mode while visible
+    pub overlays: Vec<CString>,
+}
+
+impl PrivacyConfig {
+    fn from_data(data: &obs::sys::obs_data) -> Self {
+        PrivacyConfig {
+            dashboard: data.get_bool(keys::CONFIG_DASHBOARD),
+            overlays: data.get_string(keys::CONFIG_OVERLAYS)
+                .map(parse_overlay_keys)
+                .unwrap_or_default(),
+        }
+    }
+
+    fn set_defaults(data: &mut obs::sys::obs_data) {
+        data.set_default_bool(keys::CONFIG_DASHBOARD, true);
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        !self.dashboard && self.overlays.is_empty()
+    }
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        PrivacyConfig {
+            dashboard: true,
+            overlays: Vec::new(),
+        }
+    }
+}
+
+/// Splits a list of overlay keys separated by commas or whitespace
+fn parse_overlay_keys(s: &str) -> Vec<CString> {
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|key| !key.is_empty())
+        .filter_map(|key| CString::new(key).ok())
+        .collect()
+}
+
+/// Path of the plugin-wide config file
+pub fn config_path() -> Option<PathBuf> {
+    crate::module::config_path(CONFIG_FILE)
+}
+
+const CONFIG_TEMPLATE: &'static str = r#"{
+    "dashboard": true,
+    "overlays": ""
+}
+"#;
+
+/// Plugin-wide privacy state, shared by every source
+struct Monitor {
+    file: ConfigFile,
+    config: PrivacyConfig,
+    active: bool,
+    checked: Option<Instant>,
+    init_attempted: Option<Instant>,
+}
+
+impl Monitor {
+    fn new() -> Self {
+        Monitor {
+            file: ConfigFile::new(CONFIG_FILE, CONFIG_TEMPLATE, PrivacyConfig::set_defaults),
+            config: PrivacyConfig::default(),
+            active: false,
+            checked: None,
+            init_attempted: None,
+        }
+    }
+
+    fn check(&mut self) -> bool {
+        let now = Instant::now();
+        if self.checked.map_or(false, |t| now.duration_since(t) < CHECK_INTERVAL) {
+            return self.active;
+        }
+        self.checked = Some(now);
+        if let Some(data) = self.file.reload() {
+            self.config = PrivacyConfig::from_data(&data);
+            debug!("privacy config: {:?}", &self.config);
+        }
+        // Held while polling the overlays, so OpenVR isn't shut down meanwhile
+        let session = if self.config.is_empty() || !crate::retry_init_openvr(&mut self.init_attempted) {
+            None
+        } else {
+            crate::openvr_session()
+        };
+        let active = session.is_some() && {
+            let config = &self.config;
+            (config.dashboard && openvr::overlay::is_dashboard_visible()) || config.overlays.iter().any(|key| {
+                openvr::overlay::find_overlay(key)
+                    .map(|overlay| overlay.is_visible())
+                    .unwrap_or(false)
+            })
+        };
+        if active != self.active {
+            info!("privacy mode {}", if active { "on" } else { "off" });
+            self.active = active;
+        }
+        active
+    }
+}
+
+static MONITOR: Mutex<Option<Monitor>> = Mutex::new(None);
+
+/// Whether any of the sensitive overlays is visible right now. Cheap enough to call every frame.
+pub fn is_active() -> bool {
+    let mut monitor = MONITOR.lock().unwrap();
+    monitor.get_or_insert_with(Monitor::new).check()
+}
+
+/// How a source obscures its captures in privacy mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivacyEffect {
+    /// Keep showing captures
+    Off,
+    Blur,
+    Pixelate,
+    /// Show the placeholder image, or nothing if there is none
+    Image,
+}
+
+impl PrivacyEffect {
+    pub const ALL: [PrivacyEffect; 4] = [PrivacyEffect::Off, PrivacyEffect::Blur, PrivacyEffect::Pixelate, PrivacyEffect::Image];
+
+    pub fn from_settings(data: &obs::sys::obs_data) -> Self {
+        let value = data.get_cstr(keys::EFFECT);
+        Self::ALL.iter()
+            .find(|effect| Some(effect.key()) == value)
+            .copied()
+            .unwrap_or(PrivacyEffect::Off)
+    }
+
+    pub fn key(self) -> &'static CStr {
+        match self {
+            PrivacyEffect::Off => keys::EFFECT_OFF,
+            PrivacyEffect::Blur => keys::EFFECT_BLUR,
+            PrivacyEffect::Pixelate => keys::EFFECT_PIXELATE,
+            PrivacyEffect::Image => keys::EFFECT_IMAGE,
+        }
+    }
+
+    pub fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            PrivacyEffect::Off => b"Off\0",
+            PrivacyEffect::Blur => b"Blur\0",
+            PrivacyEffect::Pixelate => b"Pixelate\0",
+            PrivacyEffect::Image => b"Show placeholder image\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+}
+
+pub fn set_defaults(settings: &mut obs::sys::obs_data) {
+    settings.set_default_string(keys::EFFECT, PrivacyEffect::Off.key());
+}
+
+pub fn add_privacy_properties(props: &mut obs::Properties) {
+    use obs::properties::{
+        PropertiesExt,
+        PropertyDescription,
+    };
+
+    let mut group = obs::Properties::new();
+    let effect_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"While sensitive overlays are visible\0") };
+    group.add_string_list_complete(PropertyDescription::new(keys::EFFECT, Some(effect_description)), PrivacyEffect::ALL.iter().map(|effect| (effect.description(), effect.key())));
+    let info = match config_path() {
+        Some(path) => format!("Sensitive overlays are set in {}", path.display()),
+        None => "Sensitive overlays are set in privacy.json in the plugin's config directory".to_owned(),
+    };
+    if let Ok(info) = CString::new(info) {
+        group.add_text(keys::CONFIG_INFO, &info, obs::sys::obs_text_type_OBS_TEXT_INFO);
+    }
+    let group_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Privacy mode\0") };
+    props.add_group(keys::GROUP, group_description, group);
+}
+
+/// Side of the blocks pixelated captures are made of, relative to a 1080 pixel tall frame
+const PIXELATE_BLOCK: u32 = 24;
+/// How much blurred captures are downscaled before being scaled back up
+const BLUR_FACTOR: u32 = 16;
+
+/// Obscures `image` with `effect` into `dst`. `Off` and `Image` copy it unchanged.
+fn obscure_into(image: &ImageRef<'_>, effect: PrivacyEffect, small: &mut ImageBuf, dst: &mut ImageBuf) {
+    let (w, h) = (image.width, image.height);
+    let (sw, sh, upscale) = match effect {
+        PrivacyEffect::Off | PrivacyEffect::Image => {
+            pixel::convert_into(image, image.format, Default::default(), dst);
+            return;
+        },
+        PrivacyEffect::Blur => (w / BLUR_FACTOR, h / BLUR_FACTOR, Filter::Bilinear),
+        PrivacyEffect::Pixelate => {
+            let block = (PIXELATE_BLOCK * h / 1080).max(2);
+            (w / block, h / block, Filter::Nearest)
+        },
+    };
+    pixel::scale_into(image, sw.max(1), sh.max(1), Filter::Box, small);
+    pixel::scale_into(&small.as_image(), w, h, upscale, dst);
+}
+
+/// Tracks privacy mode for a source, and obscures its captures while it's on
+pub struct Privacy {
+    effect: PrivacyEffect,
+    active: bool,
+    small: ImageBuf,
+    obscured: ImageBuf,
+}
+
+impl Privacy {
+    pub fn new(effect: PrivacyEffect) -> Self {
+        Privacy {
+            effect: effect,
+            active: false,
+            small: ImageBuf::new(pixel::Format::Rgba, 0, 0),
+            obscured: ImageBuf::new(pixel::Format::Rgba, 0, 0),
+        }
+    }
+
+    #[inline]
+    pub fn update(&mut self, effect: PrivacyEffect) {
+        self.effect = effect;
+    }
+
+    #[inline]
+    pub fn effect(&self) -> PrivacyEffect {
+        self.effect
+    }
+
+    /// Checks whether captures have to be obscured. Returns `true` if that changed, so content
+    /// that didn't change has to be captured again.
+    pub fn poll(&mut self) -> bool {
+        let active = self.effect != PrivacyEffect::Off && is_active();
+        let changed = active != self.active;
+        self.active = active;
+        changed
+    }
+
+    /// Whether captures are being obscured, as of the last `poll`
+    #[inline]
+    pub fn is_obscuring(&self) -> bool {
+        self.active
+    }
+
+    /// What to show instead of `image` while obscuring captures, with the same dimensions.
+    /// `Image` falls back to a transparent frame if there is no placeholder image.
+    pub fn obscure<'a>(&'a mut self, image: &ImageRef<'_>, placeholder: &'a mut Placeholder) -> ImageRef<'a> {
+        let dimensions = (image.width, image.height);
+        if self.effect == PrivacyEffect::Image {
+            if let Some(image) = placeholder.image(dimensions) {
+                return image;
+            }
+            self.obscured.reset(pixel::Format::Rgba, dimensions.0, dimensions.1);
+            self.obscured.clear();
+        } else {
+            obscure_into(image, self.effect, &mut self.small, &mut self.obscured);
+        }
+        self.obscured.as_image()
+    }
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const GROUP: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"privacy\0")
+    };
+    pub const EFFECT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"privacy_effect\0")
+    };
+    pub const CONFIG_INFO: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"privacy_config_info\0")
+    };
+    pub const EFFECT_OFF: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"off\0")
+    };
+    pub const EFFECT_BLUR: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"blur\0")
+    };
+    pub const EFFECT_PIXELATE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"pixelate\0")
+    };
+    pub const EFFECT_IMAGE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"image\0")
+    };
+    /// Keys in the plugin-wide config file
+    pub const CONFIG_DASHBOARD: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"dashboard\0")
+    };
+    pub const CONFIG_OVERLAYS: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"overlays\0")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlay_keys_split_on_commas_and_whitespace() {
+        let keys = parse_overlay_keys(" system.keyboard,valve.steam.desktop\n\nmy.overlay ,");
+        let keys: Vec<&str> = keys.iter().map(|k| k.to_str().unwrap()).collect();
+        assert_eq!(keys, vec!["system.keyboard", "valve.steam.desktop", "my.overlay"]);
+    }
+
+    #[test]
+    fn pixelate_makes_solid_blocks() {
+        let data: Vec<u8> = (0..64 * 64).flat_map(|i| vec![(i % 251) as u8, (i % 13) as u8, 0, 0xff]).collect();
+        let image = ImageRef::new(pixel::Format::Rgba, 64, 64, &data);
+        let (mut small, mut out) = (ImageBuf::new(pixel::Format::Rgba, 0, 0), ImageBuf::new(pixel::Format::Rgba, 0, 0));
+        obscure_into(&image, PrivacyEffect::Pixelate, &mut small, &mut out);
+        assert_eq!(out.dimensions(), (64, 64));
+        let out = out.as_image();
+        // 2 pixel blocks at this height
+        for y in (0..64).step_by(2) {
+            let row = out.row(0, y);
+            assert_eq!(row, out.row(0, y + 1));
+            assert!(row.chunks_exact(8).all(|block| block[..4] == block[4..]));
+        }
+    }
+
+    #[test]
+    fn blur_keeps_dimensions_and_smooths() {
+        let data: Vec<u8> = (0..32 * 32).flat_map(|i| if (i / 32 + i % 32) % 2 == 0 { [0xff; 4] } else { [0, 0, 0, 0xff] }.to_vec()).collect();
+        let image = ImageRef::new(pixel::Format::Rgba, 32, 32, &data);
+        let (mut small, mut out) = (ImageBuf::new(pixel::Format::Rgba, 0, 0), ImageBuf::new(pixel::Format::Rgba, 0, 0));
+        obscure_into(&image, PrivacyEffect::Blur, &mut small, &mut out);
+        assert_eq!(out.dimensions(), (32, 32));
+        // The checkerboard averages out to gray
+        assert!(out.data().chunks_exact(4).all(|px| px[0] > 0x60 && px[0] < 0xa0));
+    }
+}
@@ -0,0 +1,187 @@
+//! Creating capture backends, retried with backoff while it fails
+
+use std::{
+    ffi::CStr,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use crate::{
+    backend::{
+        CaptureBackend,
+        Delivery,
+    },
+    status::SourceStatus,
+};
+
+/// Delay before retrying a failed creation, doubled after each failure up to `MAX_BACKOFF`
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+const BACKOFF_FACTOR: u32 = 2;
+
+/// The backend of a source, created from the source's config when it's needed. While creating it
+/// fails, like when SteamVR isn't running, it's retried after a growing delay.
+pub(crate) struct BackendSlot<B: CaptureBackend> {
+    backend: Option<B>,
+    delivery: Delivery,
+    /// Delay after the next failure
+    backoff: Duration,
+    /// When creation may be tried again, after a failure
+    retry_at: Option<Instant>,
+}
+
+impl<B: CaptureBackend> BackendSlot<B> {
+    pub fn new(delivery: Delivery) -> Self {
+        BackendSlot {
+            backend: None,
+            delivery: delivery,
+            backoff: MIN_BACKOFF,
+            retry_at: None,
+        }
+    }
+
+    fn name(&self) -> &'static CStr {
+        match self.delivery {
+            Delivery::RenderThread => B::SYNC_NAME,
+            Delivery::Async => B::ASYNC_NAME,
+        }
+    }
+
+    fn create(&mut self, config: &B::Config, status: &SourceStatus, now: Instant) -> Option<B> {
+        match B::create(config, self.delivery, status) {
+            Ok(v) => {
+                self.backoff = MIN_BACKOFF;
+                self.retry_at = None;
+                Some(v)
+            },
+            Err(e) => {
+                warn!("{:?}: error creating capture backend, retrying in {:?}: {}", self.name(), self.backoff, &e);
+                status.record_error(&e);
+                self.retry_at = Some(now + self.backoff);
+                self.backoff = (self.backoff * BACKOFF_FACTOR).min(MAX_BACKOFF);
+                None
+            },
+        }
+    }
+
+    /// The current backend, without creating one
+    #[inline]
+    pub fn get_mut(&mut self) -> Option<&mut B> {
+        self.backend.as_mut()
+    }
+
+    /// Replaces the backend with one created from a changed `config` right away. The new one is
+    /// created before the old one is dropped, so shared captures survive.
+    pub fn replace(&mut self, config: &B::Config, status: &SourceStatus) {
+        self.backoff = MIN_BACKOFF;
+        self.backend = self.create(config, status, Instant::now());
+    }
+
+    /// The backend, created from `config` if there is none or its OpenVR session ended, unless
+    /// the last attempt failed too recently
+    pub fn get(&mut self, config: &B::Config, status: &SourceStatus, now: Instant) -> Option<&mut B> {
+        if self.backend.as_ref().map_or(false, B::is_stale) {
+            debug!("{:?}: recreating capture backend for the new OpenVR session", self.name());
+            // Dropped first, since captures of the old session can't be shared with the new one
+            self.backend = None;
+        }
+        if self.backend.is_none() && self.retry_at.map_or(true, |t| now >= t) {
+            self.backend = self.create(config, status, now);
+        }
+        self.backend.as_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        cell::Cell,
+        ptr,
+    };
+    use crate::backend::Capture;
+
+    thread_local! {
+        /// Number of upcoming `FlakyBackend::create` calls that fail
+        static FAILURES: Cell<u32> = Cell::new(0);
+    }
+
+    struct FlakyBackend;
+
+    impl CaptureBackend for FlakyBackend {
+        type Config = ();
+        type Error = &'static str;
+
+        const SYNC_ID: &'static [u8] = b"flaky\0";
+        const SYNC_NAME: &'static CStr = unsafe {
+            CStr::from_bytes_with_nul_unchecked(b"Flaky\0")
+        };
+        const ASYNC_ID: &'static CStr = unsafe {
+            CStr::from_bytes_with_nul_unchecked(b"flaky-async\0")
+        };
+        const ASYNC_NAME: &'static CStr = unsafe {
+            CStr::from_bytes_with_nul_unchecked(b"Flaky (async)\0")
+        };
+
+        fn config(_data: &obs::sys::obs_data) {
+        }
+
+        fn add_properties(_props: &mut obs::Properties) {
+        }
+
+        fn create(_config: &(), _delivery: Delivery, _status: &SourceStatus) -> Result<Self, &'static str> {
+            FAILURES.with(|failures| match failures.get() {
+                0 => Ok(FlakyBackend),
+                n => {
+                    failures.set(n - 1);
+                    Err("not yet")
+                },
+            })
+        }
+
+        fn capture(&mut self, _status: &SourceStatus) -> Result<Capture<'_>, &'static str> {
+            Ok(Capture::NotDue)
+        }
+    }
+
+    fn failures() -> u32 {
+        FAILURES.with(Cell::get)
+    }
+
+    #[test]
+    fn failed_creation_is_retried_after_backoff() {
+        FAILURES.with(|failures| failures.set(2));
+        let status = SourceStatus::new(ptr::null_mut(), false);
+        let mut slot = BackendSlot::<FlakyBackend>::new(Delivery::Async);
+        let start = Instant::now();
+        assert!(slot.get(&(), &status, start).is_none());
+        // Not retried before the backoff passed
+        assert!(slot.get(&(), &status, start + MIN_BACKOFF / 2).is_none());
+        assert_eq!(failures(), 1);
+        let retried = start + MIN_BACKOFF;
+        assert!(slot.get(&(), &status, retried).is_none());
+        assert_eq!(failures(), 0);
+        // Doubled after the second failure
+        assert!(slot.get(&(), &status, retried + MIN_BACKOFF).is_none());
+        assert!(slot.get(&(), &status, retried + MIN_BACKOFF * BACKOFF_FACTOR).is_some());
+        assert!(slot.get_mut().is_some());
+    }
+
+    #[test]
+    fn backoff_is_capped_and_reset_by_replace() {
+        FAILURES.with(|failures| failures.set(u32::MAX));
+        let status = SourceStatus::new(ptr::null_mut(), false);
+        let mut slot = BackendSlot::<FlakyBackend>::new(Delivery::RenderThread);
+        let mut now = Instant::now();
+        for _ in 0..10 {
+            assert!(slot.get(&(), &status, now).is_none());
+            now += MAX_BACKOFF;
+        }
+        assert_eq!(slot.backoff, MAX_BACKOFF);
+        FAILURES.with(|failures| failures.set(0));
+        slot.replace(&(), &status);
+        assert!(slot.get_mut().is_some());
+        assert_eq!(slot.backoff, MIN_BACKOFF);
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    ffi::CStr,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::Instant,
+};
+use obs::graphics::{
+    GsTexture,
+    with_blend_function,
+};
+use crate::{
+    backend::{
+        Capture,
+        CaptureBackend,
+        Delivery,
+        FrameData,
+        TextureFrame,
+        add_common_properties,
+        placeholder::{
+            self,
+            Placeholder,
+            PlaceholderSettings,
+            Shown,
+            VisualState,
+        },
+        privacy::{
+            self,
+            Privacy,
+            PrivacyEffect,
+        },
+        retry::BackendSlot,
+    },
+    color::Encoding,
+    metrics::Stage,
+    pixel::{
+        self,
+        ImageBuf,
+    },
+    presence,
+    status::{
+        SourceStatus,
+        StatusProvider,
+        add_status_properties,
+    },
+    sync::{
+        self,
+        AtomicDimensions,
+    },
+    timing::Timer,
+};
+
+/// A captured frame, handed from `video_tick` to `video_render`
+struct RenderFrame {
+    /// Packed RGBA or BGRA pixels, unless the frame is a `texture`
+    image: ImageBuf,
+    encoding: Encoding,
+    premultiplied: bool,
+    texture: Option<Arc<dyn TextureFrame>>,
+}
+
+impl RenderFrame {
+    fn new() -> Self {
+        RenderFrame {
+            image: ImageBuf::new(pixel::Format::Rgba, 0, 0),
+            encoding: Encoding::Srgb,
+            premultiplied: false,
+            texture: None,
+        }
+    }
+}
+
+struct CaptureState<B: CaptureBackend> {
+    config: Option<B::Config>,
+    backend: BackendSlot<B>,
+    output: sync::Input<RenderFrame>,
+    placeholder: Placeholder,
+    privacy: Privacy,
+    /// CPU copy of texture frames, which privacy mode obscures
+    readback: ImageBuf,
+}
+
+struct RenderState {
+    frames: sync::Output<RenderFrame>,
+    /// Texture `image` frames are uploaded to
+    texture: Option<obs::graphics::Texture>,
+}
+
+impl RenderState {
+    /// Uploads the latest frame's image to `texture`. Must be called with the graphics context
+    /// entered.
+    fn upload(&mut self, status: &SourceStatus) {
+        let frame = self.frames.buffer();
+        if frame.texture.is_some() {
+            return;
+        }
+        let image = frame.image.as_image();
+        if image.width == 0 || image.height == 0 {
+            // Transparent placeholder before the first capture
+            self.texture = None;
+            return;
+        }
+        let color_format = frame.encoding.gs_color_format(image.format)
+            .expect("render frames are always packed RGBA or BGRA");
+        match &mut self.texture {
+            &mut Some(ref mut texture) if texture.get_dimensions() == (image.width, image.height) && texture.get_color_format() == color_format => unsafe {
+                texture.set_image_unchecked(image.data, image.stride as u32, false);
+            },
+            texture => unsafe {
+                *texture = obs::graphics::Texture::new(image.width, image.height, color_format, &[image.data.as_ptr()], obs::sys::GS_DYNAMIC);
+                if texture.is_none() {
+                    error!("Error creating obs texture from image data");
+                    status.record_error("Error creating obs texture from image data");
+                }
+            },
+        }
+    }
+
+    fn draw(&self) {
+        let frame = self.frames.buffer();
+        let draw = || match frame.texture.as_ref() {
+            Some(texture) => texture.draw(),
+            None => self.texture.iter().for_each(|texture| obs::source::draw(texture, 0, 0, 0, 0, false)),
+        };
+        if frame.premultiplied {
+            with_blend_function(obs::sys::gs_blend_type_GS_BLEND_ONE, obs::sys::gs_blend_type_GS_BLEND_INVSRCALPHA, draw);
+        } else {
+            draw();
+        }
+    }
+}
+
+/// Source capturing frames from `B` in `video_tick`, and drawing them in `video_render`
+pub struct SyncCaptureSource<B: CaptureBackend> {
+    handle: *mut obs::sys::obs_source_t,
+    dimensions: AtomicDimensions,
+    /// Capture state, shared by `update` and `video_tick`
+    capture: Mutex<CaptureState<B>>,
+    /// Render state, only used by `video_render`, so capture never waits on rendering
+    render: Mutex<RenderState>,
+    status: Arc<SourceStatus>,
+}
+
+impl<B: CaptureBackend> SyncCaptureSource<B> {
+    #[inline(always)]
+    pub fn is_showing(&self) -> bool {
+        unsafe {
+            obs::sys::obs_source_showing(self.handle)
+        }
+    }
+
+    /// Moves to `state`, publishing a placeholder frame if one has to be shown
+    fn enter_state(&self, placeholder: &mut Placeholder, output: &mut sync::Input<RenderFrame>, state: VisualState) {
+        let dimensions = self.dimensions.load();
+        let frame = output.buffer();
+        match placeholder.enter(state, dimensions) {
+            None | Some(Shown::Hold) => return,
+            Some(Shown::Image(image)) => {
+                pixel::convert_into(&image, image.format, Default::default(), &mut frame.image);
+                self.dimensions.store((image.width, image.height));
+            },
+            Some(Shown::Transparent) => {
+                frame.image.reset(pixel::Format::Rgba, dimensions.0, dimensions.1);
+                frame.image.clear();
+            },
+        }
+        frame.encoding = Encoding::Srgb;
+        frame.premultiplied = false;
+        frame.texture = None;
+        output.publish();
+    }
+}
+
+impl<B: CaptureBackend> obs::source::VideoSource for SyncCaptureSource<B> {
+    const ID: &'static [u8] = B::SYNC_ID;
+    const OUTPUT_FLAGS: Option<u32> = Some(B::OUTPUT_FLAGS | obs::sys::OBS_SOURCE_SRGB);
+
+    fn create(settings: &mut obs::sys::obs_data, source: *mut obs::sys::obs_source_t) -> Self {
+        let (input, output) = sync::triple_buffer(RenderFrame::new);
+        let ret = SyncCaptureSource {
+            handle: source,
+            dimensions: AtomicDimensions::new((0, 0)),
+            capture: Mutex::new(CaptureState {
+                config: None,
+                backend: BackendSlot::new(Delivery::RenderThread),
+                output: input,
+                placeholder: Placeholder::new(PlaceholderSettings::from_settings(settings)),
+                privacy: Privacy::new(PrivacyEffect::from_settings(settings)),
+                readback: ImageBuf::new(pixel::Format::Rgba, 0, 0),
+            }),
+            render: Mutex::new(RenderState {
+                frames: output,
+                texture: None,
+            }),
+            status: Arc::new(SourceStatus::new(source, B::TRACKS_OVERLAY)),
+        };
+        ret.update(settings);
+        ret
+    }
+
+    fn get_name() -> &'static CStr {
+        B::SYNC_NAME
+    }
+
+    fn get_defaults(settings: &mut obs::sys::obs_data) {
+        B::get_defaults(settings);
+        placeholder::set_defaults(settings);
+        privacy::set_defaults(settings);
+    }
+
+    #[inline]
+    fn get_dimensions(&self) -> (u32, u32) {
+        self.dimensions.load()
+    }
+
+    fn get_properties(&self) -> *mut obs::sys::obs_properties {
+        let mut props = obs::Properties::new();
+        B::add_properties(&mut props);
+        add_common_properties(&mut props);
+        placeholder::add_placeholder_properties(&mut props);
+        privacy::add_privacy_properties(&mut props);
+        add_status_properties(&mut props, self);
+        unsafe { props.leak() }
+    }
+
+    fn update(&self, data: &obs::sys::obs_data) {
+        self.status.update_settings(data);
+        let config = B::config(data);
+        let mut capture = self.capture.lock().unwrap();
+        capture.placeholder.update(PlaceholderSettings::from_settings(data));
+        capture.privacy.update(PrivacyEffect::from_settings(data));
+        if capture.config.as_ref() == Some(&config) && capture.backend.get_mut().is_some() {
+            return;
+        }
+        trace!("{:?}: updating backend with {:?}", B::SYNC_NAME, &config);
+        let reconfigured = capture.backend.get_mut()
+            .map(|backend| backend.reconfigure(&config))
+            .unwrap_or(false);
+        if !reconfigured {
+            capture.backend.replace(&config, &self.status);
+        }
+        capture.config = Some(config);
+    }
+
+    fn video_tick(&self, _seconds: f32) {
+        if !self.is_showing() {
+            return;
+        }
+        let mut capture = self.capture.lock().unwrap();
+        let capture = &mut *capture;
+        let backend = match capture.config.as_ref() {
+            Some(config) => capture.backend.get(config, &self.status, Instant::now()),
+            None => None,
+        };
+        let backend = match backend {
+            Some(v) => v,
+            None => {
+                self.status.record_skipped();
+                self.enter_state(&mut capture.placeholder, &mut capture.output, VisualState::Disconnected);
+                return;
+            },
+        };
+        if capture.placeholder.hides_while_away() && !presence::is_user_present() {
+            self.status.record_skipped();
+            self.enter_state(&mut capture.placeholder, &mut capture.output, VisualState::Away);
+            return;
+        }
+        let privacy_changed = capture.privacy.poll();
+        if privacy_changed || capture.placeholder.state() != VisualState::Live {
+            // A placeholder or obscured frame may be showing, so the next frame has to replace it
+            backend.invalidate();
+        }
+        let state = match backend.capture(&self.status) {
+            Ok(Capture::Frame(frame)) => Ok(frame),
+            Ok(Capture::Unchanged) => {
+                self.status.record_unchanged();
+                return;
+            },
+            Ok(Capture::NotDue) => return,
+            Ok(Capture::Hidden) => Err(VisualState::Idle),
+            Ok(Capture::Loading) => Err(VisualState::Loading),
+            Ok(Capture::Disconnected) => Err(VisualState::Disconnected),
+            Err(e) => {
+                error!("{:?}: capture error: {}", B::SYNC_NAME, &e);
+                self.status.record_error(&e);
+                Err(VisualState::Disconnected)
+            },
+        };
+        let frame = match state {
+            Ok(frame) => frame,
+            Err(state) => {
+                if state != VisualState::Disconnected {
+                    self.status.record_skipped();
+                }
+                self.enter_state(&mut capture.placeholder, &mut capture.output, state);
+                return;
+            },
+        };
+        capture.placeholder.enter(VisualState::Live, frame.dimensions);
+        let mut timer = Timer::new();
+        let output = capture.output.buffer();
+        output.encoding = frame.encoding;
+        output.premultiplied = frame.premultiplied;
+        let obscuring = capture.privacy.is_obscuring();
+        match frame.data {
+            FrameData::Texture(texture) if !obscuring => {
+                output.texture = Some(texture);
+            },
+            data => {
+                let mut image = match data {
+                    FrameData::Image(image) => image,
+                    FrameData::Texture(texture) => {
+                        if !texture.read(&mut capture.readback) {
+                            capture.readback.reset(pixel::Format::Rgba, frame.dimensions.0, frame.dimensions.1);
+                            capture.readback.clear();
+                        }
+                        capture.readback.as_image()
+                    },
+                };
+                if obscuring {
+                    if capture.privacy.effect() == PrivacyEffect::Image {
+                        output.encoding = Encoding::Srgb;
+                        output.premultiplied = false;
+                    }
+                    image = capture.privacy.obscure(&image, &mut capture.placeholder);
+                }
+                // OBS textures only take packed RGBA and BGRA
+                let format = match image.format {
+                    pixel::Format::Rgba | pixel::Format::Bgra => image.format,
+                    _ => pixel::Format::Rgba,
+                };
+                pixel::convert_into(&image, format, Default::default(), &mut output.image);
+                output.texture = None;
+            },
+        }
+        capture.output.publish();
+        self.status.record_stage(Stage::Convert, timer.checkpoint());
+        self.dimensions.store(frame.dimensions);
+        self.status.record_frame(frame.dimensions);
+    }
+
+    fn video_render(&self, _effect: *mut obs::sys::gs_effect_t) {
+        let mut render = self.render.lock().unwrap();
+        if render.frames.update() {
+            let mut timer = Timer::new();
+            render.upload(&self.status);
+            self.status.record_stage(Stage::Upload, timer.checkpoint());
+        }
+        render.draw();
+    }
+}
+
+impl<B: CaptureBackend> StatusProvider for SyncCaptureSource<B> {
+    fn status(&self) -> Option<Arc<SourceStatus>> {
+        Some(self.status.clone())
+    }
+}
@@ -0,0 +1,201 @@
+//! Process-wide sharing of captures, so sources capturing the same eye or overlay only fetch
+//! and convert each frame once
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        Arc,
+        Mutex,
+        MutexGuard,
+        Weak,
+    },
+};
+
+/// Capture state shared by every source using the same cache key
+#[derive(Debug)]
+pub struct SharedCapture<T> {
+    state: Mutex<SharedState<T>>,
+}
+
+#[derive(Debug)]
+struct SharedState<T> {
+    value: T,
+    /// Frame the value was last captured for
+    frame: Option<u64>,
+    /// Incremented every time a capture changes the content
+    generation: u64,
+}
+
+/// Locked access to a shared capture
+pub struct SharedGuard<'a, T> {
+    state: MutexGuard<'a, SharedState<T>>,
+}
+
+impl<'a, T> SharedGuard<'a, T> {
+    /// Generation of the current content. Consumers compare it with the last generation they
+    /// used to tell whether the content changed since.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.state.generation
+    }
+
+    #[inline]
+    pub fn get(&self) -> &T {
+        &self.state.value
+    }
+
+    /// Captures into the shared value with `capture`, unless that already happened for `frame`.
+    /// `capture` returns whether the content changed.
+    pub fn capture_once<E, F>(&mut self, frame: u64, capture: F) -> Result<(), E> where
+        F: FnOnce(&mut T) -> Result<bool, E>,
+    {
+        if self.state.frame == Some(frame) {
+            return Ok(());
+        }
+        let changed = capture(&mut self.state.value)?;
+        self.state.frame = Some(frame);
+        if changed {
+            self.state.generation += 1;
+        }
+        Ok(())
+    }
+
+    /// Makes the next `capture_once` run, whatever frame it is for
+    pub fn invalidate(&mut self) {
+        self.state.frame = None;
+    }
+}
+
+impl<T> SharedCapture<T> {
+    fn new(value: T) -> Self {
+        SharedCapture {
+            state: Mutex::new(SharedState {
+                value: value,
+                frame: None,
+                generation: 0,
+            }),
+        }
+    }
+
+    pub fn lock(&self) -> SharedGuard<'_, T> {
+        SharedGuard {
+            state: self.state.lock().unwrap(),
+        }
+    }
+}
+
+/// Reference counted captures by key. Entries live as long as some source holds the
+/// `Arc<SharedCapture>` returned for them.
+#[derive(Debug)]
+pub struct CaptureCache<K, T> {
+    entries: Mutex<Option<HashMap<K, Weak<SharedCapture<T>>>>>,
+}
+
+impl<K: Hash + Eq + Clone, T> CaptureCache<K, T> {
+    pub const fn new() -> Self {
+        CaptureCache {
+            entries: Mutex::new(None),
+        }
+    }
+
+    /// Returns the capture for `key`, creating it with `create` if no source is using it yet
+    pub fn get_or_try_insert_with<E, F>(&self, key: &K, create: F) -> Result<Arc<SharedCapture<T>>, E> where
+        F: FnOnce() -> Result<T, E>,
+    {
+        let mut entries = self.entries.lock().unwrap();
+        let entries = entries.get_or_insert_with(HashMap::new);
+        if let Some(existing) = entries.get(key).and_then(Weak::upgrade) {
+            return Ok(existing);
+        }
+        // Forget entries whose last user went away
+        entries.retain(|_, entry| entry.strong_count() > 0);
+        let ret = Arc::new(SharedCapture::new(create()?));
+        entries.insert(key.clone(), Arc::downgrade(&ret));
+        Ok(ret)
+    }
+
+    /// Number of keys with at least one user
+    pub fn len(&self) -> usize {
+        let entries = self.entries.lock().unwrap();
+        entries.iter()
+            .flat_map(HashMap::values)
+            .filter(|entry| entry.strong_count() > 0)
+            .count()
+    }
+}
+
+/// Current OBS video frame, for `SharedGuard::capture_once`
+#[inline]
+pub fn current_frame() -> u64 {
+    unsafe { obs::sys::obs_get_video_frame_time() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        cell::Cell,
+        convert::Infallible,
+        rc::Rc,
+    };
+
+    #[test]
+    fn same_key_shares_capture() {
+        let cache: CaptureCache<u32, u32> = CaptureCache::new();
+        let created = Cell::new(0);
+        let create = || {
+            created.set(created.get() + 1);
+            Ok::<_, Infallible>(0)
+        };
+        let a = cache.get_or_try_insert_with(&1, create).unwrap();
+        let b = cache.get_or_try_insert_with(&1, create).unwrap();
+        let _c = cache.get_or_try_insert_with(&2, create).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(created.get(), 2);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn released_with_last_user() {
+        let cache: CaptureCache<u32, Rc<()>> = CaptureCache::new();
+        let value = Rc::new(());
+        let a = cache.get_or_try_insert_with(&1, || Ok::<_, Infallible>(value.clone())).unwrap();
+        let b = cache.get_or_try_insert_with(&1, || Ok::<_, Infallible>(value.clone())).unwrap();
+        assert_eq!(Rc::strong_count(&value), 2);
+        drop(a);
+        assert_eq!(cache.len(), 1);
+        drop(b);
+        assert_eq!(Rc::strong_count(&value), 1);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn captures_once_per_frame() {
+        let cache: CaptureCache<u32, u32> = CaptureCache::new();
+        let shared = cache.get_or_try_insert_with(&1, || Ok::<_, Infallible>(0)).unwrap();
+        let capture = |v: &mut u32| {
+            *v += 1;
+            Ok::<_, Infallible>(true)
+        };
+        for _ in 0..3 {
+            shared.lock().capture_once(10, capture).unwrap();
+        }
+        assert_eq!(*shared.lock().get(), 1);
+        assert_eq!(shared.lock().generation(), 1);
+        shared.lock().capture_once(11, |_| Ok::<_, Infallible>(false)).unwrap();
+        assert_eq!(shared.lock().generation(), 1);
+        let mut guard = shared.lock();
+        guard.invalidate();
+        guard.capture_once(11, capture).unwrap();
+        assert_eq!(*guard.get(), 2);
+    }
+
+    #[test]
+    fn failed_create_is_not_cached() {
+        let cache: CaptureCache<u32, u32> = CaptureCache::new();
+        assert!(cache.get_or_try_insert_with(&1, || Err(())).is_err());
+        assert_eq!(cache.len(), 0);
+        assert!(cache.get_or_try_insert_with(&1, || Ok::<_, ()>(5)).is_ok());
+    }
+}
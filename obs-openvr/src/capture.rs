@@ -0,0 +1,411 @@
+use std::{
+    ffi::CStr,
+    sync::{
+        Arc,
+        RwLock,
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+    },
+};
+use obs::{
+    OwnedPointerContainer,
+    data::ObsData,
+    properties::{
+        Properties,
+        PropertiesExt,
+        PropertyDescription,
+        PropertyExt,
+    },
+    source::{
+        AsyncVideoSource,
+        OwnedSource,
+        VideoSource,
+    },
+};
+use crate::{
+    backend::{
+        self,
+        Delivery,
+        add_backend_properties,
+    },
+    profiles::{
+        self,
+        ProfileSource,
+    },
+    status::{
+        SourceStatus,
+        StatusProvider,
+        add_status_properties,
+    },
+};
+
+/// What the unified source captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureMode {
+    #[cfg(feature = "mirror-source")]
+    Mirror,
+    #[cfg(feature = "mirror-source")]
+    HeadsetView,
+    #[cfg(feature = "overlay-source")]
+    Overlay,
+}
+
+impl CaptureMode {
+    const ALL: &'static [CaptureMode] = &[
+        #[cfg(feature = "mirror-source")]
+        CaptureMode::Mirror,
+        #[cfg(feature = "mirror-source")]
+        CaptureMode::HeadsetView,
+        #[cfg(feature = "overlay-source")]
+        CaptureMode::Overlay,
+    ];
+
+    fn from_settings(data: &obs::sys::obs_data) -> Self {
+        let value = data.get_cstr(keys::MODE);
+        Self::ALL.iter()
+            .find(|mode| Some(mode.key()) == value)
+            .copied()
+            .unwrap_or(Self::ALL[0])
+    }
+
+    fn key(self) -> &'static CStr {
+        match self {
+            #[cfg(feature = "mirror-source")]
+            CaptureMode::Mirror => keys::MODE_MIRROR,
+            #[cfg(feature = "mirror-source")]
+            CaptureMode::HeadsetView => keys::MODE_HEADSET_VIEW,
+            #[cfg(feature = "overlay-source")]
+            CaptureMode::Overlay => keys::MODE_OVERLAY,
+        }
+    }
+
+    fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            #[cfg(feature = "mirror-source")]
+            CaptureMode::Mirror => b"Eye mirror\0",
+            #[cfg(feature = "mirror-source")]
+            CaptureMode::HeadsetView => b"Headset view\0",
+            #[cfg(feature = "overlay-source")]
+            CaptureMode::Overlay => b"Single overlay\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+
+    /// Name of the property group holding this mode's settings
+    fn group(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            #[cfg(feature = "mirror-source")]
+            CaptureMode::Mirror => b"mirror_group\0",
+            #[cfg(feature = "mirror-source")]
+            CaptureMode::HeadsetView => b"headset_view_group\0",
+            #[cfg(feature = "overlay-source")]
+            CaptureMode::Overlay => b"overlay_group\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+
+    /// Adds the properties only this mode has. The ones modes share are added once, outside of
+    /// the modes' groups, since OBS refuses groups repeating a property name.
+    fn add_properties(self, props: &mut Properties) {
+        match self {
+            #[cfg(feature = "mirror-source")]
+            CaptureMode::Mirror => add_backend_properties::<crate::mirror::MirrorBackend>(props),
+            #[cfg(feature = "mirror-source")]
+            CaptureMode::HeadsetView => crate::mirror::add_headset_view_properties(props),
+            #[cfg(feature = "overlay-source")]
+            CaptureMode::Overlay => add_backend_properties::<crate::overlay::OverlayBackend>(props),
+        }
+    }
+
+    /// Whether this mode is captured by a `CaptureBackend`, which shows placeholders, obscures
+    /// captures in privacy mode and can capture in the capture host
+    fn uses_backend(self) -> bool {
+        match self {
+            #[cfg(feature = "mirror-source")]
+            CaptureMode::HeadsetView => false,
+            _ => true,
+        }
+    }
+
+    #[inline]
+    fn supports_async(self) -> bool {
+        self.source_id(Delivery::Async).is_some()
+    }
+
+    /// ID of the source type implementing this mode with the given delivery, if there is one
+    fn source_id(self, delivery: Delivery) -> Option<&'static CStr> {
+        match (self, delivery) {
+            #[cfg(feature = "mirror-source")]
+            (CaptureMode::Mirror, Delivery::RenderThread) => Some(video_id::<crate::mirror::OpenVRMirrorSource>()),
+            #[cfg(feature = "mirror-source")]
+            (CaptureMode::Mirror, Delivery::Async) => Some(crate::mirror::OpenVRAsyncMirrorSource::ID),
+            #[cfg(feature = "mirror-source")]
+            (CaptureMode::HeadsetView, Delivery::RenderThread) => Some(video_id::<crate::mirror::OpenVRHeadsetViewSource>()),
+            #[cfg(feature = "mirror-source")]
+            (CaptureMode::HeadsetView, Delivery::Async) => None,
+            #[cfg(feature = "overlay-source")]
+            (CaptureMode::Overlay, Delivery::RenderThread) => Some(video_id::<crate::overlay::OpenVROverlaySource>()),
+            #[cfg(feature = "overlay-source")]
+            (CaptureMode::Overlay, Delivery::Async) => Some(crate::overlay::OpenVRAsyncOverlaySource::ID),
+        }
+    }
+}
+
+#[inline]
+fn video_id<S: VideoSource>() -> &'static CStr {
+    CStr::from_bytes_with_nul(S::ID).unwrap()
+}
+
+/// Status of a child source, if its type (as identified by its ID) is `S`
+unsafe fn status_if<S: StatusProvider>(child: &OwnedSource, id: &CStr) -> Option<Option<Arc<SourceStatus>>> {
+    if child.id() != id {
+        return None;
+    }
+    Some((child.data() as *const S).as_ref().and_then(StatusProvider::status))
+}
+
+fn child_status(child: &OwnedSource) -> Option<Arc<SourceStatus>> {
+    let candidates: &[unsafe fn(&OwnedSource) -> Option<Option<Arc<SourceStatus>>>] = &[
+        #[cfg(feature = "mirror-source")]
+        |child| status_if::<crate::mirror::OpenVRMirrorSource>(child, video_id::<crate::mirror::OpenVRMirrorSource>()),
+        #[cfg(feature = "mirror-source")]
+        |child| status_if::<crate::mirror::OpenVRAsyncMirrorSource>(child, crate::mirror::OpenVRAsyncMirrorSource::ID),
+        #[cfg(feature = "mirror-source")]
+        |child| status_if::<crate::mirror::OpenVRHeadsetViewSource>(child, video_id::<crate::mirror::OpenVRHeadsetViewSource>()),
+        #[cfg(feature = "overlay-source")]
+        |child| status_if::<crate::overlay::OpenVROverlaySource>(child, video_id::<crate::overlay::OpenVROverlaySource>()),
+        #[cfg(feature = "overlay-source")]
+        |child| status_if::<crate::overlay::OpenVRAsyncOverlaySource>(child, crate::overlay::OpenVRAsyncOverlaySource::ID),
+    ];
+    candidates.iter()
+        .find_map(|f| unsafe { f(child) })
+        .flatten()
+}
+
+/// Picks the source type for `mode` and `delivery`, falling back to render-thread delivery for modes
+/// with no async implementation
+fn child_id(mode: CaptureMode, delivery: Delivery) -> &'static CStr {
+    mode.source_id(delivery)
+        .or_else(|| {
+            warn!("{:?} capture has no {:?} delivery, capturing on the render thread instead", mode, delivery);
+            mode.source_id(Delivery::RenderThread)
+        })
+        .unwrap()
+}
+
+/// Single "OpenVR Capture" source, which selects capture mode and delivery in its properties and
+/// forwards everything to a private source of the matching (legacy) type
+pub struct OpenVRCaptureSource {
+    handle: *mut obs::sys::obs_source_t,
+    child: RwLock<Option<OwnedSource>>,
+    showing: AtomicBool,
+}
+
+impl OpenVRCaptureSource {
+    const CHILD_NAME: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"obs-openvr-capture-child\0")
+    };
+
+    fn replace_child(&self, id: &CStr, data: &obs::sys::obs_data) {
+        let mut child = self.child.write().unwrap();
+        if let Some(current) = child.as_ref() {
+            if current.id() == id {
+                current.update(data);
+                return;
+            }
+            if self.showing.load(Ordering::Relaxed) {
+                current.dec_showing();
+            }
+        }
+        trace!("OpenVRCaptureSource: creating child source of type {:?}", id);
+        // Give the child its own copy of the settings, so updates can be applied on top of them
+        let settings = obs::Data::new().map(|mut settings| {
+            settings.apply(data);
+            settings
+        });
+        *child = OwnedSource::create_private(id, Self::CHILD_NAME, settings.as_deref());
+        match child.as_ref() {
+            Some(new_child) if self.showing.load(Ordering::Relaxed) => new_child.inc_showing(),
+            Some(..) => {},
+            None => error!("Error creating child source of type {:?}", id),
+        }
+    }
+}
+
+unsafe extern "C" fn mode_modified(props: *mut obs::sys::obs_properties_t, _property: *mut obs::sys::obs_property_t, settings: *mut obs::sys::obs_data_t) -> bool {
+    let settings = match settings.as_ref() {
+        Some(v) => v,
+        None => return false,
+    };
+    let mode = CaptureMode::from_settings(settings);
+    obs::properties::with_raw(props, |props| {
+        CaptureMode::ALL.iter().for_each(|&m| {
+            if let Some(group) = props.get(m.group()) {
+                group.set_visible(m == mode);
+            }
+        });
+        for &key in [backend::keys::DELIVERY, backend::keys::PACING, crate::frame::keys::OUTPUT_FORMAT].iter() {
+            if let Some(property) = props.get(key) {
+                property.set_visible(mode.supports_async());
+            }
+        }
+        for &key in [crate::remote::keys::CAPTURE_HOST, backend::placeholder::keys::GROUP, backend::privacy::keys::GROUP].iter() {
+            if let Some(property) = props.get(key) {
+                property.set_visible(mode.uses_backend());
+            }
+        }
+    });
+    true
+}
+
+impl VideoSource for OpenVRCaptureSource {
+    const ID: &'static [u8] = b"obs-openvr-capture\0";
+    const OUTPUT_FLAGS: Option<u32> = Some(obs::sys::OBS_SOURCE_SRGB);
+
+    fn create(settings: &mut obs::sys::obs_data, source: *mut obs::sys::obs_source_t) -> Self {
+        let ret = OpenVRCaptureSource {
+            handle: source,
+            child: RwLock::new(None),
+            showing: AtomicBool::new(false),
+        };
+        profiles::register(source);
+        ret.update(settings);
+        ret
+    }
+
+    fn get_name() -> &'static CStr {
+        unsafe { CStr::from_bytes_with_nul_unchecked(b"OpenVR Capture\0") }
+    }
+
+    fn get_defaults(settings: &mut obs::sys::obs_data) {
+        settings.set_default_string(keys::MODE, CaptureMode::ALL[0].key());
+        settings.set_default_string(backend::keys::DELIVERY, backend::keys::DELIVERY_RENDER_THREAD);
+        backend::placeholder::set_defaults(settings);
+        backend::privacy::set_defaults(settings);
+        profiles::set_defaults(settings);
+        #[cfg(feature = "mirror-source")]
+        <crate::mirror::OpenVRHeadsetViewSource as VideoSource>::get_defaults(settings);
+        #[cfg(feature = "overlay-source")]
+        <crate::overlay::OpenVROverlaySource as VideoSource>::get_defaults(settings);
+    }
+
+    fn get_dimensions(&self) -> (u32, u32) {
+        let child = self.child.read().unwrap();
+        child.as_ref()
+            .map(OwnedSource::get_dimensions)
+            .unwrap_or((0, 0))
+    }
+
+    fn get_properties(&self) -> *mut obs::sys::obs_properties {
+        let mut props = Properties::new();
+
+        let mode_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Capture mode\0") };
+        {
+            let mut modes = props.add_string_list(PropertyDescription::new(keys::MODE, Some(mode_description)), false);
+            CaptureMode::ALL.iter().for_each(|&mode| {
+                modes.add_string(mode.description(), mode.key());
+            });
+        }
+        if let Some(mode) = props.get(keys::MODE) {
+            mode.set_modified_callback(mode_modified);
+        }
+
+        let delivery_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Delivery\0") };
+        let render_thread_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Render thread\0") };
+        let async_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Async (worker thread)\0") };
+        props.add_string_list_complete(PropertyDescription::new(backend::keys::DELIVERY, Some(delivery_description)), [
+            (render_thread_description, backend::keys::DELIVERY_RENDER_THREAD),
+            (async_description, backend::keys::DELIVERY_ASYNC),
+        ].iter().map(|&v| v));
+        backend::add_common_properties(&mut props);
+        backend::add_async_properties(&mut props);
+
+        CaptureMode::ALL.iter().for_each(|&mode| {
+            let mut group = Properties::new();
+            mode.add_properties(&mut group);
+            if props.add_group(mode.group(), mode.description(), group).is_none() {
+                error!("Error adding the properties of {:?} capture", mode);
+            }
+        });
+        backend::placeholder::add_placeholder_properties(&mut props);
+        backend::privacy::add_privacy_properties(&mut props);
+        profiles::add_profile_properties(&mut props, self);
+
+        add_status_properties(&mut props, self);
+
+        unsafe { props.leak() }
+    }
+
+    fn update(&self, data: &obs::sys::obs_data) {
+        let mode = CaptureMode::from_settings(data);
+        let delivery = Delivery::from_settings(data);
+        trace!("OpenVRCaptureSource::update: {:?} ({:?})", mode, delivery);
+        self.replace_child(child_id(mode, delivery), data);
+        profiles::set_enabled(self.handle, data.get_bool(profiles::keys::ENABLED));
+    }
+
+    fn show(&self) {
+        self.showing.store(true, Ordering::Relaxed);
+        let child = self.child.read().unwrap();
+        child.iter().for_each(OwnedSource::inc_showing);
+    }
+
+    fn hide(&self) {
+        self.showing.store(false, Ordering::Relaxed);
+        let child = self.child.read().unwrap();
+        child.iter().for_each(OwnedSource::dec_showing);
+    }
+
+    fn video_tick(&self, _seconds: f32) {
+    }
+
+    fn video_render(&self, _effect: *mut obs::sys::gs_effect_t) {
+        let child = self.child.read().unwrap();
+        child.iter().for_each(OwnedSource::video_render);
+    }
+}
+
+impl StatusProvider for OpenVRCaptureSource {
+    fn status(&self) -> Option<Arc<SourceStatus>> {
+        let child = self.child.read().unwrap();
+        child.as_ref().and_then(child_status)
+    }
+}
+
+impl ProfileSource for OpenVRCaptureSource {
+    fn source_handle(&self) -> *mut obs::sys::obs_source_t {
+        self.handle
+    }
+}
+
+impl Drop for OpenVRCaptureSource {
+    fn drop(&mut self) {
+        profiles::unregister(self.handle);
+        let child = self.child.get_mut().unwrap();
+        if self.showing.load(Ordering::Relaxed) {
+            child.iter().for_each(OwnedSource::dec_showing);
+        }
+    }
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const MODE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"capture_mode\0")
+    };
+    #[cfg(feature = "mirror-source")]
+    pub const MODE_MIRROR: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"mirror\0")
+    };
+    #[cfg(feature = "mirror-source")]
+    pub const MODE_HEADSET_VIEW: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"headset_view\0")
+    };
+    #[cfg(feature = "overlay-source")]
+    pub const MODE_OVERLAY: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"overlay\0")
+    };
+}
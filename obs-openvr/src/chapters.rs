@@ -0,0 +1,187 @@
+//! Chapter markers in the OBS recording when the VR app changes, the dashboard opens or a SteamVR
+//! screenshot is taken, and a CSV file next to the recording listing every `VrEvent` with its
+//! position in the recording
+
+use std::{
+    borrow::Cow,
+    ffi::{
+        CStr,
+        CString,
+    },
+    fs::File,
+    io::Write,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use crate::{
+    recording::{
+        self,
+        Recording,
+    },
+    vr_events::VrEvent,
+};
+
+/// Replaces the recording's extension in the name of the event file
+const EVENTS_SUFFIX: &'static str = ".vr-events.csv";
+const EVENTS_HEADER: &'static str = "seconds,timecode,event,detail\n";
+
+/// Name to show for an application: its display name if SteamVR knows it, otherwise its key
+fn app_name(app: &CStr) -> CString {
+    openvr::applications::application_name(app)
+        .unwrap_or_else(|| app.to_owned())
+}
+
+/// Name of the chapter an event starts, if it starts one. Closing the dashboard goes back to the
+/// app, if there is one.
+fn chapter_name(event: &VrEvent, current_app: Option<&CStr>) -> Option<CString> {
+    match event {
+        VrEvent::AppStarted(app) => Some(app_name(app)),
+        VrEvent::DashboardClosed => current_app.map(app_name),
+        VrEvent::DashboardOpened => CString::new("SteamVR Dashboard").ok(),
+        VrEvent::ScreenshotTaken => CString::new("Screenshot").ok(),
+        _ => None,
+    }
+}
+
+/// `hh:mm:ss.mmm`, as video editors show positions
+fn timecode(position: Duration) -> String {
+    let millis = position.as_millis();
+    format!("{:02}:{:02}:{:02}.{:03}", millis / 3_600_000, (millis / 60_000) % 60, (millis / 1000) % 60, millis % 1000)
+}
+
+/// Quotes a CSV field, if it needs it
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+fn csv_row(position: Duration, event: &str, detail: &str) -> String {
+    format!("{:.3},{},{},{}\n", position.as_secs_f64(), timecode(position), csv_field(event), csv_field(detail))
+}
+
+/// Event file of a recording
+struct EventFile {
+    recording: u64,
+    /// `None` if the recording has no path, or the file couldn't be created
+    file: Option<File>,
+}
+
+impl EventFile {
+    fn create(recording: &Recording) -> Self {
+        let file = recording.sidecar_path(EVENTS_SUFFIX).and_then(|path| {
+            let file = File::create(&path)
+                .and_then(|mut file| file.write_all(EVENTS_HEADER.as_bytes()).map(|_| file));
+            match file {
+                Ok(file) => {
+                    info!("writing VR events to {}", path.display());
+                    Some(file)
+                },
+                Err(e) => {
+                    warn!("Error creating VR event file {}: {}", path.display(), &e);
+                    None
+                },
+            }
+        });
+        EventFile {
+            recording: recording.id,
+            file: file,
+        }
+    }
+
+    fn write_row(&mut self, position: Duration, event: &str, detail: &str) {
+        let result = match self.file.as_mut() {
+            Some(file) => file.write_all(csv_row(position, event, detail).as_bytes()),
+            None => return,
+        };
+        if let Err(e) = result {
+            warn!("Error writing VR event, no longer writing this recording's events: {}", &e);
+            self.file = None;
+        }
+    }
+}
+
+/// Marks `VrEvent`s in the recording on the event thread
+pub struct ChapterMonitor {
+    events: Option<EventFile>,
+}
+
+impl ChapterMonitor {
+    pub fn new() -> Self {
+        ChapterMonitor {
+            events: None,
+        }
+    }
+
+    /// Follows the recording starting or stopping. Returns the recording in progress.
+    fn sync(&mut self) -> Option<Recording> {
+        let recording = match recording::current() {
+            Some(v) => v,
+            None => {
+                self.events = None;
+                return None;
+            },
+        };
+        if self.events.as_ref().map_or(true, |events| events.recording != recording.id) {
+            let mut events = EventFile::create(&recording);
+            // Start with whatever is running, so every part of the recording is in a chapter
+            let app = crate::profiles::current_app();
+            let position = recording.elapsed(unsafe { obs::sys::os_gettime_ns() });
+            events.write_row(position, "recording_started", &app.as_ref().map_or(Cow::Borrowed(""), |app| app.to_string_lossy()));
+            if let Some(app) = app.as_ref() {
+                add_chapter(&app_name(app));
+            }
+            self.events = Some(events);
+        }
+        Some(recording)
+    }
+
+    pub fn handle_event(&mut self, event: &VrEvent) {
+        let recording = match self.sync() {
+            Some(v) => v,
+            None => return,
+        };
+        let position = recording.elapsed(unsafe { obs::sys::os_gettime_ns() });
+        if let Some(events) = self.events.as_mut() {
+            events.write_row(position, event.name(), &event.detail());
+        }
+        let app = crate::profiles::current_app();
+        if let Some(name) = chapter_name(event, app.as_ref().map(|app| app.as_c_str())) {
+            add_chapter(&name);
+        }
+    }
+
+    pub fn tick(&mut self, _now: Instant) {
+        self.sync();
+    }
+}
+
+fn add_chapter(name: &CStr) {
+    if obs::frontend::recording_add_chapter(name) {
+        debug!("added chapter {:?}", name);
+    } else {
+        // Most likely a recording format without chapters, the event file still has them
+        trace!("couldn't add chapter {:?}", name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timecodes() {
+        assert_eq!(timecode(Duration::from_millis(0)), "00:00:00.000");
+        assert_eq!(timecode(Duration::from_millis(3_723_045)), "01:02:03.045");
+    }
+
+    #[test]
+    fn rows_quote_fields() {
+        assert_eq!(csv_row(Duration::from_millis(1500), "app_started", "steam.app.620"), "1.500,00:00:01.500,app_started,steam.app.620\n");
+        assert_eq!(csv_row(Duration::from_secs(2), "app_started", "a,\"b\""), "2.000,00:00:02.000,app_started,\"a,\"\"b\"\"\"\n");
+    }
+}
@@ -0,0 +1,99 @@
+use std::ffi::CStr;
+use obs::data::ObsData;
+use crate::pixel;
+
+/// Color space setting of a source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// Use what the captured texture declares, falling back to sRGB
+    Auto,
+    Srgb,
+    Linear,
+}
+
+/// Transfer function captured pixel values are encoded with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    pub const ALL: [ColorSpace; 3] = [ColorSpace::Auto, ColorSpace::Srgb, ColorSpace::Linear];
+
+    pub fn from_settings<D: ObsData>(data: &D) -> Self {
+        let value = data.get_cstr(keys::COLOR_SPACE);
+        Self::ALL.iter()
+            .find(|space| Some(space.key()) == value)
+            .copied()
+            .unwrap_or(ColorSpace::Auto)
+    }
+
+    pub fn key(self) -> &'static CStr {
+        match self {
+            ColorSpace::Auto => keys::COLOR_SPACE_AUTO,
+            ColorSpace::Srgb => keys::COLOR_SPACE_SRGB,
+            ColorSpace::Linear => keys::COLOR_SPACE_LINEAR,
+        }
+    }
+
+    pub fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            ColorSpace::Auto => b"Auto\0",
+            ColorSpace::Srgb => b"sRGB\0",
+            ColorSpace::Linear => b"Linear\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+
+    /// Encoding of captured pixels, given what could be detected from the captured texture
+    pub fn resolve(self, detected: Option<Encoding>) -> Encoding {
+        match self {
+            ColorSpace::Auto => detected.unwrap_or(Encoding::Srgb),
+            ColorSpace::Srgb => Encoding::Srgb,
+            ColorSpace::Linear => Encoding::Linear,
+        }
+    }
+}
+
+impl Encoding {
+    /// OBS texture format for packed pixels with this encoding. sRGB formats are sampled through
+    /// an sRGB view by sources with `OBS_SOURCE_SRGB`, while `UNORM` formats are sampled as-is,
+    /// which is what linear data needs.
+    pub fn gs_color_format(self, format: pixel::Format) -> Option<obs::sys::gs_color_format> {
+        match (format, self) {
+            (pixel::Format::Rgba, Encoding::Srgb) => Some(obs::sys::gs_color_format_GS_RGBA),
+            (pixel::Format::Rgba, Encoding::Linear) => Some(obs::sys::gs_color_format_GS_RGBA_UNORM),
+            (pixel::Format::Bgra, Encoding::Srgb) => Some(obs::sys::gs_color_format_GS_BGRA),
+            (pixel::Format::Bgra, Encoding::Linear) => Some(obs::sys::gs_color_format_GS_BGRA_UNORM),
+            _ => None,
+        }
+    }
+}
+
+pub fn add_color_space_property(props: &mut obs::Properties) {
+    use obs::properties::{
+        PropertiesExt,
+        PropertyDescription,
+    };
+
+    let description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Color space\0") };
+    props.add_string_list_complete(PropertyDescription::new(keys::COLOR_SPACE, Some(description)), ColorSpace::ALL.iter().map(|space| (space.description(), space.key())));
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const COLOR_SPACE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"color_space\0")
+    };
+    pub const COLOR_SPACE_AUTO: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"auto\0")
+    };
+    pub const COLOR_SPACE_SRGB: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"srgb\0")
+    };
+    pub const COLOR_SPACE_LINEAR: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"linear\0")
+    };
+}
@@ -0,0 +1,80 @@
+//! Plugin-wide settings, kept in JSON files in the module's config directory and reloaded when
+//! they change
+
+use std::{
+    ffi::{
+        CStr,
+        CString,
+    },
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::{
+        Duration,
+        Instant,
+        SystemTime,
+    },
+};
+
+/// How often files are checked for changes
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A config file in the module's config directory
+pub struct ConfigFile {
+    path: Option<PathBuf>,
+    /// Written to the file if it doesn't exist, so users can see what they can set
+    template: &'static str,
+    set_defaults: fn(&mut obs::sys::obs_data),
+    modified: Option<SystemTime>,
+    checked: Option<Instant>,
+}
+
+impl ConfigFile {
+    pub fn new(name: &CStr, template: &'static str, set_defaults: fn(&mut obs::sys::obs_data)) -> Self {
+        ConfigFile {
+            path: crate::module::config_path(name),
+            template: template,
+            set_defaults: set_defaults,
+            modified: None,
+            checked: None,
+        }
+    }
+
+    #[inline]
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(|p| p.as_path())
+    }
+
+    /// Loads the file if it changed since it was last loaded, checking at most every second.
+    /// Writes the template first if there is no file.
+    pub fn reload(&mut self) -> Option<obs::Data> {
+        let now = Instant::now();
+        if self.checked.map_or(false, |t| now.duration_since(t) < CHECK_INTERVAL) {
+            return None;
+        }
+        self.checked = Some(now);
+        let path = self.path.as_ref()?;
+        if !path.exists() {
+            match fs::write(path, self.template) {
+                Ok(()) => info!("Wrote default config to {}", path.display()),
+                Err(e) => warn!("Error writing default config to {}: {}", path.display(), &e),
+            }
+        }
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        if modified.is_some() && modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        let mut data = CString::new(path.as_os_str().as_bytes()).ok()
+            .and_then(|path| obs::Data::from_json_file(&path))
+            .or_else(|| {
+                warn!("Error loading config from {}, using defaults", path.display());
+                obs::Data::new()
+            })?;
+        (self.set_defaults)(&mut data);
+        Some(data)
+    }
+}
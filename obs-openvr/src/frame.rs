@@ -0,0 +1,154 @@
+use std::{
+    ffi::CStr,
+    ptr,
+};
+use obs::data::ObsData;
+use crate::{
+    pixel::{
+        self,
+        ImageRef,
+    },
+    source_handle::SourceHandle,
+};
+
+/// Pixel format async sources hand their frames to OBS in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Whatever the capture produces, converted to RGBA only if OBS can't take it directly
+    Native,
+    Rgba,
+    Bgra,
+    Nv12,
+    I420,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 5] = [OutputFormat::Native, OutputFormat::Rgba, OutputFormat::Bgra, OutputFormat::Nv12, OutputFormat::I420];
+
+    pub fn from_settings(data: &obs::sys::obs_data) -> Self {
+        let value = data.get_cstr(keys::OUTPUT_FORMAT);
+        Self::ALL.iter()
+            .find(|format| Some(format.key()) == value)
+            .copied()
+            .unwrap_or(OutputFormat::Native)
+    }
+
+    pub fn key(self) -> &'static CStr {
+        match self {
+            OutputFormat::Native => keys::OUTPUT_FORMAT_NATIVE,
+            OutputFormat::Rgba => keys::OUTPUT_FORMAT_RGBA,
+            OutputFormat::Bgra => keys::OUTPUT_FORMAT_BGRA,
+            OutputFormat::Nv12 => keys::OUTPUT_FORMAT_NV12,
+            OutputFormat::I420 => keys::OUTPUT_FORMAT_I420,
+        }
+    }
+
+    pub fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            OutputFormat::Native => b"Native (no conversion)\0",
+            OutputFormat::Rgba => b"RGBA\0",
+            OutputFormat::Bgra => b"BGRA\0",
+            OutputFormat::Nv12 => b"NV12 (no alpha)\0",
+            OutputFormat::I420 => b"I420 (no alpha)\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+
+    /// Format frames captured in `native` format should be converted to before output
+    pub fn target(self, native: pixel::Format) -> pixel::Format {
+        match self {
+            OutputFormat::Native if video_format(native).is_some() => native,
+            OutputFormat::Native | OutputFormat::Rgba => pixel::Format::Rgba,
+            OutputFormat::Bgra => pixel::Format::Bgra,
+            OutputFormat::Nv12 => pixel::Format::Nv12,
+            OutputFormat::I420 => pixel::Format::I420,
+        }
+    }
+}
+
+/// OBS async video format matching a pixel format, if OBS supports it
+pub fn video_format(format: pixel::Format) -> Option<obs::sys::video_format> {
+    match format {
+        pixel::Format::Rgb => None,
+        pixel::Format::Rgba => Some(obs::sys::video_format_VIDEO_FORMAT_RGBA),
+        pixel::Format::Bgra => Some(obs::sys::video_format_VIDEO_FORMAT_BGRA),
+        pixel::Format::Nv12 => Some(obs::sys::video_format_VIDEO_FORMAT_NV12),
+        pixel::Format::I420 => Some(obs::sys::video_format_VIDEO_FORMAT_I420),
+    }
+}
+
+/// Row-major 4x4 identity, the color matrix of full range RGB frames
+const IDENTITY_MATRIX: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+];
+
+/// Hands `image` to OBS as an async frame of `source`. `image`'s format must be supported by OBS
+/// (see `video_format`), and its colors must be sRGB-encoded.
+pub unsafe fn output_frame(source: SourceHandle, image: &ImageRef<'_>, timestamp: u64) {
+    let format = video_format(image.format)
+        .expect("unsupported async frame format");
+    let mut data: [*mut u8; 8] = [ptr::null_mut(); 8];
+    let mut linesize: [u32; 8] = [0; 8];
+    (0..image.format.plane_count()).for_each(|idx| {
+        let (plane_data, _, stride) = image.plane(idx);
+        data[idx] = plane_data.as_ptr() as *mut _;
+        linesize[idx] = stride as u32;
+    });
+    let mut frame = obs::sys::obs_source_frame2 {
+        data: data,
+        linesize: linesize,
+        width: image.width,
+        height: image.height,
+        timestamp: timestamp,
+        format: format,
+        range: obs::sys::video_range_type_VIDEO_RANGE_FULL,
+        color_matrix: IDENTITY_MATRIX,
+        color_range_min: [0.0; 3],
+        color_range_max: [1.0; 3],
+        flip: false,
+        flags: 0,
+        trc: obs::sys::video_trc_VIDEO_TRC_SRGB
+    };
+    if image.format.is_yuv() {
+        // Matches `pixel::YuvParams::default()`
+        frame.range = obs::sys::video_range_type_VIDEO_RANGE_PARTIAL;
+        obs::sys::video_format_get_parameters(obs::sys::video_colorspace_VIDEO_CS_709, frame.range, frame.color_matrix.as_mut_ptr(), frame.color_range_min.as_mut_ptr(), frame.color_range_max.as_mut_ptr());
+    }
+    source.output_video(&frame);
+}
+
+pub fn add_output_format_property(props: &mut obs::Properties) {
+    use obs::properties::{
+        PropertiesExt,
+        PropertyDescription,
+    };
+
+    let description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Output format\0") };
+    props.add_string_list_complete(PropertyDescription::new(keys::OUTPUT_FORMAT, Some(description)), OutputFormat::ALL.iter().map(|format| (format.description(), format.key())));
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const OUTPUT_FORMAT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"output_format\0")
+    };
+    pub const OUTPUT_FORMAT_NATIVE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"native\0")
+    };
+    pub const OUTPUT_FORMAT_RGBA: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"rgba\0")
+    };
+    pub const OUTPUT_FORMAT_BGRA: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"bgra\0")
+    };
+    pub const OUTPUT_FORMAT_NV12: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"nv12\0")
+    };
+    pub const OUTPUT_FORMAT_I420: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"i420\0")
+    };
+}
@@ -6,6 +6,8 @@ extern crate image;
 extern crate env_logger;
 extern crate thiserror;
 extern crate mcoffin_option_ext;
+extern crate obs_openvr_ipc;
+extern crate obs_openvr_publish;
 
 pub use obs::sys as obs_sys;
 
@@ -14,17 +16,55 @@ pub mod module;
 #[cfg(feature = "mirror-source")]
 pub(crate) mod native_utils;
 pub(crate) mod timing;
+pub(crate) mod sync;
+pub(crate) mod metrics;
 pub(crate) mod thread_utils;
+pub(crate) mod source_handle;
+pub(crate) mod status;
+pub(crate) mod chapters;
+pub(crate) mod config;
+pub(crate) mod presence;
+pub(crate) mod profiles;
+pub(crate) mod publish;
+pub(crate) mod recording;
+pub(crate) mod rules;
+pub(crate) mod tracking;
+pub(crate) mod vr_events;
+pub mod pixel;
+pub mod pose_transform;
+#[cfg(any(feature = "mirror-source", feature = "overlay-source"))]
+pub(crate) mod frame;
+#[cfg(any(feature = "mirror-source", feature = "overlay-source"))]
+pub(crate) mod color;
+#[cfg(any(feature = "mirror-source", feature = "overlay-source"))]
+pub(crate) mod cache;
+#[cfg(any(feature = "mirror-source", feature = "overlay-source"))]
+pub(crate) mod backend;
+#[cfg(any(feature = "mirror-source", feature = "overlay-source"))]
+pub(crate) mod remote;
 #[cfg(feature = "overlay-source")]
 pub mod overlay;
 #[cfg(feature = "mirror-source")]
 pub mod mirror;
+#[cfg(any(feature = "mirror-source", feature = "overlay-source"))]
+pub mod capture;
 
 pub use openvr::sys as openvr_sys;
 
 use std::{
     borrow::Cow,
-    sync::RwLock,
+    sync::{
+        RwLock,
+        RwLockReadGuard,
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+    },
+    time::{
+        Duration,
+        Instant,
+    },
 };
 
 #[derive(Debug, Clone, Copy, thiserror::Error)]
@@ -37,6 +77,10 @@ pub enum ObsOpenVRError {
     OpenVRInitNoError,
     #[error("OpenVR was not actually shut down on obs_module_unload")]
     OpenVRShutdown,
+    #[error("SteamVR is quitting")]
+    SteamVRQuitting,
+    #[error("SteamVR quit")]
+    SteamVRQuit,
 }
 
 struct ObsOpenVRModule {}
@@ -44,7 +88,14 @@ struct ObsOpenVRModule {}
 impl ObsOpenVRModule {
     fn unload_internal() -> Result<(), <Self as obs::ObsModule>::UnloadErr> {
         trace!("unloading");
-        if !openvr::shutdown() {
+        #[cfg(any(feature = "mirror-source", feature = "overlay-source"))]
+        remote::shutdown();
+        vr_events::shutdown();
+        publish::shutdown();
+        recording::shutdown();
+        // Nothing may have needed OpenVR in OBS
+        let initialized = matches!(openvr_init_status(), Ok(()) | Err(ObsOpenVRError::SteamVRQuitting));
+        if initialized && !openvr::shutdown() {
             return Err(ObsOpenVRError::OpenVRShutdown);
         }
         trace!("unloaded");
@@ -54,28 +105,116 @@ impl ObsOpenVRModule {
 
 static OPENVR_INIT_RESULT: RwLock<Result<(), ObsOpenVRError>> = RwLock::new(Err(ObsOpenVRError::InitNotAttempted));
 pub fn init_openvr() -> Result<(), ObsOpenVRError> {
+    // Nothing starts a new session until the one SteamVR quit is released
+    let settled = |result: &Result<(), ObsOpenVRError>| matches!(result, Ok(()) | Err(ObsOpenVRError::SteamVRQuitting));
     {
         let init_result = OPENVR_INIT_RESULT.read().unwrap();
-        if init_result.is_ok() {
-            return Ok(());
+        if settled(&init_result) {
+            return *init_result;
         }
     }
     let mut init_result = OPENVR_INIT_RESULT.write().unwrap();
-    if init_result.is_ok() {
-        return Ok(());
+    if settled(&init_result) {
+        return *init_result;
     }
-    *init_result = {
-        let vr_initialized = openvr::init(openvr_sys::EVRApplicationType::EVRApplicationType_VRApplication_Background)
-            .map(|result| result.value())
-            .map_err(ObsOpenVRError::OpenVRInit)?;
-        if !vr_initialized {
-            return Err(ObsOpenVRError::OpenVRInitNoError);
-        }
-        Ok(())
+    // Failures are stored too, so `openvr_init_status` reports them
+    *init_result = match openvr::init(openvr_sys::EVRApplicationType::EVRApplicationType_VRApplication_Background) {
+        Ok(result) if result.value() => Ok(()),
+        Ok(_) => Err(ObsOpenVRError::OpenVRInitNoError),
+        Err(e) => Err(ObsOpenVRError::OpenVRInit(e)),
     };
+    if init_result.is_ok() {
+        OPENVR_SESSION.fetch_add(1, Ordering::Relaxed);
+    }
     *init_result
 }
 
+/// Number of OpenVR sessions started so far, which identifies the current one. Only changed while
+/// `OPENVR_INIT_RESULT` is locked for writing.
+static OPENVR_SESSION: AtomicU64 = AtomicU64::new(0);
+
+/// A hold on the current OpenVR session, which keeps `release_openvr` from shutting it down while
+/// it's held. Must not be held while initializing OpenVR, or taken again on the same thread.
+pub struct OpenVRSession {
+    _init_result: RwLockReadGuard<'static, Result<(), ObsOpenVRError>>,
+    id: u64,
+}
+
+impl OpenVRSession {
+    /// Identifies the session, so whatever was created in it can tell when it was replaced
+    #[inline]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+/// The current OpenVR session, unless OpenVR isn't initialized or SteamVR is quitting. Doesn't
+/// try to initialize OpenVR.
+pub fn openvr_session() -> Option<OpenVRSession> {
+    let init_result = OPENVR_INIT_RESULT.read().unwrap();
+    if init_result.is_err() {
+        return None;
+    }
+    Some(OpenVRSession {
+        id: OPENVR_SESSION.load(Ordering::Relaxed),
+        _init_result: init_result,
+    })
+}
+
+/// The current OpenVR session, initializing OpenVR if it isn't yet
+pub fn init_openvr_session() -> Result<OpenVRSession, ObsOpenVRError> {
+    init_openvr()?;
+    // Only SteamVR quitting ends a session
+    openvr_session().ok_or(ObsOpenVRError::SteamVRQuitting)
+}
+
+/// Id of the current OpenVR session, if there is one
+pub fn openvr_session_id() -> Option<u64> {
+    openvr_session().map(|session| session.id())
+}
+
+/// How often `retry_init_openvr` retries initializing OpenVR while it fails
+const INIT_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Whether OpenVR is initialized, retrying `init_openvr` if the last attempt by the caller, at
+/// `last_attempt`, was long enough ago. For callers polling OpenVR frequently.
+pub fn retry_init_openvr(last_attempt: &mut Option<Instant>) -> bool {
+    if openvr_init_status().is_ok() {
+        return true;
+    }
+    if last_attempt.map_or(false, |t| t.elapsed() < INIT_RETRY_INTERVAL) {
+        return false;
+    }
+    *last_attempt = Some(Instant::now());
+    init_openvr().is_ok()
+}
+
+/// Result of the last attempt to initialize OpenVR, without retrying
+pub fn openvr_init_status() -> Result<(), ObsOpenVRError> {
+    *OPENVR_INIT_RESULT.read().unwrap()
+}
+
+/// Stops handing out the OpenVR session after SteamVR asked to quit. Called on the event thread,
+/// which stops polling OpenVR itself. Everything else calls into OpenVR while holding an
+/// `OpenVRSession`, which isn't handed out anymore.
+pub fn openvr_quitting() {
+    let mut init_result = OPENVR_INIT_RESULT.write().unwrap();
+    if init_result.is_ok() {
+        *init_result = Err(ObsOpenVRError::SteamVRQuitting);
+    }
+}
+
+/// Releases the session after `openvr_quitting`, waiting for the `OpenVRSession`s still held, so
+/// the retries in `retry_init_openvr` start a new one when SteamVR is back. Captures created in
+/// the released session see its id change, and are recreated.
+pub fn release_openvr() {
+    let mut init_result = OPENVR_INIT_RESULT.write().unwrap();
+    if matches!(*init_result, Err(ObsOpenVRError::SteamVRQuitting)) {
+        openvr::shutdown();
+        *init_result = Err(ObsOpenVRError::SteamVRQuit);
+    }
+}
+
 impl obs::ObsModule for ObsOpenVRModule {
     const CRATE_NAME: &'static str = env!("CARGO_CRATE_NAME");
     type LoadErr = ObsOpenVRError;
@@ -85,14 +224,23 @@ impl obs::ObsModule for ObsOpenVRModule {
         logging::init();
         info!("logging initialized");
 
-        // Try to Initialize OpenVR
-        if let Err(e) = init_openvr() {
-            warn!("error initializing openvr on startup: {}", &e);
-        }
+        // OpenVR is initialized on first use, by in-process sources and the plugin-wide features
+        // that need it, so sources captured by the capture host keep it out of OBS
+        recording::start();
+        vr_events::start();
+        publish::start();
 
         // Create source info struct, and register it
+        #[cfg(any(feature = "mirror-source", feature = "overlay-source"))]
+        obs::register_video_source!(capture::OpenVRCaptureSource);
+        obs::register_video_source!(pose_transform::PoseTransformSource);
+        // Legacy sources, kept registered (but hidden) so existing scenes keep working
         #[cfg(feature = "mirror-source")]
         obs::register_video_source!(mirror::OpenVRMirrorSource);
+        #[cfg(feature = "mirror-source")]
+        obs::register_async_video_source!(mirror::OpenVRAsyncMirrorSource);
+        #[cfg(feature = "mirror-source")]
+        obs::register_video_source!(mirror::OpenVRHeadsetViewSource);
         #[cfg(feature = "overlay-source")]
         obs::register_video_source!(overlay::OpenVROverlaySource);
         #[cfg(feature = "overlay-source")]
@@ -111,3 +259,24 @@ impl obs::ObsModule for ObsOpenVRModule {
 }
 
 obs::register_module!(ObsOpenVRModule);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quitting_resets_init_result() {
+        *OPENVR_INIT_RESULT.write().unwrap() = Ok(());
+        assert!(openvr_session().is_some());
+        openvr_quitting();
+        assert!(matches!(openvr_init_status(), Err(ObsOpenVRError::SteamVRQuitting)));
+        // Refused without trying, while the old session is still being released
+        assert!(matches!(init_openvr(), Err(ObsOpenVRError::SteamVRQuitting)));
+        assert!(openvr_session().is_none());
+        release_openvr();
+        assert!(matches!(openvr_init_status(), Err(ObsOpenVRError::SteamVRQuit)));
+        // Quitting again, without a new session, changes nothing
+        openvr_quitting();
+        assert!(matches!(openvr_init_status(), Err(ObsOpenVRError::SteamVRQuit)));
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    collections::VecDeque,
+    fmt::{
+        self,
+        Display,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+/// Number of samples kept per stage for percentile calculations
+const HISTOGRAM_WINDOW: usize = 512;
+
+/// Pipeline stage of a single captured frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Reading the image from OpenVR (mirror texture readback or overlay image)
+    Fetch,
+    /// Converting pixel data before handing it to OBS
+    Convert,
+    /// Uploading pixel data into an OBS texture
+    Upload,
+    /// Passing an async frame to OBS
+    Output,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Fetch, Stage::Convert, Stage::Upload, Stage::Output];
+
+    #[inline]
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Fetch => "fetch",
+            Stage::Convert => "convert",
+            Stage::Upload => "upload",
+            Stage::Output => "output",
+        }
+    }
+}
+
+/// Rolling window of the most recent durations recorded for a stage
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    samples: VecDeque<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl Histogram {
+    pub fn record(&mut self, sample: Duration) {
+        if self.samples.len() >= HISTOGRAM_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn percentiles(&self) -> Option<Percentiles> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        // Nearest rank, with halves rounding up
+        let at = |p: usize| sorted[((sorted.len() - 1) * p + 50) / 100];
+        Some(Percentiles {
+            p50: at(50),
+            p95: at(95),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+#[inline]
+fn as_ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+impl Display for Percentiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "p50 {:.2}ms, p95 {:.2}ms, max {:.2}ms", as_ms(self.p50), as_ms(self.p95), as_ms(self.max))
+    }
+}
+
+/// Per-source capture counters and stage timings
+#[derive(Debug, Default)]
+pub struct CaptureMetrics {
+    stages: [Histogram; 4],
+    pub frames_captured: u64,
+    /// Frames that were fetched, but not passed on to OBS because their content hadn't changed
+    pub frames_unchanged: u64,
+    pub frames_skipped: u64,
+    pub errors: u64,
+    log_interval: Option<Duration>,
+    last_log: Option<Instant>,
+}
+
+impl CaptureMetrics {
+    #[inline]
+    pub fn record_stage(&mut self, stage: Stage, duration: Duration) {
+        self.stages[stage.index()].record(duration);
+    }
+
+    #[inline]
+    pub fn stage(&self, stage: Stage) -> &Histogram {
+        &self.stages[stage.index()]
+    }
+
+    /// Sets how often `take_summary_due` returns a summary. `None` disables periodic summaries.
+    pub fn set_log_interval(&mut self, interval: Option<Duration>) {
+        self.log_interval = interval;
+        self.last_log = None;
+    }
+
+    /// Returns `true` at most once every log interval, starting one interval after the first call
+    pub fn take_summary_due(&mut self, now: Instant) -> bool {
+        let interval = match self.log_interval {
+            Some(v) => v,
+            None => return false,
+        };
+        match self.last_log {
+            Some(last) if now.duration_since(last) < interval => false,
+            Some(..) => {
+                self.last_log = Some(now);
+                true
+            },
+            None => {
+                self.last_log = Some(now);
+                false
+            },
+        }
+    }
+
+    /// Text describing the timings of a single stage
+    pub fn stage_summary(&self, stage: Stage) -> String {
+        match self.stage(stage).percentiles() {
+            Some(p) => format!("{}: {}", stage.name(), &p),
+            None => format!("{}: no samples", stage.name()),
+        }
+    }
+
+    pub fn counters_summary(&self) -> String {
+        format!("{} captured, {} unchanged, {} skipped, {} errors", self.frames_captured, self.frames_unchanged, self.frames_skipped, self.errors)
+    }
+}
+
+impl Display for CaptureMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &self.counters_summary())?;
+        Stage::ALL.iter()
+            .filter(|&&stage| !self.stage(stage).samples.is_empty())
+            .try_for_each(|&stage| write!(f, "; {}", &self.stage_summary(stage)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(v: u64) -> Duration {
+        Duration::from_millis(v)
+    }
+
+    fn histogram(samples: impl IntoIterator<Item=u64>) -> Histogram {
+        let mut ret = Histogram::default();
+        samples.into_iter().for_each(|v| ret.record(ms(v)));
+        ret
+    }
+
+    #[test]
+    fn empty_has_no_percentiles() {
+        assert_eq!(Histogram::default().percentiles(), None);
+    }
+
+    #[test]
+    fn single_sample() {
+        let expected = Percentiles {
+            p50: ms(7),
+            p95: ms(7),
+            max: ms(7),
+        };
+        assert_eq!(histogram(Some(7)).percentiles(), Some(expected));
+    }
+
+    #[test]
+    fn percentile_ranks_round_to_nearest() {
+        // Ranks 4.5 and 8.55 of 0 to 9
+        let expected = Percentiles {
+            p50: ms(6),
+            p95: ms(10),
+            max: ms(10),
+        };
+        assert_eq!(histogram((1..=10).rev()).percentiles(), Some(expected));
+        // Rank 0.95 of 0 to 1 is the larger sample
+        assert_eq!(histogram(vec![1, 2]).percentiles().map(|p| p.p95), Some(ms(2)));
+    }
+
+    #[test]
+    fn window_drops_oldest_samples() {
+        let extra = 10;
+        let histogram = histogram((0..(HISTOGRAM_WINDOW + extra) as u64).map(|v| 1000 - v));
+        assert_eq!(histogram.samples.len(), HISTOGRAM_WINDOW);
+        // The largest samples came first, and were dropped
+        let oldest_kept = 1000 - extra as u64;
+        let expected = Percentiles {
+            p50: ms(oldest_kept - 255),
+            p95: ms(oldest_kept - 26),
+            max: ms(oldest_kept),
+        };
+        assert_eq!(histogram.percentiles(), Some(expected));
+    }
+}
@@ -1,6 +1,7 @@
 #include "mirror-utils.h"
 #include "glad/glad/glad.h"
 #include <stdio.h>
+#include <stdlib.h>
 
 void obs_openvr_get_gl_texture_size(GLuint texture, struct obs_openvr_gl_texture_size *out) {
 	glBindTexture(GL_TEXTURE_2D, texture);
@@ -8,6 +9,13 @@ void obs_openvr_get_gl_texture_size(GLuint texture, struct obs_openvr_gl_texture
 	glGetTexLevelParameteriv(GL_TEXTURE_2D, 0, GL_TEXTURE_HEIGHT, &out->height);
 }
 
+GLint obs_openvr_get_gl_texture_internal_format(GLuint texture) {
+	GLint format = 0;
+	glBindTexture(GL_TEXTURE_2D, texture);
+	glGetTexLevelParameteriv(GL_TEXTURE_2D, 0, GL_TEXTURE_INTERNAL_FORMAT, &format);
+	return format;
+}
+
 int obs_openvr_copy_gl_texture(GLuint texture, GLenum format, uint8_t *img) {
 	GLenum status = glGetError();
 	if (status != GL_NO_ERROR) {
@@ -18,6 +26,8 @@ int obs_openvr_copy_gl_texture(GLuint texture, GLenum format, uint8_t *img) {
 		fprintf(stderr, "glBindTexture failed with error: %x\n", status);
 		return status;
 	}
+	// Rows of 3-byte formats aren't 4-byte aligned, and the buffer is tightly packed
+	glPixelStorei(GL_PACK_ALIGNMENT, 1);
 	glGetTexImage(GL_TEXTURE_2D, 0, format, GL_UNSIGNED_BYTE, img);
 	if ((status = glGetError()) != GL_NO_ERROR) {
 		fprintf(stderr, "glGetTexImage failed with error: %x\n", status);
@@ -25,3 +35,90 @@ int obs_openvr_copy_gl_texture(GLuint texture, GLenum format, uint8_t *img) {
 	}
 	return GL_NO_ERROR;
 }
+
+EGLint obs_openvr_egl_context_create_shared(struct obs_openvr_egl_context **out) {
+	EGLDisplay display = eglGetCurrentDisplay();
+	EGLContext share_context = eglGetCurrentContext();
+	if (display == EGL_NO_DISPLAY || share_context == EGL_NO_CONTEXT) {
+		return OBS_OPENVR_EGL_NO_CURRENT_CONTEXT;
+	}
+
+	EGLint config_id = 0;
+	if (!eglQueryContext(display, share_context, EGL_CONFIG_ID, &config_id)) {
+		return eglGetError();
+	}
+	const EGLint config_attribs[] = {
+		EGL_CONFIG_ID, config_id,
+		EGL_NONE,
+	};
+	EGLConfig config;
+	EGLint num_configs = 0;
+	if (!eglChooseConfig(display, config_attribs, &config, 1, &num_configs) || num_configs < 1) {
+		EGLint status = eglGetError();
+		return status != EGL_SUCCESS ? status : EGL_BAD_CONFIG;
+	}
+
+	if (!eglBindAPI(EGL_OPENGL_API)) {
+		return eglGetError();
+	}
+	const EGLint context_attribs[] = {
+		EGL_CONTEXT_MAJOR_VERSION, 3,
+		EGL_CONTEXT_MINOR_VERSION, 3,
+		EGL_CONTEXT_OPENGL_PROFILE_MASK, EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT,
+		EGL_NONE,
+	};
+	EGLContext context = eglCreateContext(display, config, share_context, context_attribs);
+	if (context == EGL_NO_CONTEXT) {
+		return eglGetError();
+	}
+
+	// The worker never draws, so a tiny pbuffer is enough. If the config can't back one, fall
+	// back to a surfaceless context.
+	const EGLint surface_attribs[] = {
+		EGL_WIDTH, 1,
+		EGL_HEIGHT, 1,
+		EGL_NONE,
+	};
+	EGLSurface surface = eglCreatePbufferSurface(display, config, surface_attribs);
+
+	struct obs_openvr_egl_context *ctx = (struct obs_openvr_egl_context *)calloc(1, sizeof(struct obs_openvr_egl_context));
+	if (ctx == NULL) {
+		if (surface != EGL_NO_SURFACE) {
+			eglDestroySurface(display, surface);
+		}
+		eglDestroyContext(display, context);
+		return EGL_BAD_ALLOC;
+	}
+	ctx->display = display;
+	ctx->context = context;
+	ctx->surface = surface;
+	*out = ctx;
+	return EGL_SUCCESS;
+}
+
+EGLint obs_openvr_egl_context_make_current(struct obs_openvr_egl_context *ctx) {
+	static int gl_loaded = 0;
+	if (!eglMakeCurrent(ctx->display, ctx->surface, ctx->surface, ctx->context)) {
+		return eglGetError();
+	}
+	if (!gl_loaded) {
+		gladLoadGLLoader((GLADloadproc)eglGetProcAddress);
+		gl_loaded = 1;
+	}
+	return EGL_SUCCESS;
+}
+
+void obs_openvr_egl_context_release_current(struct obs_openvr_egl_context *ctx) {
+	eglMakeCurrent(ctx->display, EGL_NO_SURFACE, EGL_NO_SURFACE, EGL_NO_CONTEXT);
+}
+
+void obs_openvr_egl_context_destroy(struct obs_openvr_egl_context *ctx) {
+	if (ctx == NULL) {
+		return;
+	}
+	if (ctx->surface != EGL_NO_SURFACE) {
+		eglDestroySurface(ctx->display, ctx->surface);
+	}
+	eglDestroyContext(ctx->display, ctx->context);
+	free(ctx);
+}
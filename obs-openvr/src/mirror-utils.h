@@ -1,11 +1,26 @@
 #pragma once
 
 #include "glad/glad/glad.h"
+#include "glad/glad/glad_egl.h"
 
 struct obs_openvr_gl_texture_size {
 	GLint width;
 	GLint height;
 };
 
+struct obs_openvr_egl_context {
+	EGLDisplay display;
+	EGLContext context;
+	EGLSurface surface;
+};
+
+#define OBS_OPENVR_EGL_NO_CURRENT_CONTEXT (-1)
+
 extern void obs_openvr_get_gl_texture_size(GLuint texture, struct obs_openvr_gl_texture_size *out);
+extern GLint obs_openvr_get_gl_texture_internal_format(GLuint texture);
 extern int obs_openvr_copy_gl_texture(GLuint texture, GLenum format, uint8_t *img);
+
+extern EGLint obs_openvr_egl_context_create_shared(struct obs_openvr_egl_context **out);
+extern EGLint obs_openvr_egl_context_make_current(struct obs_openvr_egl_context *ctx);
+extern void obs_openvr_egl_context_release_current(struct obs_openvr_egl_context *ctx);
+extern void obs_openvr_egl_context_destroy(struct obs_openvr_egl_context *ctx);
@@ -0,0 +1,329 @@
+use std::{
+    ffi::CStr,
+    mem,
+    sync::Arc,
+};
+use obs::graphics::with_graphics;
+use obs_openvr_ipc::{
+    client::{
+        Poll,
+        StreamError,
+    },
+    protocol::{
+        Eye,
+        Target,
+    },
+};
+use crate::{
+    ObsOpenVRError,
+    backend::{
+        Capture,
+        CaptureBackend,
+        Delivery,
+        Frame,
+        FrameData,
+        TextureFrame,
+    },
+    cache::SharedCapture,
+    color::{
+        ColorSpace,
+        Encoding,
+    },
+    metrics::Stage,
+    mirror::{
+        MirrorSourceSettings,
+        add_mirror_properties,
+        capture::{
+            self,
+            CopyTextureError,
+            OpenVRMirrorCapture,
+            SharedMirrorCapture,
+        },
+        egl::{
+            EglError,
+            SharedGlContext,
+        },
+        utils::{
+            self,
+            TextureFormat,
+        },
+    },
+    pixel::{
+        self,
+        ImageBuf,
+        ImageRef,
+    },
+    remote::{
+        self,
+        CaptureHost,
+        RemoteCapture,
+    },
+    status::SourceStatus,
+    timing::Timer,
+};
+
+/// Settings of a mirror capture
+#[derive(Debug, Clone, PartialEq)]
+pub struct MirrorConfig {
+    eye: openvr::sys::EVREye,
+    readback_format: TextureFormat,
+    color_space: ColorSpace,
+    host: CaptureHost,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MirrorCaptureError {
+    #[error("{0}")]
+    Init(#[from] ObsOpenVRError),
+    #[error("Error getting mirror texture: {0:?}")]
+    Compositor(openvr::sys::EVRCompositorError),
+    #[error("{0}")]
+    Copy(#[from] CopyTextureError),
+    #[error("{0}")]
+    Egl(#[from] EglError),
+    #[error("{0}")]
+    Remote(#[from] StreamError),
+}
+
+/// Reads the mirror texture back on the async capture thread, through a GL context shared with OBS
+pub struct ThreadedMirrorCapture {
+    /// Released in `drop`, while `context` is current and its OpenVR session is still running
+    texture_info: Option<openvr::compositor::MirrorTextureInfo>,
+    /// Id of the OpenVR session `texture_info` belongs to
+    session: u64,
+    context: SharedGlContext,
+    dimensions: (u32, u32),
+    format: TextureFormat,
+    encoding: Encoding,
+    buffer: Vec<u8>,
+}
+
+impl ThreadedMirrorCapture {
+    /// Must be called while holding the `OpenVRSession` with the id `session`
+    fn new(config: &MirrorConfig, session: u64) -> Result<Self, MirrorCaptureError> {
+        let context = with_graphics(|| unsafe { SharedGlContext::new() })?;
+        let (texture_info, (w, h), detected) = {
+            let _current = context.make_current()?;
+            let texture_info = unsafe { openvr::compositor::get_mirror_texture_gl(config.eye) }
+                .map_err(MirrorCaptureError::Compositor)?;
+            let (w, h): (i32, i32) = unsafe { utils::get_gl_texture_size(texture_info.id) }.into();
+            let detected = unsafe { utils::get_gl_texture_encoding(texture_info.id) };
+            (texture_info, (w as u32, h as u32), detected)
+        };
+        let encoding = config.color_space.resolve(detected);
+        trace!("mirror thread capturing {:?} at {}x{} ({:?})", &config.eye, w, h, encoding);
+        Ok(ThreadedMirrorCapture {
+            texture_info: Some(texture_info),
+            session: session,
+            context: context,
+            dimensions: (w, h),
+            format: config.readback_format,
+            encoding: encoding,
+            buffer: vec![0u8; w as usize * h as usize * config.readback_format.bytes_per_pixel() as usize],
+        })
+    }
+
+    fn capture(&mut self, status: &SourceStatus) -> Result<Capture<'_>, MirrorCaptureError> {
+        let _current = self.context.make_current()?;
+        let texture_info = self.texture_info.as_ref().unwrap();
+        let timestamp = unsafe { obs::sys::os_gettime_ns() };
+        let mut timer = Timer::new();
+        unsafe {
+            let _lock = texture_info.lock();
+            utils::copy_gl_texture(texture_info.id, self.format.into(), self.buffer.as_mut_ptr())
+                .map_err(CopyTextureError::Gl)?;
+        }
+        status.record_stage(Stage::Fetch, timer.checkpoint());
+        let (w, h) = self.dimensions;
+        Ok(Capture::Frame(Frame {
+            dimensions: self.dimensions,
+            data: FrameData::Image(ImageRef::new(self.format.pixel_format(), w, h, self.buffer.as_slice())),
+            encoding: self.encoding,
+            premultiplied: false,
+            timestamp: timestamp,
+        }))
+    }
+}
+
+impl Drop for ThreadedMirrorCapture {
+    fn drop(&mut self) {
+        let texture_info = self.texture_info.take();
+        let session = match crate::openvr_session() {
+            Some(v) if v.id() == self.session => v,
+            _ => {
+                // OpenVR was shut down, which already released the texture
+                mem::forget(texture_info);
+                return;
+            },
+        };
+        match self.context.make_current() {
+            Ok(_current) => mem::drop(texture_info),
+            Err(e) => warn!("Error making GL context current to release mirror texture: {}", &e),
+        }
+        mem::drop(session);
+    }
+}
+
+impl TextureFrame for SharedCapture<OpenVRMirrorCapture> {
+    fn draw(&self) {
+        let capture = self.lock();
+        if let Some(texture) = capture.get().texture() {
+            obs::source::draw(texture, 0, 0, 0, 0, false);
+        }
+    }
+
+    fn read(&self, dst: &mut ImageBuf) -> bool {
+        let capture = self.lock();
+        let image = capture.get().image();
+        if image.width == 0 || image.height == 0 {
+            return false;
+        }
+        let format = match image.format {
+            pixel::Format::Rgba | pixel::Format::Bgra => image.format,
+            _ => pixel::Format::Rgba,
+        };
+        pixel::convert_into(&image, format, Default::default(), dst);
+        true
+    }
+}
+
+/// Captures the mirror texture of one eye
+pub enum MirrorBackend {
+    /// Reads back and uploads on the graphics thread, sharing the texture with other sources
+    Shared(SharedMirrorCapture),
+    Threaded(ThreadedMirrorCapture),
+    /// Reads back in the capture host, which always sends RGBA
+    Remote {
+        capture: RemoteCapture,
+        color_space: ColorSpace,
+    },
+}
+
+impl MirrorBackend {
+    /// Id of the OpenVR session the mirror texture belongs to, unless captured in the capture host
+    fn session(&self) -> Option<u64> {
+        match self {
+            MirrorBackend::Shared(shared) => Some(shared.lock().get().session()),
+            MirrorBackend::Threaded(capture) => Some(capture.session),
+            MirrorBackend::Remote { .. } => None,
+        }
+    }
+}
+
+impl CaptureBackend for MirrorBackend {
+    type Config = MirrorConfig;
+    type Error = MirrorCaptureError;
+
+    const SYNC_ID: &'static [u8] = b"obs-openvr-mirror\0";
+    const SYNC_NAME: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"OpenVR Mirror Source\0")
+    };
+    const ASYNC_ID: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"obs-openvr-mirror-async\0")
+    };
+    const ASYNC_NAME: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"OpenVR Mirror Source (async)\0")
+    };
+    const OUTPUT_FLAGS: u32 = obs::sys::OBS_SOURCE_DEPRECATED;
+
+    fn config(data: &obs::sys::obs_data) -> MirrorConfig {
+        MirrorConfig {
+            eye: data.get_eye(),
+            readback_format: data.get_readback_format(),
+            color_space: ColorSpace::from_settings(data),
+            host: CaptureHost::from_settings(data),
+        }
+    }
+
+    fn add_properties(props: &mut obs::Properties) {
+        add_mirror_properties(props);
+    }
+
+    fn create(config: &MirrorConfig, delivery: Delivery, _status: &SourceStatus) -> Result<Self, MirrorCaptureError> {
+        trace!("Creating {:?} mirror capture with {:?}", delivery, config);
+        if config.host == CaptureHost::Helper {
+            let eye = match config.eye {
+                openvr::sys::EVREye::EVREye_Eye_Left => Eye::Left,
+                openvr::sys::EVREye::EVREye_Eye_Right => Eye::Right,
+            };
+            return Ok(MirrorBackend::Remote {
+                capture: RemoteCapture::open(Target::Mirror(eye)),
+                color_space: config.color_space,
+            });
+        }
+        let session = crate::init_openvr_session()?;
+        match delivery {
+            Delivery::RenderThread => OpenVRMirrorCapture::shared(config.eye, config.readback_format, config.color_space, session.id())
+                .map(MirrorBackend::Shared)
+                .map_err(MirrorCaptureError::Compositor),
+            Delivery::Async => ThreadedMirrorCapture::new(config, session.id()).map(MirrorBackend::Threaded),
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        match self.session() {
+            Some(session) => crate::openvr_session_id() != Some(session),
+            None => false,
+        }
+    }
+
+    fn invalidate(&mut self) {
+        // The other variants produce a frame on every capture anyway
+        if let MirrorBackend::Remote { capture, .. } = self {
+            capture.invalidate();
+        }
+    }
+
+    fn capture(&mut self, status: &SourceStatus) -> Result<Capture<'_>, MirrorCaptureError> {
+        // Held while calling into OpenVR, so it isn't shut down before the capture is done
+        let _session = if matches!(self, MirrorBackend::Remote { .. }) {
+            None
+        } else {
+            let session = match crate::openvr_session() {
+                Some(v) if self.session() == Some(v.id()) => v,
+                _ => return Ok(Capture::Disconnected),
+            };
+            if openvr::compositor::in_transition() {
+                // The mirror texture shows the compositor's loading environment
+                return Ok(Capture::Loading);
+            }
+            Some(session)
+        };
+        match self {
+            MirrorBackend::Shared(shared) => {
+                let timestamp = unsafe { obs::sys::os_gettime_ns() };
+                let mut capture = shared.lock();
+                capture::capture_frame(&mut capture, status)?;
+                let (w, h) = capture.get().dimensions();
+                let encoding = capture.get().encoding();
+                Ok(Capture::Frame(Frame {
+                    dimensions: (w as u32, h as u32),
+                    data: FrameData::Texture(shared.clone() as Arc<dyn TextureFrame>),
+                    encoding: encoding,
+                    premultiplied: false,
+                    timestamp: timestamp,
+                }))
+            },
+            MirrorBackend::Threaded(capture) => capture.capture(status),
+            MirrorBackend::Remote { capture, color_space } => {
+                let mut timer = Timer::new();
+                match capture.poll(status)? {
+                    Some(Poll::Frame) => {},
+                    Some(Poll::Unchanged) => return Ok(Capture::Unchanged),
+                    Some(Poll::Hidden) => return Ok(Capture::Hidden),
+                    Some(Poll::Loading) => return Ok(Capture::Loading),
+                    None => return Ok(Capture::Disconnected),
+                }
+                status.record_stage(Stage::Fetch, timer.checkpoint());
+                let info = *capture.info().unwrap();
+                Ok(Capture::Frame(Frame {
+                    dimensions: (info.width, info.height),
+                    data: FrameData::Image(ImageRef::with_stride(remote::pixel_format(info.layout), info.width, info.height, info.stride, capture.data())),
+                    encoding: color_space.resolve(remote::detected_encoding(info.transfer)),
+                    premultiplied: info.premultiplied,
+                    timestamp: info.timestamp,
+                }))
+            },
+        }
+    }
+}
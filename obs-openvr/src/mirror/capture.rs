@@ -3,12 +3,35 @@ use std::{
         self,
         Debug,
     },
+    mem,
+    sync::Arc,
 };
 use crate::mirror::utils::{
     self,
     TextureFormat,
 };
 use obs::graphics::GsTexture;
+use crate::{
+    cache::{
+        self,
+        CaptureCache,
+        SharedCapture,
+        SharedGuard,
+    },
+    color::{
+        ColorSpace,
+        Encoding,
+    },
+    metrics::Stage,
+    mirror::OBS_TEXTURE_FLAGS,
+    pixel::{
+        self,
+        ImageBuf,
+        ImageRef,
+    },
+    status::SourceStatus,
+    timing::Timer,
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum TextureCreationError {
@@ -26,16 +49,37 @@ pub enum CopyTextureError {
     TextureCreation(#[from] TextureCreationError)
 }
 
+/// Settings that make two mirror captures produce the same texture
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct MirrorKey {
+    eye: u32,
+    format: TextureFormat,
+    color_space: ColorSpace,
+    /// Mirror textures can't be shared with captures of a later OpenVR session
+    session: u64,
+}
+
+static SHARED_CAPTURES: CaptureCache<MirrorKey, OpenVRMirrorCapture> = CaptureCache::new();
+
+/// Mirror capture shared between every source capturing the same eye with the same settings
+pub type SharedMirrorCapture = Arc<SharedCapture<OpenVRMirrorCapture>>;
+
 fn required_buffer_size(dimensions: (i32, i32), format: TextureFormat) -> usize {
     dimensions.0 as usize * dimensions.1 as usize * format.bytes_per_pixel() as usize
 }
 
 pub struct OpenVRMirrorCapture {
     eye: openvr::sys::EVREye,
+    /// Released in `drop`, if its OpenVR session is still running
     texture_info: openvr::compositor::MirrorTextureInfo,
+    /// Id of the OpenVR session `texture_info` belongs to
+    session: u64,
     dimensions: (i32, i32),
     format: TextureFormat,
+    encoding: Encoding,
     buffer: Vec<u8>,
+    /// `buffer` converted to RGBA, for readback formats OBS can't take directly
+    converted: Option<ImageBuf>,
     texture_flags: u32,
     texture: Option<obs::graphics::Texture>,
 }
@@ -45,32 +89,38 @@ impl Debug for OpenVRMirrorCapture {
         f.debug_struct("OpenVRMirrorCapture")
             .field("eye", &self.eye)
             .field("texture_info", &self.texture_info)
+            .field("session", &self.session)
             .field("dimensions", &self.dimensions)
             .field("format", &self.format)
+            .field("encoding", &self.encoding)
             .finish()
     }
 }
 
 impl OpenVRMirrorCapture {
-    pub fn new(eye: openvr::sys::EVREye, texture_flags: u32) -> Result<Self, openvr::sys::EVRCompositorError> {
+    /// Must be called while holding the `OpenVRSession` with the id `session`
+    pub fn new(eye: openvr::sys::EVREye, format: TextureFormat, color_space: ColorSpace, texture_flags: u32, session: u64) -> Result<Self, openvr::sys::EVRCompositorError> {
         trace!("Creating OpenVRMirrorCapture with eye: {:?}", &eye);
-        let (texture_info, texture_size) = obs::graphics::with_graphics(|| {
+        let (texture_info, texture_size, detected) = obs::graphics::with_graphics(|| {
             unsafe {
                 openvr::compositor::get_mirror_texture_gl(eye)
                     .map(|info| {
                         let size = utils::get_gl_texture_size(info.id);
-                        (info, size)
+                        let detected = utils::get_gl_texture_encoding(info.id);
+                        (info, size, detected)
                     })
             }
         })?;
-        let format = TextureFormat::Rgba;
         trace!("Created capture context with texture info: {:?}", &texture_info);
         let ret = OpenVRMirrorCapture {
             eye: eye,
             texture_info: texture_info,
+            session: session,
             dimensions: texture_size.into(),
             format: format,
+            encoding: color_space.resolve(detected),
             buffer: vec![0; required_buffer_size(texture_size.into(), format)],
+            converted: None,
             texture_flags: texture_flags,
             texture: None,
         };
@@ -78,6 +128,19 @@ impl OpenVRMirrorCapture {
         Ok(ret)
     }
 
+    /// Capture of `eye` shared with other sources using the same settings, created if there is none
+    pub fn shared(eye: openvr::sys::EVREye, format: TextureFormat, color_space: ColorSpace, session: u64) -> Result<SharedMirrorCapture, openvr::sys::EVRCompositorError> {
+        let key = MirrorKey {
+            eye: eye as u32,
+            format: format,
+            color_space: color_space,
+            session: session,
+        };
+        let ret = SHARED_CAPTURES.get_or_try_insert_with(&key, || OpenVRMirrorCapture::new(eye, format, color_space, OBS_TEXTURE_FLAGS, session))?;
+        trace!("{} shared mirror captures in use", SHARED_CAPTURES.len());
+        Ok(ret)
+    }
+
     pub fn required_buffer_size(&self) -> usize {
         required_buffer_size(self.dimensions, self.format)
     }
@@ -87,17 +150,67 @@ impl OpenVRMirrorCapture {
         self.format
     }
 
+    #[inline(always)]
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
     #[inline(always)]
     pub fn eye(&self) -> openvr::sys::EVREye {
         self.eye
     }
 
+    /// Id of the OpenVR session the mirror texture belongs to
+    #[inline(always)]
+    pub fn session(&self) -> u64 {
+        self.session
+    }
+
     pub unsafe fn copy_texture(&mut self) -> Result<(), CopyTextureError> {
+        self.fetch()?;
+        self.convert();
+        self.upload()
+    }
+
+    /// Reads the mirror texture back into the capture buffer
+    pub unsafe fn fetch(&mut self) -> Result<(), CopyTextureError> {
         utils::copy_gl_texture(self.texture_info.id, self.format.into(), self.buffer.as_mut_ptr())
-            .map_err(CopyTextureError::Gl)?;
-        let linesize = self.linesize();
+            .map_err(CopyTextureError::Gl)
+    }
+
+    /// Converts the capture buffer to RGBA if OBS can't use the readback format directly
+    pub fn convert(&mut self) {
+        if self.format.to_gs_format().is_some() {
+            return;
+        }
+        let (w, h) = self.dimensions();
+        let src = ImageRef::new(self.format.pixel_format(), w as u32, h as u32, self.buffer.as_slice());
+        let converted = self.converted.get_or_insert_with(|| ImageBuf::new(pixel::Format::Rgba, w as u32, h as u32));
+        pixel::convert_into(&src, pixel::Format::Rgba, Default::default(), converted);
+    }
+
+    /// The captured image, in the format it is uploaded to OBS in
+    pub fn image(&self) -> ImageRef<'_> {
+        match self.converted.as_ref() {
+            Some(converted) => converted.as_image(),
+            None => {
+                let (w, h) = self.dimensions();
+                ImageRef::new(self.format.pixel_format(), w as u32, h as u32, self.buffer.as_slice())
+            },
+        }
+    }
+
+    /// Uploads the captured image to the OBS texture, creating it if needed
+    pub unsafe fn upload(&mut self) -> Result<(), CopyTextureError> {
         if let Some(texture) = self.texture.as_mut() {
-            texture.set_image_unchecked(self.buffer.as_slice(), linesize, false);
+            let image = match self.converted.as_ref() {
+                Some(converted) => converted.as_image(),
+                None => {
+                    let (w, h) = self.dimensions;
+                    ImageRef::new(self.format.pixel_format(), w as u32, h as u32, self.buffer.as_slice())
+                },
+            };
+            texture.set_image_unchecked(image.data, image.stride as u32, false);
             Ok(())
         } else {
             self.texture = self.create_texture()
@@ -122,10 +235,6 @@ impl OpenVRMirrorCapture {
         self.dimensions.1
     }
 
-    fn linesize(&self) -> u32 {
-        self.width() as u32 * self.format.bytes_per_pixel() as u32
-    }
-
     #[inline(always)]
     pub fn texture<'a>(&'a self) -> Option<&'a obs::graphics::Texture> {
         self.texture.as_ref()
@@ -133,12 +242,40 @@ impl OpenVRMirrorCapture {
 
     unsafe fn create_texture(&self) -> Result<obs::graphics::Texture, TextureCreationError> {
         let (w, h) = self.dimensions();
-        let format: Option<obs::sys::gs_color_format> = self.format.into();
-        let format = format
+        let image = self.image();
+        let format = self.encoding.gs_color_format(image.format)
             .map(Ok)
             .unwrap_or_else(|| Err(TextureCreationError::FormatTranslation(self.format)))?;
-        obs::graphics::Texture::new(w as u32, h as u32, format, &[self.buffer.as_slice().as_ptr()], self.texture_flags)
+        obs::graphics::Texture::new(w as u32, h as u32, format, &[image.data.as_ptr()], self.texture_flags)
             .map(Ok)
             .unwrap_or(Err(TextureCreationError::TextureAllocation))
     }
 }
+
+impl Drop for OpenVRMirrorCapture {
+    fn drop(&mut self) {
+        let texture_info = mem::replace(&mut self.texture_info, openvr::compositor::MirrorTextureInfo::empty());
+        match crate::openvr_session() {
+            Some(session) if session.id() == self.session => mem::drop(texture_info),
+            // OpenVR was shut down, which already released the texture
+            _ => mem::forget(texture_info),
+        }
+    }
+}
+
+/// Fetches, converts and uploads a shared capture for the current OBS frame, unless another source
+/// already did. Stage timings are recorded to `status` of whichever source does the work.
+pub fn capture_frame(capture: &mut SharedGuard<'_, OpenVRMirrorCapture>, status: &SourceStatus) -> Result<(), CopyTextureError> {
+    capture.capture_once(cache::current_frame(), |capture| {
+        let mut timer = Timer::new();
+        obs::graphics::with_graphics(|| unsafe {
+            capture.fetch()?;
+            status.record_stage(Stage::Fetch, timer.checkpoint());
+            capture.convert();
+            status.record_stage(Stage::Convert, timer.checkpoint());
+            capture.upload()?;
+            status.record_stage(Stage::Upload, timer.checkpoint());
+            Ok(true)
+        })
+    })
+}
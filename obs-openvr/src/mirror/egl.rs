@@ -0,0 +1,75 @@
+use std::{
+    marker::PhantomData,
+    ptr,
+};
+
+const EGL_SUCCESS: i32 = 0x3000;
+const NO_CURRENT_CONTEXT: i32 = -1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum EglError {
+    #[error("OBS graphics context is not an EGL context")]
+    NoCurrentContext,
+    #[error("EGL error: 0x{0:x}")]
+    Egl(i32),
+}
+
+#[inline]
+fn status_to_result(status: i32) -> Result<(), EglError> {
+    match status {
+        EGL_SUCCESS => Ok(()),
+        NO_CURRENT_CONTEXT => Err(EglError::NoCurrentContext),
+        e => Err(EglError::Egl(e)),
+    }
+}
+
+/// An EGL context that shares its objects with the OBS graphics context, so that worker threads
+/// can read GL textures without entering the OBS graphics context
+pub struct SharedGlContext(*mut libc::c_void);
+
+impl SharedGlContext {
+    /// Creates a context sharing objects with the *current* context. Must be called from inside
+    /// `obs::graphics::with_graphics`
+    pub unsafe fn new() -> Result<Self, EglError> {
+        let mut ctx = ptr::null_mut();
+        status_to_result(obs_openvr_egl_context_create_shared(&mut ctx as *mut _))?;
+        Ok(SharedGlContext(ctx))
+    }
+
+    /// Makes this context current on the calling thread until the returned guard is dropped
+    pub fn make_current<'a>(&'a self) -> Result<CurrentContext<'a>, EglError> {
+        unsafe {
+            status_to_result(obs_openvr_egl_context_make_current(self.0))?;
+        }
+        Ok(CurrentContext(self, PhantomData {}))
+    }
+}
+
+impl Drop for SharedGlContext {
+    fn drop(&mut self) {
+        unsafe {
+            obs_openvr_egl_context_destroy(self.0);
+        }
+    }
+}
+
+unsafe impl Send for SharedGlContext {}
+
+/// Guard for a `SharedGlContext` being current on this thread. Not `Send`, since EGL contexts are
+/// current per-thread
+pub struct CurrentContext<'a>(&'a SharedGlContext, PhantomData<*const ()>);
+
+impl<'a> Drop for CurrentContext<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            obs_openvr_egl_context_release_current((self.0).0);
+        }
+    }
+}
+
+extern "C" {
+    fn obs_openvr_egl_context_create_shared(out: *mut *mut libc::c_void) -> i32;
+    fn obs_openvr_egl_context_make_current(ctx: *mut libc::c_void) -> i32;
+    fn obs_openvr_egl_context_release_current(ctx: *mut libc::c_void);
+    fn obs_openvr_egl_context_destroy(ctx: *mut libc::c_void);
+}
@@ -0,0 +1,341 @@
+use std::{
+    ffi::CStr,
+    mem,
+    sync::{
+        Arc,
+        Mutex,
+        RwLock,
+    },
+    time::Instant,
+};
+use obs::{
+    OwnedPointerContainer,
+    data::ObsData,
+    graphics::with_graphics,
+};
+use openvr::headset_view::{
+    HeadsetView,
+    HeadsetViewMode,
+};
+use crate::{
+    color::{
+        ColorSpace,
+        add_color_space_property,
+    },
+    mirror::{
+        capture::{
+            self,
+            OpenVRMirrorCapture,
+            SharedMirrorCapture,
+        },
+        try_init_openvr,
+        utils::TextureFormat,
+    },
+    status::{
+        SourceStatus,
+        StatusProvider,
+        add_status_properties,
+    },
+};
+
+const EYES: [openvr::sys::EVREye; 2] = [
+    openvr::sys::EVREye::EVREye_Eye_Left,
+    openvr::sys::EVREye::EVREye_Eye_Right,
+];
+
+/// Headset view settings as last read back from SteamVR
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ViewState {
+    mode: HeadsetViewMode,
+    aspect_ratio: f32,
+    cropped: bool,
+}
+
+impl ViewState {
+    fn new() -> Self {
+        ViewState {
+            mode: HeadsetViewMode::Both,
+            aspect_ratio: 16.0 / 9.0,
+            cropped: true,
+        }
+    }
+
+    fn query(view: &HeadsetView) -> Self {
+        ViewState {
+            mode: view.mode().unwrap_or(HeadsetViewMode::Both),
+            aspect_ratio: view.get_aspect_ratio(),
+            cropped: view.get_cropped(),
+        }
+    }
+
+    /// Indices into `EYES` of the eyes shown in this mode, in left-to-right order
+    fn eyes(&self) -> &'static [usize] {
+        match self.mode {
+            HeadsetViewMode::Left => &[0],
+            HeadsetViewMode::Right => &[1],
+            HeadsetViewMode::Both => &[0, 1],
+        }
+    }
+
+    /// Region `(x, y, width, height)` of each eye texture to show, given the size of the eye texture
+    fn eye_region(&self, eye_size: (u32, u32)) -> (u32, u32, u32, u32) {
+        let (w, h) = eye_size;
+        if !self.cropped || w == 0 || h == 0 || self.aspect_ratio <= 0.0 {
+            return (0, 0, w, h);
+        }
+        let eye_aspect = self.aspect_ratio / self.eyes().len() as f32;
+        let (crop_w, crop_h) = if (w as f32 / h as f32) > eye_aspect {
+            ((h as f32 * eye_aspect).round() as u32, h)
+        } else {
+            (w, (w as f32 / eye_aspect).round() as u32)
+        };
+        ((w - crop_w) / 2, (h - crop_h) / 2, crop_w, crop_h)
+    }
+}
+
+pub struct OpenVRHeadsetViewSource {
+    handle: *mut obs::sys::obs_source,
+    captures: RwLock<[Option<SharedMirrorCapture>; 2]>,
+    view: RwLock<ViewState>,
+    color_space: RwLock<ColorSpace>,
+    status: Arc<SourceStatus>,
+    /// Last time `video_tick` tried to initialize OpenVR
+    init_attempted: Mutex<Option<Instant>>,
+}
+
+impl OpenVRHeadsetViewSource {
+    #[inline(always)]
+    pub fn is_showing(&self) -> bool {
+        unsafe {
+            obs::sys::obs_source_showing(self.handle)
+        }
+    }
+
+    fn eye_size(&self) -> Option<(u32, u32)> {
+        let captures = self.captures.read().unwrap();
+        captures.iter()
+            .filter_map(Option::as_ref)
+            .next()
+            .map(|capture| {
+                let (w, h) = capture.lock().get().dimensions();
+                (w as u32, h as u32)
+            })
+    }
+}
+
+impl obs::source::VideoSource for OpenVRHeadsetViewSource {
+    const ID: &'static [u8] = b"obs-openvr-headset-view\0";
+    const OUTPUT_FLAGS: Option<u32> = Some(obs::sys::OBS_SOURCE_DEPRECATED | obs::sys::OBS_SOURCE_SRGB);
+
+    fn create(settings: &mut obs::sys::obs_data, source: *mut obs::sys::obs_source_t) -> Self {
+        let ret = OpenVRHeadsetViewSource {
+            handle: source,
+            captures: RwLock::new([None, None]),
+            view: RwLock::new(ViewState::new()),
+            color_space: RwLock::new(ColorSpace::Auto),
+            status: Arc::new(SourceStatus::new(source, false)),
+            init_attempted: Mutex::new(None),
+        };
+        ret.update(settings);
+        ret
+    }
+
+    fn get_name() -> &'static CStr {
+        unsafe { CStr::from_bytes_with_nul_unchecked(b"OpenVR Headset View Source\0") }
+    }
+
+    fn get_defaults(settings: &mut obs::sys::obs_data) {
+        settings.set_default_string(keys::MODE, keys::MODE_BOTH);
+        settings.set_default_int(keys::WIDTH, 0);
+        settings.set_default_int(keys::HEIGHT, 0);
+        settings.set_default_bool(keys::CROPPED, true);
+    }
+
+    fn get_dimensions(&self) -> (u32, u32) {
+        let view = self.view.read().unwrap();
+        self.eye_size()
+            .map(|eye_size| {
+                let (_, _, w, h) = view.eye_region(eye_size);
+                (w * view.eyes().len() as u32, h)
+            })
+            .unwrap_or((0, 0))
+    }
+
+    fn get_properties(&self) -> *mut obs::sys::obs_properties {
+        let mut props = obs::Properties::new();
+        add_headset_view_properties(&mut props);
+        add_color_space_property(&mut props);
+        add_status_properties(&mut props, self);
+        unsafe { props.leak() }
+    }
+
+    fn update(&self, data: &obs::sys::obs_data) {
+        self.status.update_settings(data);
+        let color_space = ColorSpace::from_settings(data);
+        if mem::replace(&mut *self.color_space.write().unwrap(), color_space) != color_space {
+            // Recreated with the new color space on the next tick
+            *self.captures.write().unwrap() = [None, None];
+        }
+        if !try_init_openvr() {
+            return;
+        }
+        let _session = match crate::openvr_session() {
+            Some(v) => v,
+            None => return,
+        };
+        let view = match HeadsetView::global() {
+            Some(v) => v,
+            None => {
+                warn!("IVRHeadsetView is unavailable, not applying headset view settings");
+                return;
+            },
+        };
+        let mode = match data.get_string(keys::MODE) {
+            Some("left") => HeadsetViewMode::Left,
+            Some("right") => HeadsetViewMode::Right,
+            _ => HeadsetViewMode::Both,
+        };
+        view.set_mode(mode);
+        let (w, h) = (data.get_int(keys::WIDTH), data.get_int(keys::HEIGHT));
+        if w > 0 && h > 0 {
+            view.set_size(w as u32, h as u32);
+        }
+        view.set_cropped(data.get_bool(keys::CROPPED));
+        let mut state = self.view.write().unwrap();
+        *state = ViewState::query(&view);
+        trace!("OpenVRHeadsetViewSource::update: {:?}", &*state);
+    }
+
+    fn video_tick(&self, _seconds: f32) {
+        if !self.is_showing() {
+            return;
+        }
+        let mut captures = self.captures.write().unwrap();
+        if !crate::retry_init_openvr(&mut self.init_attempted.lock().unwrap()) {
+            *captures = [None, None];
+            mem::drop(captures);
+            self.status.record_skipped();
+            return;
+        }
+        // Captures of an ended session are released before holding the current one
+        let current = crate::openvr_session_id();
+        for capture in captures.iter_mut() {
+            if capture.as_ref().map_or(false, |capture| Some(capture.lock().get().session()) != current) {
+                *capture = None;
+            }
+        }
+        let session = match crate::openvr_session() {
+            Some(v) if Some(v.id()) == current => v,
+            _ => {
+                mem::drop(captures);
+                self.status.record_skipped();
+                return;
+            },
+        };
+        // Follow changes made from the SteamVR desktop window as well as our own settings
+        let view = match HeadsetView::global() {
+            Some(view) => ViewState::query(&view),
+            None => return,
+        };
+        *self.view.write().unwrap() = view;
+        for &idx in view.eyes() {
+            if captures[idx].is_none() {
+                let color_space = *self.color_space.read().unwrap();
+                captures[idx] = match OpenVRMirrorCapture::shared(EYES[idx], TextureFormat::Rgba, color_space, session.id()) {
+                    Ok(v) => Some(v),
+                    Err(e) => {
+                        error!("Error creating mirror capture for {:?}: {:?}", &EYES[idx], &e);
+                        self.status.record_error(&format!("Error creating mirror capture for {:?}: {:?}", &EYES[idx], &e));
+                        None
+                    },
+                };
+            }
+            if captures[idx].is_none() {
+                mem::drop(captures);
+                self.status.record_skipped();
+                return;
+            }
+            if let Some(capture) = captures[idx].as_ref() {
+                if let Err(e) = capture::capture_frame(&mut capture.lock(), &self.status) {
+                    error!("Error copying texture data from OpenVR mirror: {:?}", &e);
+                    self.status.record_error(&e);
+                    return;
+                }
+            }
+        }
+        mem::drop(session);
+        mem::drop(captures);
+        self.status.record_frame(<Self as obs::source::VideoSource>::get_dimensions(self));
+    }
+
+    fn video_render(&self, _effect: *mut obs::sys::gs_effect_t) {
+        let view = *self.view.read().unwrap();
+        let captures = self.captures.read().unwrap();
+        with_graphics(|| {
+            view.eyes().iter()
+                .filter_map(|&idx| captures[idx].as_ref())
+                .enumerate()
+                .for_each(|(n, capture)| {
+                    let guard = capture.lock();
+                    let capture = guard.get();
+                    let (w, h) = capture.dimensions();
+                    let (x, y, cx, cy) = view.eye_region((w as u32, h as u32));
+                    if let Some(texture) = capture.texture() {
+                        obs::source::draw_subregion(&*texture, (n as u32 * cx) as f32, 0.0, x, y, cx, cy, false);
+                    }
+                });
+        });
+    }
+}
+
+impl StatusProvider for OpenVRHeadsetViewSource {
+    fn status(&self) -> Option<Arc<SourceStatus>> {
+        Some(self.status.clone())
+    }
+}
+
+pub fn add_headset_view_properties(props: &mut obs::Properties) {
+    use obs::properties::{
+        PropertiesExt,
+        PropertyDescription,
+    };
+
+    let mode_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Mode\0") };
+    props.add_string_list_complete(PropertyDescription::new(keys::MODE, Some(mode_description)), [
+        (keys::MODE_LEFT, keys::MODE_LEFT),
+        (keys::MODE_RIGHT, keys::MODE_RIGHT),
+        (keys::MODE_BOTH, keys::MODE_BOTH),
+    ].iter().map(|&v| v));
+    let width_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Window width (0 to keep SteamVR's)\0") };
+    let height_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Window height (0 to keep SteamVR's)\0") };
+    let cropped_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Cropped\0") };
+    props.add_int(keys::WIDTH, width_description, 0, 8192, 1);
+    props.add_int(keys::HEIGHT, height_description, 0, 8192, 1);
+    props.add_bool(keys::CROPPED, cropped_description);
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const MODE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"headset_view_mode\0")
+    };
+    pub const MODE_LEFT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"left\0")
+    };
+    pub const MODE_RIGHT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"right\0")
+    };
+    pub const MODE_BOTH: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"both\0")
+    };
+    pub const WIDTH: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"headset_view_width\0")
+    };
+    pub const HEIGHT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"headset_view_height\0")
+    };
+    pub const CROPPED: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"headset_view_cropped\0")
+    };
+}
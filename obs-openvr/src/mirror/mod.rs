@@ -1,99 +1,35 @@
 pub mod utils;
+mod backend;
 mod capture;
+mod egl;
+mod headset_view;
 
-use capture::OpenVRMirrorCapture;
-use std::{
-    convert::TryFrom,
-    ffi::CStr,
-    sync::RwLock,
+use utils::TextureFormat;
+use std::ffi::CStr;
+
+use crate::backend::{
+    AsyncCaptureSource,
+    SyncCaptureSource,
 };
-use obs::{
-    graphics::with_graphics,
-    data::ObsData,
-    OwnedPointerContainer,
+
+pub use backend::MirrorBackend;
+pub use headset_view::{
+    OpenVRHeadsetViewSource,
+    add_headset_view_properties,
 };
 
+/// Mirror source capturing on the render thread
+pub type OpenVRMirrorSource = SyncCaptureSource<MirrorBackend>;
+/// Mirror source capturing on a worker thread
+pub type OpenVRAsyncMirrorSource = AsyncCaptureSource<MirrorBackend>;
+
 const DEFAULT_EYE: openvr::sys::EVREye = openvr::sys::EVREye::EVREye_Eye_Left;
 
 const OBS_TEXTURE_FLAGS: u32 = obs::sys::GS_DYNAMIC;
 
-#[derive(Debug)]
-struct OpenVRMirrorSourceSettings {
-    eye: openvr::sys::EVREye,
-}
-
-impl OpenVRMirrorSourceSettings {
-    fn update<D: obs::data::ObsData>(&mut self, data: &D) {
-        self.eye = data.get_eye();
-        trace!("OpenVRMirrorSourceSettings::update: {:?}", self);
-    }
-
-    #[inline(always)]
-    pub fn eye(&self) -> openvr::sys::EVREye {
-        self.eye
-    }
-}
-
-impl<'a, T: obs::data::ObsData> From<&'a T> for OpenVRMirrorSourceSettings {
-    fn from(data: &'a T) -> Self {
-        OpenVRMirrorSourceSettings {
-            eye: data.get_eye(),
-        }
-    }
-}
-
-impl<'a> TryFrom<&'a OpenVRMirrorSourceSettings> for OpenVRMirrorCapture {
-    type Error = openvr::sys::EVRCompositorError;
-
-    #[inline]
-    fn try_from(settings: &'a OpenVRMirrorSourceSettings) -> Result<Self, Self::Error> {
-        OpenVRMirrorCapture::new(settings.eye(), OBS_TEXTURE_FLAGS)
-    }
-}
-
-pub struct OpenVRMirrorSource {
-    handle: *mut obs::sys::obs_source,
-    settings: RwLock<OpenVRMirrorSourceSettings>,
-    capture_context: RwLock<Option<OpenVRMirrorCapture>>,
-}
-
-impl OpenVRMirrorSource {
-    pub fn new(settings: &mut obs::sys::obs_data, handle: *mut obs::sys::obs_source) -> Self {
-        let ret = OpenVRMirrorSource {
-            handle: handle,
-            settings: RwLock::new(OpenVRMirrorSourceSettings::from(settings as &_)),
-            capture_context: RwLock::new(None),
-        };
-        if try_init_openvr() {
-            let settings = ret.settings.read().unwrap();
-            trace!("Creating capture context with settings: {:?}", &*settings);
-            ret.recreate_capture_context(&*settings);
-        }
-        ret
-    }
-
-    fn recreate_capture_context(&self, settings: &OpenVRMirrorSourceSettings) {
-        let new_context = match OpenVRMirrorCapture::try_from(settings) {
-            Ok(v) => Some(v),
-            Err(e) => {
-                error!("Error creating mirror capture: {:?}", &e);
-                None
-            },
-        };
-        let mut capture_context = self.capture_context.write().unwrap();
-        *capture_context = new_context;
-    }
-
-    #[inline(always)]
-    pub fn is_showing(&self) -> bool {
-        unsafe {
-            obs::sys::obs_source_showing(self.handle)
-        }
-    }
-}
-
 trait MirrorSourceSettings {
     fn get_eye(&self) -> openvr::sys::EVREye;
+    fn get_readback_format(&self) -> TextureFormat;
 }
 
 impl<T> MirrorSourceSettings for T where
@@ -112,6 +48,56 @@ impl<T> MirrorSourceSettings for T where
             })
             .unwrap_or(DEFAULT_EYE)
     }
+
+    fn get_readback_format(&self) -> TextureFormat {
+        self.get_string(keys::READBACK_FORMAT)
+            .and_then(|s| match s {
+                "rgba" => Some(TextureFormat::Rgba),
+                "rgb" => Some(TextureFormat::Rgb),
+                _ => None,
+            })
+            .unwrap_or(TextureFormat::Rgba)
+    }
+}
+
+pub(crate) fn add_eye_property(props: &mut obs::Properties) {
+    use obs::properties::{
+        PropertiesExt,
+        PropertyDescription,
+    };
+
+    let eye_name: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"eye\0") };
+    let left_eye: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"left\0") };
+    let right_eye: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"right\0") };
+    props.add_string_list_complete(PropertyDescription::new(eye_name, None), [(left_eye, left_eye), (right_eye, right_eye)].iter().map(|&v| v));
+}
+
+/// Properties shared by all mirror sources, besides `backend::add_common_properties`
+pub(crate) fn add_mirror_properties(props: &mut obs::Properties) {
+    use obs::properties::{
+        PropertiesExt,
+        PropertyDescription,
+    };
+
+    add_eye_property(props);
+    let readback_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Readback format\0") };
+    let rgba_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"RGBA\0") };
+    let rgb_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"RGB (less GPU bandwidth, converted on the CPU)\0") };
+    props.add_string_list_complete(PropertyDescription::new(keys::READBACK_FORMAT, Some(readback_description)), [(rgba_description, keys::READBACK_RGBA), (rgb_description, keys::READBACK_RGB)].iter().map(|&v| v));
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const READBACK_FORMAT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"readback_format\0")
+    };
+    pub const READBACK_RGBA: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"rgba\0")
+    };
+    pub const READBACK_RGB: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"rgb\0")
+    };
 }
 
 fn try_init_openvr() -> bool {
@@ -126,73 +112,3 @@ fn try_init_openvr() -> bool {
         },
     }
 }
-
-impl obs::source::VideoSource for OpenVRMirrorSource {
-    const ID: &'static [u8] = b"obs-openvr-mirror\0";
-    const OUTPUT_FLAGS: Option<u32> = None;
-
-    fn create(settings: &mut obs::sys::obs_data, source: *mut obs::sys::obs_source_t) -> Self {
-        OpenVRMirrorSource::new(settings, source)
-    }
-
-    fn get_name() -> &'static CStr {
-        unsafe { CStr::from_bytes_with_nul_unchecked(b"OpenVR Mirror Source\0") }
-    }
-
-    fn get_dimensions(&self) -> (u32, u32) {
-        let capture_context = self.capture_context.read().unwrap();
-        capture_context.as_ref()
-            .map(|ctx| {
-                let (w, h) = ctx.dimensions();
-                (w as u32, h as u32)
-            })
-            .unwrap_or((0, 0))
-    }
-
-    fn get_properties(&self) -> *mut obs::sys::obs_properties {
-        use obs::properties::{
-            Properties,
-            PropertiesExt,
-            PropertyDescription,
-        };
-
-        let mut props = Properties::new();
-
-        let eye_name: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"eye\0") };
-        let left_eye: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"left\0") };
-        let right_eye: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"right\0") };
-        props.add_string_list_complete(PropertyDescription::new(eye_name, None), [(left_eye, left_eye), (right_eye, right_eye)].iter().map(|&v| v));
-
-        unsafe { props.leak() }
-    }
-
-    fn update(&self, data: &obs::sys::obs_data) {
-        if try_init_openvr() {
-            let mut settings = self.settings.write().unwrap();
-            settings.update(data);
-            self.recreate_capture_context(&*settings);
-        }
-    }
-
-    fn video_tick(&self, _seconds: f32) {
-        if !self.is_showing() {
-            return;
-        }
-        let mut capture_context = self.capture_context.write().unwrap();
-        if let Some(capture_context) = capture_context.as_mut() {
-            if let Err(e) = with_graphics(|| unsafe { capture_context.copy_texture() }) {
-                error!("Error copying texture data from OpenVR mirror: {:?}", &e);
-                return;
-            }
-        }
-    }
-
-    fn video_render(&self, _effect: *mut obs::sys::gs_effect_t) {
-        let capture_context = self.capture_context.read().unwrap();
-        if let Some(texture) = capture_context.as_ref().and_then(OpenVRMirrorCapture::texture) {
-            with_graphics(|| {
-                obs::source::draw(&*texture, 0, 0, 0, 0, false);
-            });
-        }
-    }
-}
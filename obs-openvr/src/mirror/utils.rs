@@ -1,5 +1,10 @@
+use crate::{
+    color::Encoding,
+    pixel,
+};
+
 #[repr(u32)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TextureFormat {
     Rgb = 0x1907,
     Rgba = 0x1908,
@@ -17,6 +22,14 @@ impl TextureFormat {
     pub fn to_gs_format(self) -> Option<obs::sys::gs_color_format> {
         self.into()
     }
+
+    #[inline]
+    pub fn pixel_format(self) -> pixel::Format {
+        match self {
+            TextureFormat::Rgb => pixel::Format::Rgb,
+            TextureFormat::Rgba => pixel::Format::Rgba,
+        }
+    }
 }
 
 impl Into<Option<obs::sys::gs_color_format>> for TextureFormat {
@@ -74,11 +87,28 @@ fn gl_error_to_result(status: u32) -> Result<(), u32> {
     }
 }
 
+/// Transfer function of a GL texture's pixels, if its internal format says
+pub unsafe fn get_gl_texture_encoding(texture: u32) -> Option<Encoding> {
+    // GL_SRGB, GL_SRGB8, GL_SRGB_ALPHA, GL_SRGB8_ALPHA8
+    const SRGB_FORMATS: [i32; 4] = [0x8C40, 0x8C41, 0x8C42, 0x8C43];
+    // GL_RGBA32F, GL_RGB32F, GL_RGBA16F, GL_RGB16F, GL_R11F_G11F_B10F
+    const LINEAR_FORMATS: [i32; 5] = [0x8814, 0x8815, 0x881A, 0x881B, 0x8C3A];
+    let format = obs_openvr_get_gl_texture_internal_format(texture);
+    if SRGB_FORMATS.contains(&format) {
+        Some(Encoding::Srgb)
+    } else if LINEAR_FORMATS.contains(&format) {
+        Some(Encoding::Linear)
+    } else {
+        None
+    }
+}
+
 pub unsafe fn copy_gl_texture(texture: u32, format: u32, img: *mut u8) -> Result<(), u32> {
     gl_error_to_result(obs_openvr_copy_gl_texture(texture, format, img))
 }
 
 extern "C" {
     fn obs_openvr_get_gl_texture_size(texture: u32, out: *mut GlTextureSize);
+    fn obs_openvr_get_gl_texture_internal_format(texture: u32) -> i32;
     fn obs_openvr_copy_gl_texture(texture: u32, format: u32, img: *mut u8) -> u32;
 }
@@ -1,4 +1,13 @@
 use obs::sys as obs_sys;
+use std::{
+    ffi::{
+        CStr,
+        OsStr,
+    },
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+};
 
 macro_rules! obs_declare_module {
     ($module_name:ident, $obs_sys:ident, $version_expr:expr) => {
@@ -68,3 +77,22 @@ pub const fn libobs_api_ver() -> SemanticVersion {
 }
 
 obs_declare_module!(obs_openvr, obs_sys, super::libobs_api_ver().into());
+
+/// Path of `file` in this module's config directory, creating the directory if needed
+pub fn config_path(file: &CStr) -> Option<PathBuf> {
+    let path = unsafe {
+        let p = obs_sys::obs_module_get_config_path(obs_openvr::obs_current_module(), file.as_ptr());
+        if p.is_null() {
+            return None;
+        }
+        let ret = PathBuf::from(OsStr::from_bytes(CStr::from_ptr(p).to_bytes()));
+        obs_sys::bfree(p as *mut _);
+        ret
+    };
+    if let Some(dir) = path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            warn!("Error creating config directory {}: {}", dir.display(), &e);
+        }
+    }
+    Some(path)
+}
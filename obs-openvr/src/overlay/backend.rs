@@ -0,0 +1,383 @@
+use std::{
+    ffi::{
+        CStr,
+        CString,
+    },
+    mem,
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use obs::data::ObsData;
+use openvr::overlay::{
+    AlphaMode,
+    ChannelOrder,
+    OverlayRef,
+    PixelFormat,
+};
+use obs_openvr_ipc::{
+    client::{
+        Poll,
+        StreamError,
+    },
+    protocol::{
+        FrameInfo,
+        Layout,
+        Target,
+    },
+};
+use crate::{
+    ObsOpenVRError,
+    backend::{
+        Capture,
+        CaptureBackend,
+        Delivery,
+        Frame,
+        FrameData,
+    },
+    cache,
+    metrics::Stage,
+    overlay::{
+        add_async_overlay_properties,
+        add_overlay_properties,
+        format::{
+            self,
+            FormatSettings,
+        },
+        keys,
+        rate::{
+            RateController,
+            RateSettings,
+        },
+        shared::{
+            self,
+            OverlayFrame,
+        },
+        size::{
+            self,
+            Resizer,
+            SizeSettings,
+        },
+    },
+    pixel::ImageRef,
+    remote::{
+        CaptureHost,
+        RemoteCapture,
+    },
+    status::SourceStatus,
+    timing::Timer,
+};
+
+/// Settings of an overlay capture
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayConfig {
+    id: Option<CString>,
+    host: CaptureHost,
+    rate: RateSettings,
+    align_vsync: bool,
+    format: FormatSettings,
+    size: SizeSettings,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum OverlayCaptureError {
+    #[error("{0}")]
+    Init(#[from] ObsOpenVRError),
+    #[error("No overlay ID set")]
+    NoId,
+    #[error("Error finding overlay with id {0:?}: {1:?}")]
+    NotFound(CString, openvr::sys::EVROverlayError),
+    #[error("Error filling overlay image: {0:?}")]
+    Fill(openvr::sys::EVROverlayError),
+    #[error("{0}")]
+    Remote(#[from] StreamError),
+    #[error("Unsupported overlay frame from the capture host: {0:?} pixels, {1} bytes per row")]
+    UnsupportedFrame(Layout, u32),
+}
+
+/// Where overlay images come from
+enum OverlayInput {
+    Local {
+        overlay: OverlayRef,
+        frame: OverlayFrame,
+        /// OpenVR session `overlay` was found in
+        session: u64,
+    },
+    Remote {
+        remote: RemoteCapture,
+        /// Copy of the latest remote frame without row padding, which is converted in place so the
+        /// remote frame stays as the host sent it
+        data: Vec<u8>,
+    },
+}
+
+/// Copies the remote frame `data` described by `info` into `out`, dropping any row padding
+fn copy_remote_frame(info: &FrameInfo, data: &[u8], out: &mut Vec<u8>) -> Result<(), OverlayCaptureError> {
+    // The host sends overlays as they come from OpenVR, 4 bytes per pixel
+    let row_len = info.width as usize * 4;
+    let stride = info.stride as usize;
+    if info.layout.bytes_per_pixel() != 4 || stride < row_len || data.len() < info.data_len() {
+        return Err(OverlayCaptureError::UnsupportedFrame(info.layout, info.stride));
+    }
+    out.clear();
+    if row_len > 0 {
+        data.chunks(stride)
+            .take(info.height as usize)
+            .for_each(|row| out.extend_from_slice(&row[..row_len]));
+    }
+    Ok(())
+}
+
+impl OverlayInput {
+    /// Format, dimensions and pixels of the latest image
+    fn image_mut(&mut self) -> (PixelFormat, (u32, u32), &mut [u8]) {
+        match self {
+            OverlayInput::Local { frame, .. } => (frame.format(), frame.dimensions(), frame.data_mut()),
+            OverlayInput::Remote { remote, data } => {
+                let info = remote.info().expect("remote overlay image polled before the first frame");
+                let format = PixelFormat {
+                    order: match info.layout {
+                        Layout::Bgra => ChannelOrder::Bgra,
+                        // `copy_remote_frame` only accepts 4-byte layouts
+                        Layout::Rgba | Layout::Rgb => ChannelOrder::Rgba,
+                    },
+                    alpha: if info.premultiplied { AlphaMode::Premultiplied } else { AlphaMode::Straight },
+                };
+                (format, (info.width, info.height), data.as_mut_slice())
+            },
+        }
+    }
+}
+
+/// Captures the image of a single OpenVR overlay
+pub struct OverlayBackend {
+    input: OverlayInput,
+    config: OverlayConfig,
+    rate: RateController,
+    resizer: Resizer,
+}
+
+/// Sleeps for `duration`, then until the next headset vsync if `align_vsync` is set
+fn wait_next_capture(duration: Option<Duration>, align_vsync: bool) {
+    if let Some(duration) = duration {
+        thread::sleep(duration);
+    }
+    if align_vsync {
+        // Not held while sleeping
+        let until_vsync = crate::openvr_session().and_then(|_session| openvr::system::time_until_next_vsync());
+        if let Some(until_vsync) = until_vsync {
+            thread::sleep(until_vsync);
+        }
+    }
+}
+
+impl CaptureBackend for OverlayBackend {
+    type Config = OverlayConfig;
+    type Error = OverlayCaptureError;
+
+    const SYNC_ID: &'static [u8] = b"obs-openvr-overlay\0";
+    const SYNC_NAME: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"OpenVR Overlay Source\0")
+    };
+    const ASYNC_ID: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"obs-openvr-overlay-async\0")
+    };
+    const ASYNC_NAME: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"OpenVR Overlay Source (async)\0")
+    };
+    const OUTPUT_FLAGS: u32 = obs::sys::OBS_SOURCE_DEPRECATED;
+    const TRACKS_OVERLAY: bool = true;
+
+    fn config(data: &obs::sys::obs_data) -> OverlayConfig {
+        OverlayConfig {
+            id: data.get_string(keys::ID).and_then(|s| CString::new(s).ok()),
+            host: CaptureHost::from_settings(data),
+            rate: RateSettings::from_settings(data),
+            align_vsync: data.get_bool(keys::ALIGN_VSYNC),
+            format: FormatSettings::from_settings(data),
+            size: SizeSettings::from_settings(data),
+        }
+    }
+
+    fn get_defaults(settings: &mut obs::sys::obs_data) {
+        size::set_defaults(settings);
+    }
+
+    fn add_properties(props: &mut obs::Properties) {
+        add_overlay_properties(props);
+    }
+
+    fn add_async_properties(props: &mut obs::Properties) {
+        add_async_overlay_properties(props);
+    }
+
+    fn create(config: &OverlayConfig, _delivery: Delivery, status: &SourceStatus) -> Result<Self, OverlayCaptureError> {
+        let id = config.id.as_ref().ok_or(OverlayCaptureError::NoId)?;
+        let input = match config.host {
+            CaptureHost::InProcess => {
+                let session = crate::init_openvr_session()?;
+                trace!("Creating overlay capture with id: {:?}", id);
+                let overlay = openvr::overlay::find_overlay(id)
+                    .map_err(|e| OverlayCaptureError::NotFound(id.clone(), e));
+                status.set_overlay(overlay.as_ref().ok().copied());
+                let overlay = overlay?;
+                trace!("Got overlay handle: {}", overlay);
+                OverlayInput::Local {
+                    overlay: overlay,
+                    frame: OverlayFrame::new(shared::shared_image(id)),
+                    session: session.id(),
+                }
+            },
+            CaptureHost::Helper => {
+                trace!("Creating remote overlay capture with id: {:?}", id);
+                // The overlay handle lives in the host, so the status panel can't show it
+                status.set_overlay(None);
+                OverlayInput::Remote {
+                    remote: RemoteCapture::open(Target::Overlay(id.to_string_lossy().into_owned())),
+                    data: Vec::new(),
+                }
+            },
+        };
+        Ok(OverlayBackend {
+            input: input,
+            config: config.clone(),
+            rate: RateController::new(config.rate),
+            resizer: Resizer::new(config.size),
+        })
+    }
+
+    fn reconfigure(&mut self, config: &OverlayConfig) -> bool {
+        if config.id != self.config.id || config.host != self.config.host {
+            return false;
+        }
+        self.rate.set_settings(config.rate);
+        self.resizer.set_settings(config.size);
+        if config.format != self.config.format || config.size != self.config.size {
+            // Re-convert the current content with the new settings
+            self.invalidate();
+        }
+        self.config = config.clone();
+        true
+    }
+
+    fn is_stale(&self) -> bool {
+        match &self.input {
+            OverlayInput::Local { session, .. } => crate::openvr_session_id() != Some(*session),
+            OverlayInput::Remote { .. } => false,
+        }
+    }
+
+    fn invalidate(&mut self) {
+        match &mut self.input {
+            OverlayInput::Local { frame, .. } => frame.invalidate(),
+            OverlayInput::Remote { remote, .. } => remote.invalidate(),
+        }
+    }
+
+    fn capture(&mut self, status: &SourceStatus) -> Result<Capture<'_>, OverlayCaptureError> {
+        // Held until the overlay image is fetched, so SteamVR quitting doesn't end the session
+        // in the middle of it
+        let session = match &self.input {
+            OverlayInput::Local { overlay, session, .. } => {
+                let current = match crate::openvr_session() {
+                    Some(v) if v.id() == *session => v,
+                    // The overlay handle went away with its session, until the source recreates
+                    // the backend
+                    _ => return Ok(Capture::Disconnected),
+                };
+                if !overlay.is_visible() {
+                    return Ok(Capture::Hidden);
+                }
+                if openvr::compositor::in_transition() {
+                    return Ok(Capture::Loading);
+                }
+                Some(current)
+            },
+            OverlayInput::Remote { .. } => None,
+        };
+        let now = Instant::now();
+        if !self.rate.is_due(now) {
+            return Ok(Capture::NotDue);
+        }
+        let mut timestamp = unsafe { obs::sys::os_gettime_ns() };
+        let mut timer = Timer::new();
+        let changed = match &mut self.input {
+            OverlayInput::Local { overlay, frame, .. } => frame.update(overlay.handle(), cache::current_frame())
+                .map_err(OverlayCaptureError::Fill)?,
+            OverlayInput::Remote { remote, data } => match remote.poll(status)? {
+                Some(Poll::Frame) => {
+                    let info = remote.info().unwrap();
+                    // Taken from the same clock as `os_gettime_ns`
+                    timestamp = info.timestamp;
+                    copy_remote_frame(info, remote.data(), data)?;
+                    true
+                },
+                Some(Poll::Unchanged) => false,
+                Some(Poll::Hidden) => return Ok(Capture::Hidden),
+                Some(Poll::Loading) => return Ok(Capture::Loading),
+                None => return Ok(Capture::Disconnected),
+            },
+        };
+        mem::drop(session);
+        self.rate.captured(now, changed);
+        status.record_stage(Stage::Fetch, timer.checkpoint());
+        if !changed {
+            return Ok(Capture::Unchanged);
+        }
+        let (source_format, (w, h), data) = self.input.image_mut();
+        let pixel_format = format::convert(data, source_format, &self.config.format);
+        let image = self.resizer.apply(ImageRef::new(format::pixel_format(pixel_format.order), w, h, data));
+        status.record_stage(Stage::Convert, timer.checkpoint());
+        Ok(Capture::Frame(Frame {
+            dimensions: (image.width, image.height),
+            data: FrameData::Image(image),
+            encoding: self.config.format.encoding,
+            premultiplied: pixel_format.alpha == AlphaMode::Premultiplied,
+            timestamp: timestamp,
+        }))
+    }
+
+    fn wait_next_capture(&self, _started: Instant) {
+        wait_next_capture(self.rate.remaining(Instant::now()), self.config.align_vsync);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use obs_openvr_ipc::protocol::Transfer;
+    use super::*;
+
+    fn info(layout: Layout, width: u32, height: u32, stride: u32) -> FrameInfo {
+        FrameInfo {
+            width: width,
+            height: height,
+            stride: stride,
+            layout: layout,
+            premultiplied: false,
+            transfer: Transfer::Unknown,
+            timestamp: 0,
+            sequence: 0,
+        }
+    }
+
+    #[test]
+    fn remote_frame_copy_drops_row_padding() {
+        let data: Vec<u8> = (0..24).collect();
+        let mut out = vec![0xff; 3];
+        copy_remote_frame(&info(Layout::Bgra, 2, 2, 12), &data, &mut out).unwrap();
+        assert_eq!(out, [0, 1, 2, 3, 4, 5, 6, 7, 12, 13, 14, 15, 16, 17, 18, 19]);
+        // The source is left as it was, so it can be copied again after an invalidation
+        assert_eq!(data[3], 3);
+    }
+
+    #[test]
+    fn remote_frame_copy_rejects_unsupported_frames() {
+        let data = vec![0; 64];
+        let mut out = Vec::new();
+        assert!(copy_remote_frame(&info(Layout::Rgb, 2, 2, 6), &data, &mut out).is_err());
+        assert!(copy_remote_frame(&info(Layout::Rgba, 4, 2, 8), &data, &mut out).is_err());
+        assert!(copy_remote_frame(&info(Layout::Rgba, 4, 8, 16), &data[..32], &mut out).is_err());
+    }
+}
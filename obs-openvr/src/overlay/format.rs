@@ -0,0 +1,161 @@
+use std::ffi::CStr;
+use obs::data::ObsData;
+use openvr::overlay::{
+    AlphaMode,
+    ChannelOrder,
+    PixelFormat,
+};
+use crate::{
+    color::{
+        ColorSpace,
+        Encoding,
+    },
+    overlay::keys,
+    pixel,
+};
+
+/// What to do with the alpha channel of overlay pixels before handing them to OBS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaHandling {
+    /// Keep alpha, converting to the straight alpha OBS expects if needed
+    Keep,
+    /// Make every pixel opaque
+    Drop,
+    /// Premultiply colors by alpha, and draw with premultiplied blending
+    Premultiply,
+    /// Make pixels close to a key color transparent
+    ColorKey,
+}
+
+impl AlphaHandling {
+    pub const ALL: [AlphaHandling; 4] = [AlphaHandling::Keep, AlphaHandling::Drop, AlphaHandling::Premultiply, AlphaHandling::ColorKey];
+
+    pub fn key(self) -> &'static CStr {
+        match self {
+            AlphaHandling::Keep => keys::ALPHA_KEEP,
+            AlphaHandling::Drop => keys::ALPHA_DROP,
+            AlphaHandling::Premultiply => keys::ALPHA_PREMULTIPLY,
+            AlphaHandling::ColorKey => keys::ALPHA_COLOR_KEY,
+        }
+    }
+
+    pub fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            AlphaHandling::Keep => b"Keep\0",
+            AlphaHandling::Drop => b"Drop (opaque)\0",
+            AlphaHandling::Premultiply => b"Premultiply\0",
+            AlphaHandling::ColorKey => b"Color key\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+}
+
+/// Pixel conversion settings for overlay sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatSettings {
+    pub alpha: AlphaHandling,
+    /// Key color as `[r, g, b]`
+    pub key_color: [u8; 3],
+    /// Largest per-channel difference from `key_color` that is still keyed out
+    pub key_tolerance: u8,
+    /// Transfer function of overlay pixels. OpenVR doesn't report it for overlay images, so
+    /// `ColorSpace::Auto` always means sRGB.
+    pub encoding: Encoding,
+}
+
+impl FormatSettings {
+    pub fn from_settings(data: &obs::sys::obs_data) -> Self {
+        let value = data.get_cstr(keys::ALPHA);
+        let alpha = AlphaHandling::ALL.iter()
+            .find(|alpha| Some(alpha.key()) == value)
+            .copied()
+            .unwrap_or(AlphaHandling::Keep);
+        // OBS stores colors as 0xAABBGGRR
+        let color = data.get_int(keys::KEY_COLOR) as u32;
+        FormatSettings {
+            alpha: alpha,
+            key_color: [color as u8, (color >> 8) as u8, (color >> 16) as u8],
+            key_tolerance: data.get_int(keys::KEY_TOLERANCE).max(0).min(255) as u8,
+            encoding: ColorSpace::from_settings(data).resolve(None),
+        }
+    }
+}
+
+#[inline]
+fn channel_indices(order: ChannelOrder) -> [usize; 3] {
+    match order {
+        ChannelOrder::Rgba => [0, 1, 2],
+        ChannelOrder::Bgra => [2, 1, 0],
+    }
+}
+
+#[inline(always)]
+fn mul_alpha(c: u8, a: u8) -> u8 {
+    ((c as u16 * a as u16 + 127) / 255) as u8
+}
+
+#[inline(always)]
+fn div_alpha(c: u8, a: u8) -> u8 {
+    if a == 0 {
+        0
+    } else {
+        ((c as u16 * 255 + a as u16 / 2) / a as u16).min(255) as u8
+    }
+}
+
+fn set_alpha_mode(data: &mut [u8], from: AlphaMode, to: AlphaMode) {
+    let f: fn(u8, u8) -> u8 = match (from, to) {
+        (AlphaMode::Straight, AlphaMode::Premultiplied) => mul_alpha,
+        (AlphaMode::Premultiplied, AlphaMode::Straight) => div_alpha,
+        _ => return,
+    };
+    data.chunks_exact_mut(4).for_each(|px| {
+        let a = px[3];
+        if a != 255 {
+            px[0] = f(px[0], a);
+            px[1] = f(px[1], a);
+            px[2] = f(px[2], a);
+        }
+    });
+}
+
+/// Converts 4-byte `data` in `format` in place, according to `settings`. Returns the format of
+/// the converted data.
+pub fn convert(data: &mut [u8], format: PixelFormat, settings: &FormatSettings) -> PixelFormat {
+    match settings.alpha {
+        AlphaHandling::Keep => {
+            set_alpha_mode(data, format.alpha, AlphaMode::Straight);
+            PixelFormat { alpha: AlphaMode::Straight, ..format }
+        },
+        AlphaHandling::Drop => {
+            data.chunks_exact_mut(4).for_each(|px| px[3] = 255);
+            PixelFormat { alpha: AlphaMode::Straight, ..format }
+        },
+        AlphaHandling::Premultiply => {
+            set_alpha_mode(data, format.alpha, AlphaMode::Premultiplied);
+            PixelFormat { alpha: AlphaMode::Premultiplied, ..format }
+        },
+        AlphaHandling::ColorKey => {
+            set_alpha_mode(data, format.alpha, AlphaMode::Straight);
+            let idx = channel_indices(format.order);
+            let tolerance = settings.key_tolerance;
+            data.chunks_exact_mut(4).for_each(|px| {
+                let keyed = idx.iter()
+                    .zip(settings.key_color.iter())
+                    .all(|(&i, &key)| (px[i] as i16 - key as i16).abs() as u8 <= tolerance);
+                if keyed {
+                    px.copy_from_slice(&[0, 0, 0, 0]);
+                }
+            });
+            PixelFormat { alpha: AlphaMode::Straight, ..format }
+        },
+    }
+}
+
+/// Packed pixel format for pixels with the given channel order
+pub fn pixel_format(order: ChannelOrder) -> pixel::Format {
+    match order {
+        ChannelOrder::Rgba => pixel::Format::Rgba,
+        ChannelOrder::Bgra => pixel::Format::Bgra,
+    }
+}
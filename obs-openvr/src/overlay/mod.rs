@@ -1,147 +1,75 @@
-mod async_source;
+mod backend;
+mod format;
+mod rate;
+mod shared;
+mod size;
 
-use std::{
-    cell::Cell,
-    ffi::{
-        CStr,
-        CString,
-    },
-    sync::{
-        RwLock,
-    },
-};
-use openvr::{
-    overlay::{
-        OverlayImage,
-        OverlayRef,
-    },
-};
-use obs::{
-    OwnedPointerContainer,
-    data::ObsData,
-    graphics::{
-        GsTexture,
-        with_graphics,
-    },
+use std::ffi::CStr;
+
+use crate::backend::{
+    AsyncCaptureSource,
+    SyncCaptureSource,
 };
 
-pub use async_source::OpenVRAsyncOverlaySource;
+pub use backend::OverlayBackend;
+use format::AlphaHandling;
+use size::{
+    FitMode,
+    SizeMode,
+};
 
-pub struct OpenVROverlaySource {
-    handle: *mut obs::sys::obs_source_t,
-    image: RwLock<OverlayImage>,
-    texture: RwLock<Option<obs::graphics::Texture>>,
-    overlay_handle: Cell<Option<OverlayRef>>,
-    dimensions: Cell<(u32, u32)>,
-}
+/// Overlay source capturing on the render thread
+pub type OpenVROverlaySource = SyncCaptureSource<OverlayBackend>;
+/// Overlay source capturing on a worker thread
+pub type OpenVRAsyncOverlaySource = AsyncCaptureSource<OverlayBackend>;
 
-impl OpenVROverlaySource {
-    #[inline(always)]
-    pub fn is_showing(&self) -> bool {
-        unsafe {
-            obs::sys::obs_source_showing(self.handle)
-        }
-    }
+/// Properties of overlay sources, besides `backend::add_common_properties`
+pub(crate) fn add_overlay_properties(props: &mut obs::Properties) {
+    use obs::properties::{
+        PropertiesExt,
+        PropertyDescription,
+    };
 
-    fn linesize(&self) -> u32 {
-        use obs::source::VideoSource;
-        let (w, _h) = self.get_dimensions();
-        w * 4
-    }
+    props.add_text(keys::ID, keys::ID, obs::sys::obs_text_type_OBS_TEXT_DEFAULT);
+    let target_fps_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Target FPS (0 to follow OBS)\0") };
+    let adaptive_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Adapt capture rate to OBS load and overlay activity\0") };
+    props.add_int(keys::TARGET_FPS, target_fps_description, 0, 240, 1);
+    props.add_bool(keys::ADAPTIVE_RATE, adaptive_description);
+    let alpha_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Alpha\0") };
+    props.add_string_list_complete(PropertyDescription::new(keys::ALPHA, Some(alpha_description)), AlphaHandling::ALL.iter().map(|alpha| (alpha.description(), alpha.key())));
+    let key_color_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Key color\0") };
+    let key_tolerance_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Key color tolerance\0") };
+    props.add_color(keys::KEY_COLOR, key_color_description);
+    props.add_int(keys::KEY_TOLERANCE, key_tolerance_description, 0, 255, 1);
+    add_size_properties(props);
 }
 
-impl obs::source::VideoSource for OpenVROverlaySource {
-    const ID: &'static [u8] = b"obs-openvr-overlay\0";
-    const OUTPUT_FLAGS: Option<u32> = None;
-
-    fn create(settings: &mut obs::sys::obs_data, source: *mut obs::sys::obs_source_t) -> Self {
-        let ret = OpenVROverlaySource {
-            handle: source,
-            image: RwLock::new(OverlayImage::new()),
-            texture: RwLock::new(None),
-            overlay_handle: Cell::new(None),
-            dimensions: Cell::new((0, 0)),
-        };
-        ret.update(settings);
-        ret
-    }
-
-    fn get_name() -> &'static CStr {
-        unsafe { CStr::from_bytes_with_nul_unchecked(b"OpenVR Overlay Source\0") }
-    }
-
-    #[inline]
-    fn get_dimensions(&self) -> (u32, u32) {
-        self.dimensions.get()
-    }
-
-    fn get_properties(&self) -> *mut obs::sys::obs_properties {
-        use obs::properties::{
-            Properties,
-            PropertiesExt,
-            PropertyDescription,
-        };
-
-        let mut props = Properties::new();
-
-        let overlay_id_name = keys::ID;
-        props.add_text(overlay_id_name, overlay_id_name, obs::sys::obs_text_type_OBS_TEXT_DEFAULT);
-
-        unsafe { props.leak() }
-    }
+fn add_size_properties(props: &mut obs::Properties) {
+    use obs::properties::{
+        PropertiesExt,
+        PropertyDescription,
+    };
 
-    fn update(&self, data: &obs::sys::obs_data) {
-        let id_key = keys::ID;
-        if let Some(id) = data.get_string(id_key).and_then(|s| CString::new(s).ok()) {
-            trace!("Updating overlay source with id: {:?}", &id);
-            let new_handle = match openvr::overlay::find_overlay(&id) {
-                Ok(v) => {
-                    trace!("Got overlay handle: {}", v);
-                    Some(v)
-                },
-                Err(e) => {
-                    warn!("Error finding overlay with id {:?}: {:?}", &id, &e);
-                    None
-                },
-            };
-            self.overlay_handle.set(new_handle);
-        }
-    }
+    let size_mode_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Output size\0") };
+    props.add_string_list_complete(PropertyDescription::new(keys::SIZE_MODE, Some(size_mode_description)), SizeMode::ALL.iter().map(|mode| (mode.description(), mode.key())));
+    let width_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Output width\0") };
+    let height_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Output height\0") };
+    props.add_int(keys::OUTPUT_WIDTH, width_description, 0, 8192, 1);
+    props.add_int(keys::OUTPUT_HEIGHT, height_description, 0, 8192, 1);
+    let fit_mode_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Aspect ratio\0") };
+    props.add_string_list_complete(PropertyDescription::new(keys::FIT_MODE, Some(fit_mode_description)), FitMode::ALL.iter().map(|fit| (fit.description(), fit.key())));
+    let scale_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Scale (%)\0") };
+    props.add_int(keys::SCALE_PERCENT, scale_description, 1, 400, 1);
+    let trim_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Trim transparent borders\0") };
+    props.add_bool(keys::TRIM_TRANSPARENT, trim_description);
+}
 
-    fn video_tick(&self, _seconds: f32) {
-        if let Some(overlay) = self.overlay_handle.get() {
-            if !self.is_showing() || !overlay.is_visible() {
-                return;
-            }
-            let overlay_handle = overlay.handle();
-            let mut image = self.image.write().unwrap();
-            if let Err(e) = image.fill(overlay_handle) {
-                error!("Error filling overlay image: {:?}", &e);
-                return;
-            }
-            self.dimensions.set(image.dimensions());
-            let mut texture = self.texture.write().unwrap();
-            with_graphics(|| match &mut *texture {
-                &mut Some(ref mut texture) if texture.get_dimensions() == self.dimensions.get() => unsafe {
-                    texture.set_image_unchecked(image.data(), self.linesize(), false);
-                },
-                texture => unsafe {
-                    let (w, h) = self.dimensions.get();
-                    *texture = obs::graphics::Texture::new(w, h, obs::sys::gs_color_format_GS_BGRA, &[image.data().as_ptr()], obs::sys::GS_DYNAMIC);
-                    if texture.is_none() {
-                        error!("Error creating obs texture from image data");
-                    }
-                },
-            });
-        }
-    }
+/// Properties only used by the async overlay source
+pub(crate) fn add_async_overlay_properties(props: &mut obs::Properties) {
+    use obs::properties::PropertiesExt;
 
-    fn video_render(&self, _effect: *mut obs::sys::gs_effect_t) {
-        let texture = self.texture.read().unwrap();
-        texture.as_ref().into_iter().for_each(|texture| {
-            obs::source::draw(texture, 0, 0, 0, 0, false);
-        });
-    }
+    let align_vsync_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Align capture to headset vsync\0") };
+    props.add_bool(keys::ALIGN_VSYNC, align_vsync_description);
 }
 
 pub(crate) mod keys {
@@ -150,4 +78,70 @@ pub(crate) mod keys {
     pub const ID: &'static CStr = unsafe {
         CStr::from_bytes_with_nul_unchecked(b"id\0")
     };
+    pub const TARGET_FPS: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"target_fps\0")
+    };
+    pub const ADAPTIVE_RATE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"adaptive_rate\0")
+    };
+    pub const ALIGN_VSYNC: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"align_vsync\0")
+    };
+    pub const ALPHA: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"alpha\0")
+    };
+    pub const ALPHA_KEEP: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"keep\0")
+    };
+    pub const ALPHA_DROP: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"drop\0")
+    };
+    pub const ALPHA_PREMULTIPLY: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"premultiply\0")
+    };
+    pub const ALPHA_COLOR_KEY: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"color_key\0")
+    };
+    pub const KEY_COLOR: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"key_color\0")
+    };
+    pub const KEY_TOLERANCE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"key_tolerance\0")
+    };
+    pub const SIZE_MODE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"size_mode\0")
+    };
+    pub const SIZE_MODE_NATIVE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"native\0")
+    };
+    pub const SIZE_MODE_FIXED: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"fixed\0")
+    };
+    pub const SIZE_MODE_SCALE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"scale\0")
+    };
+    pub const OUTPUT_WIDTH: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"output_width\0")
+    };
+    pub const OUTPUT_HEIGHT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"output_height\0")
+    };
+    pub const FIT_MODE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"fit_mode\0")
+    };
+    pub const FIT_MODE_FIT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"fit\0")
+    };
+    pub const FIT_MODE_FILL: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"fill\0")
+    };
+    pub const FIT_MODE_STRETCH: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"stretch\0")
+    };
+    pub const SCALE_PERCENT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"scale_percent\0")
+    };
+    pub const TRIM_TRANSPARENT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"trim_transparent\0")
+    };
 }
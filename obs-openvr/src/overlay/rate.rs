@@ -0,0 +1,141 @@
+use std::time::{
+    Duration,
+    Instant,
+};
+use obs::data::ObsData;
+use crate::overlay::keys;
+
+/// Slowest rate adaptive capture will drop to
+const MIN_INTERVAL_ADAPTIVE: Duration = Duration::from_secs(1);
+/// How long overlay content has to stay the same before adaptive capture slows down
+const IDLE_AFTER: Duration = Duration::from_secs(2);
+/// Factor the capture interval is multiplied by when backing off
+const BACKOFF_FACTOR: u32 = 2;
+/// Fraction (out of 8) of the distance to the target interval recovered on every quiet frame
+const RECOVERY_EIGHTHS: u32 = 1;
+
+/// Capture rate settings for overlay sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateSettings {
+    /// Target capture rate. `None` follows the OBS output frame rate.
+    pub target_fps: Option<u32>,
+    pub adaptive: bool,
+}
+
+impl RateSettings {
+    pub fn from_settings(data: &obs::sys::obs_data) -> Self {
+        let target_fps = match data.get_int(keys::TARGET_FPS) {
+            n if n > 0 => Some(n as u32),
+            _ => None,
+        };
+        RateSettings {
+            target_fps: target_fps,
+            adaptive: data.get_bool(keys::ADAPTIVE_RATE),
+        }
+    }
+
+    fn target_interval(&self) -> Duration {
+        match self.target_fps {
+            Some(fps) => Duration::from_secs(1) / fps,
+            None => Duration::from_nanos(unsafe { obs::sys::obs_get_frame_interval_ns() }),
+        }
+    }
+}
+
+/// Frame counters OBS keeps for frames it couldn't render or encode in time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ObsFrameCounters {
+    lagged: u32,
+    skipped: u32,
+}
+
+impl ObsFrameCounters {
+    fn query() -> Self {
+        unsafe {
+            let video = obs::sys::obs_get_video();
+            ObsFrameCounters {
+                lagged: obs::sys::obs_get_lagged_frames(),
+                skipped: if video.is_null() { 0 } else { obs::sys::video_output_get_skipped_frames(video) },
+            }
+        }
+    }
+}
+
+/// Decides when an overlay source should capture its next frame.
+///
+/// With adaptive capture enabled, the capture interval is backed off when OBS reports lagged or
+/// skipped frames or when the overlay content stops changing, and recovers towards the target
+/// rate once OBS keeps up and the content changes again.
+#[derive(Debug)]
+pub struct RateController {
+    settings: RateSettings,
+    interval: Duration,
+    next_capture: Option<Instant>,
+    counters: ObsFrameCounters,
+    last_change: Instant,
+}
+
+impl RateController {
+    pub fn new(settings: RateSettings) -> Self {
+        RateController {
+            settings: settings,
+            interval: settings.target_interval(),
+            next_capture: None,
+            counters: ObsFrameCounters::query(),
+            last_change: Instant::now(),
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: RateSettings) {
+        if settings != self.settings {
+            *self = RateController::new(settings);
+        }
+    }
+
+    /// Whether a capture should happen at `now`. Used by sources that are polled every OBS frame.
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.next_capture.map(|t| now >= t).unwrap_or(true)
+    }
+
+    /// Time left until the next capture, for sources that sleep between captures
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.next_capture.and_then(|t| t.checked_duration_since(now))
+    }
+
+    /// Records a capture started at `started`, adapting the capture rate if enabled. `changed`
+    /// is whether the overlay content differed from the previous capture.
+    pub fn captured(&mut self, started: Instant, changed: bool) {
+        if self.settings.adaptive {
+            self.adapt(started, changed);
+        }
+        self.next_capture = Some(started + self.interval);
+    }
+
+    fn adapt(&mut self, now: Instant, changed: bool) {
+        let target = self.settings.target_interval();
+
+        if changed {
+            self.last_change = now;
+        }
+
+        let counters = ObsFrameCounters::query();
+        let lagging = counters.lagged > self.counters.lagged || counters.skipped > self.counters.skipped;
+        self.counters = counters;
+
+        let idle = now.duration_since(self.last_change) >= IDLE_AFTER;
+        let max_interval = MIN_INTERVAL_ADAPTIVE.max(target);
+        self.interval = if lagging || idle {
+            (self.interval * BACKOFF_FACTOR).min(max_interval)
+        } else if changed && self.interval > target {
+            // Content is moving again, go straight back to the target rate
+            target
+        } else if self.interval > target {
+            self.interval - ((self.interval - target) * RECOVERY_EIGHTHS) / 8
+        } else {
+            target
+        };
+        if lagging || idle {
+            trace!("backing off overlay capture to {:?} (lagging: {}, idle: {})", &self.interval, lagging, idle);
+        }
+    }
+}
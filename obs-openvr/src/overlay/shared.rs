@@ -0,0 +1,97 @@
+use std::{
+    convert::Infallible,
+    ffi::{
+        CStr,
+        CString,
+    },
+    sync::Arc,
+};
+use openvr::overlay::{
+    OverlayImage,
+    PixelFormat,
+};
+use crate::cache::{
+    CaptureCache,
+    SharedCapture,
+};
+
+static SHARED_IMAGES: CaptureCache<CString, OverlayImage> = CaptureCache::new();
+
+/// Overlay image fetched once per frame for every source capturing the same overlay key
+pub type SharedOverlayImage = Arc<SharedCapture<OverlayImage>>;
+
+pub fn shared_image(key: &CStr) -> SharedOverlayImage {
+    let ret = SHARED_IMAGES.get_or_try_insert_with(&key.to_owned(), || Ok::<_, Infallible>(OverlayImage::new()));
+    trace!("{} shared overlay images in use", SHARED_IMAGES.len());
+    match ret {
+        Ok(v) => v,
+        Err(e) => match e {},
+    }
+}
+
+/// A source's own copy of a shared overlay image, which it is free to convert in place
+pub struct OverlayFrame {
+    shared: SharedOverlayImage,
+    /// Generation of the shared image last copied, if any
+    generation: Option<u64>,
+    data: Vec<u8>,
+    format: PixelFormat,
+    dimensions: (u32, u32),
+}
+
+impl OverlayFrame {
+    pub fn new(shared: SharedOverlayImage) -> Self {
+        OverlayFrame {
+            shared: shared,
+            generation: None,
+            data: Vec::new(),
+            format: PixelFormat::OVERLAY_DATA,
+            dimensions: (0, 0),
+        }
+    }
+
+    /// Fetches the overlay for OBS frame `frame`, unless another source already did, and copies
+    /// it if it changed since the last update. Returns whether it changed.
+    pub fn update(&mut self, handle: openvr::sys::VROverlayHandle_t, frame: u64) -> Result<bool, openvr::sys::EVROverlayError> {
+        let mut shared = self.shared.lock();
+        shared.capture_once(frame, |image| {
+            image.fill(handle)?;
+            Ok(image.changed())
+        })?;
+        if self.generation == Some(shared.generation()) {
+            return Ok(false);
+        }
+        let image = shared.get();
+        self.data.clear();
+        self.data.extend_from_slice(image.data());
+        self.format = image.format();
+        self.dimensions = image.dimensions();
+        self.generation = Some(shared.generation());
+        Ok(true)
+    }
+
+    /// Makes the next `update` copy the shared image, even if it didn't change
+    pub fn invalidate(&mut self) {
+        self.generation = None;
+    }
+
+    #[inline]
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    #[inline]
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    #[inline]
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
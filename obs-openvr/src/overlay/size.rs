@@ -0,0 +1,329 @@
+use std::ffi::CStr;
+use obs::data::ObsData;
+use crate::{
+    overlay::keys,
+    pixel::{
+        self,
+        Filter,
+        ImageBuf,
+        ImageRef,
+        Rect,
+    },
+};
+
+/// How the output size of an overlay source is chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeMode {
+    /// Whatever size the overlay currently has
+    Native,
+    /// A fixed size, independent of the overlay
+    Fixed,
+    /// A percentage of the overlay's size
+    Scale,
+}
+
+/// How overlay content is placed in a fixed output size with a different aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitMode {
+    /// Scale to fit inside the output, letterboxing with transparent borders
+    Fit,
+    /// Scale to cover the output, cropping what doesn't fit
+    Fill,
+    /// Scale each axis independently
+    Stretch,
+}
+
+impl SizeMode {
+    pub const ALL: [SizeMode; 3] = [SizeMode::Native, SizeMode::Fixed, SizeMode::Scale];
+
+    pub fn key(self) -> &'static CStr {
+        match self {
+            SizeMode::Native => keys::SIZE_MODE_NATIVE,
+            SizeMode::Fixed => keys::SIZE_MODE_FIXED,
+            SizeMode::Scale => keys::SIZE_MODE_SCALE,
+        }
+    }
+
+    pub fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            SizeMode::Native => b"Native\0",
+            SizeMode::Fixed => b"Fixed size\0",
+            SizeMode::Scale => b"Scale\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+}
+
+impl FitMode {
+    pub const ALL: [FitMode; 3] = [FitMode::Fit, FitMode::Fill, FitMode::Stretch];
+
+    pub fn key(self) -> &'static CStr {
+        match self {
+            FitMode::Fit => keys::FIT_MODE_FIT,
+            FitMode::Fill => keys::FIT_MODE_FILL,
+            FitMode::Stretch => keys::FIT_MODE_STRETCH,
+        }
+    }
+
+    pub fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            FitMode::Fit => b"Fit (letterbox)\0",
+            FitMode::Fill => b"Fill (crop)\0",
+            FitMode::Stretch => b"Stretch\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+}
+
+/// Output size settings for overlay sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeSettings {
+    pub mode: SizeMode,
+    /// Output size in `SizeMode::Fixed`. A zero dimension falls back to the native size.
+    pub width: u32,
+    pub height: u32,
+    pub fit: FitMode,
+    /// Output size in `SizeMode::Scale`, in percent of the native size. Zero means 100%.
+    pub scale_percent: u32,
+    /// Whether fully transparent borders are trimmed before sizing
+    pub trim_transparent: bool,
+}
+
+/// Where overlay content ends up in the output image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// Region of the overlay image that is shown
+    pub src: Rect,
+    /// Region of the output it is scaled into
+    pub dst: Rect,
+    pub output: (u32, u32),
+}
+
+impl Layout {
+    fn is_identity(&self, content: (u32, u32)) -> bool {
+        let full = Rect::new(0, 0, content.0, content.1);
+        self.src == full && self.dst == full && self.output == content
+    }
+}
+
+#[inline]
+fn scaled(v: u32, num: u64, den: u64) -> u32 {
+    ((v as u64 * num + den / 2) / den).max(1) as u32
+}
+
+impl SizeSettings {
+    pub fn from_settings(data: &obs::sys::obs_data) -> Self {
+        let mode = data.get_cstr(keys::SIZE_MODE);
+        let fit = data.get_cstr(keys::FIT_MODE);
+        SizeSettings {
+            mode: SizeMode::ALL.iter()
+                .find(|m| Some(m.key()) == mode)
+                .copied()
+                .unwrap_or(SizeMode::Native),
+            width: data.get_int(keys::OUTPUT_WIDTH).max(0) as u32,
+            height: data.get_int(keys::OUTPUT_HEIGHT).max(0) as u32,
+            fit: FitMode::ALL.iter()
+                .find(|f| Some(f.key()) == fit)
+                .copied()
+                .unwrap_or(FitMode::Fit),
+            scale_percent: data.get_int(keys::SCALE_PERCENT).max(0) as u32,
+            trim_transparent: data.get_bool(keys::TRIM_TRANSPARENT),
+        }
+    }
+
+    /// Whether output is always the overlay image as-is
+    #[inline]
+    pub fn is_native(&self) -> bool {
+        self.mode == SizeMode::Native && !self.trim_transparent
+    }
+
+    /// Lays out the `content` region of an overlay image in the output
+    pub fn layout(&self, content: Rect) -> Layout {
+        let (sw, sh) = (content.width, content.height);
+        let full = |w: u32, h: u32| Layout {
+            src: content,
+            dst: Rect::new(0, 0, w, h),
+            output: (w, h),
+        };
+        if sw == 0 || sh == 0 {
+            return full(sw, sh);
+        }
+        match self.mode {
+            SizeMode::Native => full(sw, sh),
+            SizeMode::Scale if self.scale_percent == 0 => full(sw, sh),
+            SizeMode::Scale => full(scaled(sw, self.scale_percent as u64, 100), scaled(sh, self.scale_percent as u64, 100)),
+            SizeMode::Fixed if self.width == 0 || self.height == 0 => full(sw, sh),
+            SizeMode::Fixed => {
+                let (ow, oh) = (self.width, self.height);
+                // Compare aspect ratios without floats: content is wider if sw / sh > ow / oh
+                let wider = sw as u64 * oh as u64 > ow as u64 * sh as u64;
+                match self.fit {
+                    FitMode::Stretch => full(ow, oh),
+                    FitMode::Fit => {
+                        let (dw, dh) = if wider {
+                            (ow, scaled(sh, ow as u64, sw as u64).min(oh))
+                        } else {
+                            (scaled(sw, oh as u64, sh as u64).min(ow), oh)
+                        };
+                        Layout {
+                            src: content,
+                            dst: Rect::new((ow - dw) / 2, (oh - dh) / 2, dw, dh),
+                            output: (ow, oh),
+                        }
+                    },
+                    FitMode::Fill => {
+                        let (cw, ch) = if wider {
+                            (scaled(ow, sh as u64, oh as u64).min(sw), sh)
+                        } else {
+                            (sw, scaled(oh, sw as u64, ow as u64).min(sh))
+                        };
+                        Layout {
+                            src: Rect::new(content.x + (sw - cw) / 2, content.y + (sh - ch) / 2, cw, ch),
+                            dst: Rect::new(0, 0, ow, oh),
+                            output: (ow, oh),
+                        }
+                    },
+                }
+            },
+        }
+    }
+}
+
+pub fn set_defaults(settings: &mut obs::sys::obs_data) {
+    settings.set_default_string(keys::SIZE_MODE, keys::SIZE_MODE_NATIVE);
+    settings.set_default_string(keys::FIT_MODE, keys::FIT_MODE_FIT);
+    settings.set_default_int(keys::SCALE_PERCENT, 100);
+}
+
+/// Applies `SizeSettings` to overlay images, reusing its buffers between frames
+#[derive(Debug)]
+pub struct Resizer {
+    settings: SizeSettings,
+    cropped: ImageBuf,
+    scaled: ImageBuf,
+    output: ImageBuf,
+}
+
+impl Resizer {
+    pub fn new(settings: SizeSettings) -> Self {
+        Resizer {
+            settings: settings,
+            cropped: ImageBuf::new(pixel::Format::Rgba, 0, 0),
+            scaled: ImageBuf::new(pixel::Format::Rgba, 0, 0),
+            output: ImageBuf::new(pixel::Format::Rgba, 0, 0),
+        }
+    }
+
+    #[inline]
+    pub fn settings(&self) -> SizeSettings {
+        self.settings
+    }
+
+    #[inline]
+    pub fn set_settings(&mut self, settings: SizeSettings) {
+        self.settings = settings;
+    }
+
+    /// Resizes packed RGBA or BGRA `src` according to the settings. Returns `src` itself if
+    /// nothing needs to change.
+    pub fn apply<'a>(&'a mut self, src: ImageRef<'a>) -> ImageRef<'a> {
+        if self.settings.is_native() {
+            return src;
+        }
+        let full = Rect::new(0, 0, src.width, src.height);
+        let content = if self.settings.trim_transparent {
+            pixel::alpha_bounds(&src).unwrap_or(full)
+        } else {
+            full
+        };
+        let layout = self.settings.layout(content);
+        if layout.is_identity((src.width, src.height)) {
+            return src;
+        }
+
+        let cropped = if layout.src == full {
+            src
+        } else {
+            pixel::crop_into(&src, layout.src, &mut self.cropped);
+            self.cropped.as_image()
+        };
+        let (dw, dh) = (layout.dst.width, layout.dst.height);
+        let scaled = if (dw, dh) == (cropped.width, cropped.height) {
+            cropped
+        } else {
+            let filter = if dw <= cropped.width && dh <= cropped.height { Filter::Box } else { Filter::Bilinear };
+            pixel::scale_into(&cropped, dw, dh, filter, &mut self.scaled);
+            self.scaled.as_image()
+        };
+        if layout.dst == Rect::new(0, 0, layout.output.0, layout.output.1) {
+            return scaled;
+        }
+        let (ow, oh) = layout.output;
+        self.output.reset(src.format, ow, oh);
+        self.output.clear();
+        pixel::blit(&scaled, &mut self.output, layout.dst.x, layout.dst.y);
+        self.output.as_image()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed(width: u32, height: u32, fit: FitMode) -> SizeSettings {
+        SizeSettings {
+            mode: SizeMode::Fixed,
+            width: width,
+            height: height,
+            fit: fit,
+            scale_percent: 0,
+            trim_transparent: false,
+        }
+    }
+
+    #[test]
+    fn fit_letterboxes_wide_content() {
+        let layout = fixed(100, 100, FitMode::Fit).layout(Rect::new(0, 0, 200, 100));
+        assert_eq!(layout.output, (100, 100));
+        assert_eq!(layout.dst, Rect::new(0, 25, 100, 50));
+        assert_eq!(layout.src, Rect::new(0, 0, 200, 100));
+    }
+
+    #[test]
+    fn fill_crops_tall_content() {
+        let layout = fixed(100, 50, FitMode::Fill).layout(Rect::new(10, 0, 100, 100));
+        assert_eq!(layout.dst, Rect::new(0, 0, 100, 50));
+        assert_eq!(layout.src, Rect::new(10, 25, 100, 50));
+    }
+
+    #[test]
+    fn fixed_output_ignores_content_size() {
+        let settings = fixed(640, 360, FitMode::Stretch);
+        assert_eq!(settings.layout(Rect::new(0, 0, 123, 456)).output, (640, 360));
+        assert_eq!(settings.layout(Rect::new(0, 0, 1920, 1080)).output, (640, 360));
+    }
+
+    #[test]
+    fn scale_percent() {
+        let settings = SizeSettings {
+            mode: SizeMode::Scale,
+            scale_percent: 50,
+            ..fixed(0, 0, FitMode::Fit)
+        };
+        assert_eq!(settings.layout(Rect::new(0, 0, 301, 100)).output, (151, 50));
+    }
+
+    #[test]
+    fn trim_and_letterbox() {
+        // 4x2 RGBA with a single opaque pixel at (1, 1)
+        let mut src = vec![0u8; 4 * 2 * 4];
+        src[(4 + 1) * 4..(4 + 2) * 4].copy_from_slice(&[9, 9, 9, 255]);
+        let mut resizer = Resizer::new(SizeSettings {
+            trim_transparent: true,
+            ..fixed(3, 1, FitMode::Fit)
+        });
+        let out = resizer.apply(ImageRef::new(pixel::Format::Rgba, 4, 2, &src));
+        assert_eq!((out.width, out.height), (3, 1));
+        assert_eq!(out.data, &[0, 0, 0, 0, 9, 9, 9, 255, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,378 @@
+use super::{
+    Format,
+    ImageBuf,
+    ImageRef,
+    simd,
+};
+
+/// RGB <-> YUV conversion matrix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YuvMatrix {
+    Bt601,
+    Bt709,
+}
+
+impl YuvMatrix {
+    /// `(Kr, Kb)` luma coefficients
+    fn coefficients(self) -> (f64, f64) {
+        match self {
+            YuvMatrix::Bt601 => (0.299, 0.114),
+            YuvMatrix::Bt709 => (0.2126, 0.0722),
+        }
+    }
+}
+
+/// Range of YUV sample values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YuvRange {
+    /// Y in 16..=235, U and V in 16..=240
+    Limited,
+    /// Y, U and V in 0..=255
+    Full,
+}
+
+/// Parameters for conversions between RGB and YUV formats. Ignored for other conversions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct YuvParams {
+    pub matrix: YuvMatrix,
+    pub range: YuvRange,
+}
+
+impl Default for YuvParams {
+    fn default() -> Self {
+        YuvParams {
+            matrix: YuvMatrix::Bt709,
+            range: YuvRange::Limited,
+        }
+    }
+}
+
+const SHIFT: u32 = 16;
+const HALF: i32 = 1 << (SHIFT - 1);
+
+#[inline(always)]
+fn fixed(v: f64) -> i32 {
+    (v * (1 << SHIFT) as f64).round() as i32
+}
+
+#[inline(always)]
+fn clamp_u8(v: i32) -> u8 {
+    v.clamp(0, 255) as u8
+}
+
+/// Fixed-point RGB <-> YUV coefficients
+#[derive(Debug, Clone, Copy)]
+struct Coefficients {
+    y: [i32; 3],
+    u: [i32; 3],
+    v: [i32; 3],
+    y_offset: i32,
+    y_scale: i32,
+    v_r: i32,
+    u_g: i32,
+    v_g: i32,
+    u_b: i32,
+}
+
+impl Coefficients {
+    fn new(params: YuvParams) -> Self {
+        let (kr, kb) = params.matrix.coefficients();
+        let kg = 1.0 - kr - kb;
+        let (y_offset, y_range, c_range) = match params.range {
+            YuvRange::Limited => (16, 219.0, 224.0),
+            YuvRange::Full => (0, 255.0, 255.0),
+        };
+        let (ys, cs) = (y_range / 255.0, c_range / 255.0);
+        let (cb, cr) = (2.0 * (1.0 - kb), 2.0 * (1.0 - kr));
+        Coefficients {
+            y: [fixed(kr * ys), fixed(kg * ys), fixed(kb * ys)],
+            u: [fixed(-kr / cb * cs), fixed(-kg / cb * cs), fixed((1.0 - kb) / cb * cs)],
+            v: [fixed((1.0 - kr) / cr * cs), fixed(-kg / cr * cs), fixed(-kb / cr * cs)],
+            y_offset: y_offset,
+            y_scale: fixed(1.0 / ys),
+            v_r: fixed(cr / cs),
+            u_g: fixed(-cb * kb / kg / cs),
+            v_g: fixed(-cr * kr / kg / cs),
+            u_b: fixed(cb / cs),
+        }
+    }
+
+    #[inline(always)]
+    fn dot(c: &[i32; 3], rgb: [i32; 3]) -> i32 {
+        c[0] * rgb[0] + c[1] * rgb[1] + c[2] * rgb[2]
+    }
+
+    #[inline(always)]
+    fn luma(&self, rgb: [i32; 3]) -> u8 {
+        clamp_u8(((Self::dot(&self.y, rgb) + HALF) >> SHIFT) + self.y_offset)
+    }
+
+    #[inline(always)]
+    fn chroma(&self, rgb: [i32; 3]) -> (u8, u8) {
+        let u = ((Self::dot(&self.u, rgb) + HALF) >> SHIFT) + 128;
+        let v = ((Self::dot(&self.v, rgb) + HALF) >> SHIFT) + 128;
+        (clamp_u8(u), clamp_u8(v))
+    }
+
+    #[inline(always)]
+    fn rgb(&self, y: u8, u: u8, v: u8) -> [u8; 3] {
+        let y = (y as i32 - self.y_offset) * self.y_scale + HALF;
+        let (u, v) = (u as i32 - 128, v as i32 - 128);
+        [
+            clamp_u8((y + self.v_r * v) >> SHIFT),
+            clamp_u8((y + self.u_g * u + self.v_g * v) >> SHIFT),
+            clamp_u8((y + self.u_b * u) >> SHIFT),
+        ]
+    }
+}
+
+/// Indices of the red, green and blue channels in a pixel of a packed format
+fn rgb_indices(format: Format) -> Option<[usize; 3]> {
+    match format {
+        Format::Rgb | Format::Rgba => Some([0, 1, 2]),
+        Format::Bgra => Some([2, 1, 0]),
+        Format::Nv12 | Format::I420 => None,
+    }
+}
+
+/// Converts `src` to `format`, allocating a new image
+pub fn convert(src: &ImageRef<'_>, format: Format, params: YuvParams) -> ImageBuf {
+    let mut ret = ImageBuf::new(format, src.width, src.height);
+    convert_into(src, format, params, &mut ret);
+    ret
+}
+
+/// Converts `src` to `format`, writing the result to `dst`
+pub fn convert_into(src: &ImageRef<'_>, format: Format, params: YuvParams, dst: &mut ImageBuf) {
+    dst.reset(format, src.width, src.height);
+    match (src.format, format) {
+        (a, b) if a == b => copy_planes(src, dst),
+        (Format::Rgba, Format::Bgra) | (Format::Bgra, Format::Rgba) => {
+            rows_into(src, dst, simd::swap_rb);
+        },
+        (Format::Rgb, Format::Rgba) | (Format::Rgb, Format::Bgra) => {
+            let order = rgb_indices(format).unwrap();
+            rows_into(src, dst, |s, d| simd::expand_rgb(s, d, order));
+        },
+        (Format::Rgba, Format::Rgb) | (Format::Bgra, Format::Rgb) => {
+            let order = rgb_indices(src.format).unwrap();
+            rows_into(src, dst, |s, d| {
+                s.chunks_exact(4).zip(d.chunks_exact_mut(3)).for_each(|(s, d)| {
+                    d[0] = s[order[0]];
+                    d[1] = s[order[1]];
+                    d[2] = s[order[2]];
+                });
+            });
+        },
+        (Format::Nv12, Format::I420) | (Format::I420, Format::Nv12) => {
+            convert_chroma_layout(src, dst);
+        },
+        (_, Format::Nv12) | (_, Format::I420) => rgb_to_yuv(src, dst, Coefficients::new(params)),
+        _ => yuv_to_rgb(src, dst, Coefficients::new(params)),
+    }
+}
+
+/// Applies `f` to each row of a single-plane `src` and the matching row of `dst`
+fn rows_into<F: FnMut(&[u8], &mut [u8])>(src: &ImageRef<'_>, dst: &mut ImageBuf, mut f: F) {
+    let plane = dst.format().planes(src.width, src.height)[0];
+    let [dst_data, _, _] = dst.planes_mut();
+    dst_data.chunks_exact_mut(plane.row_bytes().max(1))
+        .take(plane.height)
+        .enumerate()
+        .for_each(|(y, row)| f(src.row(0, y), row));
+}
+
+fn copy_planes(src: &ImageRef<'_>, dst: &mut ImageBuf) {
+    let planes = src.format.planes(src.width, src.height);
+    let dst_planes = dst.planes_mut();
+    for (idx, (plane, dst_data)) in planes.iter().zip(dst_planes).enumerate().take(src.format.plane_count()) {
+        if plane.size() == 0 {
+            continue;
+        }
+        dst_data.chunks_exact_mut(plane.row_bytes())
+            .enumerate()
+            .for_each(|(y, row)| row.copy_from_slice(src.row(idx, y)));
+    }
+}
+
+fn copy_luma(src: &ImageRef<'_>, dst: &mut [u8]) {
+    let plane = src.format.planes(src.width, src.height)[0];
+    if plane.size() == 0 {
+        return;
+    }
+    dst.chunks_exact_mut(plane.row_bytes())
+        .enumerate()
+        .for_each(|(y, row)| row.copy_from_slice(src.row(0, y)));
+}
+
+/// NV12 <-> I420
+fn convert_chroma_layout(src: &ImageRef<'_>, dst: &mut ImageBuf) {
+    let [y_plane, p1, p2] = dst.planes_mut();
+    copy_luma(src, y_plane);
+    match src.format {
+        Format::Nv12 => {
+            let (uv, _, _) = src.plane(1);
+            uv.chunks_exact(2)
+                .zip(p1.iter_mut().zip(p2.iter_mut()))
+                .for_each(|(uv, (u, v))| {
+                    *u = uv[0];
+                    *v = uv[1];
+                });
+        },
+        _ => {
+            let (u, _, _) = src.plane(1);
+            let (v, _, _) = src.plane(2);
+            p1.chunks_exact_mut(2)
+                .zip(u.iter().zip(v.iter()))
+                .for_each(|(uv, (&u, &v))| {
+                    uv[0] = u;
+                    uv[1] = v;
+                });
+        },
+    }
+}
+
+fn rgb_to_yuv(src: &ImageRef<'_>, dst: &mut ImageBuf, c: Coefficients) {
+    let order = rgb_indices(src.format).unwrap();
+    let bpp = src.format.bytes_per_pixel().unwrap();
+    let nv12 = dst.format() == Format::Nv12;
+    let (w, h) = (src.width as usize, src.height as usize);
+    let cw = w.div_ceil(2);
+    let [y_plane, p1, p2] = dst.planes_mut();
+    let pixel = |row: &[u8], x: usize| -> [i32; 3] {
+        let p = &row[x * bpp..];
+        [p[order[0]] as i32, p[order[1]] as i32, p[order[2]] as i32]
+    };
+    for cy in 0..h.div_ceil(2) {
+        let rows: Vec<&[u8]> = (cy * 2..(cy * 2 + 2).min(h)).map(|y| src.row(0, y)).collect();
+        for (dy, row) in rows.iter().enumerate() {
+            let out = &mut y_plane[(cy * 2 + dy) * w..(cy * 2 + dy + 1) * w];
+            out.iter_mut().enumerate().for_each(|(x, out)| *out = c.luma(pixel(row, x)));
+        }
+        for cx in 0..cw {
+            let mut sum = [0i32; 3];
+            let mut n = 0;
+            for row in rows.iter() {
+                for x in cx * 2..(cx * 2 + 2).min(w) {
+                    let p = pixel(row, x);
+                    sum.iter_mut().zip(p.iter()).for_each(|(s, v)| *s += v);
+                    n += 1;
+                }
+            }
+            let avg = [(sum[0] + n / 2) / n, (sum[1] + n / 2) / n, (sum[2] + n / 2) / n];
+            let (u, v) = c.chroma(avg);
+            let idx = cy * cw + cx;
+            if nv12 {
+                p1[idx * 2] = u;
+                p1[idx * 2 + 1] = v;
+            } else {
+                p1[idx] = u;
+                p2[idx] = v;
+            }
+        }
+    }
+}
+
+fn yuv_to_rgb(src: &ImageRef<'_>, dst: &mut ImageBuf, c: Coefficients) {
+    let order = rgb_indices(dst.format()).unwrap();
+    let bpp = dst.format().bytes_per_pixel().unwrap();
+    let (w, h) = (src.width as usize, src.height as usize);
+    let cw = w.div_ceil(2);
+    let (p1, _, _) = src.plane(1);
+    let p2 = if src.format == Format::I420 { src.plane(2).0 } else { &[][..] };
+    let chroma = |cx: usize, cy: usize| -> (u8, u8) {
+        let idx = cy * cw + cx;
+        if src.format == Format::Nv12 {
+            (p1[idx * 2], p1[idx * 2 + 1])
+        } else {
+            (p1[idx], p2[idx])
+        }
+    };
+    let [out, _, _] = dst.planes_mut();
+    for y in 0..h {
+        let luma = src.row(0, y);
+        let row = &mut out[y * w * bpp..(y + 1) * w * bpp];
+        row.chunks_exact_mut(bpp).enumerate().for_each(|(x, px)| {
+            let (u, v) = chroma(x / 2, y / 2);
+            let rgb = c.rgb(luma[x], u, v);
+            px[order[0]] = rgb[0];
+            px[order[1]] = rgb[1];
+            px[order[2]] = rgb[2];
+            if bpp == 4 {
+                px[3] = 255;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(format: Format, w: u32, h: u32, rgb: [u8; 3]) -> ImageBuf {
+        let rgba: Vec<u8> = (0..w * h).flat_map(|_| vec![rgb[0], rgb[1], rgb[2], 255]).collect();
+        convert(&ImageRef::new(Format::Rgba, w, h, &rgba), format, YuvParams::default())
+    }
+
+    #[test]
+    fn rgba_to_bgra_swaps_red_and_blue() {
+        let src = [1, 2, 3, 4, 5, 6, 7, 8];
+        let out = convert(&ImageRef::new(Format::Rgba, 2, 1, &src), Format::Bgra, YuvParams::default());
+        assert_eq!(out.data(), &[3, 2, 1, 4, 7, 6, 5, 8]);
+    }
+
+    #[test]
+    fn rgb_round_trips_through_rgba() {
+        let src: Vec<u8> = (0..3 * 7 * 3).map(|v| v as u8).collect();
+        let rgba = convert(&ImageRef::new(Format::Rgb, 7, 3, &src), Format::Rgba, YuvParams::default());
+        assert_eq!(&rgba.data()[..8], &[0, 1, 2, 255, 3, 4, 5, 255]);
+        let rgb = convert(&rgba.as_image(), Format::Rgb, YuvParams::default());
+        assert_eq!(rgb.data(), &src[..]);
+    }
+
+    #[test]
+    fn limited_range_extremes() {
+        let black = solid(Format::I420, 2, 2, [0, 0, 0]);
+        assert_eq!(black.data(), &[16, 16, 16, 16, 128, 128]);
+        let white = solid(Format::I420, 2, 2, [255, 255, 255]);
+        assert_eq!(white.data(), &[235, 235, 235, 235, 128, 128]);
+    }
+
+    #[test]
+    fn yuv_round_trip_is_close() {
+        for &params in &[YuvParams::default(), YuvParams { matrix: YuvMatrix::Bt601, range: YuvRange::Full }] {
+            for &color in &[[255, 0, 0], [0, 255, 0], [0, 0, 255], [12, 200, 77], [128, 128, 128]] {
+                let rgba = [color[0], color[1], color[2], 255].repeat(4);
+                for &format in &[Format::Nv12, Format::I420] {
+                    let yuv = convert(&ImageRef::new(Format::Rgba, 2, 2, &rgba), format, params);
+                    let back = convert(&yuv.as_image(), Format::Rgba, params);
+                    back.data().chunks_exact(4).for_each(|px| {
+                        px.iter().zip(color.iter()).for_each(|(&a, &b)| {
+                            assert!((a as i32 - b as i32).abs() <= 2, "{:?} -> {:?} with {:?}", color, px, params);
+                        });
+                    });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nv12_and_i420_interleave_chroma() {
+        let nv12 = [1, 2, 3, 4, 10, 20];
+        let i420 = convert(&ImageRef::new(Format::Nv12, 2, 2, &nv12), Format::I420, YuvParams::default());
+        assert_eq!(i420.data(), &[1, 2, 3, 4, 10, 20]);
+        let nv12_odd = [0; 9 + 8];
+        let i420 = convert(&ImageRef::new(Format::Nv12, 3, 3, &nv12_odd), Format::I420, YuvParams::default());
+        assert_eq!(i420.data().len(), 9 + 4 + 4);
+        let back = convert(&i420.as_image(), Format::Nv12, YuvParams::default());
+        assert_eq!(back.data(), &nv12_odd[..]);
+    }
+
+    #[test]
+    fn odd_dimensions_average_partial_blocks() {
+        let out = solid(Format::Nv12, 3, 3, [255, 255, 255]);
+        assert_eq!(out.data().len(), Format::Nv12.buffer_size(3, 3));
+        assert!(out.data()[..9].iter().all(|&y| y == 235));
+        assert!(out.data()[9..].iter().all(|&c| c == 128));
+    }
+}
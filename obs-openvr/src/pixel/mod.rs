@@ -0,0 +1,314 @@
+//! Pure-Rust conversion, cropping and scaling of CPU-side images, so transforms of captured
+//! frames don't need another GL round-trip
+
+mod convert;
+mod scale;
+mod simd;
+mod srgb;
+
+pub use convert::{
+    YuvMatrix,
+    YuvParams,
+    YuvRange,
+    convert,
+    convert_into,
+};
+pub use scale::{
+    Filter,
+    alpha_bounds,
+    blit,
+    crop,
+    crop_into,
+    scale,
+    scale_into,
+};
+pub use srgb::encode_srgb;
+
+/// Pixel layout of an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Packed 8-bit RGB
+    Rgb,
+    /// Packed 8-bit RGBA
+    Rgba,
+    /// Packed 8-bit BGRA
+    Bgra,
+    /// Y plane followed by an interleaved, half-resolution UV plane
+    Nv12,
+    /// Y plane followed by half-resolution U and V planes
+    I420,
+}
+
+impl Format {
+    #[inline]
+    pub fn is_yuv(self) -> bool {
+        matches!(self, Format::Nv12 | Format::I420)
+    }
+
+    /// Bytes per pixel of packed formats
+    pub fn bytes_per_pixel(self) -> Option<usize> {
+        match self {
+            Format::Rgb => Some(3),
+            Format::Rgba | Format::Bgra => Some(4),
+            Format::Nv12 | Format::I420 => None,
+        }
+    }
+
+    pub fn plane_count(self) -> usize {
+        match self {
+            Format::Rgb | Format::Rgba | Format::Bgra => 1,
+            Format::Nv12 => 2,
+            Format::I420 => 3,
+        }
+    }
+
+    /// Layout of each plane of a tightly packed `width` x `height` image in this format. Planes
+    /// past `plane_count` are empty.
+    pub fn planes(self, width: u32, height: u32) -> [Plane; 3] {
+        let (w, h) = (width as usize, height as usize);
+        let (cw, ch) = (w.div_ceil(2), h.div_ceil(2));
+        let empty = Plane::new(0, 0, 0);
+        match self {
+            Format::Rgb | Format::Rgba | Format::Bgra => {
+                let bpp = self.bytes_per_pixel().unwrap();
+                [Plane::new(w, h, bpp), empty, empty]
+            },
+            Format::Nv12 => [Plane::new(w, h, 1), Plane::new(cw, ch, 2), empty],
+            Format::I420 => [Plane::new(w, h, 1), Plane::new(cw, ch, 1), Plane::new(cw, ch, 1)],
+        }
+    }
+
+    /// Size of a tightly packed `width` x `height` image in this format
+    pub fn buffer_size(self, width: u32, height: u32) -> usize {
+        self.planes(width, height).iter()
+            .map(Plane::size)
+            .sum()
+    }
+}
+
+/// Dimensions of a single plane of an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Plane {
+    pub width: usize,
+    pub height: usize,
+    /// Bytes per sample
+    pub channels: usize,
+}
+
+impl Plane {
+    #[inline]
+    const fn new(width: usize, height: usize, channels: usize) -> Self {
+        Plane {
+            width: width,
+            height: height,
+            channels: channels,
+        }
+    }
+
+    /// Bytes in one row of samples, without padding
+    #[inline]
+    pub fn row_bytes(&self) -> usize {
+        self.width * self.channels
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.row_bytes() * self.height
+    }
+}
+
+/// Sub-rectangle of an image, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub const fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Rect {
+            x: x,
+            y: y,
+            width: width,
+            height: height,
+        }
+    }
+
+    /// Clamps the rectangle to a `width` x `height` image
+    pub fn clamp(self, width: u32, height: u32) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        Rect {
+            x: x,
+            y: y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        }
+    }
+}
+
+/// Borrowed image data. Planes are stored back to back, with only the first plane allowed to have
+/// padding at the end of its rows.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageRef<'a> {
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    /// Bytes between the starts of consecutive rows of the first plane
+    pub stride: usize,
+    pub data: &'a [u8],
+}
+
+impl<'a> ImageRef<'a> {
+    /// Tightly packed image
+    pub fn new(format: Format, width: u32, height: u32, data: &'a [u8]) -> Self {
+        let stride = format.planes(width, height)[0].row_bytes();
+        Self::with_stride(format, width, height, stride, data)
+    }
+
+    pub fn with_stride(format: Format, width: u32, height: u32, stride: usize, data: &'a [u8]) -> Self {
+        let ret = ImageRef {
+            format: format,
+            width: width,
+            height: height,
+            stride: stride,
+            data: data,
+        };
+        assert!(stride >= format.planes(width, height)[0].row_bytes(), "stride too small for {:?} image", format);
+        assert!(data.len() >= ret.required_len(), "{} bytes of data is too small for a {}x{} {:?} image", data.len(), width, height, format);
+        ret
+    }
+
+    fn required_len(&self) -> usize {
+        let planes = self.format.planes(self.width, self.height);
+        self.stride * planes[0].height + planes[1..].iter().map(Plane::size).sum::<usize>()
+    }
+
+    /// Data, layout and stride of plane `idx`
+    pub fn plane(&self, idx: usize) -> (&'a [u8], Plane, usize) {
+        let planes = self.format.planes(self.width, self.height);
+        let first_size = self.stride * planes[0].height;
+        if idx == 0 {
+            return (&self.data[..first_size], planes[0], self.stride);
+        }
+        let offset = first_size + planes[1..idx].iter().map(Plane::size).sum::<usize>();
+        let plane = planes[idx];
+        (&self.data[offset..offset + plane.size()], plane, plane.row_bytes())
+    }
+
+    /// Row `y` of plane `idx`, without padding
+    #[inline]
+    pub fn row(&self, idx: usize, y: usize) -> &'a [u8] {
+        let (data, plane, stride) = self.plane(idx);
+        &data[y * stride..y * stride + plane.row_bytes()]
+    }
+}
+
+/// Owned, tightly packed image data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageBuf {
+    format: Format,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl ImageBuf {
+    pub fn new(format: Format, width: u32, height: u32) -> Self {
+        ImageBuf {
+            format: format,
+            width: width,
+            height: height,
+            data: vec![0; format.buffer_size(width, height)],
+        }
+    }
+
+    /// Changes the format and dimensions of the image, reusing its allocation. Contents are
+    /// unspecified afterwards.
+    pub fn reset(&mut self, format: Format, width: u32, height: u32) {
+        self.format = format;
+        self.width = width;
+        self.height = height;
+        self.data.resize(format.buffer_size(width, height), 0);
+    }
+
+    #[inline(always)]
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    #[inline(always)]
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    #[inline(always)]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Zeroes the image, which is transparent black for formats with alpha
+    pub fn clear(&mut self) {
+        self.data.iter_mut().for_each(|v| *v = 0);
+    }
+
+    #[inline]
+    pub fn as_image(&self) -> ImageRef<'_> {
+        ImageRef::new(self.format, self.width, self.height, &self.data)
+    }
+
+    /// Byte offset and row size of each plane, for handing the image to APIs that take plane
+    /// pointers and line sizes
+    pub fn plane_layout(&self) -> [(usize, usize); 3] {
+        let planes = self.format.planes(self.width, self.height);
+        let mut offset = 0;
+        let mut ret = [(0, 0); 3];
+        planes.iter().zip(ret.iter_mut()).for_each(|(plane, out)| {
+            *out = (offset, plane.row_bytes());
+            offset += plane.size();
+        });
+        ret
+    }
+
+    /// Mutable data of each plane. Planes past `plane_count` are empty.
+    pub fn planes_mut(&mut self) -> [&mut [u8]; 3] {
+        let planes = self.format.planes(self.width, self.height);
+        let (first, rest) = self.data.split_at_mut(planes[0].size());
+        let (second, third) = rest.split_at_mut(planes[1].size());
+        [first, second, third]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffer_sizes() {
+        assert_eq!(Format::Rgb.buffer_size(3, 2), 18);
+        assert_eq!(Format::Bgra.buffer_size(3, 2), 24);
+        assert_eq!(Format::Nv12.buffer_size(4, 2), 8 + 4);
+        assert_eq!(Format::I420.buffer_size(3, 3), 9 + 4 + 4);
+    }
+
+    #[test]
+    fn plane_layout_of_i420() {
+        let image = ImageBuf::new(Format::I420, 4, 4);
+        assert_eq!(image.plane_layout(), [(0, 4), (16, 2), (20, 2)]);
+    }
+
+    #[test]
+    fn strided_rows_skip_padding() {
+        let data = [1, 2, 3, 0, 4, 5, 6, 0];
+        let image = ImageRef::with_stride(Format::Rgb, 1, 2, 4, &data);
+        assert_eq!(image.row(0, 1), &[4, 5, 6]);
+    }
+
+    #[test]
+    fn rect_clamps_to_image() {
+        assert_eq!(Rect::new(3, 1, 10, 10).clamp(4, 4), Rect::new(3, 1, 1, 3));
+        assert_eq!(Rect::new(8, 8, 2, 2).clamp(4, 4), Rect::new(4, 4, 0, 0));
+    }
+}
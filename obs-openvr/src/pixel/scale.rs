@@ -0,0 +1,282 @@
+use super::{
+    Format,
+    ImageBuf,
+    ImageRef,
+    Rect,
+};
+
+/// Resampling filter used by `scale`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Averages every source pixel covered by a destination pixel. Best for downscaling.
+    Box,
+    /// Interpolates between the 4 nearest source pixels
+    Bilinear,
+    /// Repeats the nearest source pixel, keeping hard edges when upscaling
+    Nearest,
+}
+
+/// Copies the `rect` region of `src` into a new image
+pub fn crop(src: &ImageRef<'_>, rect: Rect) -> ImageBuf {
+    let mut ret = ImageBuf::new(src.format, 0, 0);
+    crop_into(src, rect, &mut ret);
+    ret
+}
+
+/// Copies the `rect` region of `src` into `dst`. `rect` is clamped to the image, and for YUV
+/// formats its origin is rounded down to even coordinates so chroma samples stay aligned.
+pub fn crop_into(src: &ImageRef<'_>, rect: Rect, dst: &mut ImageBuf) {
+    let mut rect = rect.clamp(src.width, src.height);
+    if src.format.is_yuv() {
+        rect.width += rect.x & 1;
+        rect.height += rect.y & 1;
+        rect.x &= !1;
+        rect.y &= !1;
+    }
+    dst.reset(src.format, rect.width, rect.height);
+    let dst_planes = dst.format().planes(rect.width, rect.height);
+    let out = dst.planes_mut();
+    for (idx, (plane, out)) in dst_planes.iter().zip(out).enumerate().take(src.format.plane_count()) {
+        // Chroma planes are half resolution in both directions
+        let (x, y) = if idx == 0 { (rect.x, rect.y) } else { (rect.x / 2, rect.y / 2) };
+        let (x, y) = (x as usize, y as usize);
+        let row_bytes = plane.row_bytes();
+        if row_bytes == 0 {
+            continue;
+        }
+        out.chunks_exact_mut(row_bytes).enumerate().for_each(|(dy, row)| {
+            let src_row = src.row(idx, y + dy);
+            let start = x * plane.channels;
+            row.copy_from_slice(&src_row[start..start + row_bytes]);
+        });
+    }
+}
+
+/// Resamples `src` to `width` x `height` into a new image
+pub fn scale(src: &ImageRef<'_>, width: u32, height: u32, filter: Filter) -> ImageBuf {
+    let mut ret = ImageBuf::new(src.format, 0, 0);
+    scale_into(src, width, height, filter, &mut ret);
+    ret
+}
+
+/// Resamples `src` to `width` x `height`, writing the result to `dst`. YUV planes are resampled
+/// independently.
+pub fn scale_into(src: &ImageRef<'_>, width: u32, height: u32, filter: Filter, dst: &mut ImageBuf) {
+    dst.reset(src.format, width, height);
+    let src_planes = src.format.planes(src.width, src.height);
+    let dst_planes = dst.format().planes(width, height);
+    let out = dst.planes_mut();
+    for (idx, out) in IntoIterator::into_iter(out).enumerate().take(src.format.plane_count()) {
+        let (src_plane, dst_plane) = (src_planes[idx], dst_planes[idx]);
+        if dst_plane.size() == 0 || src_plane.size() == 0 {
+            continue;
+        }
+        let rows = |y: usize| src.row(idx, y);
+        match filter {
+            Filter::Box => scale_plane_box(&rows, src_plane.width, src_plane.height, src_plane.channels, out, dst_plane.width, dst_plane.height),
+            Filter::Bilinear => scale_plane_bilinear(&rows, src_plane.width, src_plane.height, src_plane.channels, out, dst_plane.width, dst_plane.height),
+            Filter::Nearest => scale_plane_nearest(&rows, src_plane.width, src_plane.height, src_plane.channels, out, dst_plane.width, dst_plane.height),
+        }
+    }
+}
+
+/// Source range `[start, end)` covered by each of `dst` destination samples
+fn box_ranges(src: usize, dst: usize) -> Vec<(usize, usize)> {
+    (0..dst)
+        .map(|d| {
+            let start = d * src / dst;
+            let end = ((d + 1) * src).div_ceil(dst);
+            (start, end.max(start + 1).min(src))
+        })
+        .collect()
+}
+
+fn scale_plane_box<'a, R: Fn(usize) -> &'a [u8]>(rows: &R, sw: usize, sh: usize, channels: usize, out: &mut [u8], dw: usize, dh: usize) {
+    let xs = box_ranges(sw, dw);
+    let ys = box_ranges(sh, dh);
+    let mut sums = vec![0u32; dw * channels];
+    for (dy, &(y0, y1)) in ys.iter().enumerate() {
+        sums.iter_mut().for_each(|s| *s = 0);
+        for y in y0..y1 {
+            let row = rows(y);
+            for (dx, &(x0, x1)) in xs.iter().enumerate() {
+                let sum = &mut sums[dx * channels..(dx + 1) * channels];
+                row[x0 * channels..x1 * channels].chunks_exact(channels).for_each(|px| {
+                    sum.iter_mut().zip(px.iter()).for_each(|(s, &v)| *s += v as u32);
+                });
+            }
+        }
+        let out_row = &mut out[dy * dw * channels..(dy + 1) * dw * channels];
+        for (dx, &(x0, x1)) in xs.iter().enumerate() {
+            let n = ((x1 - x0) * (y1 - y0)) as u32;
+            let sum = &sums[dx * channels..(dx + 1) * channels];
+            out_row[dx * channels..(dx + 1) * channels].iter_mut()
+                .zip(sum.iter())
+                .for_each(|(o, &s)| *o = ((s + n / 2) / n) as u8);
+        }
+    }
+}
+
+/// For each destination sample, the two nearest source samples and the 8-bit weight of the second
+fn bilinear_taps(src: usize, dst: usize) -> Vec<(usize, usize, u32)> {
+    (0..dst)
+        .map(|d| {
+            // Sample centers line up: (d + 0.5) * src / dst - 0.5, in 8.8 fixed point
+            let pos = (((2 * d + 1) * src * 256) / (2 * dst)) as i64 - 128;
+            let pos = pos.max(0) as usize;
+            let i0 = (pos >> 8).min(src - 1);
+            let i1 = (i0 + 1).min(src - 1);
+            (i0, i1, (pos & 0xff) as u32)
+        })
+        .collect()
+}
+
+fn scale_plane_bilinear<'a, R: Fn(usize) -> &'a [u8]>(rows: &R, sw: usize, sh: usize, channels: usize, out: &mut [u8], dw: usize, dh: usize) {
+    let xs = bilinear_taps(sw, dw);
+    let ys = bilinear_taps(sh, dh);
+    for (dy, &(y0, y1, fy)) in ys.iter().enumerate() {
+        let (top, bottom) = (rows(y0), rows(y1));
+        let out_row = &mut out[dy * dw * channels..(dy + 1) * dw * channels];
+        for (dx, &(x0, x1, fx)) in xs.iter().enumerate() {
+            for c in 0..channels {
+                let lerp = |row: &[u8]| row[x0 * channels + c] as u32 * (256 - fx) + row[x1 * channels + c] as u32 * fx;
+                let v = lerp(top) * (256 - fy) + lerp(bottom) * fy;
+                out_row[dx * channels + c] = ((v + (1 << 15)) >> 16) as u8;
+            }
+        }
+    }
+}
+
+fn scale_plane_nearest<'a, R: Fn(usize) -> &'a [u8]>(rows: &R, sw: usize, sh: usize, channels: usize, out: &mut [u8], dw: usize, dh: usize) {
+    let xs: Vec<usize> = (0..dw).map(|d| ((2 * d + 1) * sw / (2 * dw)).min(sw - 1)).collect();
+    for dy in 0..dh {
+        let row = rows(((2 * dy + 1) * sh / (2 * dh)).min(sh - 1));
+        let out_row = &mut out[dy * dw * channels..(dy + 1) * dw * channels];
+        for (dx, &x) in xs.iter().enumerate() {
+            out_row[dx * channels..(dx + 1) * channels].copy_from_slice(&row[x * channels..(x + 1) * channels]);
+        }
+    }
+}
+
+/// Copies packed `src` into `dst` with its top left corner at `(x, y)`, clipping whatever falls
+/// outside `dst`
+///
+/// # Panics
+///
+/// If the images aren't in the same packed format
+pub fn blit(src: &ImageRef<'_>, dst: &mut ImageBuf, x: u32, y: u32) {
+    assert_eq!(src.format, dst.format(), "blit between different formats");
+    let bpp = src.format.bytes_per_pixel()
+        .expect("blit needs packed pixels");
+    let (dst_w, dst_h) = dst.dimensions();
+    let w = src.width.min(dst_w.saturating_sub(x)) as usize;
+    let h = src.height.min(dst_h.saturating_sub(y)) as usize;
+    let dst_stride = dst_w as usize * bpp;
+    let [out, _, _] = dst.planes_mut();
+    (0..h).for_each(|sy| {
+        let start = (y as usize + sy) * dst_stride + x as usize * bpp;
+        out[start..start + w * bpp].copy_from_slice(&src.row(0, sy)[..w * bpp]);
+    });
+}
+
+/// Smallest rectangle containing every pixel of packed RGBA or BGRA `src` that isn't fully
+/// transparent, or `None` if all of them are
+pub fn alpha_bounds(src: &ImageRef<'_>) -> Option<Rect> {
+    assert!(matches!(src.format, Format::Rgba | Format::Bgra), "alpha bounds need pixels with alpha");
+    let visible = |px: &[u8]| px[3] != 0;
+    let rows: Vec<usize> = (0..src.height as usize)
+        .filter(|&y| src.row(0, y).chunks_exact(4).any(visible))
+        .collect();
+    let (&top, &bottom) = (rows.first()?, rows.last()?);
+    let (left, right) = rows.iter()
+        .map(|&y| {
+            let row = src.row(0, y);
+            let left = row.chunks_exact(4).position(visible).unwrap();
+            let right = row.chunks_exact(4).rposition(visible).unwrap();
+            (left, right)
+        })
+        .fold((usize::MAX, 0), |(l, r), (left, right)| (l.min(left), r.max(right)));
+    Some(Rect::new(left as u32, top as u32, (right - left + 1) as u32, (bottom - top + 1) as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crop_packed() {
+        let src: Vec<u8> = (0..4 * 3).map(|v| v as u8).collect();
+        let out = crop(&ImageRef::new(Format::Rgb, 2, 2, &src), Rect::new(1, 1, 5, 5));
+        assert_eq!(out.dimensions(), (1, 1));
+        assert_eq!(out.data(), &[9, 10, 11]);
+    }
+
+    #[test]
+    fn crop_yuv_aligns_to_chroma() {
+        // 4x2 I420: Y 0..8, U [100, 101], V [200, 201]
+        let mut src: Vec<u8> = (0..8).collect();
+        src.extend_from_slice(&[100, 101, 200, 201]);
+        let out = crop(&ImageRef::new(Format::I420, 4, 2, &src), Rect::new(3, 0, 1, 2));
+        assert_eq!(out.dimensions(), (2, 2));
+        assert_eq!(out.data(), &[2, 3, 6, 7, 101, 201]);
+    }
+
+    #[test]
+    fn box_downscale_averages() {
+        let src = [0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120, 130, 140, 150];
+        let image = ImageRef::new(Format::I420, 4, 2, &src[..12]);
+        let out = scale(&image, 2, 1, Filter::Box);
+        // Y: average of 2x2 blocks, U/V: a single sample each
+        assert_eq!(&out.data()[..2], &[25, 45]);
+        assert_eq!(out.data().len(), Format::I420.buffer_size(2, 1));
+    }
+
+    #[test]
+    fn box_keeps_solid_color() {
+        let src = [7, 8, 9, 10].repeat(15 * 9);
+        let out = scale(&ImageRef::new(Format::Rgba, 15, 9, &src), 4, 3, Filter::Box);
+        assert!(out.data().chunks_exact(4).all(|px| px == [7, 8, 9, 10]));
+    }
+
+    #[test]
+    fn bilinear_interpolates_between_samples() {
+        let src = [0, 0, 0, 255, 200, 100, 50, 255];
+        let out = scale(&ImageRef::new(Format::Rgba, 2, 1, &src), 4, 1, Filter::Bilinear);
+        let reds: Vec<u8> = out.data().chunks_exact(4).map(|px| px[0]).collect();
+        assert_eq!(reds, vec![0, 50, 150, 200]);
+        assert!(out.data().chunks_exact(4).all(|px| px[3] == 255));
+    }
+
+    #[test]
+    fn bilinear_identity() {
+        let src: Vec<u8> = (0..3 * 5 * 4).map(|v| (v * 7) as u8).collect();
+        let out = scale(&ImageRef::new(Format::Rgb, 5, 4, &src), 5, 4, Filter::Bilinear);
+        assert_eq!(out.data(), &src[..]);
+    }
+
+    #[test]
+    fn nearest_repeats_pixels() {
+        let src = ImageBuf { format: Format::Rgb, width: 2, height: 1, data: vec![10, 20, 30, 40, 50, 60] };
+        let scaled = scale(&src.as_image(), 4, 2, Filter::Nearest);
+        assert_eq!(scaled.data(), &[10, 20, 30, 10, 20, 30, 40, 50, 60, 40, 50, 60, 10, 20, 30, 10, 20, 30, 40, 50, 60, 40, 50, 60][..]);
+    }
+
+    #[test]
+    fn blit_clips_to_destination() {
+        let mut dst = ImageBuf::new(Format::Rgb, 3, 2);
+        let src = [1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4];
+        blit(&ImageRef::new(Format::Rgb, 2, 2, &src), &mut dst, 2, 1);
+        assert_eq!(dst.data(), &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn alpha_bounds_skip_transparent_borders() {
+        let mut src = vec![0u8; 4 * 4 * 3];
+        // Visible pixels at (1, 0) and (2, 1)
+        src[4 + 3] = 255;
+        src[4 * (4 + 2) + 3] = 1;
+        let image = ImageRef::new(Format::Rgba, 4, 3, &src);
+        assert_eq!(alpha_bounds(&image), Some(Rect::new(1, 0, 2, 2)));
+        assert_eq!(alpha_bounds(&ImageRef::new(Format::Rgba, 4, 3, &[0; 48])), None);
+    }
+}
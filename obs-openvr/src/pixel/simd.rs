@@ -0,0 +1,131 @@
+//! Row kernels, with SSSE3 versions on x86_64 when the CPU supports them
+
+/// Swaps the first and third byte of every 4-byte pixel (RGBA <-> BGRA)
+pub fn swap_rb(src: &[u8], dst: &mut [u8]) {
+    debug_assert_eq!(src.len(), dst.len());
+    debug_assert_eq!(src.len() % 4, 0);
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("ssse3") {
+            let done = unsafe { x86::shuffle4(src, dst, x86::SWAP_RB) };
+            swap_rb_scalar(&src[done..], &mut dst[done..]);
+            return;
+        }
+    }
+    swap_rb_scalar(src, dst);
+}
+
+fn swap_rb_scalar(src: &[u8], dst: &mut [u8]) {
+    src.chunks_exact(4).zip(dst.chunks_exact_mut(4)).for_each(|(s, d)| {
+        d[0] = s[2];
+        d[1] = s[1];
+        d[2] = s[0];
+        d[3] = s[3];
+    });
+}
+
+/// Expands 3-byte RGB pixels to opaque 4-byte pixels, writing the red, green and blue channels to
+/// the indices in `order`
+pub fn expand_rgb(src: &[u8], dst: &mut [u8], order: [usize; 3]) {
+    debug_assert_eq!(src.len() / 3, dst.len() / 4);
+    #[cfg(target_arch = "x86_64")]
+    {
+        let mask = match order {
+            [0, 1, 2] => Some(x86::EXPAND_RGB),
+            [2, 1, 0] => Some(x86::EXPAND_BGR),
+            _ => None,
+        };
+        if let Some(mask) = mask.filter(|_| is_x86_feature_detected!("ssse3")) {
+            let done = unsafe { x86::expand3(src, dst, mask) };
+            expand_rgb_scalar(&src[done * 3..], &mut dst[done * 4..], order);
+            return;
+        }
+    }
+    expand_rgb_scalar(src, dst, order);
+}
+
+fn expand_rgb_scalar(src: &[u8], dst: &mut [u8], order: [usize; 3]) {
+    src.chunks_exact(3).zip(dst.chunks_exact_mut(4)).for_each(|(s, d)| {
+        d[order[0]] = s[0];
+        d[order[1]] = s[1];
+        d[order[2]] = s[2];
+        d[3] = 255;
+    });
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    pub const SWAP_RB: [i8; 16] = [2, 1, 0, 3, 6, 5, 4, 7, 10, 9, 8, 11, 14, 13, 12, 15];
+    pub const EXPAND_RGB: [i8; 16] = [0, 1, 2, -1, 3, 4, 5, -1, 6, 7, 8, -1, 9, 10, 11, -1];
+    pub const EXPAND_BGR: [i8; 16] = [2, 1, 0, -1, 5, 4, 3, -1, 8, 7, 6, -1, 11, 10, 9, -1];
+    const ALPHA: [u8; 16] = [0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255];
+
+    #[inline(always)]
+    unsafe fn load_mask(mask: [i8; 16]) -> __m128i {
+        _mm_loadu_si128(mask.as_ptr() as *const __m128i)
+    }
+
+    /// Shuffles 16 bytes at a time. Returns the number of bytes processed.
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn shuffle4(src: &[u8], dst: &mut [u8], mask: [i8; 16]) -> usize {
+        let mask = load_mask(mask);
+        let len = src.len().min(dst.len()) & !15;
+        let mut i = 0;
+        while i < len {
+            let v = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+            _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, _mm_shuffle_epi8(v, mask));
+            i += 16;
+        }
+        len
+    }
+
+    /// Expands 4 pixels at a time. Returns the number of pixels processed.
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn expand3(src: &[u8], dst: &mut [u8], mask: [i8; 16]) -> usize {
+        let mask = load_mask(mask);
+        let alpha = _mm_loadu_si128(ALPHA.as_ptr() as *const __m128i);
+        let pixels = (src.len() / 3).min(dst.len() / 4);
+        let mut done = 0;
+        // Each load reads 16 bytes but only uses 12, so stop before reading past the end
+        while (done + 4) * 3 + 4 <= src.len() && done + 4 <= pixels {
+            let v = _mm_loadu_si128(src.as_ptr().add(done * 3) as *const __m128i);
+            let v = _mm_or_si128(_mm_shuffle_epi8(v, mask), alpha);
+            _mm_storeu_si128(dst.as_mut_ptr().add(done * 4) as *mut __m128i, v);
+            done += 4;
+        }
+        done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_rb_matches_scalar() {
+        let src: Vec<u8> = (0..(4 * 37)).map(|v| v as u8).collect();
+        let mut fast = vec![0; src.len()];
+        let mut slow = vec![0; src.len()];
+        swap_rb(&src, &mut fast);
+        swap_rb_scalar(&src, &mut slow);
+        assert_eq!(fast, slow);
+        assert_eq!(&fast[..4], &[2, 1, 0, 3]);
+    }
+
+    #[test]
+    fn expand_rgb_matches_scalar() {
+        let src: Vec<u8> = (0..(3 * 29)).map(|v| v as u8).collect();
+        for &order in &[[0, 1, 2], [2, 1, 0]] {
+            let mut fast = vec![0; 4 * 29];
+            let mut slow = vec![0; 4 * 29];
+            expand_rgb(&src, &mut fast, order);
+            expand_rgb_scalar(&src, &mut slow, order);
+            assert_eq!(fast, slow);
+        }
+        let mut dst = [0; 4];
+        expand_rgb(&[1, 2, 3], &mut dst, [2, 1, 0]);
+        assert_eq!(dst, [3, 2, 1, 255]);
+    }
+}
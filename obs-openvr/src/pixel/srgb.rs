@@ -0,0 +1,64 @@
+use std::sync::OnceLock;
+use super::Format;
+
+/// sRGB transfer function, for a linear value in `0.0..=1.0`
+fn encode(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn encode_table() -> &'static [u8; 256] {
+    static TABLE: OnceLock<[u8; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0u8; 256];
+        table.iter_mut().enumerate().for_each(|(i, v)| {
+            *v = (encode(i as f32 / 255.0) * 255.0).round() as u8;
+        });
+        table
+    })
+}
+
+/// Re-encodes linear 8-bit color channels of packed `data` with the sRGB transfer function, in
+/// place. Alpha is left untouched.
+///
+/// # Panics
+///
+/// If `format` is not a packed RGB format
+pub fn encode_srgb(data: &mut [u8], format: Format) {
+    let bpp = format.bytes_per_pixel()
+        .filter(|_| !format.is_yuv())
+        .expect("sRGB encoding needs packed RGB data");
+    let table = encode_table();
+    data.chunks_exact_mut(bpp).for_each(|px| {
+        px[..3].iter_mut().for_each(|c| *c = table[*c as usize]);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_keeps_endpoints_and_alpha() {
+        let mut data = [0, 255, 0, 128];
+        encode_srgb(&mut data, Format::Rgba);
+        assert_eq!(data, [0, 255, 0, 128]);
+    }
+
+    #[test]
+    fn encode_brightens_midtones() {
+        // Linear 0.5 is ~0.735 in sRGB
+        let mut data = [128, 64, 16];
+        encode_srgb(&mut data, Format::Rgb);
+        assert_eq!(data, [188, 137, 71]);
+    }
+
+    #[test]
+    fn encode_is_monotonic() {
+        let table = encode_table();
+        assert!(table.windows(2).all(|w| w[0] <= w[1]));
+    }
+}
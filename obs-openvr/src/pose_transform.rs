@@ -0,0 +1,821 @@
+//! A helper source that drives a scene item's position, rotation and scale from a tracked device's
+//! pose, like a facecam sliding with head yaw or a name tag following the headset. It draws
+//! nothing itself, and can sit anywhere: the item it moves is found by scene and source name.
+//! Devices can also be followed by where they show up in an eye's mirror view, like a hand cam
+//! crop box following a controller around a mirror source.
+
+use std::{
+    ffi::{
+        CStr,
+        CString,
+    },
+    mem,
+    sync::Mutex,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use obs::{
+    data::ObsData,
+    properties::{
+        Properties,
+        PropertiesExt,
+        PropertyDescription,
+    },
+    scene::SceneItem,
+    source::{
+        OwnedSource,
+        VideoSource,
+    },
+};
+use openvr::{
+    pose::{
+        Eye,
+        Pose,
+        TrackingUniverse,
+    },
+    system::ControllerRole,
+};
+
+/// How often the scene item is looked up again, in case it was removed or renamed
+const RESOLVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Changes smaller than these aren't applied, so a still device doesn't keep updating the scene
+const POSITION_EPSILON: f32 = 0.01;
+const ROTATION_EPSILON: f32 = 0.01;
+const SCALE_EPSILON: f32 = 0.0001;
+
+/// Tracked device a source follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackedDevice {
+    Hmd,
+    LeftController,
+    RightController,
+    /// The device at a fixed index, for trackers
+    Index,
+}
+
+impl TrackedDevice {
+    pub const ALL: [TrackedDevice; 4] = [TrackedDevice::Hmd, TrackedDevice::LeftController, TrackedDevice::RightController, TrackedDevice::Index];
+
+    fn from_key(value: Option<&CStr>) -> Self {
+        Self::ALL.iter()
+            .find(|device| Some(device.key()) == value)
+            .copied()
+            .unwrap_or(TrackedDevice::Hmd)
+    }
+
+    pub fn key(self) -> &'static CStr {
+        match self {
+            TrackedDevice::Hmd => keys::DEVICE_HMD,
+            TrackedDevice::LeftController => keys::DEVICE_LEFT_CONTROLLER,
+            TrackedDevice::RightController => keys::DEVICE_RIGHT_CONTROLLER,
+            TrackedDevice::Index => keys::DEVICE_INDEX_MODE,
+        }
+    }
+
+    pub fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            TrackedDevice::Hmd => b"Headset\0",
+            TrackedDevice::LeftController => b"Left controller\0",
+            TrackedDevice::RightController => b"Right controller\0",
+            TrackedDevice::Index => b"Device index\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+
+    /// Current index of the device, if it's connected
+    fn index(self, fixed_index: u32) -> Option<u32> {
+        match self {
+            TrackedDevice::Hmd => Some(0),
+            TrackedDevice::LeftController => openvr::system::device_for_role(ControllerRole::LeftHand),
+            TrackedDevice::RightController => openvr::system::device_for_role(ControllerRole::RightHand),
+            TrackedDevice::Index => Some(fixed_index),
+        }
+    }
+}
+
+/// Part of a pose a channel follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    X,
+    Y,
+    Z,
+    Yaw,
+    Pitch,
+    Roll,
+    /// Across the mirror view, see `MirrorView`
+    MirrorX,
+    MirrorY,
+}
+
+impl Input {
+    pub const ALL: [Input; 8] = [Input::X, Input::Y, Input::Z, Input::Yaw, Input::Pitch, Input::Roll, Input::MirrorX, Input::MirrorY];
+
+    fn from_key(value: Option<&CStr>, default: Input) -> Self {
+        Self::ALL.iter()
+            .find(|input| Some(input.key()) == value)
+            .copied()
+            .unwrap_or(default)
+    }
+
+    pub fn key(self) -> &'static CStr {
+        match self {
+            Input::X => keys::INPUT_X,
+            Input::Y => keys::INPUT_Y,
+            Input::Z => keys::INPUT_Z,
+            Input::Yaw => keys::INPUT_YAW,
+            Input::Pitch => keys::INPUT_PITCH,
+            Input::Roll => keys::INPUT_ROLL,
+            Input::MirrorX => keys::INPUT_MIRROR_X,
+            Input::MirrorY => keys::INPUT_MIRROR_Y,
+        }
+    }
+
+    pub fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            Input::X => b"Position X (meters, right)\0",
+            Input::Y => b"Position Y (meters, up)\0",
+            Input::Z => b"Position Z (meters, back)\0",
+            Input::Yaw => b"Yaw (degrees, left)\0",
+            Input::Pitch => b"Pitch (degrees, up)\0",
+            Input::Roll => b"Roll (degrees, left)\0",
+            Input::MirrorX => b"Mirror view X (0 to 1, right)\0",
+            Input::MirrorY => b"Mirror view Y (0 to 1, down)\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+
+    fn uses_mirror_view(self) -> bool {
+        matches!(self, Input::MirrorX | Input::MirrorY)
+    }
+
+    /// Value of this input for `pose`, in meters or degrees, or from `mirror`, the device's
+    /// position in the mirror view. `None` for mirror inputs while the device isn't in front of
+    /// the eye.
+    fn value(self, pose: &Pose, mirror: Option<[f32; 2]>) -> Option<f32> {
+        let [x, y, z] = pose.position();
+        let [yaw, pitch, roll] = pose.yaw_pitch_roll();
+        match self {
+            Input::X => Some(x),
+            Input::Y => Some(y),
+            Input::Z => Some(z),
+            Input::Yaw => Some(yaw.to_degrees()),
+            Input::Pitch => Some(pitch.to_degrees()),
+            Input::Roll => Some(roll.to_degrees()),
+            Input::MirrorX => mirror.map(|[x, _]| x),
+            Input::MirrorY => mirror.map(|[_, y]| y),
+        }
+    }
+}
+
+/// An eye's mirror view, as a mirror source shows it after cropping, for the mirror inputs
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MirrorView {
+    eye: Eye,
+    /// Fractions of the view cropped off the left, top, right and bottom
+    crop: [f32; 4],
+}
+
+impl MirrorView {
+    /// Where `device` shows up in this view, from 0 to 1 across the cropped view from the left and
+    /// from the top (beyond that outside of it). `eye_to_head` and `projection` are the eye's
+    /// `openvr::pose::eye_to_head` and `openvr::pose::projection_raw`. `None` if the device isn't
+    /// in front of the eye.
+    fn project(&self, device: &Pose, hmd: &Pose, eye_to_head: &Pose, projection: [f32; 4]) -> Option<[f32; 2]> {
+        let [x, y, z] = device.relative_to(hmd).relative_to(eye_to_head).position();
+        // The eye looks down -Z
+        if z > -1e-3 {
+            return None;
+        }
+        let (x, y) = (x / -z, y / -z);
+        // As the projection matrix OpenVR builds from these does, with +Y at the top of the view
+        let [left, right, top, bottom] = projection;
+        let u = (x - left) / (right - left);
+        let v = (bottom - y) / (bottom - top);
+        let [crop_left, crop_top, crop_right, crop_bottom] = self.crop;
+        let width = (1.0 - crop_left - crop_right).max(0.01);
+        let height = (1.0 - crop_top - crop_bottom).max(0.01);
+        Some([(u - crop_left) / width, (v - crop_top) / height]).filter(|[u, v]| u.is_finite() && v.is_finite())
+    }
+
+    /// Current position of `device` in this view, see `project`
+    fn position(&self, device: &Pose, hmd: &Pose) -> Option<[f32; 2]> {
+        if !hmd.valid {
+            return None;
+        }
+        let eye_to_head = openvr::pose::eye_to_head(self.eye)?;
+        let projection = openvr::pose::projection_raw(self.eye)?;
+        self.project(device, hmd, &eye_to_head, projection)
+    }
+}
+
+/// Maps a range of input values onto a range of outputs, linearly, clamping at the ends. Either
+/// range may be reversed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mapping {
+    pub in_min: f32,
+    pub in_max: f32,
+    /// Output for `in_min`
+    pub out_min: f32,
+    /// Output for `in_max`
+    pub out_max: f32,
+    /// Inputs this close to the middle of the input range all map to the middle of the output
+    /// range. The rest of the input range is stretched to cover the whole output range, so the
+    /// output doesn't jump leaving the dead zone.
+    pub dead_zone: f32,
+}
+
+impl Mapping {
+    pub fn map(&self, value: f32) -> f32 {
+        let center = (self.in_min + self.in_max) / 2.0;
+        let half = (self.in_max - self.in_min).abs() / 2.0;
+        if half <= self.dead_zone || !value.is_finite() {
+            return (self.out_min + self.out_max) / 2.0;
+        }
+        let offset = value - center;
+        let offset = if offset.abs() <= self.dead_zone {
+            0.0
+        } else {
+            offset.signum() * (offset.abs() - self.dead_zone.max(0.0)) * half / (half - self.dead_zone.max(0.0))
+        };
+        let t = ((center + offset - self.in_min) / (self.in_max - self.in_min)).max(0.0).min(1.0);
+        self.out_min + t * (self.out_max - self.out_min)
+    }
+}
+
+/// Smooths changes to a value, exponentially approaching new values
+#[derive(Debug, Clone, Copy, Default)]
+struct Smoother(Option<f32>);
+
+impl Smoother {
+    /// Moves towards `target` over `seconds` since the last update. `time_constant` is how long
+    /// it takes to get about two thirds of the way there, with 0 jumping straight to it.
+    fn update(&mut self, target: f32, seconds: f32, time_constant: f32) -> f32 {
+        let value = match self.0 {
+            Some(current) if time_constant > 0.0 => {
+                let alpha = 1.0 - (-seconds.max(0.0) / time_constant).exp();
+                current + (target - current) * alpha
+            },
+            _ => target,
+        };
+        self.0 = Some(value);
+        value
+    }
+}
+
+/// Part of a scene item's transform a source drives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Channel {
+    PositionX,
+    PositionY,
+    Rotation,
+    Scale,
+}
+
+impl Channel {
+    const ALL: [Channel; 4] = [Channel::PositionX, Channel::PositionY, Channel::Rotation, Channel::Scale];
+
+    fn keys(self) -> &'static keys::ChannelKeys {
+        match self {
+            Channel::PositionX => &keys::POSITION_X,
+            Channel::PositionY => &keys::POSITION_Y,
+            Channel::Rotation => &keys::ROTATION,
+            Channel::Scale => &keys::SCALE,
+        }
+    }
+
+    fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            Channel::PositionX => b"Position X (pixels)\0",
+            Channel::PositionY => b"Position Y (pixels)\0",
+            Channel::Rotation => b"Rotation (degrees, clockwise)\0",
+            Channel::Scale => b"Scale\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+
+    /// Defaults for a facecam sliding and tilting with the headset, with only X enabled
+    fn defaults(self) -> (bool, Input, Mapping) {
+        let (enabled, input, in_range, out_range, dead_zone) = match self {
+            Channel::PositionX => (true, Input::Yaw, (-45.0, 45.0), (1440.0, 480.0), 5.0),
+            Channel::PositionY => (false, Input::Pitch, (-30.0, 30.0), (780.0, 300.0), 5.0),
+            Channel::Rotation => (false, Input::Roll, (-45.0, 45.0), (45.0, -45.0), 0.0),
+            Channel::Scale => (false, Input::Z, (-1.0, 1.0), (1.5, 0.5), 0.0),
+        };
+        let mapping = Mapping {
+            in_min: in_range.0,
+            in_max: in_range.1,
+            out_min: out_range.0,
+            out_max: out_range.1,
+            dead_zone: dead_zone,
+        };
+        (enabled, input, mapping)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PoseTransformSettings {
+    scene: Option<CString>,
+    item: Option<CString>,
+    device: TrackedDevice,
+    device_index: u32,
+    /// Follow the device relative to the headset, rather than the play area
+    relative_to_hmd: bool,
+    /// Smoothing time constant, in seconds
+    smoothing: f32,
+    /// Input and mapping of each enabled channel, in the order of `Channel::ALL`
+    channels: [Option<(Input, Mapping)>; 4],
+    mirror_view: MirrorView,
+}
+
+impl PoseTransformSettings {
+    fn from_settings(data: &obs::sys::obs_data) -> Self {
+        let name = |key: &CStr| data.get_cstr(key)
+            .filter(|name| !name.to_bytes().is_empty())
+            .map(CStr::to_owned);
+        let mut channels = [None; 4];
+        for (channel, settings) in Channel::ALL.iter().zip(channels.iter_mut()) {
+            let keys = channel.keys();
+            if !data.get_bool(keys.enabled) {
+                continue;
+            }
+            let (_, default_input, _) = channel.defaults();
+            let mapping = Mapping {
+                in_min: data.get_double(keys.in_min) as f32,
+                in_max: data.get_double(keys.in_max) as f32,
+                out_min: data.get_double(keys.out_min) as f32,
+                out_max: data.get_double(keys.out_max) as f32,
+                dead_zone: data.get_double(keys.dead_zone) as f32,
+            };
+            *settings = Some((Input::from_key(data.get_cstr(keys.input), default_input), mapping));
+        }
+        PoseTransformSettings {
+            scene: name(keys::SCENE),
+            item: name(keys::ITEM),
+            device: TrackedDevice::from_key(data.get_cstr(keys::DEVICE)),
+            device_index: data.get_int(keys::DEVICE_INDEX).max(0).min(openvr::pose::MAX_DEVICES as i64 - 1) as u32,
+            relative_to_hmd: data.get_bool(keys::RELATIVE_TO_HMD),
+            smoothing: data.get_double(keys::SMOOTHING).max(0.0) as f32,
+            channels: channels,
+            mirror_view: MirrorView {
+                eye: if data.get_cstr(keys::MIRROR_EYE) == Some(keys::EYE_RIGHT) {
+                    Eye::Right
+                } else {
+                    Eye::Left
+                },
+                crop: [keys::MIRROR_CROP_LEFT, keys::MIRROR_CROP_TOP, keys::MIRROR_CROP_RIGHT, keys::MIRROR_CROP_BOTTOM]
+                    .map(|key| (data.get_double(key) / 100.0).max(0.0).min(1.0) as f32),
+            },
+        }
+    }
+
+    /// Current pose of the followed device, if it's tracked, and its position in the mirror view
+    /// if any channel follows that and it's in front of the eye
+    fn pose(&self) -> Option<(Pose, Option<[f32; 2]>)> {
+        let index = self.device.index(self.device_index)? as usize;
+        let poses = openvr::pose::device_poses(TrackingUniverse::Standing, 0.0)?;
+        let pose = poses.get(index)?;
+        let mirror = if self.channels.iter().flatten().any(|(input, _)| input.uses_mirror_view()) && pose.valid {
+            self.mirror_view.position(pose, &poses[0])
+        } else {
+            None
+        };
+        let pose = if self.relative_to_hmd {
+            pose.relative_to(&poses[0])
+        } else {
+            *pose
+        };
+        Some(pose).filter(|pose| pose.valid).map(|pose| (pose, mirror))
+    }
+}
+
+struct PoseTransformState {
+    settings: PoseTransformSettings,
+    item: Option<SceneItem>,
+    resolved: Option<Instant>,
+    smoothers: [Smoother; 4],
+    init_attempted: Option<Instant>,
+}
+
+impl PoseTransformState {
+    /// The scene item being driven, looking it up again every `RESOLVE_INTERVAL`
+    fn item(&mut self) -> Option<&SceneItem> {
+        let now = Instant::now();
+        if self.resolved.map_or(true, |t| now.duration_since(t) >= RESOLVE_INTERVAL) {
+            self.resolved = Some(now);
+            let settings = &self.settings;
+            self.item = settings.scene.as_ref()
+                .zip(settings.item.as_ref())
+                .and_then(|(scene, item)| SceneItem::find(&OwnedSource::by_name(scene)?, item));
+        }
+        self.item.as_ref()
+    }
+
+    fn tick(&mut self, seconds: f32) {
+        if self.settings.channels.iter().all(Option::is_none) || !crate::retry_init_openvr(&mut self.init_attempted) {
+            return;
+        }
+        let session = match crate::openvr_session() {
+            Some(v) => v,
+            None => return,
+        };
+        let (pose, mirror) = match self.settings.pose() {
+            Some(v) => v,
+            None => return,
+        };
+        let mut values = [None; 4];
+        let channels = self.settings.channels;
+        let smoothing = self.settings.smoothing;
+        for ((channel, smoother), value) in channels.iter().zip(self.smoothers.iter_mut()).zip(values.iter_mut()) {
+            *value = channel.and_then(|(input, mapping)| {
+                Some(smoother.update(mapping.map(input.value(&pose, mirror)?), seconds, smoothing))
+            });
+        }
+        mem::drop(session);
+        let item = match self.item() {
+            Some(v) => v,
+            None => return,
+        };
+        let [x, y, rotation, scale] = values;
+        if x.is_some() || y.is_some() {
+            let current = item.pos();
+            let pos = [x.unwrap_or(current[0]), y.unwrap_or(current[1])];
+            if (pos[0] - current[0]).abs() > POSITION_EPSILON || (pos[1] - current[1]).abs() > POSITION_EPSILON {
+                item.set_pos(pos);
+            }
+        }
+        if let Some(rotation) = rotation {
+            if (rotation - item.rot()).abs() > ROTATION_EPSILON {
+                item.set_rot(rotation);
+            }
+        }
+        if let Some(scale) = scale {
+            let current = item.scale();
+            if (scale - current[0]).abs() > SCALE_EPSILON || (scale - current[1]).abs() > SCALE_EPSILON {
+                item.set_scale([scale, scale]);
+            }
+        }
+    }
+}
+
+pub struct PoseTransformSource {
+    state: Mutex<PoseTransformState>,
+}
+
+impl VideoSource for PoseTransformSource {
+    const ID: &'static [u8] = b"obs-openvr-pose-transform\0";
+    const OUTPUT_FLAGS: Option<u32> = None;
+
+    fn create(settings: &mut obs::sys::obs_data, _source: *mut obs::sys::obs_source_t) -> Self {
+        PoseTransformSource {
+            state: Mutex::new(PoseTransformState {
+                settings: PoseTransformSettings::from_settings(settings),
+                item: None,
+                resolved: None,
+                smoothers: Default::default(),
+                init_attempted: None,
+            }),
+        }
+    }
+
+    fn get_name() -> &'static CStr {
+        unsafe { CStr::from_bytes_with_nul_unchecked(b"OpenVR Pose Transform\0") }
+    }
+
+    fn get_defaults(settings: &mut obs::sys::obs_data) {
+        settings.set_default_string(keys::DEVICE, TrackedDevice::Hmd.key());
+        settings.set_default_int(keys::DEVICE_INDEX, 0);
+        settings.set_default_bool(keys::RELATIVE_TO_HMD, false);
+        settings.set_default_double(keys::SMOOTHING, 0.1);
+        settings.set_default_string(keys::MIRROR_EYE, keys::EYE_LEFT);
+        for &key in [keys::MIRROR_CROP_LEFT, keys::MIRROR_CROP_TOP, keys::MIRROR_CROP_RIGHT, keys::MIRROR_CROP_BOTTOM].iter() {
+            settings.set_default_double(key, 0.0);
+        }
+        for &channel in Channel::ALL.iter() {
+            let keys = channel.keys();
+            let (enabled, input, mapping) = channel.defaults();
+            settings.set_default_bool(keys.enabled, enabled);
+            settings.set_default_string(keys.input, input.key());
+            settings.set_default_double(keys.in_min, mapping.in_min as f64);
+            settings.set_default_double(keys.in_max, mapping.in_max as f64);
+            settings.set_default_double(keys.out_min, mapping.out_min as f64);
+            settings.set_default_double(keys.out_max, mapping.out_max as f64);
+            settings.set_default_double(keys.dead_zone, mapping.dead_zone as f64);
+        }
+    }
+
+    fn get_properties(&self) -> *mut obs::sys::obs_properties {
+        let mut props = Properties::new();
+
+        let scene_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Scene\0") };
+        let item_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Source to move\0") };
+        props.add_text(keys::SCENE, scene_description, obs::sys::obs_text_type_OBS_TEXT_DEFAULT);
+        props.add_text(keys::ITEM, item_description, obs::sys::obs_text_type_OBS_TEXT_DEFAULT);
+
+        let device_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Tracked device\0") };
+        props.add_string_list_complete(PropertyDescription::new(keys::DEVICE, Some(device_description)), TrackedDevice::ALL.iter().map(|&device| (device.description(), device.key())));
+        let index_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Device index\0") };
+        props.add_int(keys::DEVICE_INDEX, index_description, 0, openvr::pose::MAX_DEVICES as libc::c_int - 1, 1);
+        let relative_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Relative to the headset\0") };
+        props.add_bool(keys::RELATIVE_TO_HMD, relative_description);
+        let smoothing_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Smoothing (seconds)\0") };
+        props.add_float(keys::SMOOTHING, smoothing_description, 0.0, 5.0, 0.01);
+
+        let enabled_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Enabled\0") };
+        let input_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Follows\0") };
+        let in_min_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Input minimum\0") };
+        let in_max_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Input maximum\0") };
+        let out_min_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Output at input minimum\0") };
+        let out_max_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Output at input maximum\0") };
+        let dead_zone_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Dead zone\0") };
+        for &channel in Channel::ALL.iter() {
+            let keys = channel.keys();
+            let mut group = Properties::new();
+            group.add_bool(keys.enabled, enabled_description);
+            group.add_string_list_complete(PropertyDescription::new(keys.input, Some(input_description)), Input::ALL.iter().map(|&input| (input.description(), input.key())));
+            group.add_float(keys.in_min, in_min_description, -1000.0, 1000.0, 0.01);
+            group.add_float(keys.in_max, in_max_description, -1000.0, 1000.0, 0.01);
+            group.add_float(keys.out_min, out_min_description, -100000.0, 100000.0, 0.01);
+            group.add_float(keys.out_max, out_max_description, -100000.0, 100000.0, 0.01);
+            group.add_float(keys.dead_zone, dead_zone_description, 0.0, 1000.0, 0.01);
+            props.add_group(keys.group, channel.description(), group);
+        }
+
+        let mut mirror = Properties::new();
+        let eye_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Eye\0") };
+        let left_eye: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Left\0") };
+        let right_eye: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Right\0") };
+        mirror.add_string_list_complete(PropertyDescription::new(keys::MIRROR_EYE, Some(eye_description)), [(left_eye, keys::EYE_LEFT), (right_eye, keys::EYE_RIGHT)].iter().copied());
+        let crops: [(&'static CStr, &'static [u8]); 4] = [
+            (keys::MIRROR_CROP_LEFT, b"Cropped off the left (%)\0"),
+            (keys::MIRROR_CROP_TOP, b"Cropped off the top (%)\0"),
+            (keys::MIRROR_CROP_RIGHT, b"Cropped off the right (%)\0"),
+            (keys::MIRROR_CROP_BOTTOM, b"Cropped off the bottom (%)\0"),
+        ];
+        for &(key, description) in crops.iter() {
+            mirror.add_float(key, unsafe { CStr::from_bytes_with_nul_unchecked(description) }, 0.0, 100.0, 0.1);
+        }
+        let mirror_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Mirror view\0") };
+        props.add_group(keys::MIRROR_VIEW, mirror_description, mirror);
+
+        unsafe { props.leak() }
+    }
+
+    fn update(&self, data: &obs::sys::obs_data) {
+        let mut state = self.state.lock().unwrap();
+        state.settings = PoseTransformSettings::from_settings(data);
+        state.item = None;
+        state.resolved = None;
+        state.smoothers = Default::default();
+    }
+
+    fn get_dimensions(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn video_tick(&self, seconds: f32) {
+        self.state.lock().unwrap().tick(seconds);
+    }
+
+    fn video_render(&self, _effect: *mut obs::sys::gs_effect_t) {
+    }
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    /// Setting keys of one `Channel`
+    pub struct ChannelKeys {
+        pub group: &'static CStr,
+        pub enabled: &'static CStr,
+        pub input: &'static CStr,
+        pub in_min: &'static CStr,
+        pub in_max: &'static CStr,
+        pub out_min: &'static CStr,
+        pub out_max: &'static CStr,
+        pub dead_zone: &'static CStr,
+    }
+
+    pub const SCENE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"scene\0")
+    };
+    pub const ITEM: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"item\0")
+    };
+    pub const DEVICE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"device\0")
+    };
+    pub const DEVICE_HMD: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"hmd\0")
+    };
+    pub const DEVICE_LEFT_CONTROLLER: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"left_controller\0")
+    };
+    pub const DEVICE_RIGHT_CONTROLLER: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"right_controller\0")
+    };
+    pub const DEVICE_INDEX_MODE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"index\0")
+    };
+    pub const DEVICE_INDEX: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"device_index\0")
+    };
+    pub const RELATIVE_TO_HMD: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"relative_to_hmd\0")
+    };
+    pub const SMOOTHING: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"smoothing\0")
+    };
+    pub const INPUT_X: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"x\0")
+    };
+    pub const INPUT_Y: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"y\0")
+    };
+    pub const INPUT_Z: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"z\0")
+    };
+    pub const INPUT_YAW: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"yaw\0")
+    };
+    pub const INPUT_PITCH: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"pitch\0")
+    };
+    pub const INPUT_ROLL: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"roll\0")
+    };
+    pub const INPUT_MIRROR_X: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"mirror_x\0")
+    };
+    pub const INPUT_MIRROR_Y: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"mirror_y\0")
+    };
+    pub const MIRROR_VIEW: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"mirror_view\0")
+    };
+    pub const MIRROR_EYE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"mirror_eye\0")
+    };
+    pub const EYE_LEFT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"left\0")
+    };
+    pub const EYE_RIGHT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"right\0")
+    };
+    pub const MIRROR_CROP_LEFT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"mirror_crop_left\0")
+    };
+    pub const MIRROR_CROP_TOP: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"mirror_crop_top\0")
+    };
+    pub const MIRROR_CROP_RIGHT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"mirror_crop_right\0")
+    };
+    pub const MIRROR_CROP_BOTTOM: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"mirror_crop_bottom\0")
+    };
+    pub const POSITION_X: ChannelKeys = ChannelKeys {
+        group: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_x\0") },
+        enabled: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_x_enabled\0") },
+        input: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_x_input\0") },
+        in_min: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_x_in_min\0") },
+        in_max: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_x_in_max\0") },
+        out_min: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_x_out_min\0") },
+        out_max: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_x_out_max\0") },
+        dead_zone: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_x_dead_zone\0") },
+    };
+    pub const POSITION_Y: ChannelKeys = ChannelKeys {
+        group: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_y\0") },
+        enabled: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_y_enabled\0") },
+        input: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_y_input\0") },
+        in_min: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_y_in_min\0") },
+        in_max: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_y_in_max\0") },
+        out_min: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_y_out_min\0") },
+        out_max: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_y_out_max\0") },
+        dead_zone: unsafe { CStr::from_bytes_with_nul_unchecked(b"position_y_dead_zone\0") },
+    };
+    pub const ROTATION: ChannelKeys = ChannelKeys {
+        group: unsafe { CStr::from_bytes_with_nul_unchecked(b"rotation\0") },
+        enabled: unsafe { CStr::from_bytes_with_nul_unchecked(b"rotation_enabled\0") },
+        input: unsafe { CStr::from_bytes_with_nul_unchecked(b"rotation_input\0") },
+        in_min: unsafe { CStr::from_bytes_with_nul_unchecked(b"rotation_in_min\0") },
+        in_max: unsafe { CStr::from_bytes_with_nul_unchecked(b"rotation_in_max\0") },
+        out_min: unsafe { CStr::from_bytes_with_nul_unchecked(b"rotation_out_min\0") },
+        out_max: unsafe { CStr::from_bytes_with_nul_unchecked(b"rotation_out_max\0") },
+        dead_zone: unsafe { CStr::from_bytes_with_nul_unchecked(b"rotation_dead_zone\0") },
+    };
+    pub const SCALE: ChannelKeys = ChannelKeys {
+        group: unsafe { CStr::from_bytes_with_nul_unchecked(b"scale\0") },
+        enabled: unsafe { CStr::from_bytes_with_nul_unchecked(b"scale_enabled\0") },
+        input: unsafe { CStr::from_bytes_with_nul_unchecked(b"scale_input\0") },
+        in_min: unsafe { CStr::from_bytes_with_nul_unchecked(b"scale_in_min\0") },
+        in_max: unsafe { CStr::from_bytes_with_nul_unchecked(b"scale_in_max\0") },
+        out_min: unsafe { CStr::from_bytes_with_nul_unchecked(b"scale_out_min\0") },
+        out_max: unsafe { CStr::from_bytes_with_nul_unchecked(b"scale_out_max\0") },
+        dead_zone: unsafe { CStr::from_bytes_with_nul_unchecked(b"scale_dead_zone\0") },
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(dead_zone: f32) -> Mapping {
+        Mapping {
+            in_min: -45.0,
+            in_max: 45.0,
+            out_min: 1000.0,
+            out_max: 0.0,
+            dead_zone: dead_zone,
+        }
+    }
+
+    #[test]
+    fn maps_and_clamps() {
+        let mapping = mapping(0.0);
+        assert_eq!(mapping.map(-45.0), 1000.0);
+        assert_eq!(mapping.map(0.0), 500.0);
+        assert_eq!(mapping.map(22.5), 250.0);
+        assert_eq!(mapping.map(90.0), 0.0);
+        assert_eq!(mapping.map(-90.0), 1000.0);
+    }
+
+    #[test]
+    fn dead_zone_holds_center_without_jumping() {
+        let mapping = mapping(5.0);
+        assert_eq!(mapping.map(4.0), 500.0);
+        assert_eq!(mapping.map(-5.0), 500.0);
+        assert!((mapping.map(5.1) - 500.0).abs() < 2.0);
+        assert_eq!(mapping.map(45.0), 0.0);
+        assert_eq!(mapping.map(25.0), 250.0);
+    }
+
+    #[test]
+    fn smoothing_approaches_target() {
+        let mut smoother = Smoother::default();
+        assert_eq!(smoother.update(10.0, 0.016, 0.1), 10.0);
+        let halfway = smoother.update(20.0, 0.1 * std::f32::consts::LN_2, 0.1);
+        assert!((halfway - 15.0).abs() < 1e-3);
+        assert_eq!(Smoother::default().update(3.0, 0.016, 0.0), 3.0);
+        let mut unsmoothed = Smoother(Some(0.0));
+        assert_eq!(unsmoothed.update(3.0, 0.016, 0.0), 3.0);
+    }
+
+    fn pose_at(matrix: [[f32; 4]; 3]) -> Pose {
+        Pose {
+            matrix: matrix,
+            velocity: [0.0; 3],
+            angular_velocity: [0.0; 3],
+            tracking_result: 0,
+            valid: true,
+            connected: true,
+        }
+    }
+
+    fn translation(x: f32, y: f32, z: f32) -> Pose {
+        pose_at([[1.0, 0.0, 0.0, x], [0.0, 1.0, 0.0, y], [0.0, 0.0, 1.0, z]])
+    }
+
+    fn assert_near(actual: Option<[f32; 2]>, expected: [f32; 2]) {
+        let [x, y] = actual.unwrap();
+        assert!((x - expected[0]).abs() < 1e-5 && (y - expected[1]).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn projects_into_mirror_view() {
+        let view = MirrorView {
+            eye: Eye::Left,
+            crop: [0.0; 4],
+        };
+        let hmd = translation(0.0, 1.5, 0.0);
+        let eye = translation(-0.03, 0.0, 0.0);
+        let projection = [-1.0, 1.0, -1.0, 1.0];
+        // Straight ahead of the eye, then a 45° tangent right and half that up
+        assert_near(view.project(&translation(-0.03, 1.5, -1.0), &hmd, &eye, projection), [0.5, 0.5]);
+        assert_near(view.project(&translation(0.97, 1.5, -1.0), &hmd, &eye, projection), [1.0, 0.5]);
+        assert_near(view.project(&translation(-0.03, 2.0, -1.0), &hmd, &eye, projection), [0.5, 0.25]);
+        // Behind the eye
+        assert_eq!(view.project(&translation(0.0, 1.5, 1.0), &hmd, &eye, projection), None);
+        // Off-center projection: the middle of the view is to the right of straight ahead
+        assert_near(view.project(&translation(-0.03, 1.5, -1.0), &hmd, &eye, [-1.5, 0.5, -1.0, 1.0]), [0.75, 0.5]);
+        // Turned 90° left, looking down world -X
+        let turned = pose_at([[0.0, 0.0, 1.0, 0.0], [0.0, 1.0, 0.0, 1.5], [-1.0, 0.0, 0.0, 0.0]]);
+        assert_near(view.project(&translation(-1.0, 1.5, 0.03), &turned, &eye, projection), [0.5, 0.5]);
+    }
+
+    #[test]
+    fn mirror_view_crop() {
+        let view = MirrorView {
+            eye: Eye::Right,
+            crop: [0.25, 0.1, 0.25, 0.3],
+        };
+        let hmd = translation(0.0, 0.0, 0.0);
+        let projection = [-1.0, 1.0, -1.0, 1.0];
+        // The center of the whole view, and its right and top edges
+        assert_near(view.project(&translation(0.0, 0.0, -2.0), &hmd, &hmd, projection), [0.5, 0.4 / 0.6]);
+        assert_near(view.project(&translation(1.0, 1.0, -1.0), &hmd, &hmd, projection), [1.5, -0.1 / 0.6]);
+        assert_near(view.project(&translation(0.0, 0.8, -1.0), &hmd, &hmd, projection), [0.5, 0.0]);
+    }
+}
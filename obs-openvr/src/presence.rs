@@ -0,0 +1,307 @@
+//! Detecting whether somebody is wearing the headset, from the HMD's proximity sensor and activity
+//! level, and switching OBS to a "be right back" scene while nobody is
+
+use std::{
+    ffi::{
+        CStr,
+        CString,
+    },
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use obs::{
+    data::ObsData,
+    source::OwnedSource,
+};
+use openvr::system::{
+    ActivityLevel,
+    Event,
+    event_type,
+};
+use crate::config::ConfigFile;
+
+/// How often the HMD's activity level is checked, on top of user interaction events
+const ACTIVITY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Tracked device index of the HMD (`k_unTrackedDeviceIndex_Hmd`)
+const HMD_INDEX: u32 = 0;
+
+const CONFIG_FILE: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"presence.json\0") };
+const CONFIG_TEMPLATE: &'static str = r#"{
+    "brb_scene": "",
+    "delay": 10,
+    "restore_scene": true
+}
+"#;
+
+static USER_PRESENT: AtomicBool = AtomicBool::new(true);
+
+/// Whether somebody is wearing the headset, as far as the event thread knows. `true` until it
+/// finds out otherwise, including while OpenVR isn't running.
+#[inline]
+pub fn is_user_present() -> bool {
+    USER_PRESENT.load(Ordering::Relaxed)
+}
+
+/// What presence changes do to the program scene
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresenceConfig {
+    /// Scene to switch to while nobody is wearing the headset, or `None` to never switch
+    pub brb_scene: Option<CString>,
+    /// How long nobody has to wear the headset before switching
+    pub delay: Duration,
+    /// Whether to switch back to the previous scene once somebody wears the headset again
+    pub restore_scene: bool,
+}
+
+impl PresenceConfig {
+    fn from_data(data: &obs::sys::obs_data) -> Self {
+        PresenceConfig {
+            brb_scene: data.get_cstr(keys::BRB_SCENE)
+                .filter(|name| !name.to_bytes().is_empty())
+                .map(|name| name.to_owned()),
+            delay: Duration::from_secs(data.get_int(keys::DELAY).max(0) as u64),
+            restore_scene: data.get_bool(keys::RESTORE_SCENE),
+        }
+    }
+
+    fn set_defaults(data: &mut obs::sys::obs_data) {
+        data.set_default_int(keys::DELAY, 10);
+        data.set_default_bool(keys::RESTORE_SCENE, true);
+    }
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        PresenceConfig {
+            brb_scene: None,
+            delay: Duration::from_secs(10),
+            restore_scene: true,
+        }
+    }
+}
+
+/// Presence an activity level clearly indicates, if any. `Idle` doesn't: somebody sitting still
+/// with the headset on looks the same.
+fn presence_from_activity(level: ActivityLevel) -> Option<bool> {
+    match level {
+        ActivityLevel::UserInteraction => Some(true),
+        ActivityLevel::Standby | ActivityLevel::IdleTimeout => Some(false),
+        ActivityLevel::Idle | ActivityLevel::UserInteractionTimeout => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SceneAction {
+    SwitchToBrb,
+    Restore,
+}
+
+/// Whether somebody is wearing the headset, and since when
+struct PresenceState {
+    present: bool,
+    since: Instant,
+    /// Whether the BRB scene was switched to, and not switched back from yet
+    switched: bool,
+}
+
+impl PresenceState {
+    fn new(now: Instant) -> Self {
+        PresenceState {
+            present: true,
+            since: now,
+            switched: false,
+        }
+    }
+
+    /// Records the current presence. Returns whether it changed.
+    fn set(&mut self, present: bool, now: Instant) -> bool {
+        if present == self.present {
+            return false;
+        }
+        self.present = present;
+        self.since = now;
+        true
+    }
+
+    /// What to do with the program scene now, if anything
+    fn action(&mut self, config: &PresenceConfig, now: Instant) -> Option<SceneAction> {
+        if self.present && self.switched {
+            self.switched = false;
+            Some(SceneAction::Restore)
+        } else if !self.present && !self.switched && config.brb_scene.is_some() && now.duration_since(self.since) >= config.delay {
+            self.switched = true;
+            Some(SceneAction::SwitchToBrb)
+        } else {
+            None
+        }
+    }
+}
+
+/// Tracks presence on the event thread, and switches scenes when it changes
+pub struct PresenceMonitor {
+    file: ConfigFile,
+    config: PresenceConfig,
+    state: PresenceState,
+    activity_checked: Option<Instant>,
+    /// Program scene before switching to the BRB scene
+    previous_scene: Option<CString>,
+}
+
+impl PresenceMonitor {
+    pub fn new() -> Self {
+        PresenceMonitor {
+            file: ConfigFile::new(CONFIG_FILE, CONFIG_TEMPLATE, PresenceConfig::set_defaults),
+            config: PresenceConfig::default(),
+            state: PresenceState::new(Instant::now()),
+            activity_checked: None,
+            previous_scene: None,
+        }
+    }
+
+    fn set_present(&mut self, present: bool, now: Instant) {
+        if self.state.set(present, now) {
+            info!("headset {}", if present { "put on" } else { "taken off" });
+            USER_PRESENT.store(present, Ordering::Relaxed);
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event, now: Instant) {
+        if event.device_index != HMD_INDEX {
+            return;
+        }
+        match event.event_type {
+            event_type::USER_INTERACTION_STARTED => self.set_present(true, now),
+            event_type::USER_INTERACTION_ENDED => self.set_present(false, now),
+            _ => {},
+        }
+    }
+
+    /// Picks up config changes. Returns whether a BRB scene is set, which needs OpenVR.
+    pub fn reload_config(&mut self) -> bool {
+        if let Some(data) = self.file.reload() {
+            self.config = PresenceConfig::from_data(&data);
+            debug!("presence config: {:?}", &self.config);
+        }
+        self.config.brb_scene.is_some()
+    }
+
+    pub fn tick(&mut self, now: Instant) {
+        if self.activity_checked.map_or(true, |t| now.duration_since(t) >= ACTIVITY_CHECK_INTERVAL) {
+            self.activity_checked = Some(now);
+            if let Some(present) = openvr::system::hmd_activity_level().and_then(presence_from_activity) {
+                self.set_present(present, now);
+            }
+        }
+        match self.state.action(&self.config, now) {
+            Some(SceneAction::SwitchToBrb) => self.switch_to_brb(),
+            Some(SceneAction::Restore) => self.restore(),
+            None => {},
+        }
+    }
+
+    fn switch_to_brb(&mut self) {
+        let name = match self.config.brb_scene.as_ref() {
+            Some(v) => v,
+            None => return,
+        };
+        let scene = match OwnedSource::by_name(name) {
+            Some(v) => v,
+            None => {
+                warn!("BRB scene {:?} not found", name);
+                return;
+            },
+        };
+        self.previous_scene = obs::frontend::current_scene()
+            .map(|scene| scene.name().to_owned())
+            .filter(|previous| previous != name);
+        info!("switching to BRB scene {:?}", name);
+        obs::frontend::set_current_scene(&scene);
+    }
+
+    fn restore(&mut self) {
+        let previous = match self.previous_scene.take() {
+            Some(v) if self.config.restore_scene => v,
+            _ => return,
+        };
+        // Scene changes made while away stick
+        let current = obs::frontend::current_scene();
+        if current.as_ref().map(|scene| scene.name()) != self.config.brb_scene.as_ref().map(|name| name.as_c_str()) {
+            return;
+        }
+        match OwnedSource::by_name(&previous) {
+            Some(scene) => {
+                info!("switching back to {:?}", &previous);
+                obs::frontend::set_current_scene(&scene);
+            },
+            None => warn!("Scene {:?} not found, staying on the BRB scene", &previous),
+        }
+    }
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const BRB_SCENE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"brb_scene\0")
+    };
+    pub const DELAY: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"delay\0")
+    };
+    pub const RESTORE_SCENE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"restore_scene\0")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> PresenceConfig {
+        PresenceConfig {
+            brb_scene: Some(CString::new("BRB").unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn switches_after_delay_and_back() {
+        let start = Instant::now();
+        let config = config();
+        let mut state = PresenceState::new(start);
+        assert_eq!(state.action(&config, start), None);
+        state.set(false, start);
+        assert_eq!(state.action(&config, start + Duration::from_secs(9)), None);
+        assert_eq!(state.action(&config, start + Duration::from_secs(10)), Some(SceneAction::SwitchToBrb));
+        assert_eq!(state.action(&config, start + Duration::from_secs(11)), None);
+        state.set(true, start + Duration::from_secs(12));
+        assert_eq!(state.action(&config, start + Duration::from_secs(12)), Some(SceneAction::Restore));
+        assert_eq!(state.action(&config, start + Duration::from_secs(13)), None);
+    }
+
+    #[test]
+    fn short_absence_doesnt_switch() {
+        let start = Instant::now();
+        let config = config();
+        let mut state = PresenceState::new(start);
+        state.set(false, start);
+        state.set(true, start + Duration::from_secs(5));
+        assert_eq!(state.action(&config, start + Duration::from_secs(20)), None);
+        // The delay restarts with every absence
+        state.set(false, start + Duration::from_secs(20));
+        assert_eq!(state.action(&config, start + Duration::from_secs(25)), None);
+    }
+
+    #[test]
+    fn never_switches_without_scene() {
+        let start = Instant::now();
+        let mut state = PresenceState::new(start);
+        state.set(false, start);
+        assert_eq!(state.action(&PresenceConfig::default(), start + Duration::from_secs(60)), None);
+    }
+}
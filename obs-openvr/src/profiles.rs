@@ -0,0 +1,335 @@
+//! Per-application settings profiles: sources that enable them switch to the settings saved for
+//! the running SteamVR scene application, and back to their own when there are none
+//!
+//! Profiles are JSON files in the `profiles` subdirectory of the module's config directory, one
+//! per application key, each mapping source names to their settings.
+
+use std::{
+    ffi::{
+        CStr,
+        CString,
+    },
+    os::unix::ffi::OsStringExt,
+    sync::Mutex,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use obs::{
+    data::ObsData,
+    source::{
+        OwnedSource,
+        WeakSource,
+    },
+};
+use openvr::system::{
+    Event,
+    event_type,
+};
+
+/// How often the scene application is checked, on top of scene application change events
+const APP_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProfileError {
+    #[error("No SteamVR application is running")]
+    NoApplication,
+    #[error("Error reading the source's settings")]
+    NoSettings,
+    #[error("Error creating the profile")]
+    Create,
+    #[error("Error writing profile {0:?}")]
+    Write(CString),
+}
+
+/// A source profiles are applied to
+struct Registered {
+    source: WeakSource,
+    /// Application the source's settings were last switched for
+    applied: Option<Option<CString>>,
+    /// Whether the source has profiles enabled
+    enabled: bool,
+}
+
+static SOURCES: Mutex<Vec<Registered>> = Mutex::new(Vec::new());
+static CURRENT_APP: Mutex<Option<CString>> = Mutex::new(None);
+
+/// Key of the running scene application, as far as the event thread knows
+pub fn current_app() -> Option<CString> {
+    CURRENT_APP.lock().unwrap().clone()
+}
+
+/// Applies profiles to `source` from now on. It is switched to the current application's
+/// profile on the next event thread tick.
+pub fn register(source: *mut obs::sys::obs_source_t) {
+    let weak = match WeakSource::new(source) {
+        Some(v) => v,
+        None => return,
+    };
+    SOURCES.lock().unwrap().push(Registered {
+        source: weak,
+        applied: None,
+        enabled: false,
+    });
+}
+
+pub fn unregister(source: *mut obs::sys::obs_source_t) {
+    SOURCES.lock().unwrap().retain(|registered| !registered.source.references(source));
+}
+
+/// Records whether `source` has profiles enabled. Enabling them makes the event thread switch it
+/// to the current application's profile again.
+pub fn set_enabled(source: *mut obs::sys::obs_source_t, enabled: bool) {
+    SOURCES.lock().unwrap().iter_mut()
+        .filter(|registered| registered.source.references(source))
+        .for_each(|registered| {
+            if enabled && !registered.enabled {
+                registered.applied = None;
+            }
+            registered.enabled = enabled;
+        });
+}
+
+/// Whether any source has profiles enabled, which needs OpenVR to know the scene application
+pub fn any_enabled() -> bool {
+    SOURCES.lock().unwrap().iter().any(|registered| registered.enabled)
+}
+
+/// File name of an application's profile, relative to the module's config directory
+fn profile_file(app: &CStr) -> CString {
+    let name: String = app.to_string_lossy().chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    CString::new(format!("profiles/{}.json", name)).unwrap()
+}
+
+fn profile_path(app: &CStr) -> Option<CString> {
+    let path = crate::module::config_path(&profile_file(app))?;
+    CString::new(path.into_os_string().into_vec()).ok()
+}
+
+/// Settings `source` uses in `app`, if a profile has them
+fn load_profile(app: &CStr, source: &CStr) -> Option<obs::Data> {
+    let path = profile_path(app)?;
+    let profiles = obs::Data::from_json_file(&path)?;
+    profiles.get_obj(source)
+}
+
+/// Copy of `settings`, without what profiles themselves keep in them
+fn profile_settings(settings: &obs::sys::obs_data) -> Option<obs::Data> {
+    let mut ret = obs::Data::new()?;
+    ret.apply(settings);
+    ret.erase(keys::ENABLED);
+    ret.erase(keys::BASE);
+    Some(ret)
+}
+
+/// Saves the settings of `source` as its profile for the current application. Returns the
+/// application's key.
+pub fn save_current(source: *mut obs::sys::obs_source_t) -> Result<CString, ProfileError> {
+    let app = current_app().ok_or(ProfileError::NoApplication)?;
+    let source = unsafe { OwnedSource::from_raw(source) }.ok_or(ProfileError::NoSettings)?;
+    let settings = source.settings().ok_or(ProfileError::NoSettings)?;
+    let settings = profile_settings(&settings).ok_or(ProfileError::Create)?;
+    let path = profile_path(&app).ok_or_else(|| ProfileError::Write(profile_file(&app)))?;
+    let mut profiles = obs::Data::from_json_file(&path)
+        .or_else(obs::Data::new)
+        .ok_or(ProfileError::Create)?;
+    profiles.set_obj(source.name(), &settings);
+    if !profiles.save_json(&path) {
+        return Err(ProfileError::Write(path));
+    }
+    info!("saved profile for {:?} in {:?}", source.name(), &app);
+    // The source already has these settings
+    SOURCES.lock().unwrap().iter_mut()
+        .filter(|registered| registered.source.references(source.as_ptr()))
+        .for_each(|registered| registered.applied = Some(Some(app.clone())));
+    Ok(app)
+}
+
+/// Whether `app` has a profile for `source`
+pub fn has_profile(app: &CStr, source: *mut obs::sys::obs_source_t) -> bool {
+    unsafe { OwnedSource::from_raw(source) }
+        .map_or(false, |source| load_profile(app, source.name()).is_some())
+}
+
+/// Replaces the user values of `settings` with `values`, keeping profiles enabled
+fn replace_settings(settings: &mut obs::sys::obs_data, values: &obs::sys::obs_data, base: Option<&obs::sys::obs_data>) {
+    settings.unset_user_values();
+    // `obs::Data::apply` needs an owned object, and this is the source's own
+    unsafe {
+        let values: *mut obs::sys::obs_data = values as *const _ as *mut _;
+        obs::sys::obs_data_apply(settings as *mut _, values);
+    }
+    settings.set_bool(keys::ENABLED, true);
+    if let Some(base) = base {
+        settings.set_obj(keys::BASE, base);
+    }
+}
+
+/// Switches `source` to its settings for `app`: its profile if there is one, otherwise the
+/// settings it had before switching to a profile. Must run on the UI thread, which owns the
+/// source's settings.
+fn switch_source(source: &OwnedSource, app: Option<&CStr>) {
+    let mut settings = match source.settings() {
+        Some(v) => v,
+        None => return,
+    };
+    if !settings.get_bool(keys::ENABLED) {
+        return;
+    }
+    match app.and_then(|app| load_profile(app, source.name())) {
+        Some(profile) => {
+            // Remember the source's own settings the first time it switches to a profile
+            let base = match settings.get_obj(keys::BASE).or_else(|| profile_settings(&settings)) {
+                Some(v) => v,
+                None => return,
+            };
+            info!("switching {:?} to its profile for {:?}", source.name(), app);
+            replace_settings(&mut settings, &profile, Some(&*base));
+        },
+        None => {
+            let base = match settings.get_obj(keys::BASE) {
+                Some(v) => v,
+                None => return,
+            };
+            info!("switching {:?} back to its own settings", source.name());
+            replace_settings(&mut settings, &base, None);
+        },
+    }
+    source.apply_settings();
+}
+
+/// Tracks the scene application on the event thread, and has the UI thread switch registered
+/// sources' profiles
+pub struct ProfileMonitor {
+    app_checked: Option<Instant>,
+}
+
+impl ProfileMonitor {
+    pub fn new() -> Self {
+        ProfileMonitor {
+            app_checked: None,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event, _now: Instant) {
+        if event.event_type == event_type::SCENE_APPLICATION_CHANGED {
+            // Check right away on the next tick
+            self.app_checked = None;
+        }
+    }
+
+    pub fn tick(&mut self, now: Instant) {
+        if self.app_checked.map_or(true, |t| now.duration_since(t) >= APP_CHECK_INTERVAL) {
+            self.app_checked = Some(now);
+            let app = openvr::applications::scene_application_key();
+            let mut current = CURRENT_APP.lock().unwrap();
+            if *current != app {
+                info!("scene application: {:?}", &app);
+                *current = app;
+            }
+        }
+        let app = current_app();
+        // Sources are switched without holding the lock, since updating them can unregister others
+        let pending: Vec<OwnedSource> = {
+            let mut sources = SOURCES.lock().unwrap();
+            sources.iter_mut()
+                .filter(|registered| registered.applied.as_ref() != Some(&app))
+                .filter_map(|registered| {
+                    registered.applied = Some(app.clone());
+                    registered.source.upgrade()
+                })
+                .collect()
+        };
+        if !pending.is_empty() {
+            obs::queue_ui_task(move || pending.iter().for_each(|source| switch_source(source, app.as_deref())));
+        }
+    }
+}
+
+pub fn set_defaults(settings: &mut obs::sys::obs_data) {
+    settings.set_default_bool(keys::ENABLED, false);
+}
+
+/// A source type with profiles, which can save them from its properties
+pub trait ProfileSource {
+    fn source_handle(&self) -> *mut obs::sys::obs_source_t;
+}
+
+unsafe extern "C" fn save_clicked<S: ProfileSource>(props: *mut obs::sys::obs_properties_t, _property: *mut obs::sys::obs_property_t, data: *mut libc::c_void) -> bool {
+    let source = match (data as *const S).as_ref() {
+        Some(v) => v,
+        None => return false,
+    };
+    let info = match save_current(source.source_handle()) {
+        Ok(app) => format!("Saved profile for {}", app.to_string_lossy()),
+        Err(e) => {
+            warn!("Error saving profile: {}", &e);
+            format!("Error saving profile: {}", &e)
+        },
+    };
+    if let Ok(info) = CString::new(info) {
+        obs::properties::with_raw(props, |props| {
+            if let Some(property) = props.get(keys::INFO) {
+                use obs::properties::PropertyExt;
+                property.set_description(&info);
+            }
+        });
+    }
+    true
+}
+
+/// Adds a "Profiles" group, with a button saving `source`'s settings as profile
+pub fn add_profile_properties<S: ProfileSource>(props: &mut obs::Properties, source: &S) {
+    let source = source.source_handle();
+    let mut group = obs::Properties::new();
+    let enabled_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Switch settings with the running VR app\0") };
+    group.add_bool(keys::ENABLED, enabled_description);
+    let info = match current_app() {
+        Some(app) if has_profile(&app, source) => format!("Running: {} (has a profile)", app.to_string_lossy()),
+        Some(app) => format!("Running: {} (no profile)", app.to_string_lossy()),
+        None => "No VR app running".to_owned(),
+    };
+    if let Ok(info) = CString::new(info) {
+        group.add_text(keys::INFO, &info, obs::sys::obs_text_type_OBS_TEXT_INFO);
+    }
+    let save_text: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Save current settings as profile for this app\0") };
+    group.add_button(keys::SAVE, save_text, save_clicked::<S>);
+    let group_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Profiles\0") };
+    props.add_group(keys::GROUP, group_description, group);
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const GROUP: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"profiles\0")
+    };
+    pub const ENABLED: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"profiles_enabled\0")
+    };
+    /// The source's own settings, while it uses a profile
+    pub const BASE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"profiles_base\0")
+    };
+    pub const INFO: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"profiles_info\0")
+    };
+    pub const SAVE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"profiles_save\0")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_file_names_are_sanitized() {
+        let name = |app: &str| profile_file(&CString::new(app).unwrap()).into_string().unwrap();
+        assert_eq!(name("steam.app.620"), "profiles/steam.app.620.json");
+        assert_eq!(name("system.generated.../game/bin/x.exe"), "profiles/system.generated..._game_bin_x.exe.json");
+    }
+}
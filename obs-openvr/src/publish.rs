@@ -0,0 +1,323 @@
+//! Publishing live tracked device state and VR events to other programs, as OSC over UDP and JSON
+//! over a local WebSocket (see `obs_openvr_publish::schema` for what's sent). State is sampled on
+//! a thread of its own, and events are published by the event thread as they happen.
+//!
+//! Set in `publish.json` in the module's config directory, reloaded when it changes.
+
+use std::{
+    ffi::CStr,
+    mem,
+    net::SocketAddr,
+    sync::{
+        Arc,
+        Mutex,
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use obs::data::ObsData;
+use obs_openvr_publish::{
+    Publisher,
+    PublisherConfig,
+    schema::{
+        Buttons,
+        Device,
+        Event,
+        State,
+    },
+};
+use openvr::{
+    pose::TrackingUniverse,
+    system::{
+        ControllerRole,
+        DeviceClass,
+    },
+};
+use crate::{
+    config::ConfigFile,
+    thread_utils::JoinOnDrop,
+    vr_events::{
+        VrEvent,
+        device_class_name,
+    },
+};
+
+const CONFIG_FILE: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"publish.json\0") };
+const CONFIG_TEMPLATE: &'static str = r#"{
+    "enabled": false,
+    "rate": 30,
+    "osc": true,
+    "osc_address": "127.0.0.1:9000",
+    "websocket": true,
+    "websocket_port": 9001
+}
+"#;
+
+/// How often the config is checked while publishing is disabled
+const DISABLED_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What to publish, and where
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PublishConfig {
+    enabled: bool,
+    /// States per second
+    rate: u32,
+    publisher: PublisherConfig,
+}
+
+impl PublishConfig {
+    fn from_data(data: &obs::sys::obs_data) -> Self {
+        let osc = if data.get_bool(keys::OSC) {
+            let address = data.get_string(keys::OSC_ADDRESS).unwrap_or("");
+            match address.parse::<SocketAddr>() {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    warn!("Invalid OSC address {:?}, not publishing over OSC: {}", address, &e);
+                    None
+                },
+            }
+        } else {
+            None
+        };
+        let websocket_port = if data.get_bool(keys::WEBSOCKET) {
+            Some(data.get_int(keys::WEBSOCKET_PORT).max(0).min(u16::MAX as _) as u16)
+        } else {
+            None
+        };
+        PublishConfig {
+            enabled: data.get_bool(keys::ENABLED),
+            rate: data.get_int(keys::RATE).max(1).min(1000) as u32,
+            publisher: PublisherConfig {
+                osc: osc,
+                websocket_port: websocket_port,
+            },
+        }
+    }
+
+    fn set_defaults(data: &mut obs::sys::obs_data) {
+        data.set_default_bool(keys::ENABLED, false);
+        data.set_default_int(keys::RATE, 30);
+        data.set_default_bool(keys::OSC, true);
+        data.set_default_string(keys::OSC_ADDRESS, keys::DEFAULT_OSC_ADDRESS);
+        data.set_default_bool(keys::WEBSOCKET, true);
+        data.set_default_int(keys::WEBSOCKET_PORT, 9001);
+    }
+}
+
+/// Name a device is published under: the HMD and controllers by role, anything else by class and
+/// index
+fn device_name(index: u32, class: Option<DeviceClass>, role: Option<ControllerRole>) -> String {
+    match (class, role) {
+        (Some(DeviceClass::Hmd), _) => "hmd".to_owned(),
+        (_, Some(ControllerRole::LeftHand)) => "left_controller".to_owned(),
+        (_, Some(ControllerRole::RightHand)) => "right_controller".to_owned(),
+        (class, _) => format!("{}_{}", class.map_or("unknown", device_class_name), index),
+    }
+}
+
+fn device(index: u32, pose: &openvr::pose::Pose) -> Device {
+    let class = openvr::system::device_class(index);
+    let has_input = matches!(class, Some(DeviceClass::Controller) | Some(DeviceClass::GenericTracker));
+    let role = if has_input {
+        openvr::system::controller_role(index)
+    } else {
+        None
+    };
+    let battery = openvr::system::battery(index);
+    let buttons = if has_input {
+        openvr::system::controller_state(index)
+    } else {
+        None
+    };
+    let rotation = pose.rotation();
+    Device {
+        name: device_name(index, class, role),
+        index: index,
+        class: class.map_or("unknown", device_class_name),
+        valid: pose.valid,
+        position: pose.position(),
+        rotation: [rotation.w, rotation.x, rotation.y, rotation.z],
+        battery: battery.map(|(level, _)| level),
+        charging: battery.map(|(_, charging)| charging),
+        buttons: buttons.map(|state| Buttons {
+            pressed: state.pressed,
+            touched: state.touched,
+            axes: state.axes,
+        }),
+    }
+}
+
+fn state(init_attempted: &mut Option<Instant>) -> Option<State> {
+    if !crate::retry_init_openvr(init_attempted) {
+        return None;
+    }
+    let _session = crate::openvr_session()?;
+    let time_ns = unsafe { obs::sys::os_gettime_ns() };
+    let poses = openvr::pose::device_poses(TrackingUniverse::Standing, 0.0)?;
+    let devices = poses.iter()
+        .enumerate()
+        .filter(|(_, pose)| pose.connected)
+        .map(|(index, pose)| device(index as u32, pose))
+        .collect();
+    Some(State {
+        time_ns: time_ns,
+        app: crate::profiles::current_app().map(|app| app.to_string_lossy().into_owned()),
+        devices: devices,
+    })
+}
+
+/// The publisher for the current config, while publishing is enabled
+static PUBLISHER: Mutex<Option<Publisher>> = Mutex::new(None);
+
+/// Replaces the publisher when the config changes
+fn apply_config(config: &PublishConfig) {
+    let mut publisher = PUBLISHER.lock().unwrap();
+    // Stop the old WebSocket server first, so the new one can take its port
+    mem::drop(publisher.take());
+    if !config.enabled {
+        return;
+    }
+    match Publisher::new(&config.publisher) {
+        Ok(v) => {
+            info!("publishing to OSC {:?}, WebSocket {:?}", &config.publisher.osc, v.websocket_addr());
+            *publisher = Some(v);
+        },
+        Err(e) => warn!("Error starting publisher: {}", &e),
+    }
+}
+
+fn run(running: Arc<AtomicBool>) {
+    let mut config_file = ConfigFile::new(CONFIG_FILE, CONFIG_TEMPLATE, PublishConfig::set_defaults);
+    let mut config: Option<PublishConfig> = None;
+    let mut next = Instant::now();
+    let mut init_attempted = None;
+    while running.load(Ordering::Relaxed) {
+        if let Some(data) = config_file.reload() {
+            let new_config = PublishConfig::from_data(&data);
+            if config.as_ref() != Some(&new_config) {
+                apply_config(&new_config);
+                config = Some(new_config);
+            }
+        }
+        let rate = match config.as_ref() {
+            Some(config) if config.enabled => config.rate,
+            _ => {
+                thread::sleep(DISABLED_INTERVAL);
+                next = Instant::now();
+                continue;
+            },
+        };
+        if let Some(state) = state(&mut init_attempted) {
+            if let Some(publisher) = PUBLISHER.lock().unwrap().as_ref() {
+                publisher.publish_state(&state);
+            }
+        }
+        next += Duration::from_secs(1) / rate;
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        } else {
+            // Fell behind, don't try to catch up
+            next = now;
+        }
+    }
+}
+
+/// Publishes `event`, if publishing is enabled. Called on the event thread.
+pub fn publish_event(event: &VrEvent) {
+    if let Some(publisher) = PUBLISHER.lock().unwrap().as_ref() {
+        publisher.publish_event(&Event {
+            time_ns: unsafe { obs::sys::os_gettime_ns() },
+            event: event.name().to_owned(),
+            detail: event.detail().into_owned(),
+        });
+    }
+}
+
+struct PublishThread {
+    running: Arc<AtomicBool>,
+    _thread: JoinOnDrop<()>,
+}
+
+static PUBLISH_THREAD: Mutex<Option<PublishThread>> = Mutex::new(None);
+
+/// Starts publishing, as the config says, if that isn't happening already
+pub fn start() {
+    let mut publish_thread = PUBLISH_THREAD.lock().unwrap();
+    if publish_thread.is_some() {
+        return;
+    }
+    let running = Arc::new(AtomicBool::new(true));
+    let thread_running = running.clone();
+    let handle = thread::Builder::new()
+        .name("obs-openvr-publish".to_owned())
+        .spawn(move || run(thread_running));
+    match handle {
+        Ok(handle) => {
+            *publish_thread = Some(PublishThread {
+                running: running,
+                _thread: handle.into(),
+            });
+        },
+        Err(e) => error!("Error starting publisher thread: {}", &e),
+    }
+}
+
+/// Stops publishing, waiting for the publisher thread to exit. Must be called before shutting
+/// down OpenVR.
+pub fn shutdown() {
+    let publish_thread = PUBLISH_THREAD.lock().unwrap().take();
+    if let Some(publish_thread) = publish_thread {
+        publish_thread.running.store(false, Ordering::Relaxed);
+        mem::drop(publish_thread);
+    }
+    *PUBLISHER.lock().unwrap() = None;
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const ENABLED: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"enabled\0")
+    };
+    pub const RATE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"rate\0")
+    };
+    pub const OSC: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"osc\0")
+    };
+    pub const OSC_ADDRESS: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"osc_address\0")
+    };
+    pub const DEFAULT_OSC_ADDRESS: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"127.0.0.1:9000\0")
+    };
+    pub const WEBSOCKET: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"websocket\0")
+    };
+    pub const WEBSOCKET_PORT: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"websocket_port\0")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_names() {
+        assert_eq!(device_name(0, Some(DeviceClass::Hmd), None), "hmd");
+        assert_eq!(device_name(3, Some(DeviceClass::Controller), Some(ControllerRole::LeftHand)), "left_controller");
+        assert_eq!(device_name(4, Some(DeviceClass::Controller), Some(ControllerRole::RightHand)), "right_controller");
+        assert_eq!(device_name(5, Some(DeviceClass::Controller), None), "controller_5");
+        assert_eq!(device_name(6, Some(DeviceClass::GenericTracker), None), "tracker_6");
+        assert_eq!(device_name(7, None, None), "unknown_7");
+    }
+}
@@ -0,0 +1,157 @@
+//! Tracking the OBS recording, for features that write files alongside it. Recordings are followed
+//! through frontend events, which only happen when the module is loaded by the OBS app, and pose
+//! recording is started and stopped along with them.
+
+use std::{
+    ffi::OsStr,
+    mem,
+    os::unix::ffi::OsStrExt,
+    path::{
+        Path,
+        PathBuf,
+    },
+    ptr,
+    sync::{
+        Mutex,
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+    },
+    time::Duration,
+};
+
+/// A recording in progress
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recording {
+    /// Distinguishes recordings, even when they're written to the same file
+    pub id: u64,
+    /// File the recording is written to, if the output has one
+    pub path: Option<PathBuf>,
+    /// OBS clock time (`os_gettime_ns`) the recording started at
+    pub started: u64,
+    /// OBS clock time the recording was paused at, while it is
+    paused_since: Option<u64>,
+    /// Time spent paused before `paused_since`, in nanoseconds
+    paused: u64,
+}
+
+impl Recording {
+    fn new(id: u64, path: Option<PathBuf>, now: u64) -> Self {
+        Recording {
+            id: id,
+            path: path,
+            started: now,
+            paused_since: None,
+            paused: 0,
+        }
+    }
+
+    fn pause(&mut self, now: u64) {
+        if self.paused_since.is_none() {
+            self.paused_since = Some(now);
+        }
+    }
+
+    fn unpause(&mut self, now: u64) {
+        if let Some(since) = self.paused_since.take() {
+            self.paused += now.saturating_sub(since);
+        }
+    }
+
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused_since.is_some()
+    }
+
+    /// Position in the recording at OBS clock time `now`, not counting time spent paused
+    pub fn elapsed(&self, now: u64) -> Duration {
+        let end = self.paused_since.unwrap_or(now);
+        Duration::from_nanos(end.saturating_sub(self.started).saturating_sub(self.paused))
+    }
+
+    /// Path of a file next to the recording, named after it with `suffix` in place of its
+    /// extension
+    pub fn sidecar_path(&self, suffix: &str) -> Option<PathBuf> {
+        let path = self.path.as_ref()?;
+        let mut name = path.file_stem()?.to_owned();
+        name.push(suffix);
+        Some(path.with_file_name(name))
+    }
+}
+
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The recording in progress, if any
+pub fn current() -> Option<Recording> {
+    RECORDING.lock().unwrap().clone()
+}
+
+fn recording_path() -> Option<PathBuf> {
+    let path = obs::frontend::recording_path()?;
+    Some(Path::new(OsStr::from_bytes(path.to_bytes())).to_owned())
+}
+
+unsafe extern "C" fn frontend_event(event: obs::sys::obs_frontend_event, _data: *mut libc::c_void) {
+    let now = obs::sys::os_gettime_ns();
+    let mut recording = RECORDING.lock().unwrap();
+    match event {
+        obs::sys::obs_frontend_event_OBS_FRONTEND_EVENT_RECORDING_STARTED => {
+            let started = Recording::new(NEXT_ID.fetch_add(1, Ordering::Relaxed), recording_path(), now);
+            info!("recording started: {:?}", &started.path);
+            *recording = Some(started.clone());
+            mem::drop(recording);
+            crate::tracking::recording_started(&started);
+        },
+        obs::sys::obs_frontend_event_OBS_FRONTEND_EVENT_RECORDING_STOPPED => {
+            if recording.take().is_some() {
+                info!("recording stopped");
+            }
+            mem::drop(recording);
+            crate::tracking::recording_stopped();
+        },
+        obs::sys::obs_frontend_event_OBS_FRONTEND_EVENT_RECORDING_PAUSED => {
+            recording.iter_mut().for_each(|recording| recording.pause(now));
+        },
+        obs::sys::obs_frontend_event_OBS_FRONTEND_EVENT_RECORDING_UNPAUSED => {
+            recording.iter_mut().for_each(|recording| recording.unpause(now));
+        },
+        _ => {},
+    }
+}
+
+/// Starts following recordings
+pub fn start() {
+    obs::frontend::add_event_callback(frontend_event, ptr::null_mut());
+}
+
+pub fn shutdown() {
+    obs::frontend::remove_event_callback(frontend_event, ptr::null_mut());
+    crate::tracking::shutdown();
+    *RECORDING.lock().unwrap() = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn elapsed_skips_pauses() {
+        let mut recording = Recording::new(0, None, 10 * SECOND);
+        assert_eq!(recording.elapsed(15 * SECOND), Duration::from_secs(5));
+        recording.pause(15 * SECOND);
+        assert_eq!(recording.elapsed(20 * SECOND), Duration::from_secs(5));
+        recording.unpause(25 * SECOND);
+        assert_eq!(recording.elapsed(30 * SECOND), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn sidecar_replaces_extension() {
+        let recording = Recording::new(0, Some(PathBuf::from("/videos/2024-01-01 12-00-00.mkv")), 0);
+        assert_eq!(recording.sidecar_path(".vr-events.csv"), Some(PathBuf::from("/videos/2024-01-01 12-00-00.vr-events.csv")));
+        assert_eq!(Recording::new(0, None, 0).sidecar_path(".vr-events.csv"), None);
+    }
+}
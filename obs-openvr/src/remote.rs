@@ -0,0 +1,221 @@
+//! Captures through `obs-openvr-host`, a helper process that owns the OpenVR session, so SteamVR
+//! crashing or hanging can't take OBS down with it
+
+use std::{
+    env,
+    ffi::{
+        CStr,
+        OsStr,
+    },
+    mem,
+    os::unix::ffi::OsStrExt,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+use obs::data::ObsData;
+use obs_openvr_ipc::{
+    client::{
+        ClientOptions,
+        CommandLauncher,
+        HostClient,
+        Poll,
+        RemoteStream,
+        StreamError,
+    },
+    protocol::{
+        FrameInfo,
+        Layout,
+        Target,
+        Transfer,
+    },
+};
+use crate::{
+    color::Encoding,
+    pixel,
+    status::SourceStatus,
+};
+
+/// Name of the host binary, looked up next to the plugin
+const HOST_BINARY: &'static str = "obs-openvr-host";
+/// Environment variable overriding the path of the host binary
+const HOST_PATH_VAR: &'static str = "OBS_OPENVR_HOST";
+
+/// Which process a source talks to OpenVR from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureHost {
+    /// Inside OBS
+    InProcess,
+    /// Inside the `obs-openvr-host` helper, which is restarted if it crashes or hangs
+    Helper,
+}
+
+impl CaptureHost {
+    pub const ALL: [CaptureHost; 2] = [CaptureHost::InProcess, CaptureHost::Helper];
+
+    pub fn from_settings(data: &obs::sys::obs_data) -> Self {
+        let value = data.get_cstr(keys::CAPTURE_HOST);
+        Self::ALL.iter()
+            .find(|host| Some(host.key()) == value)
+            .copied()
+            .unwrap_or(CaptureHost::InProcess)
+    }
+
+    pub fn key(self) -> &'static CStr {
+        match self {
+            CaptureHost::InProcess => keys::CAPTURE_HOST_IN_PROCESS,
+            CaptureHost::Helper => keys::CAPTURE_HOST_HELPER,
+        }
+    }
+
+    pub fn description(self) -> &'static CStr {
+        let s: &'static [u8] = match self {
+            CaptureHost::InProcess => b"Inside OBS\0",
+            CaptureHost::Helper => b"Helper process\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+}
+
+pub fn add_capture_host_property(props: &mut obs::Properties) {
+    use obs::properties::{
+        PropertiesExt,
+        PropertyDescription,
+    };
+
+    let description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Capture host\0") };
+    props.add_string_list_complete(PropertyDescription::new(keys::CAPTURE_HOST, Some(description)), CaptureHost::ALL.iter().map(|host| (host.description(), host.key())));
+}
+
+/// Path of the shared object this function was loaded from
+fn plugin_path() -> Option<PathBuf> {
+    let mut info: libc::Dl_info = unsafe { mem::zeroed() };
+    let found = unsafe { libc::dladdr(plugin_path as *const libc::c_void, &mut info as *mut _) };
+    if found == 0 || info.dli_fname.is_null() {
+        return None;
+    }
+    let path = unsafe { CStr::from_ptr(info.dli_fname) };
+    Some(PathBuf::from(OsStr::from_bytes(path.to_bytes())))
+}
+
+/// `$OBS_OPENVR_HOST`, or the host binary next to the plugin, or whatever `obs-openvr-host` is
+/// on `$PATH`
+fn host_path() -> PathBuf {
+    if let Some(path) = env::var_os(HOST_PATH_VAR) {
+        return PathBuf::from(path);
+    }
+    plugin_path()
+        .as_ref()
+        .and_then(|plugin| plugin.parent())
+        .map(|dir| dir.join(HOST_BINARY))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| Path::new(HOST_BINARY).to_owned())
+}
+
+static CLIENT: Mutex<Option<Arc<HostClient>>> = Mutex::new(None);
+
+/// The connection to the capture host, launching it on first use
+pub fn client() -> Arc<HostClient> {
+    let mut client = CLIENT.lock().unwrap();
+    client.get_or_insert_with(|| {
+        let path = host_path();
+        info!("starting capture host {:?}", &path);
+        Arc::new(HostClient::start(CommandLauncher::new(path), ClientOptions::default()))
+    }).clone()
+}
+
+/// Stops the capture host, if it was started. Called when the module is unloaded.
+pub fn shutdown() {
+    if let Some(client) = CLIENT.lock().unwrap().take() {
+        client.stop();
+    }
+}
+
+pub fn pixel_format(layout: Layout) -> pixel::Format {
+    match layout {
+        Layout::Rgb => pixel::Format::Rgb,
+        Layout::Rgba => pixel::Format::Rgba,
+        Layout::Bgra => pixel::Format::Bgra,
+    }
+}
+
+/// Encoding the host detected for a frame, if any
+pub fn detected_encoding(transfer: Transfer) -> Option<Encoding> {
+    match transfer {
+        Transfer::Unknown => None,
+        Transfer::Srgb => Some(Encoding::Srgb),
+        Transfer::Linear => Some(Encoding::Linear),
+    }
+}
+
+/// A stream of captures from the host, which keeps working across host restarts
+pub struct RemoteCapture {
+    stream: RemoteStream,
+    /// Whether the host was available at the last poll, so losing it is only reported once
+    available: bool,
+}
+
+impl RemoteCapture {
+    pub fn open(target: Target) -> Self {
+        let frame_interval = Duration::from_nanos(unsafe { obs::sys::obs_get_frame_interval_ns() });
+        RemoteCapture {
+            stream: client().open(target, frame_interval),
+            available: true,
+        }
+    }
+
+    /// Takes the latest frame from the host. While the host isn't running, returns `None`, and
+    /// records the error to `status` only once.
+    pub fn poll(&mut self, status: &SourceStatus) -> Result<Option<Poll>, StreamError> {
+        match self.stream.poll() {
+            Err(StreamError::Disconnected) => {
+                if self.available {
+                    warn!("capture host unavailable for {:?}", self.stream.target());
+                    status.record_error(&StreamError::Disconnected);
+                    self.available = false;
+                }
+                Ok(None)
+            },
+            result => {
+                self.available = true;
+                result.map(Some)
+            },
+        }
+    }
+
+    #[inline]
+    pub fn invalidate(&mut self) {
+        self.stream.invalidate();
+    }
+
+    /// Description of the frame `data` holds. Only `None` before the first frame.
+    #[inline]
+    pub fn info(&self) -> Option<&FrameInfo> {
+        self.stream.info()
+    }
+
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        self.stream.data()
+    }
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const CAPTURE_HOST: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"capture_host\0")
+    };
+    pub const CAPTURE_HOST_IN_PROCESS: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"in_process\0")
+    };
+    pub const CAPTURE_HOST_HELPER: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"helper\0")
+    };
+}
@@ -0,0 +1,343 @@
+//! Automation rules: OBS frontend actions run when something happens in VR, like switching scenes
+//! when the dashboard opens or starting to record when an app starts
+//!
+//! Rules are read from `rules.json` in the module's config directory, as a list of triggers, each
+//! with the actions to run when it fires.
+
+use std::ffi::{
+    CStr,
+    CString,
+};
+use obs::{
+    data::ObsData,
+    scene::SceneItem,
+    source::OwnedSource,
+};
+use openvr::system::DeviceClass;
+use crate::{
+    config::ConfigFile,
+    vr_events::{
+        VrEvent,
+        device_class_name,
+    },
+};
+
+const CONFIG_FILE: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"rules.json\0") };
+const CONFIG_TEMPLATE: &'static str = r#"{
+    "rules": []
+}
+"#;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RuleError {
+    #[error("Missing {0:?}")]
+    Missing(&'static CStr),
+    #[error("Unknown trigger {0:?}")]
+    UnknownTrigger(String),
+    #[error("Unknown action {0:?}")]
+    UnknownAction(String),
+    #[error("Unknown device class {0:?}")]
+    UnknownDeviceClass(String),
+}
+
+fn parse_device_class(name: &str) -> Result<DeviceClass, RuleError> {
+    const ALL: [DeviceClass; 5] = [
+        DeviceClass::Hmd,
+        DeviceClass::Controller,
+        DeviceClass::GenericTracker,
+        DeviceClass::TrackingReference,
+        DeviceClass::DisplayRedirect,
+    ];
+    ALL.iter()
+        .copied()
+        .find(|&class| device_class_name(class) == name)
+        .ok_or_else(|| RuleError::UnknownDeviceClass(name.to_owned()))
+}
+
+/// Which `VrEvent`s a rule reacts to. Application keys and device classes narrow down the events,
+/// and match any when `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    AppStarted(Option<CString>),
+    AppStopped(Option<CString>),
+    DashboardOpened,
+    DashboardClosed,
+    DeviceConnected(Option<DeviceClass>),
+    DeviceDisconnected(Option<DeviceClass>),
+    HeadsetOn,
+    HeadsetOff,
+    ScreenshotTaken,
+    SteamVRQuit,
+}
+
+impl Trigger {
+    fn new(name: &str, app: Option<CString>, device_class: Option<DeviceClass>) -> Result<Self, RuleError> {
+        let ret = match name {
+            "app_started" => Trigger::AppStarted(app),
+            "app_stopped" => Trigger::AppStopped(app),
+            "dashboard_opened" => Trigger::DashboardOpened,
+            "dashboard_closed" => Trigger::DashboardClosed,
+            "device_connected" => Trigger::DeviceConnected(device_class),
+            "device_disconnected" => Trigger::DeviceDisconnected(device_class),
+            "headset_on" => Trigger::HeadsetOn,
+            "headset_off" => Trigger::HeadsetOff,
+            "screenshot_taken" => Trigger::ScreenshotTaken,
+            "steamvr_quit" => Trigger::SteamVRQuit,
+            _ => return Err(RuleError::UnknownTrigger(name.to_owned())),
+        };
+        Ok(ret)
+    }
+
+    fn from_data(data: &obs::sys::obs_data) -> Result<Self, RuleError> {
+        let name = data.get_string(keys::TRIGGER).ok_or(RuleError::Missing(keys::TRIGGER))?;
+        let app = data.get_cstr(keys::APP)
+            .filter(|app| !app.to_bytes().is_empty())
+            .map(|app| app.to_owned());
+        let device_class = data.get_string(keys::DEVICE_CLASS)
+            .filter(|class| !class.is_empty())
+            .map(parse_device_class)
+            .transpose()?;
+        Trigger::new(name, app, device_class)
+    }
+
+    pub fn matches(&self, event: &VrEvent) -> bool {
+        fn filter<T: PartialEq>(filter: &Option<T>, value: &T) -> bool {
+            filter.as_ref().map_or(true, |filter| filter == value)
+        }
+        match (self, event) {
+            (Trigger::AppStarted(app), VrEvent::AppStarted(started)) => filter(app, started),
+            (Trigger::AppStopped(app), VrEvent::AppStopped(stopped)) => filter(app, stopped),
+            (Trigger::DeviceConnected(class), VrEvent::DeviceConnected(connected)) => class.is_none() || class == connected,
+            (Trigger::DeviceDisconnected(class), VrEvent::DeviceDisconnected(disconnected)) => class.is_none() || class == disconnected,
+            (Trigger::DashboardOpened, VrEvent::DashboardOpened) |
+            (Trigger::DashboardClosed, VrEvent::DashboardClosed) |
+            (Trigger::HeadsetOn, VrEvent::HeadsetOn) |
+            (Trigger::HeadsetOff, VrEvent::HeadsetOff) |
+            (Trigger::ScreenshotTaken, VrEvent::ScreenshotTaken) |
+            (Trigger::SteamVRQuit, VrEvent::SteamVRQuit) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Something a rule does through the OBS frontend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    SwitchScene(CString),
+    StartRecording,
+    StopRecording,
+    StartStreaming,
+    StopStreaming,
+    StartReplayBuffer,
+    StopReplayBuffer,
+    SaveReplayBuffer,
+    /// Shows or hides a source in a scene, or in the program scene if `scene` is `None`
+    SetSourceVisible {
+        scene: Option<CString>,
+        source: CString,
+        visible: bool,
+    },
+}
+
+impl Action {
+    fn from_data(data: &obs::sys::obs_data) -> Result<Self, RuleError> {
+        let name = data.get_string(keys::ACTION).ok_or(RuleError::Missing(keys::ACTION))?;
+        let get_name = |key: &'static CStr| data.get_cstr(key)
+            .filter(|name| !name.to_bytes().is_empty())
+            .map(|name| name.to_owned());
+        let set_visible = |visible: bool| -> Result<Action, RuleError> {
+            Ok(Action::SetSourceVisible {
+                scene: get_name(keys::SCENE),
+                source: get_name(keys::SOURCE).ok_or(RuleError::Missing(keys::SOURCE))?,
+                visible: visible,
+            })
+        };
+        let ret = match name {
+            "switch_scene" => Action::SwitchScene(get_name(keys::SCENE).ok_or(RuleError::Missing(keys::SCENE))?),
+            "start_recording" => Action::StartRecording,
+            "stop_recording" => Action::StopRecording,
+            "start_streaming" => Action::StartStreaming,
+            "stop_streaming" => Action::StopStreaming,
+            "start_replay_buffer" => Action::StartReplayBuffer,
+            "stop_replay_buffer" => Action::StopReplayBuffer,
+            "save_replay_buffer" => Action::SaveReplayBuffer,
+            "show_source" => set_visible(true)?,
+            "hide_source" => set_visible(false)?,
+            _ => return Err(RuleError::UnknownAction(name.to_owned())),
+        };
+        Ok(ret)
+    }
+
+    /// Runs the action. Switching scenes blocks until the UI thread handled it, so this must not
+    /// be called while holding locks the UI thread could wait on.
+    fn run(&self) {
+        use obs::frontend;
+        match self {
+            Action::SwitchScene(name) => match OwnedSource::by_name(name) {
+                Some(scene) => frontend::set_current_scene(&scene),
+                None => warn!("Scene {:?} not found", name),
+            },
+            Action::StartRecording if !frontend::recording_active() => frontend::recording_start(),
+            Action::StopRecording if frontend::recording_active() => frontend::recording_stop(),
+            Action::StartStreaming if !frontend::streaming_active() => frontend::streaming_start(),
+            Action::StopStreaming if frontend::streaming_active() => frontend::streaming_stop(),
+            Action::StartReplayBuffer if !frontend::replay_buffer_active() => frontend::replay_buffer_start(),
+            Action::StopReplayBuffer if frontend::replay_buffer_active() => frontend::replay_buffer_stop(),
+            Action::SaveReplayBuffer if frontend::replay_buffer_active() => frontend::replay_buffer_save(),
+            Action::SetSourceVisible { scene, source, visible } => {
+                let scene = match scene {
+                    Some(name) => OwnedSource::by_name(name),
+                    None => frontend::current_scene(),
+                };
+                let item = scene.as_ref().and_then(|scene| SceneItem::find(scene, source));
+                match item {
+                    Some(item) => {
+                        item.set_visible(*visible);
+                    },
+                    None => warn!("Source {:?} not found in scene {:?}", source, scene.as_ref().map(|scene| scene.name())),
+                }
+            },
+            // Outputs already in the requested state
+            _ => {},
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub trigger: Trigger,
+    pub actions: Vec<Action>,
+}
+
+impl Rule {
+    fn from_data(data: &obs::sys::obs_data) -> Result<Self, RuleError> {
+        let actions = data.get_array(keys::ACTIONS).ok_or(RuleError::Missing(keys::ACTIONS))?;
+        let actions = actions.iter()
+            .map(|action| Action::from_data(&action))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Rule {
+            trigger: Trigger::from_data(data)?,
+            actions: actions,
+        })
+    }
+}
+
+/// Rules in a config file, skipping (and logging) invalid ones
+fn rules_from_data(data: &obs::sys::obs_data) -> Vec<Rule> {
+    let rules = match data.get_array(keys::RULES) {
+        Some(v) => v,
+        None => return Vec::new(),
+    };
+    rules.iter()
+        .enumerate()
+        .filter_map(|(i, rule)| match Rule::from_data(&rule) {
+            Ok(rule) => Some(rule),
+            Err(e) => {
+                warn!("Skipping rule {}: {}", i, &e);
+                None
+            },
+        })
+        .collect()
+}
+
+fn set_defaults(_data: &mut obs::sys::obs_data) {}
+
+/// Runs rules for the `VrEvent`s on the event thread
+pub struct RuleMonitor {
+    file: ConfigFile,
+    rules: Vec<Rule>,
+}
+
+impl RuleMonitor {
+    pub fn new() -> Self {
+        RuleMonitor {
+            file: ConfigFile::new(CONFIG_FILE, CONFIG_TEMPLATE, set_defaults),
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &VrEvent) {
+        self.rules.iter()
+            .filter(|rule| rule.trigger.matches(event))
+            .flat_map(|rule| rule.actions.iter())
+            .for_each(|action| {
+                info!("{:?}: running {:?}", event, action);
+                action.run();
+            });
+    }
+
+    /// Picks up config changes. Returns whether there are any rules, which need OpenVR.
+    pub fn reload_config(&mut self) -> bool {
+        if let Some(data) = self.file.reload() {
+            self.rules = rules_from_data(&data);
+            debug!("rules: {:?}", &self.rules);
+        }
+        !self.rules.is_empty()
+    }
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const RULES: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"rules\0")
+    };
+    pub const TRIGGER: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"trigger\0")
+    };
+    pub const APP: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"app\0")
+    };
+    pub const DEVICE_CLASS: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"device_class\0")
+    };
+    pub const ACTIONS: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"actions\0")
+    };
+    pub const ACTION: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"action\0")
+    };
+    pub const SCENE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"scene\0")
+    };
+    pub const SOURCE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"source\0")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(key: &str) -> CString {
+        CString::new(key).unwrap()
+    }
+
+    #[test]
+    fn app_triggers_match_their_app() {
+        let any = Trigger::new("app_started", None, None).unwrap();
+        let specific = Trigger::new("app_started", Some(app("steam.app.620")), None).unwrap();
+        assert!(any.matches(&VrEvent::AppStarted(app("steam.app.620"))));
+        assert!(any.matches(&VrEvent::AppStarted(app("steam.app.546560"))));
+        assert!(specific.matches(&VrEvent::AppStarted(app("steam.app.620"))));
+        assert!(!specific.matches(&VrEvent::AppStarted(app("steam.app.546560"))));
+        assert!(!specific.matches(&VrEvent::AppStopped(app("steam.app.620"))));
+    }
+
+    #[test]
+    fn device_triggers_match_their_class() {
+        let trackers = Trigger::new("device_connected", None, Some(parse_device_class("tracker").unwrap())).unwrap();
+        assert!(trackers.matches(&VrEvent::DeviceConnected(Some(DeviceClass::GenericTracker))));
+        assert!(!trackers.matches(&VrEvent::DeviceConnected(Some(DeviceClass::Controller))));
+        assert!(!trackers.matches(&VrEvent::DeviceConnected(None)));
+        assert!(!trackers.matches(&VrEvent::DeviceDisconnected(Some(DeviceClass::GenericTracker))));
+        let any = Trigger::new("device_disconnected", None, None).unwrap();
+        assert!(any.matches(&VrEvent::DeviceDisconnected(None)));
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        assert!(matches!(Trigger::new("dashboard_toggled", None, None), Err(RuleError::UnknownTrigger(_))));
+        assert!(matches!(parse_device_class("lighthouse"), Err(RuleError::UnknownDeviceClass(_))));
+    }
+}
@@ -0,0 +1,53 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceHandle(*mut obs::sys::obs_source_t);
+
+impl SourceHandle {
+    #[inline(always)]
+    pub fn is_visible(&self) -> bool {
+        unsafe {
+            obs::sys::obs_source_showing(self.0)
+        }
+    }
+
+    #[inline(always)]
+    pub fn unwrap(self) -> *mut obs::sys::obs_source_t {
+        self.0
+    }
+
+    #[inline(always)]
+    pub fn handle(&self) -> *mut obs::sys::obs_source_t {
+        self.0
+    }
+
+    /// Name of the source as shown in OBS
+    pub fn name(&self) -> String {
+        unsafe {
+            let name = obs::sys::obs_source_get_name(self.0);
+            if name.is_null() {
+                return String::new();
+            }
+            std::ffi::CStr::from_ptr(name).to_string_lossy().into_owned()
+        }
+    }
+
+    #[inline]
+    pub unsafe fn output_video(&self, frame: &obs::sys::obs_source_frame2) {
+        obs::source::output_video2(self.handle(), frame);
+    }
+
+    /// Stops showing the last async frame
+    #[inline]
+    pub unsafe fn clear_video(&self) {
+        obs::source::clear_video(self.handle());
+    }
+}
+
+impl From<*mut obs::sys::obs_source_t> for SourceHandle {
+    #[inline]
+    fn from(p: *mut obs::sys::obs_source_t) -> Self {
+        SourceHandle(p)
+    }
+}
+
+unsafe impl Send for SourceHandle {}
+unsafe impl Sync for SourceHandle {}
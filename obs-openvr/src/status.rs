@@ -0,0 +1,278 @@
+use std::{
+    collections::VecDeque,
+    ffi::{
+        CStr,
+        CString,
+    },
+    fmt::Display,
+    mem,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::{
+        Duration,
+        Instant,
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+use obs::{
+    data::ObsData,
+    properties::{
+        Properties,
+        PropertyExt,
+    },
+};
+use openvr::overlay::OverlayRef;
+use crate::{
+    metrics::{
+        CaptureMetrics,
+        Stage,
+    },
+    remote::{
+        self,
+        CaptureHost,
+    },
+    source_handle::SourceHandle,
+};
+
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Sources that can report a `SourceStatus` in their properties
+pub trait StatusProvider {
+    fn status(&self) -> Option<Arc<SourceStatus>>;
+}
+
+#[derive(Debug, Default)]
+struct StatusState {
+    /// Whether the source captures through the capture host rather than OpenVR in OBS
+    helper: bool,
+    overlay: Option<OverlayRef>,
+    resolution: Option<(u32, u32)>,
+    frame_times: VecDeque<Instant>,
+    last_error: Option<(SystemTime, String)>,
+    metrics: CaptureMetrics,
+}
+
+/// Capture state of a single source, updated by the capturing code and shown read-only in the
+/// source's properties
+#[derive(Debug)]
+pub struct SourceStatus {
+    source: SourceHandle,
+    tracks_overlay: bool,
+    state: Mutex<StatusState>,
+}
+
+impl SourceStatus {
+    pub fn new(source: *mut obs::sys::obs_source_t, tracks_overlay: bool) -> Self {
+        SourceStatus {
+            source: SourceHandle::from(source),
+            tracks_overlay: tracks_overlay,
+            state: Mutex::new(StatusState::default()),
+        }
+    }
+
+    pub fn set_overlay(&self, overlay: Option<OverlayRef>) {
+        self.state.lock().unwrap().overlay = overlay;
+    }
+
+    /// Applies the status-related settings (see `add_status_properties`) from a source's settings
+    pub fn update_settings(&self, data: &obs::sys::obs_data) {
+        let interval = match data.get_int(keys::LOG_INTERVAL) {
+            n if n > 0 => Some(Duration::from_secs(n as u64)),
+            _ => None,
+        };
+        let mut state = self.state.lock().unwrap();
+        state.helper = CaptureHost::from_settings(data) == CaptureHost::Helper;
+        state.metrics.set_log_interval(interval);
+    }
+
+    pub fn record_frame(&self, dimensions: (u32, u32)) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.resolution = Some(dimensions);
+        state.frame_times.push_back(now);
+        while state.frame_times.front().map(|&t| now.duration_since(t) > FPS_WINDOW).unwrap_or(false) {
+            state.frame_times.pop_front();
+        }
+        state.metrics.frames_captured += 1;
+        if state.metrics.take_summary_due(now) {
+            info!("capture metrics for {:?}: {}", self.source.name(), &state.metrics);
+        }
+    }
+
+    /// Records a frame the source was showing for, but didn't capture
+    pub fn record_skipped(&self) {
+        self.state.lock().unwrap().metrics.frames_skipped += 1;
+    }
+
+    /// Records a frame that was fetched, but dropped because its content hadn't changed
+    pub fn record_unchanged(&self) {
+        self.state.lock().unwrap().metrics.frames_unchanged += 1;
+    }
+
+    #[inline]
+    pub fn record_stage(&self, stage: Stage, duration: Duration) {
+        self.state.lock().unwrap().metrics.record_stage(stage, duration);
+    }
+
+    pub fn record_error<E: Display + ?Sized>(&self, e: &E) {
+        let mut state = self.state.lock().unwrap();
+        state.last_error = Some((SystemTime::now(), e.to_string()));
+        state.metrics.errors += 1;
+    }
+
+    fn fps(state: &StatusState) -> usize {
+        let now = Instant::now();
+        state.frame_times.iter()
+            .filter(|&&t| now.duration_since(t) <= FPS_WINDOW)
+            .count()
+    }
+
+    /// Current status lines, as `(property name, text)`
+    fn lines(&self) -> Vec<(&'static CStr, String)> {
+        let state = self.state.lock().unwrap();
+        let mut ret = Vec::with_capacity(7 + Stage::ALL.len());
+        let connection = if state.helper {
+            let client = remote::client();
+            match client.host_pid() {
+                Some(pid) => format!("capture host connected (pid {}, {} launches)", pid, client.launches()),
+                None => format!("capture host not connected ({} launches)", client.launches()),
+            }
+        } else {
+            match crate::openvr_init_status() {
+                Ok(..) => "connected".to_owned(),
+                Err(e) => format!("not connected ({})", &e),
+            }
+        };
+        ret.push((keys::CONNECTION, format!("Connection: {}", connection)));
+        if self.tracks_overlay {
+            let overlay = state.overlay
+                .map(|overlay| format!("0x{:x}", overlay.handle()))
+                .unwrap_or_else(|| "none".to_owned());
+            ret.push((keys::OVERLAY, format!("Overlay handle: {}", overlay)));
+        }
+        let resolution = state.resolution
+            .map(|(w, h)| format!("{}x{}", w, h))
+            .unwrap_or_else(|| "unknown".to_owned());
+        ret.push((keys::RESOLUTION, format!("Resolution: {}", resolution)));
+        ret.push((keys::FPS, format!("Capture FPS: {}", Self::fps(&state))));
+        let last_error = state.last_error.as_ref()
+            .map(|(t, e)| format!("[{}] {}", format_local_time(*t), e))
+            .unwrap_or_else(|| "none".to_owned());
+        ret.push((keys::LAST_ERROR, format!("Last error: {}", last_error)));
+        let yes_no = |v: bool| if v { "yes" } else { "no" };
+        // The overlay is only asked while its session can't be shut down
+        let overlay_visible = state.overlay
+            .filter(|_| self.tracks_overlay)
+            .and_then(|overlay| crate::openvr_session().map(|_session| overlay.is_visible()));
+        let visible = match overlay_visible {
+            Some(overlay_visible) => format!("Visible: source {}, overlay {}", yes_no(self.source.is_visible()), yes_no(overlay_visible)),
+            None => format!("Visible: source {}", yes_no(self.source.is_visible())),
+        };
+        ret.push((keys::VISIBLE, visible));
+        ret.push((keys::FRAMES, format!("Frames: {}", state.metrics.counters_summary())));
+        Stage::ALL.iter().for_each(|&stage| {
+            ret.push((keys::stage(stage), format!("Timing {}", state.metrics.stage_summary(stage))));
+        });
+        ret
+    }
+
+    /// Updates the descriptions of status properties previously added by `add_status_properties`
+    pub fn update_properties(&self, props: &mut Properties) {
+        self.lines().into_iter().for_each(|(name, text)| {
+            if let (Some(prop), Ok(text)) = (props.get(name), CString::new(text)) {
+                prop.set_description(&text);
+            }
+        });
+    }
+}
+
+unsafe extern "C" fn refresh_clicked<S: StatusProvider>(props: *mut obs::sys::obs_properties_t, _property: *mut obs::sys::obs_property_t, data: *mut libc::c_void) -> bool {
+    let source = match (data as *const S).as_ref() {
+        Some(v) => v,
+        None => return false,
+    };
+    if let Some(status) = source.status() {
+        obs::properties::with_raw(props, |props| status.update_properties(props));
+    }
+    true
+}
+
+/// Adds a read-only "Status" group showing `source`'s current status, with a button to refresh it
+pub fn add_status_properties<S: StatusProvider>(props: &mut Properties, source: &S) {
+    let status = match source.status() {
+        Some(v) => v,
+        None => return,
+    };
+    let mut group = Properties::new();
+    status.lines().into_iter().for_each(|(name, text)| {
+        if let Ok(text) = CString::new(text) {
+            group.add_text(name, &text, obs::sys::obs_text_type_OBS_TEXT_INFO);
+        }
+    });
+    let log_interval_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Log metrics summary every N seconds (0 to disable)\0") };
+    group.add_int(keys::LOG_INTERVAL, log_interval_description, 0, 3600, 1);
+    let refresh_text: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Refresh\0") };
+    group.add_button(keys::REFRESH, refresh_text, refresh_clicked::<S>);
+    let group_description: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"Status\0") };
+    props.add_group(keys::GROUP, group_description, group);
+}
+
+fn format_local_time(t: SystemTime) -> String {
+    let secs = t.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0) as libc::time_t;
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    if unsafe { libc::localtime_r(&secs as *const _, &mut tm as *mut _) }.is_null() {
+        return format!("{}", secs);
+    }
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+    use crate::metrics::Stage;
+
+    pub const GROUP: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"status_group\0")
+    };
+    pub const CONNECTION: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"status_connection\0")
+    };
+    pub const OVERLAY: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"status_overlay\0")
+    };
+    pub const RESOLUTION: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"status_resolution\0")
+    };
+    pub const FPS: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"status_fps\0")
+    };
+    pub const LAST_ERROR: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"status_last_error\0")
+    };
+    pub const VISIBLE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"status_visible\0")
+    };
+    pub const REFRESH: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"status_refresh\0")
+    };
+    pub const FRAMES: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"status_frames\0")
+    };
+    pub const LOG_INTERVAL: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"metrics_log_interval\0")
+    };
+
+    pub fn stage(stage: Stage) -> &'static CStr {
+        let s: &'static [u8] = match stage {
+            Stage::Fetch => b"status_timing_fetch\0",
+            Stage::Convert => b"status_timing_convert\0",
+            Stage::Upload => b"status_timing_upload\0",
+            Stage::Output => b"status_timing_output\0",
+        };
+        unsafe { CStr::from_bytes_with_nul_unchecked(s) }
+    }
+}
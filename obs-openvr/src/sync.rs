@@ -0,0 +1,214 @@
+//! Lock-free state shared between the threads OBS calls sources from
+
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        Arc,
+        atomic::{
+            AtomicU64,
+            AtomicU8,
+            Ordering,
+        },
+    },
+};
+
+/// Width and height packed in a single atomic, so readers never see a mix of two sizes
+#[derive(Debug, Default)]
+pub struct AtomicDimensions(AtomicU64);
+
+impl AtomicDimensions {
+    #[inline]
+    fn pack((width, height): (u32, u32)) -> u64 {
+        ((width as u64) << 32) | height as u64
+    }
+
+    #[inline]
+    fn unpack(v: u64) -> (u32, u32) {
+        ((v >> 32) as u32, v as u32)
+    }
+
+    pub fn new(dimensions: (u32, u32)) -> Self {
+        AtomicDimensions(AtomicU64::new(Self::pack(dimensions)))
+    }
+
+    #[inline]
+    pub fn load(&self) -> (u32, u32) {
+        Self::unpack(self.0.load(Ordering::Acquire))
+    }
+
+    #[inline]
+    pub fn store(&self, dimensions: (u32, u32)) {
+        self.0.store(Self::pack(dimensions), Ordering::Release);
+    }
+}
+
+/// Set in `TripleBuffer::middle` when the middle buffer holds a frame the output hasn't taken
+const FRESH: u8 = 0b100;
+const INDEX_MASK: u8 = 0b011;
+
+struct TripleBuffer<T> {
+    buffers: [UnsafeCell<T>; 3],
+    /// Index of the buffer that is neither being written nor read, plus `FRESH`
+    middle: AtomicU8,
+}
+
+// Each buffer is only ever accessed by whichever of `Input` and `Output` currently owns its index
+unsafe impl<T: Send> Sync for TripleBuffer<T> {}
+
+/// Writing side of a triple buffer. Publishing never waits for the reader.
+pub struct Input<T> {
+    shared: Arc<TripleBuffer<T>>,
+    index: u8,
+}
+
+/// Reading side of a triple buffer. Always sees the most recently published buffer.
+pub struct Output<T> {
+    shared: Arc<TripleBuffer<T>>,
+    index: u8,
+}
+
+/// Creates a triple buffer with every buffer initialized by `init`
+pub fn triple_buffer<T, F: FnMut() -> T>(mut init: F) -> (Input<T>, Output<T>) {
+    let shared = Arc::new(TripleBuffer {
+        buffers: [UnsafeCell::new(init()), UnsafeCell::new(init()), UnsafeCell::new(init())],
+        middle: AtomicU8::new(1),
+    });
+    let input = Input {
+        shared: shared.clone(),
+        index: 0,
+    };
+    let output = Output {
+        shared: shared,
+        index: 2,
+    };
+    (input, output)
+}
+
+impl<T> Input<T> {
+    /// The buffer to write the next frame into. It may still hold an old frame.
+    #[inline]
+    pub fn buffer(&mut self) -> &mut T {
+        unsafe { &mut *self.shared.buffers[self.index as usize].get() }
+    }
+
+    /// Hands the written buffer to the output, replacing any frame it hasn't taken yet
+    pub fn publish(&mut self) {
+        let previous = self.shared.middle.swap(self.index | FRESH, Ordering::AcqRel);
+        self.index = previous & INDEX_MASK;
+    }
+}
+
+impl<T> Output<T> {
+    /// Takes the most recently published frame, if there is one the output hasn't seen.
+    /// Returns whether `buffer` changed.
+    pub fn update(&mut self) -> bool {
+        if self.shared.middle.load(Ordering::Relaxed) & FRESH == 0 {
+            return false;
+        }
+        let previous = self.shared.middle.swap(self.index, Ordering::AcqRel);
+        self.index = previous & INDEX_MASK;
+        true
+    }
+
+    #[inline]
+    pub fn buffer(&self) -> &T {
+        unsafe { &*self.shared.buffers[self.index as usize].get() }
+    }
+}
+
+unsafe impl<T: Send> Send for Input<T> {}
+unsafe impl<T: Send> Send for Output<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::AtomicBool,
+        thread,
+    };
+
+    #[test]
+    fn output_sees_latest_frame() {
+        let (mut input, mut output) = triple_buffer(|| 0u32);
+        assert!(!output.update());
+        for v in 1..=3 {
+            *input.buffer() = v;
+            input.publish();
+        }
+        assert!(output.update());
+        assert_eq!(*output.buffer(), 3);
+        assert!(!output.update());
+        assert_eq!(*output.buffer(), 3);
+    }
+
+    #[test]
+    fn concurrent_publish_and_read() {
+        const FRAMES: u64 = 100_000;
+        let (mut input, mut output) = triple_buffer(|| vec![0u64; 64]);
+        let writer = thread::spawn(move || {
+            for frame in 1..=FRAMES {
+                input.buffer().iter_mut().for_each(|v| *v = frame);
+                input.publish();
+            }
+        });
+        let mut last = 0;
+        while last < FRAMES {
+            if output.update() {
+                let buffer = output.buffer();
+                // Never a torn frame, and never going back in time
+                assert!(buffer.iter().all(|&v| v == buffer[0]));
+                assert!(buffer[0] > last);
+                last = buffer[0];
+            }
+        }
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn concurrent_update_and_tick() {
+        // Emulates a source: `update` changes settings, `tick` captures frames sized from them,
+        // and `render` reads both, all at the same time
+        const ITERATIONS: u32 = 50_000;
+        let dimensions = Arc::new(AtomicDimensions::new((0, 0)));
+        let requested = Arc::new(AtomicDimensions::new((1, 1)));
+        let done = Arc::new(AtomicBool::new(false));
+        let (mut input, mut output) = triple_buffer(|| (0u32, 0u32));
+
+        let update = {
+            let requested = requested.clone();
+            thread::spawn(move || {
+                for idx in 1..=ITERATIONS {
+                    requested.store((idx, idx * 3));
+                }
+            })
+        };
+        let tick = {
+            let dimensions = dimensions.clone();
+            let requested = requested.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                for idx in 1..=ITERATIONS {
+                    let (w, h) = requested.load();
+                    assert_eq!(h, w * 3);
+                    dimensions.store((idx, idx * 2));
+                    *input.buffer() = (idx, idx * 2);
+                    input.publish();
+                }
+                done.store(true, Ordering::Release);
+            })
+        };
+        while !done.load(Ordering::Acquire) {
+            let (w, h) = dimensions.load();
+            assert_eq!(h, w * 2);
+            if output.update() {
+                let (w, h) = *output.buffer();
+                assert_eq!(h, w * 2);
+            }
+        }
+        update.join().unwrap();
+        tick.join().unwrap();
+        // The last frame may already have been taken inside the loop
+        output.update();
+        assert_eq!(*output.buffer(), (ITERATIONS, ITERATIONS * 2));
+    }
+}
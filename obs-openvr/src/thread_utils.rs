@@ -10,6 +10,13 @@ impl<T> From<JoinHandle<T>> for JoinOnDrop<T> {
     }
 }
 
+impl<T> JoinOnDrop<T> {
+    /// Whether the thread exited, so dropping this won't block
+    pub fn is_finished(&self) -> bool {
+        self.0.as_ref().map_or(true, JoinHandle::is_finished)
+    }
+}
+
 impl<T> Drop for JoinOnDrop<T> {
     fn drop(&mut self) {
         if let Some(Err(e)) = self.0.take().map(JoinHandle::join) {
@@ -0,0 +1,614 @@
+//! Recording tracked device poses alongside the OBS recording, for post-production: sampled on a
+//! thread of their own while recording, and written next to the recording as CSV, JSON Lines and
+//! BVH motion capture
+//!
+//! Set in `tracking.json` in the module's config directory, read when a recording starts.
+
+use std::{
+    collections::BTreeMap,
+    ffi::CStr,
+    fs::{
+        self,
+        File,
+    },
+    io::{
+        self,
+        BufRead,
+        BufReader,
+        BufWriter,
+        Write,
+    },
+    mem,
+    path::PathBuf,
+    sync::{
+        Arc,
+        Mutex,
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use obs::data::ObsData;
+use openvr::{
+    pose::{
+        Pose,
+        TrackingUniverse,
+    },
+    system::DeviceClass,
+};
+use crate::{
+    config::ConfigFile,
+    recording::{
+        self,
+        Recording,
+    },
+    thread_utils::JoinOnDrop,
+    vr_events::device_class_name,
+};
+
+const CONFIG_FILE: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"tracking.json\0") };
+const CONFIG_TEMPLATE: &'static str = r#"{
+    "enabled": false,
+    "rate": 90,
+    "universe": "standing",
+    "csv": true,
+    "jsonl": true,
+    "bvh": true
+}
+"#;
+
+/// BVH units per meter, since motion capture tools expect centimeters
+const BVH_SCALE: f32 = 100.0;
+
+/// What to record, and how
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackingConfig {
+    pub enabled: bool,
+    /// Samples per second
+    pub rate: u32,
+    pub universe: TrackingUniverse,
+    pub csv: bool,
+    pub jsonl: bool,
+    pub bvh: bool,
+}
+
+impl TrackingConfig {
+    fn from_data(data: &obs::sys::obs_data) -> Self {
+        let universe = match data.get_string(keys::UNIVERSE) {
+            Some("seated") => TrackingUniverse::Seated,
+            Some("standing") => TrackingUniverse::Standing,
+            other => {
+                warn!("Unknown tracking universe {:?}, using standing", other);
+                TrackingUniverse::Standing
+            },
+        };
+        TrackingConfig {
+            enabled: data.get_bool(keys::ENABLED),
+            rate: data.get_int(keys::RATE).max(1).min(1000) as u32,
+            universe: universe,
+            csv: data.get_bool(keys::CSV),
+            jsonl: data.get_bool(keys::JSONL),
+            bvh: data.get_bool(keys::BVH),
+        }
+    }
+
+    fn set_defaults(data: &mut obs::sys::obs_data) {
+        data.set_default_bool(keys::ENABLED, false);
+        data.set_default_int(keys::RATE, 90);
+        data.set_default_string(keys::UNIVERSE, keys::STANDING);
+        data.set_default_bool(keys::CSV, true);
+        data.set_default_bool(keys::JSONL, true);
+        data.set_default_bool(keys::BVH, true);
+    }
+
+    fn load() -> Option<Self> {
+        let data = ConfigFile::new(CONFIG_FILE, CONFIG_TEMPLATE, Self::set_defaults).reload()?;
+        Some(Self::from_data(&data))
+    }
+}
+
+/// Pose of a connected device
+#[derive(Debug, Clone, Copy)]
+struct DevicePose {
+    index: u32,
+    class: Option<DeviceClass>,
+    pose: Pose,
+}
+
+/// Poses of all connected devices at one point in time
+#[derive(Debug, Clone)]
+struct Sample {
+    /// OBS clock time (`os_gettime_ns`) the poses are for
+    obs_time: u64,
+    /// Position in the recording
+    position: Duration,
+    devices: Vec<DevicePose>,
+}
+
+fn class_name(class: Option<DeviceClass>) -> &'static str {
+    class.map_or("unknown", device_class_name)
+}
+
+/// A file format poses are written in
+trait PoseWriter: Send {
+    fn write(&mut self, sample: &Sample) -> io::Result<()>;
+    /// Completes the file, after the last sample
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+const CSV_HEADER: &'static str = "obs_time_ns,seconds,device,class,valid,x,y,z,qw,qx,qy,qz,vx,vy,vz,avx,avy,avz\n";
+
+/// One row per device per sample
+struct CsvWriter<W: Write>(W);
+
+impl<W: Write> CsvWriter<W> {
+    fn new(mut w: W) -> io::Result<Self> {
+        w.write_all(CSV_HEADER.as_bytes())?;
+        Ok(CsvWriter(w))
+    }
+}
+
+impl<W: Write + Send> PoseWriter for CsvWriter<W> {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        for device in sample.devices.iter() {
+            let pose = &device.pose;
+            let [x, y, z] = pose.position();
+            let q = pose.rotation();
+            let [vx, vy, vz] = pose.velocity;
+            let [avx, avy, avz] = pose.angular_velocity;
+            writeln!(
+                self.0, "{},{:.6},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                sample.obs_time, sample.position.as_secs_f64(), device.index, class_name(device.class), pose.valid as u8,
+                x, y, z, q.w, q.x, q.y, q.z, vx, vy, vz, avx, avy, avz
+            )?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn json_number(v: f32) -> String {
+    if v.is_finite() {
+        format!("{}", v)
+    } else {
+        "null".to_owned()
+    }
+}
+
+fn json_vector(v: &[f32]) -> String {
+    let values: Vec<String> = v.iter().copied().map(json_number).collect();
+    format!("[{}]", values.join(","))
+}
+
+/// One JSON object per sample
+struct JsonLinesWriter<W: Write>(W);
+
+impl<W: Write + Send> PoseWriter for JsonLinesWriter<W> {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let devices: Vec<String> = sample.devices.iter()
+            .map(|device| {
+                let pose = &device.pose;
+                let q = pose.rotation();
+                format!(
+                    r#"{{"index":{},"class":"{}","valid":{},"position":{},"rotation":{},"velocity":{},"angular_velocity":{}}}"#,
+                    device.index, class_name(device.class), pose.valid, json_vector(&pose.position()),
+                    json_vector(&[q.w, q.x, q.y, q.z]), json_vector(&pose.velocity), json_vector(&pose.angular_velocity)
+                )
+            })
+            .collect();
+        writeln!(self.0, r#"{{"obs_time_ns":{},"seconds":{:.6},"devices":[{}]}}"#, sample.obs_time, sample.position.as_secs_f64(), devices.join(","))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Channels of a device in a BVH frame: position, then rotation in degrees about Z, X and Y
+type BvhChannels = [f32; 6];
+
+fn bvh_channels(pose: &Pose) -> BvhChannels {
+    let [x, y, z] = pose.position();
+    let [rz, rx, ry] = pose.euler_zxy();
+    [x * BVH_SCALE, y * BVH_SCALE, z * BVH_SCALE, rz.to_degrees(), rx.to_degrees(), ry.to_degrees()]
+}
+
+/// BVH hierarchy: a static root at the tracking origin, with a joint per device
+fn bvh_header(devices: &BTreeMap<u32, Option<DeviceClass>>, frames: u64, frame_time: f64) -> String {
+    const CHANNELS: &'static str = "CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation";
+    let mut ret = format!("HIERARCHY\nROOT origin\n{{\n\tOFFSET 0 0 0\n\t{}\n", CHANNELS);
+    for (index, class) in devices.iter() {
+        ret.push_str(&format!(
+            "\tJOINT {}_{}\n\t{{\n\t\tOFFSET 0 0 0\n\t\t{}\n\t\tEnd Site\n\t\t{{\n\t\t\tOFFSET 0 0 -10\n\t\t}}\n\t}}\n",
+            class_name(*class), index, CHANNELS
+        ));
+    }
+    ret.push_str(&format!("}}\nMOTION\nFrames: {}\nFrame Time: {:.6}\n", frames, frame_time));
+    ret
+}
+
+/// Index of the BVH frame a sample at `position` in the recording falls in
+fn bvh_frame_index(position: Duration, frame_time: f64) -> u64 {
+    (position.as_secs_f64() / frame_time).round() as u64
+}
+
+/// A BVH frame, with the last known channels of every device in the hierarchy
+fn bvh_frame(devices: &BTreeMap<u32, Option<DeviceClass>>, channels: &BTreeMap<u32, BvhChannels>) -> String {
+    let root = [0.0f32; 6];
+    let values: Vec<String> = std::iter::once(&root)
+        .chain(devices.keys().map(|index| channels.get(index).unwrap_or(&root)))
+        .flat_map(|channels| channels.iter())
+        .map(|v| format!("{:.4}", v))
+        .collect();
+    values.join(" ")
+}
+
+/// Writes the motion of the temporary file `part` as a frame at every `frame_time`: samples
+/// that were missed repeat the last known channels, and only the last of the samples falling in
+/// the same frame is kept
+fn write_bvh_motion<R: BufRead, W: Write>(part: R, out: &mut W, devices: &BTreeMap<u32, Option<DeviceClass>>) -> io::Result<()> {
+    let mut channels: BTreeMap<u32, BvhChannels> = BTreeMap::new();
+    let mut written = 0u64;
+    let mut last_frame = None;
+    for line in part.lines() {
+        let line = line?;
+        let (frame, line) = match line.split_once('|').and_then(|(frame, line)| Some((frame.parse::<u64>().ok()?, line))) {
+            Some(v) => v,
+            None => continue,
+        };
+        // Frames before this one are complete
+        while written < frame {
+            writeln!(out, "{}", bvh_frame(devices, &channels))?;
+            written += 1;
+        }
+        for device in line.split(';').filter(|device| !device.is_empty()) {
+            let mut values = device.split(' ');
+            let index = values.next().and_then(|index| index.parse::<u32>().ok());
+            let mut parsed: BvhChannels = [0.0; 6];
+            let count = values.zip(parsed.iter_mut())
+                .filter_map(|(v, dst)| v.parse::<f32>().ok().map(|v| *dst = v))
+                .count();
+            if let (Some(index), 6) = (index, count) {
+                channels.insert(index, parsed);
+            }
+        }
+        last_frame = Some(last_frame.map_or(frame, |last: u64| last.max(frame)));
+    }
+    if let Some(last_frame) = last_frame {
+        while written <= last_frame {
+            writeln!(out, "{}", bvh_frame(devices, &channels))?;
+            written += 1;
+        }
+    }
+    Ok(())
+}
+
+/// BVH needs the hierarchy, and so every device that will ever show up, before the motion data.
+/// Samples are written to a temporary file, with the frame their position in the recording falls
+/// in, and turned into the real file once the recording stops.
+struct BvhWriter {
+    path: PathBuf,
+    part_path: PathBuf,
+    part: Option<BufWriter<File>>,
+    devices: BTreeMap<u32, Option<DeviceClass>>,
+    frames: u64,
+    frame_time: f64,
+}
+
+impl BvhWriter {
+    fn create(path: PathBuf, rate: u32) -> io::Result<Self> {
+        let mut part_path = path.clone().into_os_string();
+        part_path.push(".part");
+        let part_path = PathBuf::from(part_path);
+        let part = File::create(&part_path)?;
+        Ok(BvhWriter {
+            path: path,
+            part_path: part_path,
+            part: Some(BufWriter::new(part)),
+            devices: BTreeMap::new(),
+            frames: 0,
+            frame_time: 1.0 / rate as f64,
+        })
+    }
+}
+
+impl PoseWriter for BvhWriter {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let part = match self.part.as_mut() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        // `index channels...` for every valid pose, separated by semicolons
+        let valid: Vec<String> = sample.devices.iter()
+            .filter(|device| device.pose.valid)
+            .map(|device| {
+                let values: Vec<String> = bvh_channels(&device.pose).iter().map(|v| v.to_string()).collect();
+                format!("{} {}", device.index, values.join(" "))
+            })
+            .collect();
+        // Frames are placed by time, since samples can be missed
+        let frame = bvh_frame_index(sample.position, self.frame_time);
+        writeln!(part, "{}|{}", frame, valid.join(";"))?;
+        for device in sample.devices.iter().filter(|device| device.pose.valid) {
+            self.devices.entry(device.index).or_insert(device.class);
+        }
+        self.frames = self.frames.max(frame + 1);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let part = match self.part.take() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        mem::drop(part.into_inner().map_err(|e| e.into_error())?);
+        let mut out = BufWriter::new(File::create(&self.path)?);
+        out.write_all(bvh_header(&self.devices, self.frames, self.frame_time).as_bytes())?;
+        write_bvh_motion(BufReader::new(File::open(&self.part_path)?), &mut out, &self.devices)?;
+        out.flush()?;
+        fs::remove_file(&self.part_path)
+    }
+}
+
+/// Opens the files `config` asks for next to `recording`
+fn create_writers(recording: &Recording, config: &TrackingConfig) -> Vec<(PathBuf, Box<dyn PoseWriter>)> {
+    let mut ret: Vec<(PathBuf, Box<dyn PoseWriter>)> = Vec::new();
+    let mut create = |suffix: &str, f: &dyn Fn(PathBuf) -> io::Result<Box<dyn PoseWriter>>| {
+        let path = match recording.sidecar_path(suffix) {
+            Some(v) => v,
+            None => return,
+        };
+        match f(path.clone()) {
+            Ok(writer) => {
+                info!("writing poses to {}", path.display());
+                ret.push((path, writer));
+            },
+            Err(e) => warn!("Error creating pose file {}: {}", path.display(), &e),
+        }
+    };
+    if config.csv {
+        create(".poses.csv", &|path| -> io::Result<Box<dyn PoseWriter>> {
+            Ok(Box::new(CsvWriter::new(BufWriter::new(File::create(path)?))?))
+        });
+    }
+    if config.jsonl {
+        create(".poses.jsonl", &|path| -> io::Result<Box<dyn PoseWriter>> {
+            Ok(Box::new(JsonLinesWriter(BufWriter::new(File::create(path)?))))
+        });
+    }
+    if config.bvh {
+        let rate = config.rate;
+        create(".poses.bvh", &|path| -> io::Result<Box<dyn PoseWriter>> {
+            Ok(Box::new(BvhWriter::create(path, rate)?))
+        });
+    }
+    ret
+}
+
+fn sample(recording: &Recording, universe: TrackingUniverse, init_attempted: &mut Option<Instant>) -> Option<Sample> {
+    if !crate::retry_init_openvr(init_attempted) {
+        return None;
+    }
+    let _session = crate::openvr_session()?;
+    let obs_time = unsafe { obs::sys::os_gettime_ns() };
+    let poses = openvr::pose::device_poses(universe, 0.0)?;
+    let devices = poses.iter()
+        .enumerate()
+        .filter(|(_, pose)| pose.connected)
+        .map(|(index, pose)| DevicePose {
+            index: index as u32,
+            class: openvr::system::device_class(index as u32),
+            pose: *pose,
+        })
+        .collect();
+    Some(Sample {
+        obs_time: obs_time,
+        position: recording.elapsed(obs_time),
+        devices: devices,
+    })
+}
+
+fn run(running: Arc<AtomicBool>, recording_id: u64, config: TrackingConfig, mut writers: Vec<(PathBuf, Box<dyn PoseWriter>)>) {
+    let interval = Duration::from_secs(1) / config.rate;
+    let mut next = Instant::now();
+    let mut init_attempted = None;
+    while running.load(Ordering::Relaxed) {
+        let recording = match recording::current() {
+            Some(v) if v.id == recording_id => v,
+            _ => break,
+        };
+        if !recording.is_paused() {
+            if let Some(sample) = sample(&recording, config.universe, &mut init_attempted) {
+                writers.retain_mut(|(path, writer)| match writer.write(&sample) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warn!("Error writing poses to {}, stopping: {}", path.display(), &e);
+                        false
+                    },
+                });
+            }
+        }
+        next += interval;
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        } else {
+            // Fell behind, don't try to catch up
+            next = now;
+        }
+    }
+    for (path, writer) in writers.iter_mut() {
+        match writer.finish() {
+            Ok(()) => info!("finished writing poses to {}", path.display()),
+            Err(e) => warn!("Error finishing pose file {}: {}", path.display(), &e),
+        }
+    }
+}
+
+struct Recorder {
+    running: Arc<AtomicBool>,
+    thread: JoinOnDrop<()>,
+}
+
+/// The recorder for the current recording, and those of earlier ones, which may still be
+/// finishing their files
+static RECORDERS: Mutex<Vec<Recorder>> = Mutex::new(Vec::new());
+
+/// Starts recording poses for `recording`, if enabled. Called on the UI thread.
+pub fn recording_started(recording: &Recording) {
+    // Earlier recorders stop on their own, and are only joined once they're done finishing their
+    // files, which can take a while for long recordings
+    RECORDERS.lock().unwrap().retain(|recorder| !recorder.thread.is_finished());
+    let config = match TrackingConfig::load() {
+        Some(v) if v.enabled => v,
+        _ => return,
+    };
+    let writers = create_writers(recording, &config);
+    if writers.is_empty() {
+        return;
+    }
+    let running = Arc::new(AtomicBool::new(true));
+    let thread_running = running.clone();
+    let recording_id = recording.id;
+    let handle = thread::Builder::new()
+        .name("obs-openvr-tracking".to_owned())
+        .spawn(move || run(thread_running, recording_id, config, writers));
+    match handle {
+        Ok(handle) => {
+            RECORDERS.lock().unwrap().push(Recorder {
+                running: running,
+                thread: handle.into(),
+            });
+        },
+        Err(e) => error!("Error starting pose recorder thread: {}", &e),
+    }
+}
+
+/// Stops recording poses, without waiting for the files to be finished. Called on the UI thread.
+pub fn recording_stopped() {
+    RECORDERS.lock().unwrap().iter().for_each(|recorder| recorder.running.store(false, Ordering::Relaxed));
+}
+
+/// Stops recording poses, waiting for the files to be finished
+pub fn shutdown() {
+    let recorders = mem::take(&mut *RECORDERS.lock().unwrap());
+    recorders.iter().for_each(|recorder| recorder.running.store(false, Ordering::Relaxed));
+    mem::drop(recorders);
+}
+
+pub(crate) mod keys {
+    use std::ffi::CStr;
+
+    pub const ENABLED: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"enabled\0")
+    };
+    pub const RATE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"rate\0")
+    };
+    pub const UNIVERSE: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"universe\0")
+    };
+    pub const STANDING: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"standing\0")
+    };
+    pub const CSV: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"csv\0")
+    };
+    pub const JSONL: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"jsonl\0")
+    };
+    pub const BVH: &'static CStr = unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"bvh\0")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Sample {
+        let pose = Pose {
+            matrix: [[1.0, 0.0, 0.0, 0.5], [0.0, 1.0, 0.0, 1.5], [0.0, 0.0, 1.0, -0.25]],
+            velocity: [0.0; 3],
+            angular_velocity: [0.0; 3],
+            tracking_result: 200,
+            valid: true,
+            connected: true,
+        };
+        Sample {
+            obs_time: 1_000_000_000,
+            position: Duration::from_millis(250),
+            devices: vec![DevicePose {
+                index: 0,
+                class: Some(DeviceClass::Hmd),
+                pose: pose,
+            }],
+        }
+    }
+
+    #[test]
+    fn csv_rows() {
+        let mut writer = CsvWriter::new(Vec::new()).unwrap();
+        writer.write(&sample()).unwrap();
+        let written = String::from_utf8(writer.0).unwrap();
+        assert_eq!(written, format!("{}1000000000,0.250000,0,hmd,1,0.5,1.5,-0.25,1,0,0,0,0,0,0,0,0,0\n", CSV_HEADER));
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut writer = JsonLinesWriter(Vec::new());
+        writer.write(&sample()).unwrap();
+        let written = String::from_utf8(writer.0).unwrap();
+        assert_eq!(written, concat!(
+            r#"{"obs_time_ns":1000000000,"seconds":0.250000,"devices":[{"index":0,"class":"hmd","valid":true,"#,
+            r#""position":[0.5,1.5,-0.25],"rotation":[1,0,0,0],"velocity":[0,0,0],"angular_velocity":[0,0,0]}]}"#,
+            "\n",
+        ));
+    }
+
+    #[test]
+    fn bvh_frames_keep_last_known_channels() {
+        let mut devices = BTreeMap::new();
+        devices.insert(0, Some(DeviceClass::Hmd));
+        devices.insert(3, Some(DeviceClass::Controller));
+        let header = bvh_header(&devices, 2, 1.0 / 90.0);
+        assert!(header.contains("JOINT hmd_0"));
+        assert!(header.contains("JOINT controller_3"));
+        assert!(header.ends_with("Frames: 2\nFrame Time: 0.011111\n"));
+        let mut channels = BTreeMap::new();
+        channels.insert(0, bvh_channels(&sample().devices[0].pose));
+        let frame = bvh_frame(&devices, &channels);
+        let values: Vec<&str> = frame.split(' ').collect();
+        assert_eq!(values.len(), 18);
+        assert_eq!(&values[6..9], &["50.0000", "150.0000", "-25.0000"]);
+        assert!(values[12..].iter().all(|v| *v == "0.0000"));
+    }
+
+    #[test]
+    fn bvh_frames_follow_sample_times() {
+        let frame_time = 1.0 / 90.0;
+        assert_eq!(bvh_frame_index(Duration::from_millis(0), frame_time), 0);
+        assert_eq!(bvh_frame_index(Duration::from_millis(1000), frame_time), 90);
+        assert_eq!(bvh_frame_index(Duration::from_micros(16_000), frame_time), 1);
+        let mut devices = BTreeMap::new();
+        devices.insert(0, Some(DeviceClass::Hmd));
+        // Frame 0 is missed, 2 and 3 are missed, and two samples fall in frame 5
+        let part = "1|0 1 0 0 0 0 0\n4|0 4 0 0 0 0 0\n5|0 5 0 0 0 0 0\n5|0 6 0 0 0 0 0\n";
+        let mut out = Vec::new();
+        write_bvh_motion(part.as_bytes(), &mut out, &devices).unwrap();
+        let xs: Vec<String> = String::from_utf8(out).unwrap()
+            .lines()
+            .map(|frame| frame.split(' ').nth(6).unwrap().to_owned())
+            .collect();
+        assert_eq!(xs, ["0.0000", "1.0000", "1.0000", "1.0000", "4.0000", "6.0000"]);
+    }
+}
@@ -0,0 +1,236 @@
+//! Plugin-wide OpenVR event handling. A background thread drains this process's OpenVR event
+//! queue, so every plugin-wide feature sees every event, and drives the features that act on
+//! them.
+
+use std::{
+    borrow::Cow,
+    ffi::CString,
+    mem,
+    sync::{
+        Arc,
+        Mutex,
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use openvr::system::{
+    DeviceClass,
+    Event,
+    event_type,
+};
+use crate::{
+    chapters::ChapterMonitor,
+    presence::PresenceMonitor,
+    profiles::ProfileMonitor,
+    rules::RuleMonitor,
+    thread_utils::JoinOnDrop,
+};
+
+/// How often the event queue is drained
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long after SteamVR asks to quit the session is released, giving everything polling OpenVR
+/// time to notice and stop
+const QUIT_RELEASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Something that happened in VR, which plugin-wide features react to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VrEvent {
+    /// An application became the scene application
+    AppStarted(CString),
+    /// An application stopped being the scene application
+    AppStopped(CString),
+    DashboardOpened,
+    DashboardClosed,
+    DeviceConnected(Option<DeviceClass>),
+    DeviceDisconnected(Option<DeviceClass>),
+    HeadsetOn,
+    HeadsetOff,
+    ScreenshotTaken,
+    SteamVRQuit,
+}
+
+impl VrEvent {
+    /// Name of the kind of event, as used in config and output files
+    pub fn name(&self) -> &'static str {
+        match self {
+            VrEvent::AppStarted(..) => "app_started",
+            VrEvent::AppStopped(..) => "app_stopped",
+            VrEvent::DashboardOpened => "dashboard_opened",
+            VrEvent::DashboardClosed => "dashboard_closed",
+            VrEvent::DeviceConnected(..) => "device_connected",
+            VrEvent::DeviceDisconnected(..) => "device_disconnected",
+            VrEvent::HeadsetOn => "headset_on",
+            VrEvent::HeadsetOff => "headset_off",
+            VrEvent::ScreenshotTaken => "screenshot_taken",
+            VrEvent::SteamVRQuit => "steamvr_quit",
+        }
+    }
+
+    /// What the event is about: the app key for app events, and the device class for device
+    /// events. Empty for other events.
+    pub fn detail(&self) -> Cow<'static, str> {
+        match self {
+            VrEvent::AppStarted(app) | VrEvent::AppStopped(app) => Cow::Owned(app.to_string_lossy().into_owned()),
+            VrEvent::DeviceConnected(class) | VrEvent::DeviceDisconnected(class) => Cow::Borrowed(class.map_or("", device_class_name)),
+            _ => Cow::Borrowed(""),
+        }
+    }
+}
+
+/// Name of a device class, as used in config and output files
+pub fn device_class_name(class: DeviceClass) -> &'static str {
+    match class {
+        DeviceClass::Hmd => "hmd",
+        DeviceClass::Controller => "controller",
+        DeviceClass::GenericTracker => "tracker",
+        DeviceClass::TrackingReference => "base_station",
+        DeviceClass::DisplayRedirect => "display_redirect",
+    }
+}
+
+/// Turns OpenVR events, and changes to the state the other features track, into `VrEvent`s
+struct VrEventTracker {
+    /// Scene application as of the last tick, `None` before the first one
+    app: Option<Option<CString>>,
+    present: bool,
+}
+
+impl VrEventTracker {
+    fn new() -> Self {
+        VrEventTracker {
+            app: None,
+            present: crate::presence::is_user_present(),
+        }
+    }
+
+    fn translate(event: &Event) -> Option<VrEvent> {
+        let ret = match event.event_type {
+            event_type::DASHBOARD_ACTIVATED => VrEvent::DashboardOpened,
+            event_type::DASHBOARD_DEACTIVATED => VrEvent::DashboardClosed,
+            event_type::DEVICE_ACTIVATED => VrEvent::DeviceConnected(openvr::system::device_class(event.device_index)),
+            event_type::DEVICE_DEACTIVATED => VrEvent::DeviceDisconnected(openvr::system::device_class(event.device_index)),
+            event_type::SCREENSHOT_TAKEN => VrEvent::ScreenshotTaken,
+            event_type::QUIT => VrEvent::SteamVRQuit,
+            _ => return None,
+        };
+        Some(ret)
+    }
+
+    /// Changes to the scene application and presence since the last tick. Must run after the
+    /// presence and profile monitors' ticks, which track them.
+    fn tick(&mut self) -> Vec<VrEvent> {
+        let mut ret = Vec::new();
+        let app = crate::profiles::current_app();
+        // The app running when OBS starts didn't just start
+        if let Some(previous) = self.app.replace(app.clone()) {
+            if previous != app {
+                ret.extend(previous.map(VrEvent::AppStopped));
+                ret.extend(app.map(VrEvent::AppStarted));
+            }
+        }
+        let present = crate::presence::is_user_present();
+        if present != self.present {
+            self.present = present;
+            ret.push(if present { VrEvent::HeadsetOn } else { VrEvent::HeadsetOff });
+        }
+        ret
+    }
+}
+
+struct EventThread {
+    running: Arc<AtomicBool>,
+    _thread: JoinOnDrop<()>,
+}
+
+static EVENT_THREAD: Mutex<Option<EventThread>> = Mutex::new(None);
+
+fn run(running: Arc<AtomicBool>) {
+    let mut presence = PresenceMonitor::new();
+    let mut profiles = ProfileMonitor::new();
+    let mut rules = RuleMonitor::new();
+    let mut chapters = ChapterMonitor::new();
+    let mut tracker = VrEventTracker::new();
+    let mut init_attempted = None;
+    let mut quit_at: Option<Instant> = None;
+    while running.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if quit_at.map_or(false, |t| now.duration_since(t) >= QUIT_RELEASE_DELAY) {
+            info!("SteamVR quit, releasing the OpenVR session");
+            crate::release_openvr();
+            quit_at = None;
+        }
+        // Features that act on their own only start OpenVR in OBS when they're configured.
+        // Otherwise events are only handled while in-process sources keep it running.
+        let wanted = presence.reload_config() | rules.reload_config() | crate::profiles::any_enabled();
+        let initialized = if wanted {
+            crate::retry_init_openvr(&mut init_attempted)
+        } else {
+            crate::openvr_init_status().is_ok()
+        };
+        if initialized {
+            chapters.tick(now);
+            let mut vr_events = Vec::new();
+            while let Some(event) = openvr::system::poll_next_event() {
+                trace!("openvr event: {:?}", &event);
+                presence.handle_event(&event, now);
+                profiles.handle_event(&event, now);
+                vr_events.extend(VrEventTracker::translate(&event));
+                if event.event_type == event_type::QUIT {
+                    openvr::system::acknowledge_quit();
+                    crate::openvr_quitting();
+                    quit_at = Some(now);
+                    break;
+                }
+            }
+            presence.tick(now);
+            profiles.tick(now);
+            vr_events.extend(tracker.tick());
+            for event in vr_events.iter() {
+                debug!("vr event: {:?}", event);
+                rules.handle_event(event);
+                chapters.handle_event(event);
+                crate::publish::publish_event(event);
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Starts handling events, if that isn't happening already
+pub fn start() {
+    let mut event_thread = EVENT_THREAD.lock().unwrap();
+    if event_thread.is_some() {
+        return;
+    }
+    let running = Arc::new(AtomicBool::new(true));
+    let thread_running = running.clone();
+    let handle = thread::Builder::new()
+        .name("obs-openvr-events".to_owned())
+        .spawn(move || run(thread_running));
+    match handle {
+        Ok(handle) => {
+            *event_thread = Some(EventThread {
+                running: running,
+                _thread: handle.into(),
+            });
+        },
+        Err(e) => error!("Error starting OpenVR event thread: {}", &e),
+    }
+}
+
+/// Stops handling events, waiting for the event thread to exit. Must be called before shutting
+/// down OpenVR.
+pub fn shutdown() {
+    let event_thread = EVENT_THREAD.lock().unwrap().take();
+    if let Some(event_thread) = event_thread {
+        event_thread.running.store(false, Ordering::Relaxed);
+        mem::drop(event_thread);
+    }
+}
@@ -5,11 +5,15 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() {
-    let _obs = pkg_config::probe_library("libobs")
+    let obs = pkg_config::probe_library("libobs")
         .expect("Error finding libobs with pkg-config");
+    // Not in libobs.pc, but installed next to it by OBS
+    println!("cargo:rustc-link-lib=obs-frontend-api");
 
     let bindings = bindgen::builder()
         .header("wrapper.h")
+        // obs-frontend-api.h includes libobs headers relative to their own directory
+        .clang_args(obs.include_paths.iter().map(|path| format!("-I{}", path.display())))
         .blocklist_type("_bindgen_ty_2")
         .blocklist_type("_bindgen_ty_3")
         .blocklist_type("_bindgen_ty_4")
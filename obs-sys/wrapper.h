@@ -1 +1,4 @@
 #include <obs/obs-module.h>
+#include <obs/util/platform.h>
+#include <obs/graphics/image-file.h>
+#include <obs/obs-frontend-api.h>
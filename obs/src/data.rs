@@ -1,7 +1,10 @@
 use obs_sys as sys;
 
 use std::{
-    ffi::CStr,
+    ffi::{
+        CStr,
+        CString,
+    },
     mem,
     ops::{
         Deref,
@@ -20,6 +23,26 @@ pub trait ObsData {
     fn get_cstr<'a, K: AsRef<CStr>>(&'a self, key: K) -> Option<&'a CStr>;
     fn get_int<K: AsRef<CStr>>(&self, k: K) -> libc::c_longlong;
     fn get_bool<K: AsRef<CStr>>(&self, k: K) -> bool;
+    fn get_double<K: AsRef<CStr>>(&self, k: K) -> f64;
+    fn set_default_int<K: AsRef<CStr>>(&mut self, k: K, v: libc::c_longlong);
+    fn set_default_bool<K: AsRef<CStr>>(&mut self, k: K, v: bool);
+    fn set_default_double<K: AsRef<CStr>>(&mut self, k: K, v: f64);
+    fn set_default_string<K: AsRef<CStr>, V: AsRef<CStr>>(&mut self, k: K, v: V);
+    fn set_bool<K: AsRef<CStr>>(&mut self, k: K, v: bool);
+    /// Nested object at `k`, see `obs_data_get_obj`
+    fn get_obj<K: AsRef<CStr>>(&self, k: K) -> Option<Data>;
+    fn set_obj<K: AsRef<CStr>>(&mut self, k: K, v: &sys::obs_data);
+    /// Array of objects at `k`, see `obs_data_get_array`
+    fn get_array<K: AsRef<CStr>>(&self, k: K) -> Option<DataArray>;
+    /// Removes the value at `k`, including its default
+    fn erase<K: AsRef<CStr>>(&mut self, k: K);
+    /// Names of the items with user values (as opposed to only defaults)
+    fn user_keys(&self) -> Vec<CString>;
+    /// Removes all user values, leaving defaults in place
+    fn unset_user_values(&mut self) {
+        self.user_keys().iter().for_each(|k| self.unset_user_value(k));
+    }
+    fn unset_user_value<K: AsRef<CStr>>(&mut self, k: K);
     fn get_string<'a, K: AsRef<CStr>>(&'a self, key: K) -> Option<&'a str> {
         self.get_cstr(key).and_then(|s| s.to_str().ok())
     }
@@ -49,7 +72,7 @@ impl ObsData for sys::obs_data {
             sys::obs_data_get_string(self_ptr, s.as_ptr())
         };
         Some(ptr)
-            .filter(|p| p.is_null())
+            .filter(|p| !p.is_null())
             .map(|p| unsafe { CStr::from_ptr(p) })
     }
     fn get_int<K: AsRef<CStr>>(&self, k: K) -> libc::c_longlong
@@ -69,6 +92,91 @@ impl ObsData for sys::obs_data {
             sys::obs_data_get_bool(self_ptr, k.as_ptr())
         }
     }
+    fn get_double<K: AsRef<CStr>>(&self, k: K) -> f64 {
+        let k = k.as_ref();
+        unsafe {
+            let self_ptr: *mut sys::obs_data = mem::transmute(self as *const _);
+            sys::obs_data_get_double(self_ptr, k.as_ptr())
+        }
+    }
+    fn set_default_int<K: AsRef<CStr>>(&mut self, k: K, v: libc::c_longlong) {
+        let k = k.as_ref();
+        unsafe {
+            sys::obs_data_set_default_int(self as *mut _, k.as_ptr(), v);
+        }
+    }
+    fn set_default_bool<K: AsRef<CStr>>(&mut self, k: K, v: bool) {
+        let k = k.as_ref();
+        unsafe {
+            sys::obs_data_set_default_bool(self as *mut _, k.as_ptr(), v);
+        }
+    }
+    fn set_default_double<K: AsRef<CStr>>(&mut self, k: K, v: f64) {
+        let k = k.as_ref();
+        unsafe {
+            sys::obs_data_set_default_double(self as *mut _, k.as_ptr(), v);
+        }
+    }
+    fn set_default_string<K: AsRef<CStr>, V: AsRef<CStr>>(&mut self, k: K, v: V) {
+        let (k, v) = (k.as_ref(), v.as_ref());
+        unsafe {
+            sys::obs_data_set_default_string(self as *mut _, k.as_ptr(), v.as_ptr());
+        }
+    }
+    fn set_bool<K: AsRef<CStr>>(&mut self, k: K, v: bool) {
+        let k = k.as_ref();
+        unsafe {
+            sys::obs_data_set_bool(self as *mut _, k.as_ptr(), v);
+        }
+    }
+    fn get_obj<K: AsRef<CStr>>(&self, k: K) -> Option<Data> {
+        let k = k.as_ref();
+        unsafe {
+            let self_ptr: *mut sys::obs_data = mem::transmute(self as *const _);
+            Data::from_owned_raw(sys::obs_data_get_obj(self_ptr, k.as_ptr()))
+        }
+    }
+    fn set_obj<K: AsRef<CStr>>(&mut self, k: K, v: &sys::obs_data) {
+        let k = k.as_ref();
+        unsafe {
+            let v_ptr: *mut sys::obs_data = mem::transmute(v as *const _);
+            sys::obs_data_set_obj(self as *mut _, k.as_ptr(), v_ptr);
+        }
+    }
+    fn get_array<K: AsRef<CStr>>(&self, k: K) -> Option<DataArray> {
+        let k = k.as_ref();
+        unsafe {
+            let self_ptr: *mut sys::obs_data = mem::transmute(self as *const _);
+            DataArray::from_owned_raw(sys::obs_data_get_array(self_ptr, k.as_ptr()))
+        }
+    }
+    fn erase<K: AsRef<CStr>>(&mut self, k: K) {
+        let k = k.as_ref();
+        unsafe {
+            sys::obs_data_erase(self as *mut _, k.as_ptr());
+        }
+    }
+    fn user_keys(&self) -> Vec<CString> {
+        let mut ret = Vec::new();
+        unsafe {
+            let self_ptr: *mut sys::obs_data = mem::transmute(self as *const _);
+            let mut item = sys::obs_data_first(self_ptr);
+            while !item.is_null() {
+                if sys::obs_data_item_has_user_value(item) {
+                    ret.push(CStr::from_ptr(sys::obs_data_item_get_name(item)).to_owned());
+                }
+                // Releases `item`, and references the next one
+                sys::obs_data_item_next(&mut item as *mut _);
+            }
+        }
+        ret
+    }
+    fn unset_user_value<K: AsRef<CStr>>(&mut self, k: K) {
+        let k = k.as_ref();
+        unsafe {
+            sys::obs_data_unset_user_value(self as *mut _, k.as_ptr());
+        }
+    }
 }
 
 /// Owned variant of `&sys::obs_data`
@@ -88,6 +196,49 @@ impl Data {
         }
     }
 
+    /// Loads a JSON file, falling back to its `.bak` backup if it is missing or invalid. See
+    /// `obs_data_create_from_json_file_safe`.
+    pub fn from_json_file(path: &CStr) -> Option<Data> {
+        let backup_ext: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"bak\0") };
+        let ptr = unsafe {
+            sys::obs_data_create_from_json_file_safe(path.as_ptr(), backup_ext.as_ptr())
+        };
+        if ptr.is_null() {
+            None
+        } else {
+            Some(Data(ptr))
+        }
+    }
+
+    /// Writes the user values to a JSON file through a temporary file, keeping the previous
+    /// version as a `.bak` backup. See `obs_data_save_json_safe`.
+    pub fn save_json(&self, path: &CStr) -> bool {
+        let (temp_ext, backup_ext): (&'static CStr, &'static CStr) = unsafe {
+            (CStr::from_bytes_with_nul_unchecked(b"tmp\0"), CStr::from_bytes_with_nul_unchecked(b"bak\0"))
+        };
+        unsafe {
+            sys::obs_data_save_json_safe(self.0, path.as_ptr(), temp_ext.as_ptr(), backup_ext.as_ptr())
+        }
+    }
+
+    /// Copies all values from `other` into this object, see `obs_data_apply`
+    pub fn apply(&mut self, other: &sys::obs_data) {
+        unsafe {
+            let other_ptr: *mut sys::obs_data = mem::transmute(other as *const _);
+            sys::obs_data_apply(self.0, other_ptr);
+        }
+    }
+
+    /// Takes ownership of a reference the caller already holds, like the ones returned by
+    /// `obs_data_get_obj`
+    pub unsafe fn from_owned_raw(p: *mut sys::obs_data) -> Option<Data> {
+        if p.is_null() {
+            None
+        } else {
+            Some(Data(p))
+        }
+    }
+
     /// Takes ownership of a remote pointer, using `sys::obs_data_addref`
     pub unsafe fn from_raw(p: *mut sys::obs_data) -> Option<Data> {
         if p.is_null() {
@@ -149,3 +300,42 @@ impl DerefMut for Data {
         }
     }
 }
+
+/// Owned reference to a `sys::obs_data_array`, released on drop
+#[derive(Debug)]
+pub struct DataArray(*mut sys::obs_data_array);
+
+impl DataArray {
+    /// Takes ownership of a reference the caller already holds, like the ones returned by
+    /// `obs_data_get_array`
+    pub unsafe fn from_owned_raw(p: *mut sys::obs_data_array) -> Option<DataArray> {
+        if p.is_null() {
+            None
+        } else {
+            Some(DataArray(p))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        unsafe {
+            sys::obs_data_array_count(self.0) as usize
+        }
+    }
+
+    /// Object at `index`, see `obs_data_array_item`
+    pub fn get(&self, index: usize) -> Option<Data> {
+        unsafe { Data::from_owned_raw(sys::obs_data_array_item(self.0, index as _)) }
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item=Data> + 'a {
+        (0..self.len()).filter_map(move |i| self.get(i))
+    }
+}
+
+impl Drop for DataArray {
+    fn drop(&mut self) {
+        unsafe {
+            sys::obs_data_array_release(self.0);
+        }
+    }
+}
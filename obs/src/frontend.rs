@@ -0,0 +1,121 @@
+//! Wrappers for the OBS frontend API (`obs-frontend-api.h`), which only does anything when the
+//! module is loaded by the OBS app
+
+use obs_sys as sys;
+
+use std::ffi::{
+    CStr,
+    CString,
+};
+use crate::{
+    data::{
+        Data,
+        ObsData,
+    },
+    source::OwnedSource,
+};
+
+/// Callback for frontend events, called on the UI thread. See `obs_frontend_add_event_callback`.
+pub type EventCallback = unsafe extern "C" fn(event: sys::obs_frontend_event, data: *mut libc::c_void);
+
+pub fn add_event_callback(callback: EventCallback, data: *mut libc::c_void) {
+    unsafe {
+        sys::obs_frontend_add_event_callback(Some(callback), data);
+    }
+}
+
+pub fn remove_event_callback(callback: EventCallback, data: *mut libc::c_void) {
+    unsafe {
+        sys::obs_frontend_remove_event_callback(Some(callback), data);
+    }
+}
+
+/// The scene shown in program output
+pub fn current_scene() -> Option<OwnedSource> {
+    unsafe { OwnedSource::from_owned_raw(sys::obs_frontend_get_current_scene()) }
+}
+
+/// Switches program output to `scene`, using the current transition. Blocks until the UI thread
+/// handled it, so must not be called while holding locks the UI thread could wait on.
+pub fn set_current_scene(scene: &OwnedSource) {
+    unsafe {
+        sys::obs_frontend_set_current_scene(scene.as_ptr());
+    }
+}
+
+pub fn recording_start() {
+    unsafe {
+        sys::obs_frontend_recording_start();
+    }
+}
+
+pub fn recording_stop() {
+    unsafe {
+        sys::obs_frontend_recording_stop();
+    }
+}
+
+pub fn recording_active() -> bool {
+    unsafe { sys::obs_frontend_recording_active() }
+}
+
+/// File the current recording is being written to, from the recording output's `path` setting
+pub fn recording_path() -> Option<CString> {
+    let path_key: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"path\0") };
+    unsafe {
+        let output = sys::obs_frontend_get_recording_output();
+        if output.is_null() {
+            return None;
+        }
+        let settings = Data::from_owned_raw(sys::obs_output_get_settings(output));
+        sys::obs_output_release(output);
+        settings?.get_cstr(path_key)
+            .filter(|path| !path.to_bytes().is_empty())
+            .map(|path| path.to_owned())
+    }
+}
+
+/// Adds a chapter marker to the current recording. Only supported by some outputs (like Hybrid
+/// MP4), and not while paused. See `obs_frontend_recording_add_chapter`.
+pub fn recording_add_chapter(name: &CStr) -> bool {
+    unsafe { sys::obs_frontend_recording_add_chapter(name.as_ptr()) }
+}
+
+pub fn streaming_start() {
+    unsafe {
+        sys::obs_frontend_streaming_start();
+    }
+}
+
+pub fn streaming_stop() {
+    unsafe {
+        sys::obs_frontend_streaming_stop();
+    }
+}
+
+pub fn streaming_active() -> bool {
+    unsafe { sys::obs_frontend_streaming_active() }
+}
+
+pub fn replay_buffer_start() {
+    unsafe {
+        sys::obs_frontend_replay_buffer_start();
+    }
+}
+
+pub fn replay_buffer_stop() {
+    unsafe {
+        sys::obs_frontend_replay_buffer_stop();
+    }
+}
+
+/// Saves the replay buffer, if it's running
+pub fn replay_buffer_save() {
+    unsafe {
+        sys::obs_frontend_replay_buffer_save();
+    }
+}
+
+pub fn replay_buffer_active() -> bool {
+    unsafe { sys::obs_frontend_replay_buffer_active() }
+}
@@ -1,6 +1,8 @@
 use obs_sys as sys;
 
 use std::{
+    ffi::CStr,
+    mem,
     ops::{
         Deref,
         DerefMut,
@@ -44,6 +46,22 @@ pub fn with_graphics<Ret, F: FnMut() -> Ret>(mut f: F) -> Ret {
     ret
 }
 
+/// Sets the blend function to `(src, dst)`, runs the provided function, then restores the previous
+/// blend state. Must be called from within the graphics context.
+pub fn with_blend_function<Ret, F>(src: sys::gs_blend_type, dst: sys::gs_blend_type, f: F) -> Ret where
+    F: FnOnce() -> Ret,
+{
+    unsafe {
+        sys::gs_blend_state_push();
+        sys::gs_blend_function(src, dst);
+    }
+    let ret = f();
+    unsafe {
+        sys::gs_blend_state_pop();
+    }
+    ret
+}
+
 pub trait GsTexture {
     unsafe fn set_image_unchecked(&mut self, data: &[u8], linesize: u32, inverted: bool);
     fn get_width(&self) -> u32;
@@ -129,6 +147,10 @@ impl DerefMut for Texture {
     }
 }
 
+// Graphics objects aren't tied to the thread that created them, only to the graphics context,
+// which `Drop` enters
+unsafe impl Send for Texture {}
+
 impl Drop for Texture {
     fn drop(&mut self) {
         with_graphics(|| {
@@ -141,3 +163,58 @@ impl Drop for Texture {
         });
     }
 }
+
+/// An image file decoded by libobs (see `gs_image_file_t`), kept in CPU memory. Animated images
+/// only have their first frame.
+pub struct ImageFile(Box<sys::gs_image_file_t>);
+
+impl ImageFile {
+    /// Decodes the image at `path`. Doesn't need the graphics context.
+    pub fn load(path: &CStr) -> Option<Self> {
+        let mut image: Box<sys::gs_image_file_t> = Box::new(unsafe { mem::zeroed() });
+        unsafe {
+            sys::gs_image_file_init(&mut *image as *mut _, path.as_ptr());
+        }
+        let ret = ImageFile(image);
+        let packed = match ret.0.format {
+            sys::gs_color_format_GS_RGBA | sys::gs_color_format_GS_BGRA | sys::gs_color_format_GS_BGRX => true,
+            _ => false,
+        };
+        if ret.0.loaded && packed && !ret.0.texture_data.is_null() {
+            Some(ret)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.0.cx, self.0.cy)
+    }
+
+    #[inline]
+    pub fn color_format(&self) -> sys::gs_color_format {
+        self.0.format
+    }
+
+    /// Tightly packed pixels of `color_format`, which is always RGBA, BGRA or BGRX
+    pub fn data(&self) -> &[u8] {
+        let (w, h) = self.dimensions();
+        let len = w as usize * h as usize * 4;
+        unsafe {
+            std::slice::from_raw_parts(self.0.texture_data, len)
+        }
+    }
+}
+
+// The decoded data is only owned by this struct
+unsafe impl Send for ImageFile {}
+
+impl Drop for ImageFile {
+    fn drop(&mut self) {
+        // Frees the texture too, if one was ever created
+        with_graphics(|| unsafe {
+            sys::gs_image_file_free(&mut *self.0 as *mut _);
+        });
+    }
+}
@@ -8,6 +8,8 @@ pub mod properties;
 pub mod graphics;
 pub mod source;
 pub mod data;
+pub mod frontend;
+pub mod scene;
 pub(crate) mod ptr;
 
 pub use data::Data;
@@ -24,6 +26,18 @@ pub unsafe fn register_source(info: &'static sys::obs_source_info, info_size: Op
     sys::obs_register_source_s(info as *const _, info_size as usize);
 }
 
+/// Runs `task` on the OBS UI thread, without waiting for it
+pub fn queue_ui_task<F: FnOnce() + Send + 'static>(task: F) {
+    unsafe extern "C" fn run<F: FnOnce()>(param: *mut libc::c_void) {
+        let task = Box::from_raw(param as *mut F);
+        task();
+    }
+    let task = Box::into_raw(Box::new(task));
+    unsafe {
+        sys::obs_queue_task(sys::obs_task_type::OBS_TASK_UI, Some(run::<F>), task as *mut libc::c_void, false);
+    }
+}
+
 #[macro_export]
 macro_rules! register_video_source {
     ($t:ty) => {
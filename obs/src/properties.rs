@@ -71,6 +71,25 @@ impl Properties {
         Properties(ptr)
     }
 
+    /// Looks up a property by name, searching groups as well
+    pub fn get(&mut self, name: &CStr) -> Option<&mut sys::obs_property> {
+        unsafe {
+            sys::obs_properties_get(self.as_ptr_mut(), name.as_ptr()).as_mut()
+        }
+    }
+
+    /// Adds `group` as a child group of these properties, taking ownership of it. `None` if OBS
+    /// refused the group, like when it holds a property named like one these properties already
+    /// have.
+    pub fn add_group(&mut self, name: &'static CStr, description: &'static CStr, group: Properties) -> Option<&mut sys::obs_property> {
+        unsafe {
+            let ret = sys::obs_properties_add_group(self.as_ptr_mut(), name.as_ptr(), description.as_ptr(), sys::obs_group_type_OBS_GROUP_NORMAL, group.0).as_mut()?;
+            // OBS only takes the group over once it's added, otherwise it's destroyed on drop
+            group.leak();
+            Some(ret)
+        }
+    }
+
     pub fn add_bool(&mut self, name: &'static CStr, description: &'static CStr) -> &mut sys::obs_property {
         unsafe {
             sys::obs_properties_add_bool(self.as_ptr_mut(), name.as_ptr(), description.as_ptr()).as_mut().unwrap()
@@ -83,12 +102,40 @@ impl Properties {
         }
     }
 
-    pub fn add_text(&mut self, name: &'static CStr, description: &'static CStr, ty: sys::obs_text_type) -> &mut sys::obs_property {
+    pub fn add_float(&mut self, name: &'static CStr, description: &'static CStr, min: f64, max: f64, step: f64) -> &mut sys::obs_property {
+        unsafe {
+            sys::obs_properties_add_float(self.as_ptr_mut(), name.as_ptr(), description.as_ptr(), min, max, step).as_mut().unwrap()
+        }
+    }
+
+    pub fn add_color(&mut self, name: &'static CStr, description: &'static CStr) -> &mut sys::obs_property {
+        unsafe {
+            sys::obs_properties_add_color(self.as_ptr_mut(), name.as_ptr(), description.as_ptr()).as_mut().unwrap()
+        }
+    }
+
+    pub fn add_text(&mut self, name: &CStr, description: &CStr, ty: sys::obs_text_type) -> &mut sys::obs_property {
         unsafe {
             sys::obs_properties_add_text(self.as_ptr_mut(), name.as_ptr(), description.as_ptr(), ty).as_mut().unwrap()
         }
     }
 
+    /// Adds a file or directory picker. `filter` is a Qt-style file filter, like
+    /// `"Images (*.png *.jpg)"`.
+    pub fn add_path(&mut self, name: &'static CStr, description: &'static CStr, ty: sys::obs_path_type, filter: Option<&CStr>, default_path: Option<&CStr>) -> &mut sys::obs_property {
+        let filter = filter.map_or(ptr::null(), |s| s.as_ptr());
+        let default_path = default_path.map_or(ptr::null(), |s| s.as_ptr());
+        unsafe {
+            sys::obs_properties_add_path(self.as_ptr_mut(), name.as_ptr(), description.as_ptr(), ty, filter, default_path).as_mut().unwrap()
+        }
+    }
+
+    pub fn add_button(&mut self, name: &'static CStr, text: &'static CStr, callback: ButtonCallback) -> &mut sys::obs_property {
+        unsafe {
+            sys::obs_properties_add_button(self.as_ptr_mut(), name.as_ptr(), text.as_ptr(), Some(callback)).as_mut().unwrap()
+        }
+    }
+
     pub fn add_string_list<'a>(&'a mut self, header: PropertyDescription<'static>, editable: bool) -> StringPropertyList<'a> {
         let combo_type = if editable {
             sys::obs_combo_type_OBS_COMBO_TYPE_EDITABLE
@@ -144,6 +191,52 @@ impl DerefMut for Properties {
     }
 }
 
+/// Callback invoked when a property is modified in the UI. Returning `true` refreshes the properties
+/// view
+pub type ModifiedCallback = unsafe extern "C" fn(props: *mut sys::obs_properties_t, property: *mut sys::obs_property_t, settings: *mut sys::obs_data_t) -> bool;
+
+/// Callback invoked when a button property is clicked. `data` is the source's private data. Returning
+/// `true` refreshes the properties view
+pub type ButtonCallback = unsafe extern "C" fn(props: *mut sys::obs_properties_t, property: *mut sys::obs_property_t, data: *mut libc::c_void) -> bool;
+
+/// Safe access functions for a single `sys::obs_property`
+pub trait PropertyExt {
+    fn set_description(&mut self, description: &CStr);
+    fn set_visible(&mut self, visible: bool);
+    fn set_modified_callback(&mut self, callback: ModifiedCallback);
+}
+
+impl PropertyExt for sys::obs_property {
+    fn set_description(&mut self, description: &CStr) {
+        unsafe {
+            sys::obs_property_set_description(self as *mut _, description.as_ptr());
+        }
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        unsafe {
+            sys::obs_property_set_visible(self as *mut _, visible);
+        }
+    }
+
+    fn set_modified_callback(&mut self, callback: ModifiedCallback) {
+        unsafe {
+            sys::obs_property_set_modified_callback(self as *mut _, Some(callback));
+        }
+    }
+}
+
+/// Runs `f` with a borrowed `Properties` for the raw pointer handed to a property callback, without
+/// taking ownership of it
+pub unsafe fn with_raw<Ret, F>(props: *mut sys::obs_properties_t, f: F) -> Ret where
+    F: FnOnce(&mut Properties) -> Ret,
+{
+    let mut props = Properties(props);
+    let ret = f(&mut props);
+    props.leak();
+    ret
+}
+
 pub trait PropertiesExt {
     fn add_string_list_complete<'a, It>(&'a mut self, header: PropertyDescription<'static>, it: It) where
         It: Iterator<Item=(&'static CStr, &'static CStr)>;
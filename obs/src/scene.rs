@@ -0,0 +1,109 @@
+//! Scenes and the items in them
+
+use obs_sys as sys;
+
+use std::{
+    ffi::CStr,
+    mem,
+};
+use crate::source::OwnedSource;
+
+/// Owned reference to a `sys::obs_sceneitem_t`, released on drop
+#[derive(Debug)]
+pub struct SceneItem(*mut sys::obs_sceneitem_t);
+
+impl SceneItem {
+    /// Finds the item showing the source named `name` in `scene`, which must be a scene source.
+    /// Groups in the scene aren't searched. See `obs_scene_find_source`.
+    pub fn find(scene: &OwnedSource, name: &CStr) -> Option<Self> {
+        unsafe {
+            let scene = sys::obs_scene_from_source(scene.as_ptr());
+            if scene.is_null() {
+                return None;
+            }
+            Self::from_raw(sys::obs_scene_find_source(scene, name.as_ptr()))
+        }
+    }
+
+    /// Takes a new reference to `p`, using `obs_sceneitem_addref`
+    pub unsafe fn from_raw(p: *mut sys::obs_sceneitem_t) -> Option<Self> {
+        if p.is_null() {
+            return None;
+        }
+        sys::obs_sceneitem_addref(p);
+        Some(SceneItem(p))
+    }
+
+    #[inline(always)]
+    pub fn as_ptr(&self) -> *mut sys::obs_sceneitem_t {
+        self.0
+    }
+
+    pub fn visible(&self) -> bool {
+        unsafe { sys::obs_sceneitem_visible(self.0) }
+    }
+
+    pub fn set_visible(&self, visible: bool) -> bool {
+        unsafe { sys::obs_sceneitem_set_visible(self.0, visible) }
+    }
+
+    /// Position in the scene, in pixels, of the point the item is aligned by (its top left
+    /// corner by default)
+    pub fn pos(&self) -> [f32; 2] {
+        unsafe {
+            let mut pos: sys::vec2 = mem::zeroed();
+            sys::obs_sceneitem_get_pos(self.0, &mut pos as *mut _);
+            from_vec2(pos)
+        }
+    }
+
+    pub fn set_pos(&self, pos: [f32; 2]) {
+        let pos = to_vec2(pos);
+        unsafe { sys::obs_sceneitem_set_pos(self.0, &pos as *const _) }
+    }
+
+    /// Rotation in degrees, clockwise
+    pub fn rot(&self) -> f32 {
+        unsafe { sys::obs_sceneitem_get_rot(self.0) }
+    }
+
+    pub fn set_rot(&self, degrees: f32) {
+        unsafe { sys::obs_sceneitem_set_rot(self.0, degrees) }
+    }
+
+    /// Horizontal and vertical scale. Ignored by OBS while the item has a bounding box.
+    pub fn scale(&self) -> [f32; 2] {
+        unsafe {
+            let mut scale: sys::vec2 = mem::zeroed();
+            sys::obs_sceneitem_get_scale(self.0, &mut scale as *mut _);
+            from_vec2(scale)
+        }
+    }
+
+    pub fn set_scale(&self, scale: [f32; 2]) {
+        let scale = to_vec2(scale);
+        unsafe { sys::obs_sceneitem_set_scale(self.0, &scale as *const _) }
+    }
+}
+
+// `vec2` is a union of `{ x, y }` and `float ptr[2]`, so it has the layout of `[f32; 2]`
+#[inline]
+fn from_vec2(v: sys::vec2) -> [f32; 2] {
+    unsafe { mem::transmute(v) }
+}
+
+#[inline]
+fn to_vec2(v: [f32; 2]) -> sys::vec2 {
+    unsafe { mem::transmute(v) }
+}
+
+impl Drop for SceneItem {
+    fn drop(&mut self) {
+        unsafe {
+            sys::obs_sceneitem_release(self.0);
+        }
+    }
+}
+
+unsafe impl Send for SceneItem {}
+unsafe impl Sync for SceneItem {}
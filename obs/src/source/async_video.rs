@@ -19,6 +19,8 @@ pub trait AsyncVideoSource: Sized {
     const OUTPUT_FLAGS: Option<NonZeroU32> = None;
     fn create(settings: &mut sys::obs_data, source: *mut sys::obs_source_t) -> Self;
     fn get_name() -> &'static CStr;
+    fn get_defaults(_settings: &mut sys::obs_data) {
+    }
     fn update(&self, _settings: &sys::obs_data) {
     }
     fn get_properties(&self) -> Properties {
@@ -39,6 +41,7 @@ pub trait AsyncVideoSource: Sized {
                 type_: sys::obs_source_type_OBS_SOURCE_TYPE_INPUT,
                 output_flags: output_flags::<Self>(),
                 get_name: Some(async_video_source_get_name::<Self>),
+                get_defaults: Some(async_video_source_get_defaults::<Self>),
                 update: Some(async_video_source_update::<Self>),
                 get_properties: Some(async_video_source_get_properties::<Self>),
                 create: Some(async_video_source_create::<Self>),
@@ -66,6 +69,10 @@ unsafe extern "C" fn async_video_source_get_name<T: AsyncVideoSource>(_data: *mu
     <T as AsyncVideoSource>::get_name().as_ptr()
 }
 
+unsafe extern "C" fn async_video_source_get_defaults<T: AsyncVideoSource>(settings: *mut sys::obs_data_t) {
+    <T as AsyncVideoSource>::get_defaults(settings.as_mut().unwrap());
+}
+
 unsafe extern "C" fn async_video_source_update<T: AsyncVideoSource>(data: *mut libc::c_void, settings: *mut sys::obs_data_t) {
     let data: &T = assert_ref(data);
     let settings = settings.as_mut().unwrap();
@@ -1,4 +1,5 @@
 mod async_video;
+mod owned;
 
 use obs_sys as sys;
 
@@ -11,6 +12,10 @@ use std::{
 use crate::ptr::*;
 
 pub use async_video::AsyncVideoSource;
+pub use owned::{
+    OwnedSource,
+    WeakSource,
+};
 
 pub struct RawSourceInfo<'a>(pub sys::obs_source_info, PhantomData<&'a ()>);
 
@@ -54,6 +59,8 @@ pub trait VideoSource: Sized {
 
     fn create(settings: &mut sys::obs_data, source: *mut sys::obs_source_t) -> Self;
     fn get_name() -> &'static CStr;
+    fn get_defaults(_settings: &mut sys::obs_data) {
+    }
     fn update(&self, _settings: &sys::obs_data) {
         print_vs_stub("update");
     }
@@ -74,17 +81,24 @@ pub trait VideoSource: Sized {
     fn video_tick(&self, _seconds: f32) {
         print_vs_stub("video_tick");
     }
+    fn show(&self) {
+    }
+    fn hide(&self) {
+    }
 
     fn raw_source_info() -> RawSourceInfo<'static> {
         let id: &'static CStr = CStr::from_bytes_with_nul(Self::ID).unwrap();
         let mut info = empty_source_info(id, sys::obs_source_type_OBS_SOURCE_TYPE_INPUT, Some(video_source_output_flags::<Self>()));
         info.0.get_name = Some(video_source_get_name::<Self>);
+        info.0.get_defaults = Some(video_source_get_defaults::<Self>);
         info.0.get_width = Some(video_source_get_width::<Self>);
         info.0.get_height = Some(video_source_get_height::<Self>);
         info.0.get_properties = Some(video_source_get_properties::<Self>);
         info.0.update = Some(video_source_update::<Self>);
         info.0.video_render = Some(video_source_video_render::<Self>);
         info.0.video_tick = Some(video_source_video_tick::<Self>);
+        info.0.show = Some(video_source_show::<Self>);
+        info.0.hide = Some(video_source_hide::<Self>);
         info.0.create = Some(video_source_create::<Self>);
         info.0.destroy = Some(video_source_destroy::<Self>);
         info
@@ -116,6 +130,10 @@ unsafe extern "C" fn video_source_get_name<T: VideoSource + Sized>(_data: *mut l
     <T as VideoSource>::get_name().as_ptr()
 }
 
+unsafe extern "C" fn video_source_get_defaults<T: VideoSource>(settings: *mut sys::obs_data_t) {
+    <T as VideoSource>::get_defaults(settings.as_mut().unwrap());
+}
+
 unsafe extern "C" fn video_source_get_width<T: VideoSource>(data: *mut libc::c_void) -> u32 {
     let data: &T = assert_ref(data);
     data.get_width()
@@ -147,6 +165,16 @@ unsafe extern "C" fn video_source_video_tick<T: VideoSource>(data: *mut libc::c_
     data.video_tick(seconds);
 }
 
+unsafe extern "C" fn video_source_show<T: VideoSource>(data: *mut libc::c_void) {
+    let data: &T = assert_ref(data);
+    data.show();
+}
+
+unsafe extern "C" fn video_source_hide<T: VideoSource>(data: *mut libc::c_void) {
+    let data: &T = assert_ref(data);
+    data.hide();
+}
+
 unsafe extern "C" fn video_source_create<S: VideoSource>(settings: *mut sys::obs_data_t, source: *mut sys::obs_source_t) -> *mut libc::c_void {
     let source = Box::new(<S as VideoSource>::create(settings.as_mut().unwrap(), source));
     let ret: *mut S = Box::leak(source) as *mut S;
@@ -166,7 +194,36 @@ pub fn draw(image: &sys::gs_texture_t, x: libc::c_int, y: libc::c_int, cx: u32,
     }
 }
 
+/// Like `draw`, but only draws the `(x, y, cx, cy)` region of `image`, positioned at `(dst_x, dst_y)`.
+/// Like `obs_source_draw`, samples `image` through its sRGB view when rendering in linear sRGB.
+pub fn draw_subregion(image: &sys::gs_texture_t, dst_x: f32, dst_y: f32, x: u32, y: u32, cx: u32, cy: u32, flip: bool) {
+    let image_name: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"image\0") };
+    unsafe {
+        let image = image as *const _ as *mut sys::gs_texture_t;
+        let effect = sys::gs_get_effect();
+        let param = sys::gs_effect_get_param_by_name(effect, image_name.as_ptr());
+        let linear_srgb = sys::gs_get_linear_srgb();
+        let previous = sys::gs_framebuffer_srgb_enabled();
+        sys::gs_enable_framebuffer_srgb(linear_srgb);
+        if linear_srgb {
+            sys::gs_effect_set_texture_srgb(param, image);
+        } else {
+            sys::gs_effect_set_texture(param, image);
+        }
+        sys::gs_matrix_push();
+        sys::gs_matrix_translate3f(dst_x, dst_y, 0.0);
+        sys::gs_draw_sprite_subregion(image, if flip { sys::GS_FLIP_V } else { 0 }, x, y, cx, cy);
+        sys::gs_matrix_pop();
+        sys::gs_enable_framebuffer_srgb(previous);
+    }
+}
+
 pub unsafe fn output_video2(source: *mut sys::obs_source_t, frame: &sys::obs_source_frame2) {
     let frame = frame as *const _;
     sys::obs_source_output_video2(source, frame);
 }
+
+/// Clears the async video of `source`, so it shows nothing until the next frame
+pub unsafe fn clear_video(source: *mut sys::obs_source_t) {
+    sys::obs_source_output_video2(source, std::ptr::null());
+}
@@ -0,0 +1,163 @@
+use obs_sys as sys;
+
+use std::{
+    ffi::CStr,
+    mem,
+};
+use crate::data::Data;
+
+/// Owned reference to a `sys::obs_source_t`, released on drop
+#[derive(Debug)]
+pub struct OwnedSource(*mut sys::obs_source_t);
+
+impl OwnedSource {
+    /// Creates a private source (not listed in the UI) of type `id`, see `obs_source_create_private`
+    pub fn create_private(id: &CStr, name: &CStr, settings: Option<&sys::obs_data>) -> Option<Self> {
+        let settings_ptr: *mut sys::obs_data = settings
+            .map(|settings| unsafe { mem::transmute(settings as *const _) })
+            .unwrap_or(std::ptr::null_mut());
+        let p = unsafe {
+            sys::obs_source_create_private(id.as_ptr(), name.as_ptr(), settings_ptr)
+        };
+        if p.is_null() {
+            None
+        } else {
+            Some(OwnedSource(p))
+        }
+    }
+
+    /// Finds a public source (or scene) by name, see `obs_get_source_by_name`
+    pub fn by_name(name: &CStr) -> Option<Self> {
+        unsafe { Self::from_owned_raw(sys::obs_get_source_by_name(name.as_ptr())) }
+    }
+
+    /// Takes a new reference to `p`, unless it's being destroyed. See `obs_source_get_ref`.
+    pub unsafe fn from_raw(p: *mut sys::obs_source_t) -> Option<Self> {
+        Self::from_owned_raw(sys::obs_source_get_ref(p))
+    }
+
+    /// Takes ownership of a reference the caller already holds, unlike `obs_source_get_ref`
+    pub unsafe fn from_owned_raw(p: *mut sys::obs_source_t) -> Option<Self> {
+        if p.is_null() {
+            None
+        } else {
+            Some(OwnedSource(p))
+        }
+    }
+
+    #[inline(always)]
+    pub fn as_ptr(&self) -> *mut sys::obs_source_t {
+        self.0
+    }
+
+    /// Private data of the source's implementation, as returned from its `create` callback
+    pub fn data(&self) -> *mut libc::c_void {
+        unsafe {
+            sys::obs_obj_get_data(self.0 as *mut libc::c_void)
+        }
+    }
+
+    pub fn id(&self) -> &CStr {
+        unsafe {
+            CStr::from_ptr(sys::obs_source_get_id(self.0))
+        }
+    }
+
+    /// The source's settings object itself, so changes apply to the source (after
+    /// `apply_settings`)
+    pub fn settings(&self) -> Option<Data> {
+        unsafe { Data::from_owned_raw(sys::obs_source_get_settings(self.0)) }
+    }
+
+    /// Updates the source with its settings, after they were changed in place through `settings`
+    pub fn apply_settings(&self) {
+        unsafe {
+            sys::obs_source_update(self.0, std::ptr::null_mut());
+        }
+    }
+
+    pub fn name(&self) -> &CStr {
+        unsafe {
+            CStr::from_ptr(sys::obs_source_get_name(self.0))
+        }
+    }
+
+    pub fn update(&self, settings: &sys::obs_data) {
+        unsafe {
+            let settings_ptr: *mut sys::obs_data = mem::transmute(settings as *const _);
+            sys::obs_source_update(self.0, settings_ptr);
+        }
+    }
+
+    pub fn video_render(&self) {
+        unsafe {
+            sys::obs_source_video_render(self.0);
+        }
+    }
+
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        unsafe {
+            (sys::obs_source_get_width(self.0), sys::obs_source_get_height(self.0))
+        }
+    }
+
+    pub fn inc_showing(&self) {
+        unsafe {
+            sys::obs_source_inc_showing(self.0);
+        }
+    }
+
+    pub fn dec_showing(&self) {
+        unsafe {
+            sys::obs_source_dec_showing(self.0);
+        }
+    }
+}
+
+impl Drop for OwnedSource {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe {
+                sys::obs_source_release(self.0);
+            }
+        }
+    }
+}
+
+unsafe impl Send for OwnedSource {}
+unsafe impl Sync for OwnedSource {}
+
+/// Weak reference to a `sys::obs_source_t`, which doesn't keep it alive
+#[derive(Debug)]
+pub struct WeakSource(*mut sys::obs_weak_source_t);
+
+impl WeakSource {
+    pub fn new(source: *mut sys::obs_source_t) -> Option<Self> {
+        let p = unsafe { sys::obs_source_get_weak_source(source) };
+        if p.is_null() {
+            None
+        } else {
+            Some(WeakSource(p))
+        }
+    }
+
+    /// A strong reference to the source, unless it's being destroyed
+    pub fn upgrade(&self) -> Option<OwnedSource> {
+        unsafe { OwnedSource::from_owned_raw(sys::obs_weak_source_get_source(self.0)) }
+    }
+
+    pub fn references(&self, source: *mut sys::obs_source_t) -> bool {
+        unsafe { sys::obs_weak_source_references_source(self.0, source) }
+    }
+}
+
+impl Drop for WeakSource {
+    fn drop(&mut self) {
+        unsafe {
+            sys::obs_weak_source_release(self.0);
+        }
+    }
+}
+
+unsafe impl Send for WeakSource {}
+unsafe impl Sync for WeakSource {}
@@ -0,0 +1,44 @@
+use std::ffi::{
+    CStr,
+    CString,
+};
+
+/// Longest application property string we read, including the terminating nul
+const MAX_PROPERTY_LENGTH: usize = 256;
+
+/// Longest application key, including the terminating nul (`k_unMaxApplicationKeyLength`)
+const MAX_APPLICATION_KEY_LENGTH: usize = 128;
+
+/// Key of the application SteamVR considers the current scene application, like
+/// `steam.app.620`, from its scene process ID. `None` if there is none, or it isn't registered
+/// with SteamVR.
+pub fn scene_application_key() -> Option<CString> {
+    let mut key: [libc::c_char; MAX_APPLICATION_KEY_LENGTH] = [0; MAX_APPLICATION_KEY_LENGTH];
+    let found = unsafe { openvr_utils_get_scene_application_key(key.as_mut_ptr(), key.len() as u32) };
+    if !found {
+        return None;
+    }
+    // Terminated even if the key was truncated
+    key[MAX_APPLICATION_KEY_LENGTH - 1] = 0;
+    let key = unsafe { CStr::from_ptr(key.as_ptr()) };
+    Some(key.to_owned())
+        .filter(|key| !key.to_bytes().is_empty())
+}
+
+/// Display name of the application registered with `key`, from its manifest
+pub fn application_name(key: &CStr) -> Option<CString> {
+    let mut name: [libc::c_char; MAX_PROPERTY_LENGTH] = [0; MAX_PROPERTY_LENGTH];
+    let found = unsafe { openvr_utils_get_application_name(key.as_ptr(), name.as_mut_ptr(), name.len() as u32) };
+    if !found {
+        return None;
+    }
+    name[MAX_PROPERTY_LENGTH - 1] = 0;
+    let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+    Some(name.to_owned())
+        .filter(|name| !name.to_bytes().is_empty())
+}
+
+extern "C" {
+    fn openvr_utils_get_scene_application_key(key: *mut libc::c_char, length: u32) -> bool;
+    fn openvr_utils_get_application_name(key: *const libc::c_char, name: *mut libc::c_char, length: u32) -> bool;
+}
@@ -73,10 +73,35 @@ pub unsafe fn get_mirror_texture_gl(eye: sys::EVREye) -> Result<MirrorTextureInf
         .map(|_| info)
 }
 
+/// Whether the application SteamVR is switching to is still loading, so the compositor shows its
+/// loading environment instead of the application's frames
+pub fn is_scene_focus_app_loading() -> bool {
+    unsafe { openvr_utils_compositor_is_scene_focus_app_loading() }
+}
+
+/// Opacity of the color the compositor is fading the scene to, from 0 (not faded) to 1 (the scene
+/// is hidden)
+pub fn fade_alpha() -> f32 {
+    let alpha = unsafe { openvr_utils_compositor_get_fade_alpha() };
+    if alpha.is_finite() {
+        alpha.max(0.0).min(1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Whether the compositor is showing a transition between applications, either the loading
+/// environment or a mostly faded out scene, instead of an application's frames
+pub fn in_transition() -> bool {
+    is_scene_focus_app_loading() || fade_alpha() >= 0.5
+}
+
 extern "C" {
     pub fn obs_openvr_vrcompositor_getmirrortexturegl(eye: sys::EVREye, tex_id: *mut sys::glUInt_t, tex_handle: *mut sys::glSharedTextureHandle_t) -> sys::EVRCompositorError;
     #[allow(dead_code)]
     fn obs_openvr_vrcompositor_locksharedgltexture(handle: sys::glSharedTextureHandle_t);
     #[allow(dead_code)]
     fn obs_openvr_vrcompositor_unlocksharedgltexture(handle: sys::glSharedTextureHandle_t);
+    fn openvr_utils_compositor_is_scene_focus_app_loading() -> bool;
+    fn openvr_utils_compositor_get_fade_alpha() -> f32;
 }
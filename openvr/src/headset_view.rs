@@ -27,6 +27,56 @@ impl HeadsetView {
             openvr_utils_headset_view_get_mode(self.0)
         }
     }
+
+    #[inline]
+    pub fn mode(&self) -> Option<HeadsetViewMode> {
+        HeadsetViewMode::from_raw(self.get_mode())
+    }
+
+    pub fn set_size(&self, width: u32, height: u32) {
+        unsafe {
+            openvr_utils_headset_view_set_size(self.0, width, height);
+        }
+    }
+
+    pub fn set_mode(&self, mode: HeadsetViewMode) {
+        unsafe {
+            openvr_utils_headset_view_set_mode(self.0, mode as sys::HeadsetViewMode_t);
+        }
+    }
+
+    pub fn get_cropped(&self) -> bool {
+        unsafe {
+            openvr_utils_headset_view_get_cropped(self.0)
+        }
+    }
+
+    pub fn set_cropped(&self, cropped: bool) {
+        unsafe {
+            openvr_utils_headset_view_set_cropped(self.0, cropped);
+        }
+    }
+}
+
+/// Which eye(s) SteamVR shows in its "VR View" window
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadsetViewMode {
+    Left = 0,
+    Right = 1,
+    Both = 2,
+}
+
+impl HeadsetViewMode {
+    pub fn from_raw(mode: sys::HeadsetViewMode_t) -> Option<Self> {
+        use HeadsetViewMode::*;
+        match mode {
+            0 => Some(Left),
+            1 => Some(Right),
+            2 => Some(Both),
+            _ => None,
+        }
+    }
 }
 
 #[repr(C)]
@@ -41,4 +91,8 @@ extern "C" {
     fn openvr_utils_headset_view_get_size(headset_view: *mut libc::c_void) -> HeadsetViewSize;
     fn openvr_utils_headset_view_get_aspect_ratio(headset_view: *mut libc::c_void) -> f32;
     fn openvr_utils_headset_view_get_mode(headset_view: *mut libc::c_void) -> sys::HeadsetViewMode_t;
+    fn openvr_utils_headset_view_set_size(headset_view: *mut libc::c_void, width: u32, height: u32);
+    fn openvr_utils_headset_view_set_mode(headset_view: *mut libc::c_void, mode: sys::HeadsetViewMode_t);
+    fn openvr_utils_headset_view_get_cropped(headset_view: *mut libc::c_void) -> bool;
+    fn openvr_utils_headset_view_set_cropped(headset_view: *mut libc::c_void, cropped: bool);
 }
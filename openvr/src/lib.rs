@@ -7,7 +7,10 @@ pub mod error_ext;
 pub mod util;
 pub mod compositor;
 pub mod headset_view;
+pub mod system;
 pub mod overlay;
+pub mod applications;
+pub mod pose;
 
 use error_ext::{
     ErrorType,
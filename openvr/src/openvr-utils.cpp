@@ -4,6 +4,8 @@
 #include <vector>
 #include <cstdlib>
 #include <memory>
+#include <algorithm>
+#include <cstring>
 
 openvr_utils::headset_view_size::headset_view_size(vr::IVRHeadsetView *headset_view):
 	m_width(0), m_height(0)
@@ -38,20 +40,239 @@ void obs_openvr_shutdown_openvr()
 
 vr::EVRCompositorError obs_openvr_vrcompositor_getmirrortexturegl(vr::EVREye eye, vr::glUInt_t *tex_id, vr::glSharedTextureHandle_t *tex_handle)
 {
-	return vr::VRCompositor()->GetMirrorTextureGL(eye, tex_id, tex_handle);
+	auto compositor = vr::VRCompositor();
+	if (compositor == nullptr) {
+		return vr::VRCompositorError_RequestFailed;
+	}
+	return compositor->GetMirrorTextureGL(eye, tex_id, tex_handle);
 }
 
 bool obs_openvr_vrcompositor_releasesharedgltexture(vr::glUInt_t id, vr::glSharedTextureHandle_t handle)
 {
-	return vr::VRCompositor()->ReleaseSharedGLTexture(id, handle);
+	// Textures outliving the session, after SteamVR quit, went away with it
+	auto compositor = vr::VRCompositor();
+	if (compositor == nullptr) {
+		return false;
+	}
+	return compositor->ReleaseSharedGLTexture(id, handle);
 }
 void obs_openvr_vrcompositor_locksharedgltexture(vr::glSharedTextureHandle_t handle)
 {
-	vr::VRCompositor()->LockGLSharedTextureForAccess(handle);
+	auto compositor = vr::VRCompositor();
+	if (compositor == nullptr) {
+		return;
+	}
+	compositor->LockGLSharedTextureForAccess(handle);
 }
 void obs_openvr_vrcompositor_unlocksharedgltexture(vr::glSharedTextureHandle_t handle)
 {
-	vr::VRCompositor()->UnlockGLSharedTextureForAccess(handle);
+	auto compositor = vr::VRCompositor();
+	if (compositor == nullptr) {
+		return;
+	}
+	compositor->UnlockGLSharedTextureForAccess(handle);
+}
+
+bool openvr_utils_compositor_is_scene_focus_app_loading()
+{
+	auto compositor = vr::VRCompositor();
+	if (compositor == nullptr) {
+		return false;
+	}
+	return compositor->IsCurrentSceneFocusAppLoading();
+}
+
+float openvr_utils_compositor_get_fade_alpha()
+{
+	auto compositor = vr::VRCompositor();
+	if (compositor == nullptr) {
+		return 0.0f;
+	}
+	return compositor->GetCurrentFadeColor(false).a;
+}
+
+bool openvr_utils_get_time_since_last_vsync(float *seconds_since_last_vsync, uint64_t *frame_counter)
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return false;
+	}
+	return system->GetTimeSinceLastVsync(seconds_since_last_vsync, frame_counter);
+}
+
+float openvr_utils_get_display_frequency()
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return 0.0f;
+	}
+	return system->GetFloatTrackedDeviceProperty(vr::k_unTrackedDeviceIndex_Hmd, vr::Prop_DisplayFrequency_Float);
+}
+
+int32_t openvr_utils_get_hmd_activity_level()
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return -1;
+	}
+	return system->GetTrackedDeviceActivityLevel(vr::k_unTrackedDeviceIndex_Hmd);
+}
+
+int32_t openvr_utils_get_tracked_device_class(uint32_t index)
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return -1;
+	}
+	return system->GetTrackedDeviceClass(index);
+}
+
+int32_t openvr_utils_get_controller_role(uint32_t index)
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return -1;
+	}
+	return system->GetControllerRoleForTrackedDeviceIndex(index);
+}
+
+uint32_t openvr_utils_get_device_for_controller_role(int32_t role)
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return vr::k_unTrackedDeviceIndexInvalid;
+	}
+	return system->GetTrackedDeviceIndexForControllerRole(static_cast<vr::ETrackedControllerRole>(role));
+}
+
+bool openvr_utils_get_battery(uint32_t index, float *level, bool *charging)
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return false;
+	}
+	vr::ETrackedPropertyError e = vr::TrackedProp_Success;
+	if (!system->GetBoolTrackedDeviceProperty(index, vr::Prop_DeviceProvidesBatteryStatus_Bool, &e) || e != vr::TrackedProp_Success) {
+		return false;
+	}
+	*level = system->GetFloatTrackedDeviceProperty(index, vr::Prop_DeviceBatteryPercentage_Float, &e);
+	if (e != vr::TrackedProp_Success) {
+		return false;
+	}
+	*charging = system->GetBoolTrackedDeviceProperty(index, vr::Prop_DeviceIsCharging_Bool, &e);
+	if (e != vr::TrackedProp_Success) {
+		*charging = false;
+	}
+	return true;
+}
+
+bool openvr_utils_get_controller_state(uint32_t index, openvr_utils_controller_state *state)
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return false;
+	}
+	vr::VRControllerState_t raw;
+	if (!system->GetControllerState(index, &raw, sizeof(raw))) {
+		return false;
+	}
+	state->pressed = raw.ulButtonPressed;
+	state->touched = raw.ulButtonTouched;
+	for (uint32_t i = 0; i < vr::k_unControllerStateAxisCount; i++) {
+		state->axes[i][0] = raw.rAxis[i].x;
+		state->axes[i][1] = raw.rAxis[i].y;
+	}
+	return true;
+}
+
+bool openvr_utils_poll_next_event(openvr_utils_event *event)
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return false;
+	}
+	vr::VREvent_t e;
+	if (!system->PollNextEvent(&e, sizeof(e))) {
+		return false;
+	}
+	event->event_type = e.eventType;
+	event->device_index = e.trackedDeviceIndex;
+	event->process_id = e.data.process.pid;
+	return true;
+}
+
+void openvr_utils_acknowledge_quit()
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return;
+	}
+	system->AcknowledgeQuit_Exiting();
+}
+
+bool openvr_utils_get_device_poses(int32_t universe, float predicted_seconds, openvr_utils_pose *poses, uint32_t count)
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return false;
+	}
+	vr::TrackedDevicePose_t raw[vr::k_unMaxTrackedDeviceCount];
+	count = std::min(count, vr::k_unMaxTrackedDeviceCount);
+	system->GetDeviceToAbsoluteTrackingPose(static_cast<vr::ETrackingUniverseOrigin>(universe), predicted_seconds, raw, count);
+	for (uint32_t i = 0; i < count; i++) {
+		std::memcpy(poses[i].matrix, raw[i].mDeviceToAbsoluteTracking.m, sizeof(poses[i].matrix));
+		std::memcpy(poses[i].velocity, raw[i].vVelocity.v, sizeof(poses[i].velocity));
+		std::memcpy(poses[i].angular_velocity, raw[i].vAngularVelocity.v, sizeof(poses[i].angular_velocity));
+		poses[i].tracking_result = raw[i].eTrackingResult;
+		poses[i].valid = raw[i].bPoseIsValid;
+		poses[i].connected = raw[i].bDeviceIsConnected;
+	}
+	return true;
+}
+
+bool openvr_utils_get_eye_to_head_transform(int32_t eye, float matrix[3][4])
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return false;
+	}
+	vr::HmdMatrix34_t raw = system->GetEyeToHeadTransform(static_cast<vr::EVREye>(eye));
+	std::memcpy(matrix, raw.m, sizeof(raw.m));
+	return true;
+}
+
+bool openvr_utils_get_projection_raw(int32_t eye, float *left, float *right, float *top, float *bottom)
+{
+	auto system = vr::VRSystem();
+	if (system == nullptr) {
+		return false;
+	}
+	system->GetProjectionRaw(static_cast<vr::EVREye>(eye), left, right, top, bottom);
+	return true;
+}
+
+bool openvr_utils_get_scene_application_key(char *key, uint32_t length)
+{
+	auto applications = vr::VRApplications();
+	if (applications == nullptr) {
+		return false;
+	}
+	uint32_t pid = applications->GetCurrentSceneProcessId();
+	if (pid == 0) {
+		return false;
+	}
+	return applications->GetApplicationKeyByProcessId(pid, key, length) == vr::VRApplicationError_None;
+}
+
+bool openvr_utils_get_application_name(const char *key, char *name, uint32_t length)
+{
+	auto applications = vr::VRApplications();
+	if (applications == nullptr) {
+		return false;
+	}
+	vr::EVRApplicationError e = vr::VRApplicationError_None;
+	applications->GetApplicationPropertyString(key, vr::VRApplicationProperty_Name_String, name, length, &e);
+	return e == vr::VRApplicationError_None;
 }
 
 openvr_utils::headset_view_size openvr_utils_headset_view_get_size(vr::IVRHeadsetView *headset_view)
@@ -70,6 +291,26 @@ vr::HeadsetViewMode_t openvr_utils_headset_view_get_mode(vr::IVRHeadsetView *hea
 	return headset_view->GetHeadsetViewMode();
 }
 
+void openvr_utils_headset_view_set_size(vr::IVRHeadsetView *headset_view, uint32_t width, uint32_t height)
+{
+	headset_view->SetHeadsetViewSize(width, height);
+}
+
+void openvr_utils_headset_view_set_mode(vr::IVRHeadsetView *headset_view, vr::HeadsetViewMode_t mode)
+{
+	headset_view->SetHeadsetViewMode(mode);
+}
+
+bool openvr_utils_headset_view_get_cropped(vr::IVRHeadsetView *headset_view)
+{
+	return headset_view->GetHeadsetViewCropped();
+}
+
+void openvr_utils_headset_view_set_cropped(vr::IVRHeadsetView *headset_view, bool cropped)
+{
+	headset_view->SetHeadsetViewCropped(cropped);
+}
+
 vr::EVROverlayError openvr_utils_find_overlay(const char *key, vr::VROverlayHandle_t *handle)
 {
 	return vr::VROverlay()->FindOverlay(key, handle);
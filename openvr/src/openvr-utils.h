@@ -34,17 +34,60 @@ extern "C" {
 		uint32_t height;
 	};
 
+	struct openvr_utils_event {
+		uint32_t event_type;
+		uint32_t device_index;
+		uint32_t process_id;
+	};
+
+	struct openvr_utils_pose {
+		float matrix[3][4];
+		float velocity[3];
+		float angular_velocity[3];
+		int32_t tracking_result;
+		bool valid;
+		bool connected;
+	};
+
+	struct openvr_utils_controller_state {
+		uint64_t pressed;
+		uint64_t touched;
+		float axes[5][2];
+	};
+
 	void obs_openvr_init_openvr(vr::EVRInitError *e, vr::EVRApplicationType application_type);
 	void obs_openvr_shutdown_openvr();
 	vr::EVRCompositorError obs_openvr_vrcompositor_getmirrortexturegl(vr::EVREye eye, vr::glUInt_t *tex_id, vr::glSharedTextureHandle_t *tex_handle);
 	bool obs_openvr_vrcompositor_releasesharedgltexture(vr::glUInt_t id, vr::glSharedTextureHandle_t handle);
 	void obs_openvr_vrcompositor_locksharedgltexture(vr::glSharedTextureHandle_t handle);
 	void obs_openvr_vrcompositor_unlocksharedgltexture(vr::glSharedTextureHandle_t handle);
+	bool openvr_utils_compositor_is_scene_focus_app_loading();
+	float openvr_utils_compositor_get_fade_alpha();
+
+	bool openvr_utils_get_time_since_last_vsync(float *seconds_since_last_vsync, uint64_t *frame_counter);
+	float openvr_utils_get_display_frequency();
+	int32_t openvr_utils_get_hmd_activity_level();
+	int32_t openvr_utils_get_tracked_device_class(uint32_t index);
+	int32_t openvr_utils_get_controller_role(uint32_t index);
+	uint32_t openvr_utils_get_device_for_controller_role(int32_t role);
+	bool openvr_utils_get_battery(uint32_t index, float *level, bool *charging);
+	bool openvr_utils_get_controller_state(uint32_t index, openvr_utils_controller_state *state);
+	bool openvr_utils_poll_next_event(openvr_utils_event *event);
+	void openvr_utils_acknowledge_quit();
+	bool openvr_utils_get_device_poses(int32_t universe, float predicted_seconds, openvr_utils_pose *poses, uint32_t count);
+	bool openvr_utils_get_eye_to_head_transform(int32_t eye, float matrix[3][4]);
+	bool openvr_utils_get_projection_raw(int32_t eye, float *left, float *right, float *top, float *bottom);
+	bool openvr_utils_get_scene_application_key(char *key, uint32_t length);
+	bool openvr_utils_get_application_name(const char *key, char *name, uint32_t length);
 
 	vr::IVRHeadsetView *openvr_utils_get_headset_view();
 	openvr_utils::headset_view_size openvr_utils_headset_view_get_size(vr::IVRHeadsetView *headset_view);
 	float openvr_utils_headset_view_get_aspect_ratio(vr::IVRHeadsetView *headset_view);
 	vr::HeadsetViewMode_t openvr_utils_headset_view_get_mode(vr::IVRHeadsetView *headset_view);
+	void openvr_utils_headset_view_set_size(vr::IVRHeadsetView *headset_view, uint32_t width, uint32_t height);
+	void openvr_utils_headset_view_set_mode(vr::IVRHeadsetView *headset_view, vr::HeadsetViewMode_t mode);
+	bool openvr_utils_headset_view_get_cropped(vr::IVRHeadsetView *headset_view);
+	void openvr_utils_headset_view_set_cropped(vr::IVRHeadsetView *headset_view, bool cropped);
 	vr::EVROverlayError openvr_utils_find_overlay(const char *key, vr::VROverlayHandle_t *handle);
 	vr::EVROverlayError openvr_utils_get_overlay_image_data(vr::VROverlayHandle_t handle, openvr_utils::OverlayImageData **data);
 	void openvr_utils_overlay_image_data_destroy(openvr_utils::OverlayImageData *data);
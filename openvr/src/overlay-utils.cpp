@@ -71,3 +71,23 @@ bool openvrs_is_overlay_visible(vr::VROverlayHandle_t handle)
 	}
 	return vroverlay->IsOverlayVisible(handle);
 }
+bool openvrs_is_overlay_premultiplied(vr::VROverlayHandle_t handle)
+{
+	auto vroverlay = vr::VROverlay();
+	if (vroverlay == nullptr) {
+		return false;
+	}
+	bool enabled = false;
+	if (vroverlay->GetOverlayFlag(handle, vr::VROverlayFlags_IsPremultiplied, &enabled) != vr::VROverlayError_None) {
+		return false;
+	}
+	return enabled;
+}
+bool openvrs_is_dashboard_visible()
+{
+	auto vroverlay = vr::VROverlay();
+	if (vroverlay == nullptr) {
+		return false;
+	}
+	return vroverlay->IsDashboardVisible();
+}
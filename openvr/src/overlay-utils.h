@@ -36,4 +36,6 @@ extern "C" {
 	vr::EVROverlayError openvrs_overlay_image_fill(openvrs::OverlayImage *image, vr::VROverlayHandle_t handle);
 	openvrs_overlay_image_data openvrs_overlay_image_get_data(openvrs::OverlayImage *image);
 	bool openvrs_is_overlay_visible(vr::VROverlayHandle_t handle);
+	bool openvrs_is_overlay_premultiplied(vr::VROverlayHandle_t handle);
+	bool openvrs_is_dashboard_visible();
 }
@@ -34,6 +34,45 @@ impl OverlayRef {
     pub fn is_visible(&self) -> bool {
         unsafe { openvrs_is_overlay_visible(self.handle()) }
     }
+
+    /// Whether the overlay's texture has premultiplied alpha (`VROverlayFlags_IsPremultiplied`)
+    #[inline]
+    pub fn is_premultiplied(&self) -> bool {
+        unsafe { openvrs_is_overlay_premultiplied(self.handle()) }
+    }
+}
+
+/// Order of the color channels in each 4-byte pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelOrder {
+    Rgba,
+    Bgra,
+}
+
+/// How color channels relate to the alpha channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Color channels are independent of alpha
+    Straight,
+    /// Color channels have already been multiplied by alpha
+    Premultiplied,
+}
+
+/// Layout of the pixels in an `OverlayImage`, always 4 bytes per pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelFormat {
+    pub order: ChannelOrder,
+    pub alpha: AlphaMode,
+}
+
+impl PixelFormat {
+    pub const BYTES_PER_PIXEL: u32 = 4;
+
+    /// Format of the data returned by `IVROverlay::GetOverlayImageData`
+    pub const OVERLAY_DATA: PixelFormat = PixelFormat {
+        order: ChannelOrder::Rgba,
+        alpha: AlphaMode::Straight,
+    };
 }
 
 impl From<sys::VROverlayHandle_t> for OverlayRef {
@@ -53,6 +92,11 @@ pub fn find_overlay<K: AsRef<CStr>>(k: K) -> Result<OverlayRef, sys::EVROverlayE
     e.into_result().map(move |_| OverlayRef::from(handle))
 }
 
+/// Whether the SteamVR dashboard is open
+pub fn is_dashboard_visible() -> bool {
+    unsafe { openvrs_is_dashboard_visible() }
+}
+
 #[repr(C)]
 struct OverlayImageInfo {
     width: u32,
@@ -123,7 +167,27 @@ impl Drop for OverlayImageData {
     }
 }
 
-pub struct OverlayImage(*mut libc::c_void);
+/// Cheap (FNV-1a over 64-bit words) hash of image data, used to detect unchanged overlay content
+fn content_hash(dimensions: (u32, u32), data: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    let step = |hash: u64, word: u64| (hash ^ word).wrapping_mul(PRIME);
+    let hash = step(OFFSET, ((dimensions.0 as u64) << 32) | dimensions.1 as u64);
+    let mut chunks = data.chunks_exact(8);
+    let hash = chunks.by_ref().fold(hash, |hash, chunk| {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(chunk);
+        step(hash, u64::from_ne_bytes(word))
+    });
+    chunks.remainder().iter().fold(hash, |hash, &b| step(hash, b as u64))
+}
+
+pub struct OverlayImage {
+    image: *mut libc::c_void,
+    content_hash: Option<u64>,
+    changed: bool,
+    format: PixelFormat,
+}
 
 impl OverlayImage {
     #[inline(always)]
@@ -131,20 +195,57 @@ impl OverlayImage {
         let p = unsafe {
             openvrs_overlay_image_create()
         };
-        OverlayImage(p)
+        OverlayImage {
+            image: p,
+            content_hash: None,
+            changed: false,
+            format: PixelFormat::OVERLAY_DATA,
+        }
     }
 
+    /// Fills the image with the current contents of an overlay, and compares them against the
+    /// previous fill (see `changed`)
     pub fn fill(&mut self, handle: sys::VROverlayHandle_t) -> Result<(), sys::EVROverlayError> {
         let status = unsafe {
-            openvrs_overlay_image_fill(self.0, handle)
+            openvrs_overlay_image_fill(self.image, handle)
         };
-        status.into_empty_result()
+        let ret = status.into_empty_result();
+        if ret.is_ok() {
+            let premultiplied = unsafe { openvrs_is_overlay_premultiplied(handle) };
+            self.format = PixelFormat {
+                alpha: if premultiplied { AlphaMode::Premultiplied } else { AlphaMode::Straight },
+                ..PixelFormat::OVERLAY_DATA
+            };
+            let hash = content_hash(self.dimensions(), self.data());
+            self.changed = self.content_hash != Some(hash);
+            self.content_hash = Some(hash);
+        }
+        ret
+    }
+
+    /// Whether the last successful `fill` produced different content than the one before it. The
+    /// first fill always counts as changed.
+    #[inline(always)]
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+
+    /// Forgets the previous contents, so the next `fill` counts as changed
+    #[inline]
+    pub fn invalidate(&mut self) {
+        self.content_hash = None;
+    }
+
+    /// Format of the data from the last successful `fill`
+    #[inline(always)]
+    pub fn format(&self) -> PixelFormat {
+        self.format
     }
 
     #[inline(always)]
     fn info(&self) -> OverlayImageInfo {
         unsafe {
-            openvrs_overlay_image_get_data(self.0)
+            openvrs_overlay_image_get_data(self.image)
         }
     }
 
@@ -153,21 +254,45 @@ impl OverlayImage {
         (info.width, info.height)
     } 
 
+    #[inline]
+    fn data_len(info: &OverlayImageInfo) -> usize {
+        let image_len = info.width as usize * info.height as usize * PixelFormat::BYTES_PER_PIXEL as usize;
+        image_len.min(info.length)
+    }
+
     pub fn data<'a>(&'a self) -> &'a [u8] {
         let info = self.info();
+        if info.data.is_null() {
+            return &[];
+        }
         unsafe {
-            slice::from_raw_parts(info.data, info.length)
+            slice::from_raw_parts(info.data, Self::data_len(&info))
+        }
+    }
+
+    /// Mutable access to the image data, for converting it in place. `format` keeps describing
+    /// the data as it was filled.
+    pub fn data_mut<'a>(&'a mut self) -> &'a mut [u8] {
+        let info = self.info();
+        if info.data.is_null() {
+            return &mut [];
+        }
+        unsafe {
+            slice::from_raw_parts_mut(info.data, Self::data_len(&info))
         }
     }
 }
 
+// The image buffer is owned by this struct alone, and not tied to any thread
+unsafe impl Send for OverlayImage {}
+
 impl Drop for OverlayImage {
     fn drop(&mut self) {
-        if self.0.is_null() {
+        if self.image.is_null() {
             return;
         }
         unsafe {
-            openvrs_overlay_image_destroy(self.0);
+            openvrs_overlay_image_destroy(self.image);
         }
     }
 }
@@ -178,6 +303,8 @@ extern "C" {
     fn openvrs_overlay_image_fill(image: *mut libc::c_void, handle: sys::VROverlayHandle_t) -> sys::EVROverlayError;
     fn openvrs_overlay_image_get_data(image: *mut libc::c_void) -> OverlayImageInfo;
     fn openvrs_is_overlay_visible(handle: sys::VROverlayHandle_t) -> bool;
+    fn openvrs_is_overlay_premultiplied(handle: sys::VROverlayHandle_t) -> bool;
+    fn openvrs_is_dashboard_visible() -> bool;
     fn openvr_utils_find_overlay(key: *const libc::c_char, handle: *mut sys::VROverlayHandle_t) -> sys::EVROverlayError;
     fn openvr_utils_get_overlay_image_data(handle: sys::VROverlayHandle_t, data: *mut OverlayImageData) -> sys::EVROverlayError;
     fn openvr_utils_overlay_image_data_destroy(data: *mut libc::c_void);
@@ -194,4 +321,14 @@ mod tests {
         let image = OverlayImage::new();
         assert_eq!(image.data().len(), 0);
     }
+
+    #[test]
+    fn content_hash_detects_changes() {
+        let data = vec![0x7fu8; 4 * 3 * 5];
+        let mut modified = data.clone();
+        modified[data.len() - 1] = 0;
+        assert_eq!(content_hash((3, 5), &data), content_hash((3, 5), &data));
+        assert_ne!(content_hash((3, 5), &data), content_hash((3, 5), &modified));
+        assert_ne!(content_hash((3, 5), &data), content_hash((5, 3), &data));
+    }
 }
@@ -0,0 +1,230 @@
+//! Tracked device poses, from `IVRSystem::GetDeviceToAbsoluteTrackingPose`
+
+/// Number of tracked device indices (`k_unMaxTrackedDeviceCount`)
+pub const MAX_DEVICES: usize = 64;
+
+/// Origin poses are relative to, see `ETrackingUniverseOrigin`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackingUniverse {
+    /// Relative to the seated zero pose
+    Seated = 0,
+    /// Relative to the center of the play area, on the floor
+    Standing = 1,
+}
+
+/// Rotation as a unit quaternion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// Pose of a tracked device, see `TrackedDevicePose_t`. Positions are in meters, in a right-handed
+/// coordinate system with +Y up and -Z forward.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct Pose {
+    /// Rotation (the first three columns) and translation (the last) of the device
+    pub matrix: [[f32; 4]; 3],
+    /// Meters per second
+    pub velocity: [f32; 3],
+    /// Radians per second
+    pub angular_velocity: [f32; 3],
+    /// Raw `ETrackingResult`
+    pub tracking_result: i32,
+    /// Whether the rest is meaningful
+    pub valid: bool,
+    pub connected: bool,
+}
+
+impl Pose {
+    const EMPTY: Pose = Pose {
+        matrix: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]],
+        velocity: [0.0; 3],
+        angular_velocity: [0.0; 3],
+        tracking_result: 0,
+        valid: false,
+        connected: false,
+    };
+
+    #[inline]
+    pub fn position(&self) -> [f32; 3] {
+        [self.matrix[0][3], self.matrix[1][3], self.matrix[2][3]]
+    }
+
+    pub fn rotation(&self) -> Quaternion {
+        let m = &self.matrix;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let (w, x, y, z) = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            (0.25 * s, (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            ((m[2][1] - m[1][2]) / s, 0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            ((m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s)
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            ((m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s)
+        };
+        Quaternion {
+            w: w,
+            x: x,
+            y: y,
+            z: z,
+        }
+    }
+
+    /// Rotation as Euler angles in radians, about the Z, then X, then Y axes (`R = Rz * Rx * Ry`).
+    /// Returned as `[z, x, y]`. Z and Y are ambiguous when X is ±90°.
+    pub fn euler_zxy(&self) -> [f32; 3] {
+        let m = &self.matrix;
+        let x = m[2][1].max(-1.0).min(1.0).asin();
+        let z = (-m[0][1]).atan2(m[1][1]);
+        let y = (-m[2][0]).atan2(m[2][2]);
+        [z, x, y]
+    }
+
+    /// Yaw (about +Y), pitch (about +X) and roll (about +Z) in radians, as `[yaw, pitch, roll]`.
+    /// Yaw is positive turning left, pitch positive looking up.
+    pub fn yaw_pitch_roll(&self) -> [f32; 3] {
+        let m = &self.matrix;
+        // R = Ry * Rx * Rz
+        let pitch = (-m[1][2]).max(-1.0).min(1.0).asin();
+        let yaw = m[0][2].atan2(m[2][2]);
+        let roll = m[1][0].atan2(m[1][1]);
+        [yaw, pitch, roll]
+    }
+
+    /// This pose as seen from `origin`, like a controller's pose in the headset's own coordinate
+    /// system. Only valid if both poses are.
+    pub fn relative_to(&self, origin: &Pose) -> Pose {
+        let (m, o) = (&self.matrix, &origin.matrix);
+        let offset = [m[0][3] - o[0][3], m[1][3] - o[1][3], m[2][3] - o[2][3]];
+        let mut matrix = [[0.0; 4]; 3];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            // Rows of the origin's inverse rotation are its rotation's columns
+            for j in 0..3 {
+                row[j] = (0..3).map(|k| o[k][i] * m[k][j]).sum();
+            }
+            row[3] = (0..3).map(|k| o[k][i] * offset[k]).sum();
+        }
+        Pose {
+            matrix: matrix,
+            valid: self.valid && origin.valid,
+            ..*self
+        }
+    }
+}
+
+/// Poses of all tracked devices, indexed by device index, as predicted `predicted_seconds` from
+/// now. `None` if OpenVR isn't initialized.
+pub fn device_poses(universe: TrackingUniverse, predicted_seconds: f32) -> Option<Vec<Pose>> {
+    let mut poses = vec![Pose::EMPTY; MAX_DEVICES];
+    let found = unsafe {
+        openvr_utils_get_device_poses(universe as i32, predicted_seconds, poses.as_mut_ptr(), poses.len() as u32)
+    };
+    if found {
+        Some(poses)
+    } else {
+        None
+    }
+}
+
+/// One of the headset's eyes, see `EVREye`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eye {
+    Left = 0,
+    Right = 1,
+}
+
+/// Pose of `eye` relative to the headset, from `IVRSystem::GetEyeToHeadTransform`. `None` if
+/// OpenVR isn't initialized.
+pub fn eye_to_head(eye: Eye) -> Option<Pose> {
+    let mut pose = Pose {
+        valid: true,
+        connected: true,
+        ..Pose::EMPTY
+    };
+    if unsafe { openvr_utils_get_eye_to_head_transform(eye as i32, pose.matrix.as_mut_ptr()) } {
+        Some(pose)
+    } else {
+        None
+    }
+}
+
+/// Tangents of the angles from `eye`'s view direction to the left, right, top and bottom edges of
+/// its view, from `IVRSystem::GetProjectionRaw`, as `[left, right, top, bottom]`. `None` if
+/// OpenVR isn't initialized.
+pub fn projection_raw(eye: Eye) -> Option<[f32; 4]> {
+    let mut ret = [0.0f32; 4];
+    let found = unsafe {
+        let [left, right, top, bottom] = &mut ret;
+        openvr_utils_get_projection_raw(eye as i32, left as *mut _, right as *mut _, top as *mut _, bottom as *mut _)
+    };
+    if found {
+        Some(ret)
+    } else {
+        None
+    }
+}
+
+extern "C" {
+    fn openvr_utils_get_device_poses(universe: i32, predicted_seconds: f32, poses: *mut Pose, count: u32) -> bool;
+    fn openvr_utils_get_eye_to_head_transform(eye: i32, matrix: *mut [f32; 4]) -> bool;
+    fn openvr_utils_get_projection_raw(eye: i32, left: *mut f32, right: *mut f32, top: *mut f32, bottom: *mut f32) -> bool;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotated_about_y(angle: f32) -> Pose {
+        let (s, c) = angle.sin_cos();
+        Pose {
+            matrix: [[c, 0.0, s, 1.0], [0.0, 1.0, 0.0, 2.0], [-s, 0.0, c, 3.0]],
+            ..Pose::EMPTY
+        }
+    }
+
+    #[test]
+    fn rotation_about_y() {
+        let angle = std::f32::consts::FRAC_PI_2;
+        let pose = rotated_about_y(angle);
+        assert_eq!(pose.position(), [1.0, 2.0, 3.0]);
+        let q = pose.rotation();
+        let half = angle / 2.0;
+        assert!((q.w - half.cos()).abs() < 1e-6);
+        assert!((q.y - half.sin()).abs() < 1e-6);
+        assert!(q.x.abs() < 1e-6 && q.z.abs() < 1e-6);
+        let [z, x, y] = pose.euler_zxy();
+        assert!(z.abs() < 1e-6 && x.abs() < 1e-6 && (y - angle).abs() < 1e-6);
+        let [yaw, pitch, roll] = pose.yaw_pitch_roll();
+        assert!((yaw - angle).abs() < 1e-6 && pitch.abs() < 1e-6 && roll.abs() < 1e-6);
+    }
+
+    #[test]
+    fn relative_to_rotated_origin() {
+        let origin = rotated_about_y(std::f32::consts::FRAC_PI_2);
+        // One meter along the origin's -Z (forward), which is world -X after turning left
+        let pose = Pose {
+            matrix: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 2.0], [0.0, 0.0, 1.0, 3.0]],
+            ..Pose::EMPTY
+        };
+        let relative = pose.relative_to(&origin);
+        let [x, y, z] = relative.position();
+        assert!(x.abs() < 1e-6 && y.abs() < 1e-6 && (z + 1.0).abs() < 1e-6);
+        let [yaw, pitch, roll] = relative.yaw_pitch_roll();
+        assert!((yaw + std::f32::consts::FRAC_PI_2).abs() < 1e-6 && pitch.abs() < 1e-6 && roll.abs() < 1e-6);
+    }
+
+    #[test]
+    fn identity_rotation() {
+        let q = Pose::EMPTY.rotation();
+        assert_eq!((q.w, q.x, q.y, q.z), (1.0, 0.0, 0.0, 0.0));
+        assert_eq!(Pose::EMPTY.euler_zxy(), [0.0, 0.0, 0.0]);
+    }
+}
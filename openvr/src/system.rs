@@ -0,0 +1,232 @@
+use openvr_sys as sys;
+use std::time::Duration;
+
+/// Time since the last vsync of the HMD display, along with the number of frames since startup
+pub fn time_since_last_vsync() -> Option<(Duration, u64)> {
+    let mut seconds: f32 = 0.0;
+    let mut frame_counter: u64 = 0;
+    let valid = unsafe {
+        openvr_utils_get_time_since_last_vsync(&mut seconds as *mut _, &mut frame_counter as *mut _)
+    };
+    if !valid || !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+    Some((Duration::from_secs_f32(seconds), frame_counter))
+}
+
+/// Refresh rate of the HMD display, in Hz
+pub fn display_frequency() -> Option<f32> {
+    let frequency = unsafe { openvr_utils_get_display_frequency() };
+    if frequency.is_finite() && frequency > 0.0 {
+        Some(frequency)
+    } else {
+        None
+    }
+}
+
+/// Time until the next vsync of the HMD display
+pub fn time_until_next_vsync() -> Option<Duration> {
+    let period = Duration::from_secs_f32(1.0 / display_frequency()?);
+    let (since_last, _) = time_since_last_vsync()?;
+    let since_last = Duration::from_nanos((since_last.as_nanos() % period.as_nanos()) as u64);
+    Some(period - since_last)
+}
+
+/// How recently the user interacted with a device, see `EDeviceActivityLevel`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityLevel {
+    /// No activity for the last 10 seconds
+    Idle,
+    /// Activity (movement or the proximity sensor) is happening now
+    UserInteraction,
+    /// No activity for the last 0.5 seconds
+    UserInteractionTimeout,
+    /// Idle for at least 5 seconds, or the proximity sensor reports nobody wearing the headset
+    Standby,
+    /// Idle for long enough to be considered asleep
+    IdleTimeout,
+}
+
+impl ActivityLevel {
+    fn from_raw(level: i32) -> Option<Self> {
+        let ret = match level {
+            0 => ActivityLevel::Idle,
+            1 => ActivityLevel::UserInteraction,
+            2 => ActivityLevel::UserInteractionTimeout,
+            3 => ActivityLevel::Standby,
+            4 => ActivityLevel::IdleTimeout,
+            _ => return None,
+        };
+        Some(ret)
+    }
+}
+
+/// Activity level of the HMD, or `None` if OpenVR isn't initialized
+pub fn hmd_activity_level() -> Option<ActivityLevel> {
+    ActivityLevel::from_raw(unsafe { openvr_utils_get_hmd_activity_level() })
+}
+
+/// Kind of a tracked device, see `ETrackedDeviceClass`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceClass {
+    Hmd,
+    Controller,
+    GenericTracker,
+    /// A base station or other tracking camera
+    TrackingReference,
+    DisplayRedirect,
+}
+
+impl DeviceClass {
+    fn from_raw(class: i32) -> Option<Self> {
+        let ret = match class {
+            1 => DeviceClass::Hmd,
+            2 => DeviceClass::Controller,
+            3 => DeviceClass::GenericTracker,
+            4 => DeviceClass::TrackingReference,
+            5 => DeviceClass::DisplayRedirect,
+            _ => return None,
+        };
+        Some(ret)
+    }
+}
+
+/// Class of the tracked device at `index`, or `None` if there is no device there or OpenVR
+/// isn't initialized
+pub fn device_class(index: u32) -> Option<DeviceClass> {
+    DeviceClass::from_raw(unsafe { openvr_utils_get_tracked_device_class(index) })
+}
+
+/// Which hand a controller is in, see `ETrackedControllerRole`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerRole {
+    LeftHand,
+    RightHand,
+}
+
+impl ControllerRole {
+    fn from_raw(role: i32) -> Option<Self> {
+        match role {
+            1 => Some(ControllerRole::LeftHand),
+            2 => Some(ControllerRole::RightHand),
+            _ => None,
+        }
+    }
+}
+
+/// Hand the controller at `index` is in, or `None` if it isn't a controller assigned to a hand
+pub fn controller_role(index: u32) -> Option<ControllerRole> {
+    ControllerRole::from_raw(unsafe { openvr_utils_get_controller_role(index) })
+}
+
+/// Index of the controller in the `role` hand, if there is one
+pub fn device_for_role(role: ControllerRole) -> Option<u32> {
+    let raw = match role {
+        ControllerRole::LeftHand => 1,
+        ControllerRole::RightHand => 2,
+    };
+    let index = unsafe { openvr_utils_get_device_for_controller_role(raw) };
+    if (index as usize) < crate::pose::MAX_DEVICES {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+/// Battery charge of the device at `index`, from 0 to 1, and whether it's charging. `None` if the
+/// device doesn't report a battery status.
+pub fn battery(index: u32) -> Option<(f32, bool)> {
+    let mut level: f32 = 0.0;
+    let mut charging = false;
+    let valid = unsafe {
+        openvr_utils_get_battery(index, &mut level as *mut _, &mut charging as *mut _)
+    };
+    if valid && level.is_finite() {
+        Some((level, charging))
+    } else {
+        None
+    }
+}
+
+/// Buttons and axes of a controller, from the legacy input API (`VRControllerState_t`)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[repr(C)]
+pub struct ControllerState {
+    /// Bit mask of the pressed buttons, bit `n` being `EVRButtonId` `n`
+    pub pressed: u64,
+    pub touched: u64,
+    /// `[x, y]` of each axis, from -1 to 1 (or 0 to 1 for triggers)
+    pub axes: [[f32; 2]; 5],
+}
+
+/// Button and axis state of the controller at `index`, or `None` if it isn't a controller
+pub fn controller_state(index: u32) -> Option<ControllerState> {
+    let mut state = ControllerState::default();
+    if unsafe { openvr_utils_get_controller_state(index, &mut state as *mut _) } {
+        Some(state)
+    } else {
+        None
+    }
+}
+
+/// An event from `IVRSystem::PollNextEvent`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Event {
+    /// Raw `EVREventType`, which may be a value the bindings don't know about. Compare against
+    /// the constants in `event_type`.
+    pub event_type: u32,
+    pub device_index: u32,
+    /// Process the event is about, only meaningful for process events like
+    /// `SCENE_APPLICATION_CHANGED`
+    pub process_id: u32,
+}
+
+/// Values of `Event::event_type`
+pub mod event_type {
+    use super::sys::EVREventType;
+
+    pub const USER_INTERACTION_STARTED: u32 = EVREventType::VREvent_TrackedDeviceUserInteractionStarted as u32;
+    pub const USER_INTERACTION_ENDED: u32 = EVREventType::VREvent_TrackedDeviceUserInteractionEnded as u32;
+    pub const SCENE_APPLICATION_CHANGED: u32 = EVREventType::VREvent_SceneApplicationChanged as u32;
+    pub const DEVICE_ACTIVATED: u32 = EVREventType::VREvent_TrackedDeviceActivated as u32;
+    pub const DEVICE_DEACTIVATED: u32 = EVREventType::VREvent_TrackedDeviceDeactivated as u32;
+    pub const DASHBOARD_ACTIVATED: u32 = EVREventType::VREvent_DashboardActivated as u32;
+    pub const DASHBOARD_DEACTIVATED: u32 = EVREventType::VREvent_DashboardDeactivated as u32;
+    pub const SCREENSHOT_TAKEN: u32 = EVREventType::VREvent_ScreenshotTaken as u32;
+    /// SteamVR is shutting down
+    pub const QUIT: u32 = EVREventType::VREvent_Quit as u32;
+}
+
+/// Takes the next event off this process's OpenVR event queue
+pub fn poll_next_event() -> Option<Event> {
+    let mut event = Event {
+        event_type: 0,
+        device_index: 0,
+        process_id: 0,
+    };
+    if unsafe { openvr_utils_poll_next_event(&mut event as *mut _) } {
+        Some(event)
+    } else {
+        None
+    }
+}
+
+/// Tells SteamVR this process is ending its session after a `QUIT` event, so it doesn't wait for
+/// it to exit
+pub fn acknowledge_quit() {
+    unsafe { openvr_utils_acknowledge_quit(); }
+}
+
+extern "C" {
+    fn openvr_utils_get_time_since_last_vsync(seconds_since_last_vsync: *mut f32, frame_counter: *mut u64) -> bool;
+    fn openvr_utils_get_display_frequency() -> f32;
+    fn openvr_utils_get_hmd_activity_level() -> i32;
+    fn openvr_utils_get_tracked_device_class(index: u32) -> i32;
+    fn openvr_utils_get_controller_role(index: u32) -> i32;
+    fn openvr_utils_get_device_for_controller_role(role: i32) -> u32;
+    fn openvr_utils_get_battery(index: u32, level: *mut f32, charging: *mut bool) -> bool;
+    fn openvr_utils_get_controller_state(index: u32, state: *mut ControllerState) -> bool;
+    fn openvr_utils_poll_next_event(event: *mut Event) -> bool;
+    fn openvr_utils_acknowledge_quit();
+}